@@ -3,7 +3,7 @@ mod trainer;
 mod trainer_parallel;
 mod test;
 
-use convolutional_neural_network::{ActivationFunction, ErrorFunction, Initialization, Layer, NeuralNetwork, PoolingType};
+use convolutional_neural_network::{ActivationFunction, ErrorFunction, Hyperparameters, Initialization, Layer, NeuralNetwork, PoolingType};
 use std::fs;
 use rand::seq::SliceRandom;
 
@@ -48,10 +48,7 @@ pub fn main() {
 
             let mut neural_net = NeuralNetwork::new(ErrorFunction::BinaryCrossEntropy);
 
-            neural_net.register_layer(
-                ActivationFunction::ReLU,
-                Layer::make_input_layer(1, (128, 128, 3))
-            );
+            neural_net.register_input_layer(Layer::make_input_layer(1, (128, 128, 3))).unwrap();
 
             neural_net.register_layer(
                 ActivationFunction::ReLU,
@@ -93,11 +90,8 @@ pub fn main() {
                 Layer::make_fully_connected_layer(512, 1)
             );
 
-            neural_net.initialize(1, Initialization::NormalHe).unwrap();
-            neural_net.initialize(3, Initialization::NormalHe).unwrap();
-            neural_net.initialize(5, Initialization::NormalHe).unwrap();
-            neural_net.initialize(7, Initialization::NormalHe).unwrap();
             neural_net.initialize(8, Initialization::NormalXavier).unwrap();
+            neural_net.initialize_all(Initialization::NormalHe);
 
             let mut write = std::io::BufWriter::new(fs::OpenOptions::new()
                 .write(true)
@@ -118,8 +112,7 @@ pub fn main() {
         "run" => {
             if args.len() < 4 { return };
 
-            let mut read = std::io::BufReader::new(fs::File::open(&args[2]).unwrap());
-            let mut neural_network: NeuralNetwork = bincode::serde::decode_from_std_read(&mut read, bincode::config::standard()).unwrap();
+            let mut neural_network = NeuralNetwork::load(&args[2]).unwrap();
 
             println!("Run: loaded model");
 
@@ -142,8 +135,7 @@ pub fn main() {
         "test" => {
             if args.len() < 4 { return };
 
-            let mut read = std::io::BufReader::new(fs::File::open(&args[2]).unwrap());
-            let mut neural_network: NeuralNetwork = bincode::serde::decode_from_std_read(&mut read, bincode::config::standard()).unwrap();
+            let mut neural_network = NeuralNetwork::load(&args[2]).unwrap();
             
             let mut read = std::io::BufReader::new(fs::File::open(&args[3]).unwrap());
             let images: Vec<image_compiler::TrainingData> = bincode::serde::decode_from_std_read(&mut read, bincode::config::standard()).unwrap();
@@ -158,8 +150,7 @@ pub fn main() {
             
             const PARALLEL: bool = true;
 
-            let mut read = std::io::BufReader::new(fs::File::open(&args[2]).unwrap());
-            let mut neural_network: NeuralNetwork = bincode::serde::decode_from_std_read(&mut read, bincode::config::standard()).unwrap();
+            let mut neural_network = NeuralNetwork::load(&args[2]).unwrap();
             
             let mut read = std::io::BufReader::new(fs::File::open(&args[3]).unwrap());
             let images: Vec<image_compiler::TrainingData> = bincode::serde::decode_from_std_read(&mut read, bincode::config::standard()).unwrap();
@@ -169,13 +160,15 @@ pub fn main() {
             let batch_size: usize = args[6].parse().unwrap();
             let epoches: usize = args[7].parse().unwrap();
 
+            neural_network.set_hyperparameters(Hyperparameters { learning_rate, ..neural_network.get_hyperparameters() });
+
             println!("Train: loaded model and images");
 
             for i in 0..epoches {
                 if PARALLEL {
-                    trainer_parallel::train(start, batch_size, learning_rate, args[2].clone(), &images, &mut neural_network);
+                    trainer_parallel::train(start, batch_size, args[2].clone(), &images, &mut neural_network);
                 } else {
-                    trainer::train(start, batch_size, learning_rate, args[2].clone(), &images, &mut neural_network);
+                    trainer::train(start, batch_size, args[2].clone(), &images, &mut neural_network);
                 }
                 println!("Completed epoch {}/{}", i + 1, epoches);
             }
@@ -2,6 +2,7 @@ use convolutional_neural_network::NeuralNetwork;
 
 use crate::image_compiler;
 
+#[allow(clippy::needless_range_loop)]
 pub fn test(images: &Vec<image_compiler::TrainingData>, neural_network: &mut NeuralNetwork) {
     let mut average_error = 0.0f32;
     let mut correct = 0;
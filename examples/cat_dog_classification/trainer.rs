@@ -1,17 +1,15 @@
 use convolutional_neural_network::NeuralNetwork;
 use crate::image_compiler;
 
-use std::fs;
-
+#[allow(clippy::ptr_arg, clippy::needless_range_loop)]
 pub fn train(
     start: usize,
     batch_size: usize,
-    learning_rate: f32,
     path: String,
     images: &Vec<image_compiler::TrainingData>,
     neural_network: &mut NeuralNetwork
 ) {
-    let total_batches = (images.len() + batch_size - 1) / batch_size;
+    let total_batches = images.len().div_ceil(batch_size);
 
     let batches = images.chunks(batch_size).enumerate().skip(start);
 
@@ -50,7 +48,7 @@ pub fn train(
             neural_network.back_propagate(&expected_vec).unwrap();
         }
 
-        neural_network.end_batch(batch_size as u8, learning_rate, 0.9, 5e-4);
+        neural_network.end_batch(batch_size as u8);
         
         println!(
             "Completed batch {}/{}, average_error={}, correct_vs_incorrect={}/{}",
@@ -62,35 +60,13 @@ pub fn train(
         );
 
         if (batch_idx + 1) % 20 == 0 {
-            let mut writer = std::io::BufWriter::new(fs::OpenOptions::new()
-                .write(true)
-                .truncate(true)
-                .create(true)
-                .open(&path)
-                .unwrap());
-
-            bincode::serde::encode_into_std_write(
-                &neural_network,
-                &mut writer,
-                bincode::config::standard()
-            ).unwrap();
+            neural_network.save(&path).unwrap();
 
             println!("Saved neural network");
         }
     }
 
-    let mut writer = std::io::BufWriter::new(fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open(&path)
-        .unwrap());
-
-    bincode::serde::encode_into_std_write(
-        &neural_network,
-        &mut writer,
-        bincode::config::standard()
-    ).unwrap();
+    neural_network.save(&path).unwrap();
 
     println!("Saved neural network");
 }
\ No newline at end of file
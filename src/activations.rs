@@ -1,14 +1,56 @@
+use crate::errors::Error;
+
 use serde::{Serialize, Deserialize};
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ActivationFunction {
     Sigmoid,
     ReLU,
     LeakyReLU(f32),
 
     None,
+
+    // new variants are appended so the serialized variant indices stay stable
+    HardSigmoid,
+    Softsign,
+
+    /// normalizes over every value of a layer, layers apply it with `softmax` and `softmax_derivative`
+    Softmax,
+    /// leaky ReLU with a negative slope per channel that is learned by the layer
+    PReLU,
+    /// a nonlinearity supplied by the user, e.g. a `static` implementing `Activation`
+    ///
+    /// it can't be serialized, so a network using it can't be saved
+    #[serde(skip)]
+    Custom(&'static dyn Activation),
+}
+
+/// A user defined activation function for `ActivationFunction::Custom`
+///
+/// it has to be `Sync` since networks are trained on several threads
+pub trait Activation: Sync {
+    fn forward(&self, x: f32) -> f32;
+
+    /// the derivative with respect to the raw (pre-activation) value `x`
+    fn derivative(&self, x: f32) -> f32;
+}
+
+impl std::fmt::Debug for dyn Activation {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("Activation")
+    }
 }
 
+/// two custom activations are only equal if they're the same object
+impl PartialEq for dyn Activation {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self, other)
+    }
+}
+
+/// the slope PReLU layers start with, also used when evaluating PReLU outside of a layer
+pub const PRELU_INITIAL_SLOPE: f32 = 0.25;
+
 pub fn eval(function_type: ActivationFunction, x: f32) -> f32 {
     match function_type {
         ActivationFunction::Sigmoid => sigmoid(x),
@@ -16,6 +58,14 @@ pub fn eval(function_type: ActivationFunction, x: f32) -> f32 {
         ActivationFunction::LeakyReLU(slope) => leaky_relu(x, slope),
 
         ActivationFunction::None => x,
+
+        ActivationFunction::HardSigmoid => hard_sigmoid(x),
+        ActivationFunction::Softsign => softsign(x),
+
+        // the softmax of a single value
+        ActivationFunction::Softmax => 1.0,
+        ActivationFunction::PReLU => leaky_relu(x, PRELU_INITIAL_SLOPE),
+        ActivationFunction::Custom(activation) => activation.forward(x),
     }
 }
 
@@ -26,11 +76,86 @@ pub fn eval_derivative(function_type: ActivationFunction, x: f32) -> f32 {
         ActivationFunction::LeakyReLU(slope) => leaky_relu_derivative(x, slope),
 
         ActivationFunction::None => 1.0,
+
+        ActivationFunction::HardSigmoid => hard_sigmoid_derivative(x),
+        ActivationFunction::Softsign => softsign_derivative(x),
+
+        ActivationFunction::Softmax => 0.0,
+        ActivationFunction::PReLU => leaky_relu_derivative(x, PRELU_INITIAL_SLOPE),
+        ActivationFunction::Custom(activation) => activation.derivative(x),
+    }
+}
+
+/// evaluates the derivative from the activated value, or returns None if the activation doesn't support it
+pub fn eval_derivative_from_output(function_type: ActivationFunction, y: f32) -> Option<f32> {
+    match function_type {
+        ActivationFunction::Sigmoid => Some(y * (1.0 - y)),
+        ActivationFunction::ReLU => Some(relu_derivative(y)),
+        ActivationFunction::LeakyReLU(slope) if slope > 0.0 && slope < 1.0 => Some(leaky_relu_derivative(y, slope)),
+
+        ActivationFunction::None => Some(1.0),
+
+        _ => None,
     }
 }
 
+/// returns true if every output is in [0, 1]
+pub fn is_bounded_to_unit_interval(function_type: ActivationFunction) -> bool {
+    matches!(function_type, ActivationFunction::Sigmoid | ActivationFunction::HardSigmoid | ActivationFunction::Softmax)
+}
+
+pub fn has_output_derivative(function_type: ActivationFunction) -> bool {
+    eval_derivative_from_output(function_type, 0.0).is_some()
+}
+
+/// the softmax of `logits` divided by `temperature`, e.g. the soft targets of a teacher network for distillation
+///
+/// a temperature above 1 flattens the distribution and one below 1 sharpens it
+pub fn softmax(logits: &[f32], temperature: f32) -> Result<Vec<f32>, Error> {
+    if !(temperature > 0.0 && temperature.is_finite()) { return Err(Error::InvalidInput) };
+
+    let scaled: Vec<f32> = logits.iter().map(|logit| logit / temperature).collect();
+    let mut result = vec![0.0; logits.len()];
+    softmax_into(&scaled, &mut result);
+
+    Ok(result)
+}
+
+/// writes the softmax of `inputs` into `outputs`
+#[allow(clippy::unused_unit)]
+pub(crate) fn softmax_into(inputs: &[f32], outputs: &mut [f32]) -> () {
+    // subtracting the maximum keeps the exponentials from overflowing
+    let max = inputs.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let mut sum = 0.0;
+
+    for i in 0..inputs.len() {
+        outputs[i] = (inputs[i] - max).exp();
+        sum += outputs[i];
+    }
+
+    for output in outputs.iter_mut() {
+        *output /= sum;
+    }
+}
+
+/// multiplies the gradients by the softmax jacobian, computed from the softmax outputs
+#[allow(clippy::unused_unit)]
+pub(crate) fn softmax_derivative(outputs: &[f32], gradients: &[f32], result: &mut [f32]) -> () {
+    let dot: f32 = outputs.iter().zip(gradients).map(|(output, gradient)| output * gradient).sum();
+
+    for i in 0..outputs.len() {
+        result[i] = outputs[i] * (gradients[i] - dot);
+    }
+}
+
+/// only ever exponentiates a non-positive value, so `exp` can't overflow for large negative inputs
 fn sigmoid(x: f32) -> f32 {
-    1.0 / (1.0 + (-x).exp())
+    if x >= 0.0 {
+        1.0 / (1.0 + (-x).exp())
+    } else {
+        let e = x.exp();
+        e / (1.0 + e)
+    }
 }
 
 fn relu(x: f32) -> f32 {
@@ -41,6 +166,15 @@ fn leaky_relu(x: f32, slope: f32) -> f32 {
     x.max(x * slope)
 }
 
+/// piecewise linear approximation of the sigmoid
+fn hard_sigmoid(x: f32) -> f32 {
+    (0.2 * x + 0.5).clamp(0.0, 1.0)
+}
+
+fn softsign(x: f32) -> f32 {
+    x / (1.0 + x.abs())
+}
+
 fn sigmoid_derivative(x: f32) -> f32 {
     let res = sigmoid(x);
 
@@ -61,4 +195,18 @@ fn leaky_relu_derivative(x: f32, slope: f32) -> f32 {
     } else {
         1.0
     }
+}
+
+fn hard_sigmoid_derivative(x: f32) -> f32 {
+    if (-2.5..=2.5).contains(&x) {
+        0.2
+    } else {
+        0.0
+    }
+}
+
+fn softsign_derivative(x: f32) -> f32 {
+    let denominator = 1.0 + x.abs();
+
+    1.0 / (denominator * denominator)
 }
\ No newline at end of file
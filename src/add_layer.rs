@@ -0,0 +1,271 @@
+use crate::layer::{Layer, LayerBase};
+use crate::errors::Error;
+use crate::{activations, util};
+
+use serde::{Serialize, Deserialize, de::{DeserializeSeed, Visitor}, ser::SerializeStruct};
+
+/// Sums the output of the previous layer with the output of an earlier layer, i.e. a residual connection
+///
+/// the network adds the earlier output after the previous layer propagates into this one and routes the gradient back to both
+#[derive(Clone)]
+pub struct AddLayer {
+    /// the index of the earlier layer in the network
+    pub(crate) source: usize,
+    pub(crate) zero_padding: usize,
+
+    pub(crate) dimension: (usize, usize, usize),
+
+    pub(crate) volume: Vec<f32>,
+    pub(crate) volume_gradients: Vec<f32>,
+
+    raw_volume: Vec<f32>,
+    pub(crate) back_activated_volume: Vec<f32>,
+}
+
+#[allow(clippy::unused_unit)]
+impl AddLayer {
+    pub fn new(source: usize, zero_padding: usize, dimension: (usize, usize, usize)) -> Self {
+        let (dimension_x, dimension_y, depth) = dimension;
+
+        Self {
+            source,
+            zero_padding,
+
+            dimension,
+
+            volume: vec![0.0; dimension_x * dimension_y * depth],
+            volume_gradients: vec![0.0; dimension_x * dimension_y * depth],
+
+            raw_volume: vec![0.0; dimension_x * dimension_y * depth],
+            back_activated_volume: vec![0.0; dimension_x * dimension_y * depth],
+        }
+    }
+
+    pub fn get_source(&self) -> usize {
+        self.source
+    }
+
+    /// takes the output of the previous layer
+    pub(crate) fn feed_forward(&mut self, input: &[f32]) -> Result<(), Error> {
+        if input.len() != self.raw_volume.len() { return Err(Error::DimensionMismatch) };
+
+        self.raw_volume.copy_from_slice(input);
+        Ok(())
+    }
+
+    /// adds the output of the source layer
+    #[allow(clippy::needless_range_loop)]
+    pub(crate) fn add_source(&mut self, source: &[f32]) -> Result<(), Error> {
+        if source.len() != self.raw_volume.len() { return Err(Error::DimensionMismatch) };
+
+        for i in 0..source.len() {
+            self.raw_volume[i] += source[i];
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn release_activations(&mut self) -> () {
+        self.volume = Vec::new();
+        self.raw_volume = Vec::new();
+    }
+
+    pub(crate) fn restore_activations(&mut self) -> () {
+        let (dimension_x, dimension_y, depth) = self.dimension;
+
+        self.volume.resize(dimension_x * dimension_y * depth, 0.0);
+        self.raw_volume.resize(dimension_x * dimension_y * depth, 0.0);
+    }
+
+    pub(crate) fn activate(&mut self, func: activations::ActivationFunction) -> () {
+        if let activations::ActivationFunction::Softmax = func {
+            activations::softmax_into(&self.raw_volume, &mut self.volume);
+            return;
+        }
+
+        for i in 0..self.volume.len() {
+            self.volume[i] = activations::eval(func, self.raw_volume[i]);
+        }
+    }
+
+    pub(crate) fn back_activate(&mut self, func: activations::ActivationFunction) -> () {
+        if let activations::ActivationFunction::Softmax = func {
+            activations::softmax_derivative(&self.volume, &self.volume_gradients, &mut self.back_activated_volume);
+            return;
+        }
+
+        for i in 0..self.volume.len() {
+            self.back_activated_volume[i] = activations::eval_derivative(func, self.raw_volume[i]) * self.volume_gradients[i];
+        }
+    }
+
+    pub(crate) fn same_structure(&self, other: &AddLayer) -> bool {
+        self.source == other.source && self.zero_padding == other.zero_padding && self.dimension == other.dimension
+    }
+}
+
+impl LayerBase for AddLayer {
+    fn forward_propagate(&self, next_layer: &mut Layer) -> Result<(), Error> {
+        match next_layer {
+            Layer::Convolutional(layer) => {
+                util::check_output_dimension(self.dimension,
+                    layer.dimension,
+                    self.zero_padding,
+                    layer.num_kernels,
+                    layer.kernel_size,
+                    layer.stride,
+                    layer.rounding
+                )?;
+
+                layer.convolve(self.dimension, &self.volume, self.zero_padding);
+            }
+
+            Layer::Pooling(layer) => {
+                util::check_output_dimension(self.dimension,
+                    layer.dimension,
+                    0, // a pooling layer doesn't take padding into account
+                    layer.dimension.2,
+                    layer.kernel_size,
+                    layer.stride,
+                    layer.rounding
+                )?;
+
+                layer.convolve(self.dimension, &self.volume);
+            }
+
+            Layer::FullyConnected(layer) => {
+                let dim = self.dimension;
+                if dim.0 * dim.1 * dim.2 != layer.num_inputs { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume);
+            }
+
+            Layer::Add(layer) => {
+                if self.dimension != layer.dimension { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume)?;
+            }
+
+            Layer::MaxUnpool(layer) => layer.feed_forward(&self.volume),
+            Layer::SpatialDropout(layer) => layer.feed_forward(&self.volume)?,
+
+            Layer::Scale(layer) => {
+                if self.dimension != layer.dimension { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume)?;
+            }
+
+            Layer::SeparableConvolutional(layer) => {
+                layer.check_input(self.dimension, self.zero_padding)?;
+
+                layer.convolve(self.dimension, &self.volume, self.zero_padding);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// the gradient passes through unchanged, the network adds it to the source layer as well
+    fn back_propagate(&mut self, previous_layer: &mut Layer) -> Result<(), Error> {
+        let previous_gradients = previous_layer.output_gradients_mut();
+        if previous_gradients.len() != self.back_activated_volume.len() { return Err(Error::DimensionMismatch) };
+        previous_gradients.copy_from_slice(&self.back_activated_volume);
+
+        Ok(())
+    }
+}
+
+impl Serialize for AddLayer {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AddLayer", 3)?;
+
+        state.serialize_field("source", &self.source)?;
+        state.serialize_field("zero_padding", &self.zero_padding)?;
+        state.serialize_field("dimension", &self.dimension)?;
+
+        state.end()
+    }
+}
+
+const ADD_LAYER_FIELDS: &[&str] = &["source", "zero_padding", "dimension"];
+
+impl<'de> Deserialize<'de> for AddLayer {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("AddLayer", ADD_LAYER_FIELDS, AddLayerVisitor)
+    }
+}
+
+/// the add layer format hasn't changed since it was introduced in format version 7
+pub(crate) struct AddLayerVisitor;
+
+impl<'de> DeserializeSeed<'de> for AddLayerVisitor {
+    type Value = AddLayer;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("AddLayer", ADD_LAYER_FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for AddLayerVisitor {
+    type Value = AddLayer;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an AddLayer struct")
+    }
+
+    fn visit_map<M>(self, mut map: M) -> Result<Self::Value, M::Error>
+        where
+            M: serde::de::MapAccess<'de>,
+    {
+        let mut source = None;
+        let mut zero_padding = None;
+        let mut dimension = None;
+
+        while let Some(key) = map.next_key::<&str>()? {
+            match key {
+                "source" => {
+                    if source.is_some() { return Err(serde::de::Error::duplicate_field("source")); };
+
+                    source = Some(map.next_value()?);
+                }
+
+                "zero_padding" => {
+                    if zero_padding.is_some() { return Err(serde::de::Error::duplicate_field("zero_padding")); };
+
+                    zero_padding = Some(map.next_value()?);
+                }
+
+                "dimension" => {
+                    if dimension.is_some() { return Err(serde::de::Error::duplicate_field("dimension")); };
+
+                    dimension = Some(map.next_value()?);
+                }
+
+                _ => return Err(serde::de::Error::unknown_field(key, ADD_LAYER_FIELDS)),
+            }
+        }
+
+        let source = source.ok_or_else(|| serde::de::Error::missing_field("source"))?;
+        let zero_padding = zero_padding.ok_or_else(|| serde::de::Error::missing_field("zero_padding"))?;
+        let dimension = dimension.ok_or_else(|| serde::de::Error::missing_field("dimension"))?;
+
+        Ok(AddLayer::new(source, zero_padding, dimension))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let source = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+        let zero_padding = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+        let dimension = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+
+        Ok(AddLayer::new(source, zero_padding, dimension))
+    }
+}
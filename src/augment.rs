@@ -0,0 +1,230 @@
+use crate::errors::Error;
+use crate::util;
+
+use rand::Rng;
+use rand_distr::{Beta, Distribution};
+
+/// mirrors the volume along its width
+#[allow(clippy::ptr_arg)]
+pub fn horizontal_flip(volume: &Vec<f32>, dimension: (usize, usize, usize)) -> Result<Vec<f32>, Error> {
+    let (dimension_x, dimension_y, depth) = dimension;
+    if volume.len() != dimension_x * dimension_y * depth { return Err(Error::DimensionMismatch) };
+
+    let mut result = vec![0.0; volume.len()];
+
+    for x in 0..dimension_x {
+        for y in 0..dimension_y {
+            for z in 0..depth {
+                let flipped = util::get_index((dimension_x - 1 - x, y, z), dimension);
+                result[flipped] = volume[util::get_index((x, y, z), dimension)];
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// flips the volume with the given probability
+pub fn random_horizontal_flip<R: Rng>(volume: &Vec<f32>, dimension: (usize, usize, usize), probability: f32, rng: &mut R) -> Result<Vec<f32>, Error> {
+    if !(0.0..=1.0).contains(&probability) { return Err(Error::InvalidInput) };
+
+    if rng.random::<f32>() < probability {
+        return horizontal_flip(volume, dimension);
+    }
+
+    if volume.len() != dimension.0 * dimension.1 * dimension.2 { return Err(Error::DimensionMismatch) };
+    Ok(volume.clone())
+}
+
+/// zeroes the (x, y, width, height) rectangle across every depth
+#[allow(clippy::ptr_arg)]
+pub fn erase(volume: &Vec<f32>, dimension: (usize, usize, usize), region: (usize, usize, usize, usize)) -> Result<Vec<f32>, Error> {
+    let (dimension_x, dimension_y, depth) = dimension;
+    if volume.len() != dimension_x * dimension_y * depth { return Err(Error::DimensionMismatch) };
+
+    let (start_x, start_y, width, height) = region;
+    if start_x + width > dimension_x || start_y + height > dimension_y { return Err(Error::InvalidInput) };
+
+    let mut result = volume.clone();
+
+    for x in start_x..(start_x + width) {
+        for y in start_y..(start_y + height) {
+            for z in 0..depth {
+                result[util::get_index((x, y, z), dimension)] = 0.0;
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// zeroes a (width, height) rectangle at a random position of the volume
+pub fn random_erasing<R: Rng>(volume: &Vec<f32>, dimension: (usize, usize, usize), size: (usize, usize), rng: &mut R) -> Result<Vec<f32>, Error> {
+    let (width, height) = size;
+    if width > dimension.0 || height > dimension.1 { return Err(Error::InvalidInput) };
+
+    let start_x = rng.random_range(0..=(dimension.0 - width));
+    let start_y = rng.random_range(0..=(dimension.1 - height));
+
+    erase(volume, dimension, (start_x, start_y, width, height))
+}
+
+/// How values outside the volume are sampled
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillMode {
+    Zero,
+    /// repeats the nearest edge value
+    Clamp,
+}
+
+/// A rotation around the center followed by a scale and translation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AffineTransform {
+    /// in radians, rotating x towards y
+    pub rotation: f32,
+    /// (x, y) in pixels
+    pub translation: (f32, f32),
+    pub scale: f32,
+}
+
+impl Default for AffineTransform {
+    fn default() -> Self {
+        Self {
+            rotation: 0.0,
+            translation: (0.0, 0.0),
+            scale: 1.0,
+        }
+    }
+}
+
+/// The ranges `random_affine` samples transforms from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AffineRanges {
+    /// the maximum rotation in either direction in radians
+    pub rotation: f32,
+    /// the maximum (x, y) translation in either direction in pixels
+    pub translation: (f32, f32),
+    /// (min, max)
+    pub scale: (f32, f32),
+}
+
+/// transforms the volume with bilinear sampling, every depth is transformed the same way
+pub fn affine(volume: &Vec<f32>, dimension: (usize, usize, usize), transform: AffineTransform, fill: FillMode) -> Result<Vec<f32>, Error> {
+    let (dimension_x, dimension_y, depth) = dimension;
+    if volume.len() != dimension_x * dimension_y * depth { return Err(Error::DimensionMismatch) };
+    if transform.scale <= 0.0 { return Err(Error::InvalidInput) };
+
+    let center = ((dimension_x as f32 - 1.0) / 2.0, (dimension_y as f32 - 1.0) / 2.0);
+    let (sin, cos) = transform.rotation.sin_cos();
+
+    let mut result = vec![0.0; volume.len()];
+
+    for x in 0..dimension_x {
+        for y in 0..dimension_y {
+            // map every output position back to where it came from
+            let offset_x = (x as f32 - center.0 - transform.translation.0) / transform.scale;
+            let offset_y = (y as f32 - center.1 - transform.translation.1) / transform.scale;
+
+            let source_x = cos * offset_x + sin * offset_y + center.0;
+            let source_y = -sin * offset_x + cos * offset_y + center.1;
+
+            for z in 0..depth {
+                result[util::get_index((x, y, z), dimension)] = sample_bilinear(volume, dimension, (source_x, source_y, z), fill);
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// transforms the volume with a transform sampled uniformly from the ranges
+pub fn random_affine<R: Rng>(volume: &Vec<f32>, dimension: (usize, usize, usize), ranges: AffineRanges, fill: FillMode, rng: &mut R) -> Result<Vec<f32>, Error> {
+    if ranges.rotation < 0.0 || ranges.translation.0 < 0.0 || ranges.translation.1 < 0.0 { return Err(Error::InvalidInput) };
+    if ranges.scale.0 <= 0.0 || ranges.scale.0 > ranges.scale.1 { return Err(Error::InvalidInput) };
+
+    let transform = AffineTransform {
+        rotation: rng.random_range(-ranges.rotation..=ranges.rotation),
+        translation: (
+            rng.random_range(-ranges.translation.0..=ranges.translation.0),
+            rng.random_range(-ranges.translation.1..=ranges.translation.1),
+        ),
+        scale: rng.random_range(ranges.scale.0..=ranges.scale.1),
+    };
+
+    affine(volume, dimension, transform, fill)
+}
+
+/// A deterministic augmentation, e.g. for test-time augmentation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Augmentation {
+    /// the volume unchanged
+    Identity,
+    HorizontalFlip,
+    /// see `erase`
+    Erase((usize, usize, usize, usize)),
+    Affine(AffineTransform, FillMode),
+}
+
+impl Augmentation {
+    pub fn apply(&self, volume: &Vec<f32>, dimension: (usize, usize, usize)) -> Result<Vec<f32>, Error> {
+        match *self {
+            Augmentation::Identity => {
+                if volume.len() != dimension.0 * dimension.1 * dimension.2 { return Err(Error::DimensionMismatch) };
+                Ok(volume.clone())
+            }
+
+            Augmentation::HorizontalFlip => horizontal_flip(volume, dimension),
+            Augmentation::Erase(region) => erase(volume, dimension, region),
+            Augmentation::Affine(transform, fill) => affine(volume, dimension, transform, fill),
+        }
+    }
+}
+
+#[allow(clippy::ptr_arg)]
+fn sample_bilinear(volume: &Vec<f32>, dimension: (usize, usize, usize), position: (f32, f32, usize), fill: FillMode) -> f32 {
+    let (x, y, z) = position;
+
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fraction_x, fraction_y) = (x - x0, y - y0);
+
+    let value = |x: f32, y: f32| -> f32 {
+        let inside = x >= 0.0 && y >= 0.0 && x < dimension.0 as f32 && y < dimension.1 as f32;
+
+        match fill {
+            FillMode::Zero if !inside => 0.0,
+
+            _ => {
+                let x = x.clamp(0.0, dimension.0 as f32 - 1.0) as usize;
+                let y = y.clamp(0.0, dimension.1 as f32 - 1.0) as usize;
+
+                volume[util::get_index((x, y, z), dimension)]
+            }
+        }
+    };
+
+    let top = value(x0, y0) * (1.0 - fraction_x) + value(x0 + 1.0, y0) * fraction_x;
+    let bottom = value(x0, y0 + 1.0) * (1.0 - fraction_x) + value(x0 + 1.0, y0 + 1.0) * fraction_x;
+
+    top * (1.0 - fraction_y) + bottom * fraction_y
+}
+
+/// returns `coefficient * a + (1 - coefficient) * b` for both the (input, target) pairs
+pub fn blend(a: (&[f32], &[f32]), b: (&[f32], &[f32]), coefficient: f32) -> Result<(Vec<f32>, Vec<f32>), Error> {
+    if a.0.len() != b.0.len() || a.1.len() != b.1.len() { return Err(Error::DimensionMismatch) };
+    if !(0.0..=1.0).contains(&coefficient) { return Err(Error::InvalidInput) };
+
+    let mix = |a: &[f32], b: &[f32]| -> Vec<f32> {
+        a.iter().zip(b).map(|(a, b)| coefficient * a + (1.0 - coefficient) * b).collect()
+    };
+
+    Ok((mix(a.0, b.0), mix(a.1, b.1)))
+}
+
+/// blends two samples and their targets by a coefficient drawn from Beta(alpha, alpha), i.e. mixup
+///
+/// a small alpha mostly keeps one of the samples, a large alpha tends towards their average
+pub fn mixup<R: Rng>(a: (&[f32], &[f32]), b: (&[f32], &[f32]), alpha: f32, rng: &mut R) -> Result<(Vec<f32>, Vec<f32>), Error> {
+    let distribution = Beta::new(alpha, alpha).map_err(|_| Error::InvalidInput)?;
+
+    blend(a, b, distribution.sample(rng))
+}
@@ -2,23 +2,28 @@ use crate::layer::{Layer, LayerBase, LearnableLayer};
 use crate::errors::Error;
 use crate::{activations, util};
 use crate::initialization;
+use crate::neural_network::FORMAT_VERSION;
+use crate::prelu::PReluSlopes;
+use crate::util::{Gradient, OutputRounding};
 
-use serde::de::{Deserialize, Visitor};
+use serde::de::{Deserialize, DeserializeSeed, Visitor};
 use serde::ser::{Serialize, SerializeStruct};
 
 #[derive(Clone)]
 pub struct ConvolutionalLayer {
-    pub(crate) stride: usize,
+    /// (x, y)
+    pub(crate) stride: (usize, usize),
     pub(crate) kernel_size: usize,
     pub(crate) num_kernels: usize,
+    pub(crate) rounding: OutputRounding,
 
     pub(crate) dimension: (usize, usize, usize),
 
     pub(crate) volume: Vec<f32>,
     pub(crate) volume_gradients: Vec<f32>,
     
-    pub(crate) bias_gradients: Vec<f32>,
-    pub(crate) kernel_gradients: Vec<f32>,
+    pub(crate) bias_gradients: Vec<Gradient>,
+    pub(crate) kernel_gradients: Vec<Gradient>,
     
     back_activated_volume: Vec<f32>,
 
@@ -28,22 +33,31 @@ pub struct ConvolutionalLayer {
     bias_velocity: Vec<f32>,
     kernel_velocity: Vec<f32>,
 
-    zero_padding: usize,
+    pub(crate) zero_padding: usize,
     
-    biases: Vec<f32>,
-    kernel: Vec<f32>,
+    pub(crate) biases: Vec<f32>,
+    pub(crate) kernel: Vec<f32>,
 
     input_depth: usize,
+    use_bias: bool,
+
+    pub(crate) prelu: PReluSlopes,
+
+    /// the activation of the layer, which is applied while convolving during inference, see `fuses_activation`
+    activation: activations::ActivationFunction,
+    pub(crate) training: bool,
 }
 
+#[allow(clippy::unused_unit, clippy::ptr_arg)]
 impl ConvolutionalLayer {
-    pub fn new(zero_padding: usize, stride: usize, kernel_size: usize, dimension: (usize, usize, usize), input_depth: usize) -> Self {
+    pub fn new(zero_padding: usize, stride: (usize, usize), kernel_size: usize, dimension: (usize, usize, usize), input_depth: usize) -> Self {
         let (dimension_x, dimension_y, depth) = dimension;
         
         Self {
             stride,
             kernel_size,
             num_kernels: depth,
+            rounding: OutputRounding::Floor,
 
             dimension,
 
@@ -66,10 +80,86 @@ impl ConvolutionalLayer {
             kernel: vec![0.0; kernel_size * kernel_size * input_depth * depth],
             
             input_depth,
+            use_bias: true,
 
+            prelu: PReluSlopes::default(),
+
+            activation: activations::ActivationFunction::None,
+            training: true,
         }
     }
     
+    /// input layers are convolutional layers without kernels
+    pub(crate) fn is_input_layer(&self) -> bool {
+        self.kernel_size == 0
+    }
+
+    /// only keeps the raw (pre-activation) volume if the activation's derivative can't be computed from its output
+    pub(crate) fn configure_activation(&mut self, func: activations::ActivationFunction) -> () {
+        if activations::has_output_derivative(func) {
+            self.raw_volume = Vec::new();
+        } else if self.raw_volume.is_empty() {
+            self.raw_volume = vec![0.0; self.volume.len()];
+        }
+
+        self.prelu.configure(func, self.num_kernels);
+        self.activation = func;
+    }
+
+    /// during inference an activation whose derivative can be computed from its output is applied while convolving,
+    /// which saves a pass over the volume. the raw volume isn't kept for it, so back propagating still works
+    pub(crate) fn fuses_activation(&self) -> bool {
+        !self.training && self.raw_volume.is_empty()
+    }
+
+    pub(crate) fn release_activations(&mut self) -> () {
+        self.volume = Vec::new();
+        self.raw_volume = Vec::new();
+    }
+
+    /// reallocates the buffers `configure_activation` would keep, without touching the PReLU slopes
+    ///
+    /// only the configured activation is fused, so the raw volume is also kept for any other `func`
+    pub(crate) fn restore_activations(&mut self, func: activations::ActivationFunction) -> () {
+        let (dimension_x, dimension_y, depth) = self.dimension;
+        let size = dimension_x * dimension_y * depth;
+
+        self.volume.resize(size, 0.0);
+        if !activations::has_output_derivative(func) || func != self.activation { self.raw_volume.resize(size, 0.0) };
+    }
+
+    /// the learned negative slope of every kernel when using PReLU, empty otherwise
+    pub fn get_prelu_slopes(&self) -> &Vec<f32> {
+        &self.prelu.slopes
+    }
+
+    pub fn set_prelu_slopes(&mut self, slopes: Vec<f32>) -> Result<(), Error> {
+        self.prelu.set_slopes(slopes)
+    }
+
+    pub fn uses_bias(&self) -> bool {
+        self.use_bias
+    }
+
+    /// without a bias the kernels are applied as is and no bias buffers are allocated, e.g. when followed by a normalization
+    pub fn set_use_bias(&mut self, use_bias: bool) -> () {
+        let len = if use_bias { self.num_kernels } else { 0 };
+
+        self.use_bias = use_bias;
+        self.biases = vec![0.0; len];
+        self.bias_gradients = vec![0.0; len];
+        self.bias_velocity = vec![0.0; len];
+    }
+
+    pub fn get_rounding(&self) -> OutputRounding {
+        self.rounding
+    }
+
+    /// sets how the output dimension is rounded, which has to match the dimension the layer was created with
+    pub fn set_rounding(&mut self, rounding: OutputRounding) -> () {
+        self.rounding = rounding;
+    }
+
     /// Data is packed in row major order and each depth is stored sequentially
     pub fn set_volume(&mut self, volume: &Vec<f32>) -> Result<(), Error> {
         if self.volume.len() != volume.len() { return Err(Error::DimensionMismatch) };
@@ -80,6 +170,14 @@ impl ConvolutionalLayer {
         Ok(())
     }
 
+    pub fn get_kernel(&self) -> &Vec<f32> {
+        &self.kernel
+    }
+
+    pub fn get_biases(&self) -> &Vec<f32> {
+        &self.biases
+    }
+
     pub fn set_kernel(&mut self, kernel: Vec<f32>) -> Result<(), Error> {
         if self.kernel.len() != kernel.len() { return Err(Error::InvalidInput) };
 
@@ -98,51 +196,190 @@ impl ConvolutionalLayer {
         Ok(())
     }
 
+    /// returns a kernel with every channel scaled to [0, 1], laid out like a (kernel_size, kernel_size, input_depth) volume
+    ///
+    /// channels with constant weights become 0
+    pub fn filter_as_normalized(&self, kernel_index: usize) -> Result<Vec<f32>, Error> {
+        if kernel_index >= self.num_kernels || self.is_input_layer() { return Err(Error::InvalidInput) };
+
+        let dimension = (self.kernel_size, self.kernel_size, self.input_depth);
+        let mut result = vec![0.0; self.kernel_size * self.kernel_size * self.input_depth];
+
+        for z in 0..self.input_depth {
+            let mut min = f32::INFINITY;
+            let mut max = f32::NEG_INFINITY;
+
+            for y in 0..self.kernel_size {
+                for x in 0..self.kernel_size {
+                    let weight = self.kernel[util::get_kernel_index((x, y, z, kernel_index), self.kernel_size, self.input_depth)];
+
+                    min = min.min(weight);
+                    max = max.max(weight);
+                }
+            }
+
+            let range = max - min;
+
+            for y in 0..self.kernel_size {
+                for x in 0..self.kernel_size {
+                    let weight = self.kernel[util::get_kernel_index((x, y, z, kernel_index), self.kernel_size, self.input_depth)];
+
+                    result[util::get_index((x, y, z), dimension)] = if range > 0.0 { (weight - min) / range } else { 0.0 };
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub(crate) fn same_structure(&self, other: &ConvolutionalLayer) -> bool {
+        self.dimension == other.dimension &&
+            self.kernel_size == other.kernel_size &&
+            self.stride == other.stride &&
+            self.rounding == other.rounding &&
+            self.zero_padding == other.zero_padding &&
+            self.input_depth == other.input_depth &&
+            self.use_bias == other.use_bias
+    }
+
+    /// copies the kernel and biases of a layer with the same structure
+    pub(crate) fn copy_weights_from(&mut self, other: &ConvolutionalLayer) -> Result<(), Error> {
+        if !self.same_structure(other) { return Err(Error::IncompatibleLayers) };
+
+        self.kernel.copy_from_slice(&other.kernel);
+        self.biases.copy_from_slice(&other.biases);
+        self.prelu.copy_from(&other.prelu);
+
+        Ok(())
+    }
+
     pub fn apply_gradients(&mut self, learning_rate: f32, momentum: f32, weight_decay: f32) -> () {
         for i in 0..self.biases.len() {
-            let vel = self.bias_velocity[i] * momentum + learning_rate * self.bias_gradients[i];
+            let vel = self.bias_velocity[i] * momentum + learning_rate * util::from_gradient(self.bias_gradients[i]);
             self.bias_velocity[i] = vel;
             self.biases[i] -= vel;
         }
 
         for i in 0..self.kernel_gradients.len() {
-            let gradient = self.kernel_gradients[i] + weight_decay * self.kernel[i];
+            let gradient = util::from_gradient(self.kernel_gradients[i]) + weight_decay * self.kernel[i];
             let vel = self.kernel_velocity[i] * momentum + learning_rate * gradient;
             self.kernel_velocity[i] = vel;
 
             self.kernel[i] -= vel;
         }
+
+        self.prelu.apply_gradients(learning_rate, momentum);
+    }
+
+    /// applies the gradients without weight decay, then shrinks the kernel by `decay` times itself as in AdamW
+    pub fn apply_gradients_decoupled(&mut self, learning_rate: f32, momentum: f32, decay: f32) -> () {
+        self.apply_gradients(learning_rate, momentum, 0.0);
+
+        for weight in &mut self.kernel {
+            *weight -= decay * *weight;
+        }
+    }
+
+    /// zeroes the momentum of the kernel, biases and PReLU slopes without changing them
+    pub fn reset_velocity(&mut self) -> () {
+        self.kernel_velocity.fill(0.0);
+        self.bias_velocity.fill(0.0);
+        self.prelu.reset_velocity();
+    }
+
+    /// convolves an external volume without zero padding and without modifying the layer
+    ///
+    /// the activation function isn't applied
+    pub fn apply(&self, input: &[f32], input_dimension: (usize, usize, usize)) -> Result<Vec<f32>, Error> {
+        if self.is_input_layer() { return Err(Error::IncompatibleLayers) };
+        if input_dimension.2 != self.input_depth { return Err(Error::DimensionMismatch) };
+        if input.len() != input_dimension.0 * input_dimension.1 * input_dimension.2 { return Err(Error::DimensionMismatch) };
+
+        let output_dimension = util::get_output_dimension_rounded(input_dimension, 0, self.num_kernels, self.kernel_size, self.stride, self.rounding)
+            .ok_or(Error::ImpossibleOutputDimension)?;
+
+        let mut output = vec![0.0; output_dimension.0 * output_dimension.1 * output_dimension.2];
+        self.convolve_into(input_dimension, input, 0, output_dimension, &mut output, None);
+
+        Ok(output)
     }
 
     pub(crate) fn convolve(&mut self, input_dimension: (usize, usize, usize), volume: &Vec<f32>, zero_padding: usize) -> () {
-        let (padded_input_x, padded_input_y) = (input_dimension.0 + zero_padding * 2, input_dimension.1 + zero_padding * 2);
-        
+        let activation = if self.fuses_activation() { Some(self.activation) } else { None };
+
+        let mut output = std::mem::take(&mut self.volume);
+        self.convolve_into(input_dimension, volume, zero_padding, self.dimension, &mut output, activation);
+        self.volume = output;
+
+        if !self.raw_volume.is_empty() { self.raw_volume.copy_from_slice(&self.volume) };
+    }
+
+    /// writes the convolution of `volume` into `output`, activated with `activation` if there is one
+    fn convolve_into(&self, input_dimension: (usize, usize, usize), volume: &[f32], zero_padding: usize, output_dimension: (usize, usize, usize), output: &mut [f32], activation: Option<activations::ActivationFunction>) -> () {
+        if self.kernel_size == 1 && zero_padding == 0 {
+            self.convolve_pointwise(input_dimension, volume, output_dimension, output, activation);
+        } else {
+            self.convolve_generic(input_dimension, volume, zero_padding, output_dimension, output, activation);
+        }
+    }
+
+    #[inline(always)]
+    fn finish(&self, value: f32, kernel: usize, activation: Option<activations::ActivationFunction>) -> f32 {
+        let value = if self.use_bias { value + self.biases[kernel] } else { value };
+
+        match activation {
+            Some(func) => activations::eval(func, value),
+            None => value,
+        }
+    }
+
+    /// a 1x1 convolution is a linear combination of the channels at every position
+    pub(crate) fn convolve_pointwise(&self, input_dimension: (usize, usize, usize), volume: &[f32], output_dimension: (usize, usize, usize), output: &mut [f32], activation: Option<activations::ActivationFunction>) -> () {
+        for (o_x, x) in (0..input_dimension.0).step_by(self.stride.0).enumerate() {
+            for (o_y, y) in (0..input_dimension.1).step_by(self.stride.1).enumerate() {
+                let input_pixel = util::get_index((x, y, 0), input_dimension);
+                let output_pixel = util::get_index((o_x, o_y, 0), output_dimension);
+
+                for k in 0..self.num_kernels {
+                    let kernel_offset = k * self.input_depth;
+                    let mut value: f32 = 0.0;
+
+                    for z in 0..input_dimension.2 {
+                        value += volume[input_pixel + z] * self.kernel[kernel_offset + z];
+                    }
+
+                    output[output_pixel + k] = self.finish(value, k, activation);
+                }
+            }
+        }
+    }
+
+    /// the windows follow the output dimension, so a last partial window of `OutputRounding::Ceil` is clipped like the padding
+    #[allow(clippy::explicit_counter_loop)]
+    pub(crate) fn convolve_generic(&self, input_dimension: (usize, usize, usize), volume: &[f32], zero_padding: usize, output_dimension: (usize, usize, usize), output: &mut [f32], activation: Option<activations::ActivationFunction>) -> () {
         for k in 0..self.num_kernels {
             let mut o_x = 0;
 
-            for x in (0..(padded_input_x - self.kernel_size + 1)).step_by(self.stride) {
+            for x in (0..output_dimension.0 * self.stride.0).step_by(self.stride.0) {
                 let mut o_y = 0;
+                let columns = util::kernel_range(x, zero_padding, input_dimension.0, self.kernel_size);
 
-                for y in (0..(padded_input_y - self.kernel_size + 1)).step_by(self.stride) {
+                for y in (0..output_dimension.1 * self.stride.1).step_by(self.stride.1) {
                     let mut value: f32 = 0.0;
+                    let rows = util::kernel_range(y, zero_padding, input_dimension.1, self.kernel_size);
 
+                    // the padding is skipped instead of multiplied by zero
                     for z in 0..input_dimension.2 {
-                        for kernel_y in 0..self.kernel_size {
-                            for kernel_x in 0..self.kernel_size {
-                                let index = util::query_zero_padded((x + kernel_x, y + kernel_y, z), input_dimension, zero_padding);
+                        for kernel_y in rows.clone() {
+                            for kernel_x in columns.clone() {
+                                let ind = util::get_index((x + kernel_x - zero_padding, y + kernel_y - zero_padding, z), input_dimension);
 
-                                if let Some(ind) = index {
-                                    value += volume[ind] * self.kernel[util::get_kernel_index((kernel_x, kernel_y, z, k), self.kernel_size, self.input_depth)];
-                                }
+                                value += volume[ind] * self.kernel[util::get_kernel_index((kernel_x, kernel_y, z, k), self.kernel_size, self.input_depth)];
                             }
                         }
                     }
 
-                    let index = util::get_index((o_x, o_y, k), self.dimension);
-
-                    let output =  value + self.biases[k];
-                    self.raw_volume[index] = output;
-                    self.volume[index] = output;
+                    output[util::get_index((o_x, o_y, k), output_dimension)] = self.finish(value, k, activation);
 
                     o_y += 1;
                 }
@@ -153,17 +390,16 @@ impl ConvolutionalLayer {
     }
 
     fn convolve_back(&mut self, input_dimension: (usize, usize, usize), volume: &Vec<f32>, volume_gradients: &mut Vec<f32>, zero_padding: usize) -> () {
-        let (padded_input_x, padded_input_y) = (input_dimension.0 + zero_padding * 2, input_dimension.1 + zero_padding * 2);
-
         volume_gradients.fill(0.0);
 
         for k in 0..self.num_kernels {
             let mut o_x = 0;
 
-            for x in (0..(padded_input_x - self.kernel_size + 1)).step_by(self.stride) {
+            for x in (0..self.dimension.0 * self.stride.0).step_by(self.stride.0) {
                 let mut o_y = 0;
+                let columns = util::kernel_range(x, zero_padding, input_dimension.0, self.kernel_size);
 
-                for y in (0..(padded_input_y - self.kernel_size + 1)).step_by(self.stride) {
+                for y in (0..self.dimension.1 * self.stride.1).step_by(self.stride.1) {
                     let index = util::get_index((o_x, o_y, k), self.dimension);
                     let derivative = self.back_activated_volume[index];
 
@@ -173,21 +409,21 @@ impl ConvolutionalLayer {
                         continue;
                     }
 
+                    let rows = util::kernel_range(y, zero_padding, input_dimension.1, self.kernel_size);
+
                     for z in 0..input_dimension.2 {
-                        for kernel_y in 0..self.kernel_size {
-                            for kernel_x in 0..self.kernel_size {
+                        for kernel_y in rows.clone() {
+                            for kernel_x in columns.clone() {
                                 let kernel_index = util::get_kernel_index((kernel_x, kernel_y, z, k), self.kernel_size, self.input_depth);
-                                let input_volume_index = util::query_zero_padded((x + kernel_x, y + kernel_y, z), input_dimension, zero_padding);
+                                let ind = util::get_index((x + kernel_x - zero_padding, y + kernel_y - zero_padding, z), input_dimension);
 
-                                if let Some(ind) = input_volume_index {
-                                    self.kernel_gradients[kernel_index] += volume[ind] * derivative;
-                                    volume_gradients[ind] += self.kernel[kernel_index] * derivative;
-                                }
+                                self.kernel_gradients[kernel_index] += (volume[ind] * derivative) as Gradient;
+                                volume_gradients[ind] += self.kernel[kernel_index] * derivative;
                             }
                         }
                     }
                     
-                    self.bias_gradients[k] += derivative * input_dimension.2 as f32;
+                    if self.use_bias { self.bias_gradients[k] += (derivative * input_dimension.2 as f32) as Gradient };
                     o_y += 1;
                 }
 
@@ -206,7 +442,8 @@ impl LayerBase for ConvolutionalLayer {
                     self.zero_padding,
                     layer.num_kernels,
                     layer.kernel_size,
-                    layer.stride
+                    layer.stride,
+                    layer.rounding
                 )?;
                 
                 layer.convolve(self.dimension, &self.volume, self.zero_padding);
@@ -218,7 +455,8 @@ impl LayerBase for ConvolutionalLayer {
                     0, // a pooling layer doesn't take padding into account
                     layer.dimension.2,
                     layer.kernel_size,
-                    layer.stride
+                    layer.stride,
+                    layer.rounding
                 )?;
 
                 layer.convolve(self.dimension, &self.volume);
@@ -230,6 +468,27 @@ impl LayerBase for ConvolutionalLayer {
 
                 layer.feed_forward(&self.volume);
             }
+
+            Layer::Add(layer) => {
+                if self.dimension != layer.dimension { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume)?;
+            }
+
+            Layer::MaxUnpool(layer) => layer.feed_forward(&self.volume),
+            Layer::SpatialDropout(layer) => layer.feed_forward(&self.volume)?,
+
+            Layer::Scale(layer) => {
+                if self.dimension != layer.dimension { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume)?;
+            }
+
+            Layer::SeparableConvolutional(layer) => {
+                layer.check_input(self.dimension, self.zero_padding)?;
+
+                layer.convolve(self.dimension, &self.volume, self.zero_padding);
+            }
         }
 
         Ok(())
@@ -243,7 +502,8 @@ impl LayerBase for ConvolutionalLayer {
                     layer.zero_padding,
                     self.num_kernels,
                     self.kernel_size,
-                    self.stride
+                    self.stride,
+                    self.rounding
                 )?;
 
                 self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients, layer.zero_padding);
@@ -255,7 +515,73 @@ impl LayerBase for ConvolutionalLayer {
                     layer.zero_padding,
                     self.num_kernels,
                     self.kernel_size,
-                    self.stride
+                    self.stride,
+                    self.rounding
+                )?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients, layer.zero_padding);
+            }
+
+            Layer::Add(layer) => {
+                util::check_output_dimension(layer.dimension,
+                    self.dimension,
+                    layer.zero_padding,
+                    self.num_kernels,
+                    self.kernel_size,
+                    self.stride,
+                    self.rounding
+                )?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients, layer.zero_padding);
+            }
+
+            Layer::MaxUnpool(layer) => {
+                util::check_output_dimension(layer.dimension,
+                    self.dimension,
+                    layer.zero_padding,
+                    self.num_kernels,
+                    self.kernel_size,
+                    self.stride,
+                    self.rounding
+                )?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients, layer.zero_padding);
+            }
+
+            Layer::SpatialDropout(layer) => {
+                util::check_output_dimension(layer.dimension,
+                    self.dimension,
+                    layer.zero_padding,
+                    self.num_kernels,
+                    self.kernel_size,
+                    self.stride,
+                    self.rounding
+                )?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients, layer.zero_padding);
+            }
+
+            Layer::Scale(layer) => {
+                util::check_output_dimension(layer.dimension,
+                    self.dimension,
+                    layer.zero_padding,
+                    self.num_kernels,
+                    self.kernel_size,
+                    self.stride,
+                    self.rounding
+                )?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients, layer.zero_padding);
+            }
+
+            Layer::SeparableConvolutional(layer) => {
+                util::check_output_dimension(layer.dimension,
+                    self.dimension,
+                    layer.zero_padding,
+                    self.num_kernels,
+                    self.kernel_size,
+                    self.stride,
+                    self.rounding
                 )?;
 
                 self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients, layer.zero_padding);
@@ -268,6 +594,7 @@ impl LayerBase for ConvolutionalLayer {
     }
 }
 
+#[allow(clippy::unused_unit)]
 impl LearnableLayer for ConvolutionalLayer {
     fn initialize(&mut self, func: initialization::Initialization) -> () {
         let inputs =  self.input_depth * self.kernel_size * self.kernel_size;
@@ -278,14 +605,56 @@ impl LearnableLayer for ConvolutionalLayer {
     }
 
     fn activate(&mut self, func: activations::ActivationFunction) -> () {
+        if let activations::ActivationFunction::Softmax = func {
+            activations::softmax_into(&self.raw_volume, &mut self.volume);
+            return;
+        }
+
+        if let activations::ActivationFunction::PReLU = func {
+            self.prelu.activate(&self.raw_volume, &mut self.volume);
+            return;
+        }
+
+        // already activated while convolving
+        if self.fuses_activation() { return };
+
+        if self.raw_volume.is_empty() {
+            for i in 0..self.volume.len() {
+                self.volume[i] = activations::eval(func, self.volume[i]);
+            }
+
+            return;
+        }
+
         for i in 0..self.volume.len() {
             self.volume[i] = activations::eval(func, self.raw_volume[i]);
         }
     }
 
     fn back_activate(&mut self, func: activations::ActivationFunction) -> () {
+        if let activations::ActivationFunction::Softmax = func {
+            activations::softmax_derivative(&self.volume, &self.volume_gradients, &mut self.back_activated_volume);
+            return;
+        }
+
+        if let activations::ActivationFunction::PReLU = func {
+            self.prelu.back_activate(&self.raw_volume, &self.volume_gradients, &mut self.back_activated_volume);
+            return;
+        }
+
+        if self.raw_volume.is_empty() {
+            for i in 0..self.volume.len() {
+                let derivative = activations::eval_derivative_from_output(func, self.volume[i])
+                    .expect("the raw volume is only released for activations differentiable from their output");
+
+                self.back_activated_volume[i] = derivative * self.volume_gradients[i];
+            }
+
+            return;
+        }
+
         for i in 0..self.volume.len() {
-            self.back_activated_volume[i] = activations::eval(func, self.raw_volume[i]) * self.volume_gradients[i];
+            self.back_activated_volume[i] = activations::eval_derivative(func, self.raw_volume[i]) * self.volume_gradients[i];
         }
     }
 
@@ -297,12 +666,14 @@ impl LearnableLayer for ConvolutionalLayer {
         for i in 0..self.kernel_gradients.len() {
             self.kernel_gradients[i] = 0.0;
         }
+
+        self.prelu.reset_gradients();
     }
 }
 
 impl Serialize for ConvolutionalLayer {
     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut state = serializer.serialize_struct("ConvolutionalLayer", 7)?;
+        let mut state = serializer.serialize_struct("ConvolutionalLayer", 10)?;
 
         state.serialize_field("zero_padding", &self.zero_padding)?;
         state.serialize_field("stride", &self.stride)?;
@@ -312,21 +683,41 @@ impl Serialize for ConvolutionalLayer {
 
         state.serialize_field("kernel", &self.kernel)?;
         state.serialize_field("biases", &self.biases)?;
+        state.serialize_field("prelu_slopes", &self.prelu.slopes)?;
+        state.serialize_field("use_bias", &self.use_bias)?;
+        state.serialize_field("rounding", &self.rounding)?;
         
         state.end()
     }
 }
 
+const CONVOLUTIONAL_LAYER_FIELDS: &[&str] = &["zero_padding", "stride", "kernel_size", "dimension", "input_depth", "kernel", "biases", "prelu_slopes", "use_bias", "rounding"];
+
 impl<'de> Deserialize<'de> for ConvolutionalLayer {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
         where
             D: serde::Deserializer<'de>,
     {
-        deserializer.deserialize_struct("ConvolutionalLayer", &["zero_padding", "stride", "kernel_size", "dimension", "input_depth", "kernel", "biases"], ConvolutionalLayerVisitor)
+        deserializer.deserialize_struct("ConvolutionalLayer", CONVOLUTIONAL_LAYER_FIELDS, ConvolutionalLayerVisitor { version: FORMAT_VERSION })
+    }
+}
+
+/// Deserializes a layer written with the given format version
+pub(crate) struct ConvolutionalLayerVisitor {
+    pub(crate) version: u32,
+}
+
+impl<'de> DeserializeSeed<'de> for ConvolutionalLayerVisitor {
+    type Value = ConvolutionalLayer;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("ConvolutionalLayer", CONVOLUTIONAL_LAYER_FIELDS, self)
     }
 }
 
-struct ConvolutionalLayerVisitor;
 impl<'de> Visitor<'de> for ConvolutionalLayerVisitor {
     type Value = ConvolutionalLayer;
 
@@ -346,6 +737,9 @@ impl<'de> Visitor<'de> for ConvolutionalLayerVisitor {
 
         let mut kernel = None;
         let mut biases = None;
+        let mut prelu_slopes = None;
+        let mut use_bias = None;
+        let mut rounding = None;
 
         while let Some(key) = map.next_key::<&str>()? {
             match key {
@@ -358,7 +752,7 @@ impl<'de> Visitor<'de> for ConvolutionalLayerVisitor {
                 "stride" => {
                     if stride.is_some() { return Err(serde::de::Error::duplicate_field("stride")); };
 
-                    stride = Some(map.next_value()?);
+                    stride = Some(if self.version < 3 { util::scalar_stride(map.next_value()?) } else { map.next_value()? });
                 },
 
                 "kernel_size" => {
@@ -391,7 +785,25 @@ impl<'de> Visitor<'de> for ConvolutionalLayerVisitor {
                     biases = Some(map.next_value()?);
                 },
 
-                _ => return Err(serde::de::Error::unknown_field(key, &["zero_padding", "stride", "kernel_size", "dimension", "input_depth", "kernel", "biases"])),
+                "prelu_slopes" => {
+                    if prelu_slopes.is_some() { return Err(serde::de::Error::duplicate_field("prelu_slopes")); };
+
+                    prelu_slopes = Some(map.next_value()?);
+                },
+
+                "use_bias" => {
+                    if use_bias.is_some() { return Err(serde::de::Error::duplicate_field("use_bias")); };
+
+                    use_bias = Some(map.next_value()?);
+                },
+
+                "rounding" => {
+                    if rounding.is_some() { return Err(serde::de::Error::duplicate_field("rounding")); };
+
+                    rounding = Some(map.next_value()?);
+                },
+
+                _ => return Err(serde::de::Error::unknown_field(key, CONVOLUTIONAL_LAYER_FIELDS)),
             }
         }
 
@@ -404,10 +816,34 @@ impl<'de> Visitor<'de> for ConvolutionalLayerVisitor {
         let kernel = kernel.ok_or_else(|| serde::de::Error::missing_field("kernel"))?;
         let biases = biases.ok_or_else(|| serde::de::Error::missing_field("biases"))?;
 
+        // PReLU slopes were added in format version 4
+        let prelu_slopes = match prelu_slopes {
+            Some(prelu_slopes) => prelu_slopes,
+            None if self.version < 4 => Vec::new(),
+            None => return Err(serde::de::Error::missing_field("prelu_slopes")),
+        };
+
+        // layers without a bias were added in format version 5
+        let use_bias = match use_bias {
+            Some(use_bias) => use_bias,
+            None if self.version < 5 => true,
+            None => return Err(serde::de::Error::missing_field("use_bias")),
+        };
+
+        // the output rounding was added in format version 11
+        let rounding = match rounding {
+            Some(rounding) => rounding,
+            None if self.version < 11 => OutputRounding::Floor,
+            None => return Err(serde::de::Error::missing_field("rounding")),
+        };
+
         let mut layer = ConvolutionalLayer::new(zero_padding, stride, kernel_size, dimension, input_depth);
+        layer.set_use_bias(use_bias);
+        layer.set_rounding(rounding);
 
         layer.kernel = kernel;
         layer.biases = biases;
+        layer.prelu.load(prelu_slopes);
 
         Ok(layer)
     }
@@ -417,7 +853,12 @@ impl<'de> Visitor<'de> for ConvolutionalLayerVisitor {
         A: serde::de::SeqAccess<'de>,
     {
         let zero_padding = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
-        let stride = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+        let stride = if self.version < 3 {
+            seq.next_element()?.map(util::scalar_stride)
+        } else {
+            seq.next_element()?
+        };
+        let stride = stride.ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
         let kernel_size = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
         let dimension = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(3, &self))?;
         let input_depth = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(4, &self))?;
@@ -425,10 +866,31 @@ impl<'de> Visitor<'de> for ConvolutionalLayerVisitor {
         let kernel = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(5, &self))?;
         let biases = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(6, &self))?;
 
+        let prelu_slopes = if self.version < 4 {
+            Vec::new()
+        } else {
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(7, &self))?
+        };
+
+        let use_bias = if self.version < 5 {
+            true
+        } else {
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(8, &self))?
+        };
+
+        let rounding = if self.version < 11 {
+            OutputRounding::Floor
+        } else {
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(9, &self))?
+        };
+
         let mut layer = ConvolutionalLayer::new(zero_padding, stride, kernel_size, dimension, input_depth);
+        layer.set_use_bias(use_bias);
+        layer.set_rounding(rounding);
         
         layer.kernel = kernel;
         layer.biases = biases;
+        layer.prelu.load(prelu_slopes);
 
         Ok(layer)
     }
@@ -0,0 +1,174 @@
+use crate::errors::Error;
+
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+/// A collection of (input, target) samples that can be read one at a time
+pub trait Dataset {
+    fn len(&self) -> usize;
+
+    fn get(&self, index: usize) -> Result<(Vec<f32>, Vec<f32>), Error>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A dataset that keeps every sample in memory
+pub struct InMemoryDataset {
+    inputs: Vec<Vec<f32>>,
+    targets: Vec<Vec<f32>>,
+}
+
+impl InMemoryDataset {
+    pub fn new(inputs: Vec<Vec<f32>>, targets: Vec<Vec<f32>>) -> Result<Self, Error> {
+        if inputs.len() != targets.len() { return Err(Error::InvalidInput) };
+
+        Ok(Self { inputs, targets })
+    }
+}
+
+impl Dataset for InMemoryDataset {
+    fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    fn get(&self, index: usize) -> Result<(Vec<f32>, Vec<f32>), Error> {
+        if index >= self.inputs.len() { return Err(Error::InvalidInput) };
+
+        Ok((self.inputs[index].clone(), self.targets[index].clone()))
+    }
+}
+
+/// A dataset that reads samples from disk on demand
+///
+/// the file is a sequence of fixed size records, each holding the input followed by the target as little endian f32s
+pub struct FileDataset {
+    /// locked for every read since a seek and the read after it share the file's cursor, e.g. between the threads of `train_parallel`
+    file: Mutex<File>,
+    input_size: usize,
+    target_size: usize,
+    len: usize,
+}
+
+impl FileDataset {
+    pub fn open<P: AsRef<Path>>(path: P, input_size: usize, target_size: usize) -> Result<Self, Error> {
+        if input_size + target_size == 0 { return Err(Error::InvalidInput) };
+
+        let file = File::open(path)?;
+        let record_size = Self::record_size(input_size, target_size) as u64;
+
+        let file_size = file.metadata()?.len();
+        if file_size % record_size != 0 { return Err(Error::DimensionMismatch) };
+
+        Ok(Self {
+            file: Mutex::new(file),
+            input_size,
+            target_size,
+            len: (file_size / record_size) as usize,
+        })
+    }
+
+    /// writes every sample of a dataset in the format read by `open`
+    pub fn write<P: AsRef<Path>, D: Dataset>(path: P, dataset: &D) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let mut sizes = None;
+
+        for i in 0..dataset.len() {
+            let (input, target) = dataset.get(i)?;
+
+            // every record must have the same size to be found by its index
+            let size = (input.len(), target.len());
+            if *sizes.get_or_insert(size) != size { return Err(Error::DimensionMismatch) };
+
+            for value in input.iter().chain(&target) {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn record_size(input_size: usize, target_size: usize) -> usize {
+        (input_size + target_size) * std::mem::size_of::<f32>()
+    }
+}
+
+impl Dataset for FileDataset {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, index: usize) -> Result<(Vec<f32>, Vec<f32>), Error> {
+        if index >= self.len { return Err(Error::InvalidInput) };
+
+        let record_size = Self::record_size(self.input_size, self.target_size);
+        let mut bytes = vec![0u8; record_size];
+
+        // every read seeks first, so a panic while it was locked doesn't leave a cursor that matters
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        file.seek(SeekFrom::Start((index * record_size) as u64))?;
+        file.read_exact(&mut bytes)?;
+
+        let mut values = bytes.chunks_exact(4).map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+
+        let input = values.by_ref().take(self.input_size).collect();
+        let target = values.collect();
+
+        Ok((input, target))
+    }
+}
+
+/// Iterates over a dataset in batches, only reading the samples of the current batch
+pub struct DataLoader<'a, D: Dataset> {
+    dataset: &'a D,
+    batch_size: usize,
+
+    order: Vec<usize>,
+    position: usize,
+}
+
+#[allow(clippy::unused_unit)]
+impl<'a, D: Dataset> DataLoader<'a, D> {
+    pub fn new(dataset: &'a D, batch_size: usize) -> Result<Self, Error> {
+        if batch_size == 0 { return Err(Error::InvalidInput) };
+
+        Ok(Self {
+            dataset,
+            batch_size,
+
+            order: (0..dataset.len()).collect(),
+            position: 0,
+        })
+    }
+
+    /// shuffles the sample order and starts over
+    pub fn shuffle<R: Rng>(&mut self, rng: &mut R) -> () {
+        self.order.shuffle(rng);
+        self.position = 0;
+    }
+
+    pub fn num_batches(&self) -> usize {
+        self.order.len().div_ceil(self.batch_size)
+    }
+}
+
+impl<D: Dataset> Iterator for DataLoader<'_, D> {
+    type Item = Result<Vec<(Vec<f32>, Vec<f32>)>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.order.len() { return None };
+
+        let end = (self.position + self.batch_size).min(self.order.len());
+        let batch = self.order[self.position..end].iter().map(|&index| self.dataset.get(index)).collect();
+
+        self.position = end;
+        Some(batch)
+    }
+}
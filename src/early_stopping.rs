@@ -0,0 +1,52 @@
+/// Tracks the best validation loss and signals when training should stop
+#[derive(Clone, Copy)]
+pub struct EarlyStopping {
+    patience: usize,
+    min_delta: f32,
+
+    best_loss: f32,
+    epochs_without_improvement: usize,
+}
+
+#[allow(clippy::unused_unit)]
+impl EarlyStopping {
+    /// stops after `patience` epochs in which the loss did not improve by more than `min_delta`
+    pub fn new(patience: usize, min_delta: f32) -> Self {
+        Self {
+            patience,
+            min_delta,
+
+            best_loss: f32::INFINITY,
+            epochs_without_improvement: 0,
+        }
+    }
+
+    /// records the validation loss of an epoch and returns true if training should stop
+    pub fn update(&mut self, validation_loss: f32) -> bool {
+        if validation_loss < self.best_loss - self.min_delta {
+            self.best_loss = validation_loss;
+            self.epochs_without_improvement = 0;
+        } else {
+            self.epochs_without_improvement += 1;
+        }
+
+        self.should_stop()
+    }
+
+    pub fn should_stop(&self) -> bool {
+        self.epochs_without_improvement >= self.patience
+    }
+
+    pub fn get_best_loss(&self) -> f32 {
+        self.best_loss
+    }
+
+    pub fn get_epochs_without_improvement(&self) -> usize {
+        self.epochs_without_improvement
+    }
+
+    pub fn reset(&mut self) -> () {
+        self.best_loss = f32::INFINITY;
+        self.epochs_without_improvement = 0;
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum Error {
     DimensionMismatch,
     IncompatibleLayers,
@@ -6,6 +6,20 @@ pub enum Error {
     ImpossibleOutputDimension,
 
     InvalidInput,
+
+    Io(std::io::ErrorKind),
+
+    /// the output activation can't produce the values the error function expects
+    IncompatibleErrorFunction,
+
+    /// the learnable layer at this index still has all-zero weights
+    UninitializedLayer(usize),
+
+    /// the input doesn't have the (width * height * depth) values of the input layer
+    InputSizeMismatch { expected: usize, provided: usize },
+
+    /// a model couldn't be encoded or decoded, e.g. because the bytes are corrupt
+    Serialization(String),
 }
 
 impl std::fmt::Display for Error {
@@ -15,10 +29,41 @@ impl std::fmt::Display for Error {
             Error::IncompatibleLayers => write!(f, "Layers are incompatible or don't exist"),
             Error::ImpossibleOutputDimension => write!(f, "Output dimension is impossible"),
             Error::InvalidInput => write!(f, "Input arguments to this function are invalid"),
+            Error::Io(kind) => write!(f, "I/O error: {}", kind),
+            Error::IncompatibleErrorFunction => write!(f, "Output activation is incompatible with the error function"),
+            Error::UninitializedLayer(index) => write!(f, "Layer {} has not been initialized", index),
+            Error::InputSizeMismatch { expected, provided } => write!(f, "Input has {} values but the input layer expects {}", provided, expected),
+            Error::Serialization(message) => write!(f, "Serialization error: {}", message),
         }
     }
 }
 
 impl std::error::Error for Error {
 
-}
\ No newline at end of file
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error.kind())
+    }
+}
+
+impl From<bincode::error::EncodeError> for Error {
+    fn from(error: bincode::error::EncodeError) -> Self {
+        match error {
+            bincode::error::EncodeError::Io { inner, .. } => Error::Io(inner.kind()),
+
+            error => Error::Serialization(error.to_string()),
+        }
+    }
+}
+
+impl From<bincode::error::DecodeError> for Error {
+    fn from(error: bincode::error::DecodeError) -> Self {
+        match error {
+            bincode::error::DecodeError::Io { inner, .. } => Error::Io(inner.kind()),
+
+            error => Error::Serialization(error.to_string()),
+        }
+    }
+}
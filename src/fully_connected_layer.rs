@@ -3,30 +3,38 @@ use crate::errors::Error;
 use crate::activations;
 use crate::initialization;
 use crate::nn_error;
+use crate::neural_network::FORMAT_VERSION;
+use crate::prelu::PReluSlopes;
+use crate::util::{self, Gradient};
 
-use serde::de::{Deserialize, Visitor};
+use serde::de::{Deserialize, DeserializeSeed, Visitor};
 use serde::ser::{Serialize, SerializeStruct};
 
 #[derive(Clone)]
 pub struct FullyConnectedLayer {
     pub(crate) num_inputs: usize,
-    pub(crate) weight_gradients: Vec<f32>,
-    pub(crate) bias_gradients: Vec<f32>,
-    
-    num_neurons: usize,
+    pub(crate) weight_gradients: Vec<Gradient>,
+    pub(crate) bias_gradients: Vec<Gradient>,
+    pub(crate) num_neurons: usize,
+
 
     raw_values: Vec<f32>,
     back_activated_values: Vec<f32>,
-    values: Vec<f32>,
-    weights: Vec<f32>,
-    biases: Vec<f32>,
+    pub(crate) values: Vec<f32>,
+    pub(crate) weights: Vec<f32>,
+    pub(crate) biases: Vec<f32>,
 
-    value_gradients: Vec<f32>,
+    pub(crate) value_gradients: Vec<f32>,
 
     weight_velocity: Vec<f32>,
     bias_velocity: Vec<f32>,
+
+    use_bias: bool,
+
+    pub(crate) prelu: PReluSlopes,
 }
 
+#[allow(clippy::unused_unit, clippy::ptr_arg)]
 impl FullyConnectedLayer {
     pub fn new(num_inputs: usize, num_neurons: usize) -> Self {
         Self {
@@ -45,43 +53,170 @@ impl FullyConnectedLayer {
 
             weight_velocity: vec![0.0; num_inputs * num_neurons],
             bias_velocity: vec![0.0; num_neurons],
+
+            use_bias: true,
+
+            prelu: PReluSlopes::default(),
         }
     }
 
+    pub fn get_weights(&self) -> &Vec<f32> {
+        &self.weights
+    }
+
+    pub fn get_biases(&self) -> &Vec<f32> {
+        &self.biases
+    }
+
+    pub fn set_weights(&mut self, weights: Vec<f32>) -> Result<(), Error> {
+        if self.weights.len() != weights.len() { return Err(Error::InvalidInput) };
+
+        self.weights.clear();
+        self.weights.extend(weights);
+
+        Ok(())
+    }
+
+    pub fn set_biases(&mut self, biases: Vec<f32>) -> Result<(), Error> {
+        if self.biases.len() != biases.len() { return Err(Error::InvalidInput) };
+
+        self.biases.clear();
+        self.biases.extend(biases);
+
+        Ok(())
+    }
+
+    pub fn uses_bias(&self) -> bool {
+        self.use_bias
+    }
+
+    /// without a bias the neurons are a weighted sum of the inputs and no bias buffers are allocated
+    pub fn set_use_bias(&mut self, use_bias: bool) -> () {
+        let len = if use_bias { self.num_neurons } else { 0 };
+
+        self.use_bias = use_bias;
+        self.biases = vec![0.0; len];
+        self.bias_gradients = vec![0.0; len];
+        self.bias_velocity = vec![0.0; len];
+    }
+
+    /// the learned negative slope of every neuron when using PReLU, empty otherwise
+    pub fn get_prelu_slopes(&self) -> &Vec<f32> {
+        &self.prelu.slopes
+    }
+
+    pub fn set_prelu_slopes(&mut self, slopes: Vec<f32>) -> Result<(), Error> {
+        self.prelu.set_slopes(slopes)
+    }
+
+    pub(crate) fn configure_activation(&mut self, func: activations::ActivationFunction) -> () {
+        self.prelu.configure(func, self.num_neurons);
+    }
+
     pub fn get_outputs(&self) -> Vec<f32> {
         self.values.clone()
     }
 
-    pub fn get_error(&self, function_type: nn_error::ErrorFunction, expected: &Vec<f32>) -> Result<f32, Error> {
+    /// `class_weights` scales the error of every output and `epsilon` clamps probabilities, see `nn_error::eval`
+    pub fn get_error(&self, function_type: nn_error::ErrorFunction, expected: &Vec<f32>, class_weights: Option<&[f32]>, epsilon: f32) -> Result<f32, Error> {
+        if self.values.len() != expected.len() { return Err(Error::InvalidInput) };
+        if class_weights.is_some_and(|weights| weights.len() != expected.len()) { return Err(Error::InvalidInput) };
+
+        Ok(nn_error::eval(function_type, &self.values, expected, class_weights, epsilon))
+    }
+
+    /// a NaN in `expected` masks that output, so its gradient is 0
+    pub fn calculate_output_gradients(&mut self, error_function_type: nn_error::ErrorFunction, expected: &Vec<f32>, class_weights: Option<&[f32]>, epsilon: f32) -> Result<(), Error> {
         if self.values.len() != expected.len() { return Err(Error::InvalidInput) };
+        if class_weights.is_some_and(|weights| weights.len() != expected.len()) { return Err(Error::InvalidInput) };
+
+        let targets = expected;
+        let masked = nn_error::mask_targets(&self.values, expected, class_weights);
+        let (values, expected, class_weights) = match &masked {
+            Some((values, expected, weights)) => (values, expected, Some(&weights[..])),
+            None => (&self.values, expected, class_weights),
+        };
+
+        for (i, target) in targets.iter().enumerate() {
+            // custom and cosine embedding losses ignore the weights, so the zero weight of a masked output doesn't reach their derivative
+            self.value_gradients[i] = if target.is_nan() { 0.0 } else { nn_error::eval_derivative(error_function_type, i, values, expected, class_weights, epsilon) };
+        }
 
-        Ok(nn_error::eval(function_type, &self.values, expected))
+        Ok(())
     }
 
-    pub fn calculate_output_gradients(&mut self, error_function_type: nn_error::ErrorFunction, expected: &Vec<f32>) -> Result<(), Error> {
+    /// computes the gradients of the raw outputs directly, which replaces `back_activate` for this pass
+    ///
+    /// see `nn_error::has_fused_derivative` for the supported activation and error function pairs
+    pub(crate) fn calculate_fused_output_gradients(&mut self, error_function_type: nn_error::ErrorFunction, expected: &Vec<f32>, class_weights: Option<&[f32]>) -> Result<(), Error> {
         if self.values.len() != expected.len() { return Err(Error::InvalidInput) };
+        if class_weights.is_some_and(|weights| weights.len() != expected.len()) { return Err(Error::InvalidInput) };
+
+        let masked = nn_error::mask_targets(&self.values, expected, class_weights);
+        let (values, expected, class_weights) = match &masked {
+            Some((values, expected, weights)) => (values, expected, Some(&weights[..])),
+            None => (&self.values, expected, class_weights),
+        };
 
-        for i in 0..expected.len() {
-            self.value_gradients[i] = nn_error::eval_derivative(error_function_type, i, &self.values, expected);
+        nn_error::eval_fused_derivative(error_function_type, values, expected, class_weights, &mut self.back_activated_values);
+
+        Ok(())
+    }
+
+    /// divides the values before activation, which is only valid between `feed_forward` and `activate`
+    pub(crate) fn divide_raw_values(&mut self, temperature: f32) -> () {
+        for value in &mut self.raw_values {
+            *value /= temperature;
         }
+    }
+
+    pub(crate) fn same_structure(&self, other: &FullyConnectedLayer) -> bool {
+        self.num_inputs == other.num_inputs && self.num_neurons == other.num_neurons && self.use_bias == other.use_bias
+    }
+
+    /// copies the weights and biases of a layer with the same structure
+    pub(crate) fn copy_weights_from(&mut self, other: &FullyConnectedLayer) -> Result<(), Error> {
+        if !self.same_structure(other) { return Err(Error::IncompatibleLayers) };
+
+        self.weights.copy_from_slice(&other.weights);
+        self.biases.copy_from_slice(&other.biases);
+        self.prelu.copy_from(&other.prelu);
 
         Ok(())
     }
 
     pub fn apply_gradients(&mut self, learning_rate: f32, momentum: f32, weight_decay: f32) -> () {
-        for i in 0..self.num_neurons {
-            let vel = self.bias_velocity[i] * momentum + learning_rate * self.bias_gradients[i];
+        for i in 0..self.biases.len() {
+            let vel = self.bias_velocity[i] * momentum + learning_rate * util::from_gradient(self.bias_gradients[i]);
             self.bias_velocity[i] = vel;
             self.biases[i] -= vel;
         }
 
         for i in 0..(self.num_inputs * self.num_neurons) {
-            let gradient = self.weight_gradients[i] + weight_decay * self.weights[i];
+            let gradient = util::from_gradient(self.weight_gradients[i]) + weight_decay * self.weights[i];
             let vel = self.weight_velocity[i] * momentum + learning_rate * gradient;
             self.weight_velocity[i] = vel;
 
             self.weights[i] -= vel;
         }
+
+        self.prelu.apply_gradients(learning_rate, momentum);
+    }
+
+    /// applies the gradients without weight decay, then shrinks the weights by `decay` times themselves as in AdamW
+    pub fn apply_gradients_decoupled(&mut self, learning_rate: f32, momentum: f32, decay: f32) -> () {
+        self.apply_gradients(learning_rate, momentum, 0.0);
+
+        for weight in &mut self.weights {
+            *weight -= decay * *weight;
+        }
+    }
+
+    /// zeroes the momentum of the weights, biases and PReLU slopes without changing them
+    pub fn reset_velocity(&mut self) -> () {
+        self.weight_velocity.fill(0.0);
+        self.bias_velocity.fill(0.0);
+        self.prelu.reset_velocity();
     }
 
     #[inline(always)]
@@ -89,9 +224,10 @@ impl FullyConnectedLayer {
         neuron * self.num_inputs + input
     }
 
+    #[allow(clippy::needless_range_loop)]
     pub(crate) fn feed_forward(&mut self, input: &Vec<f32>) -> () {
         for i in 0..self.num_neurons {
-            let mut value = self.biases[i];
+            let mut value = if self.use_bias { self.biases[i] } else { 0.0 };
 
             for j in 0..self.num_inputs {
                 value += input[j] * self.weights[self.get_weight(j, i)];
@@ -107,11 +243,11 @@ impl FullyConnectedLayer {
 
         for i in 0..self.num_neurons {
             let derivative = self.back_activated_values[i];
-            self.bias_gradients[i] += derivative;
+            if self.use_bias { self.bias_gradients[i] += derivative as Gradient };
 
             for j in 0..self.num_inputs {
                 let index = self.get_weight(j, i);
-                self.weight_gradients[index] += derivative * input[j];
+                self.weight_gradients[index] += (derivative * input[j]) as Gradient;
 
                 input_gradients[j] += derivative * self.weights[index];
             }
@@ -119,14 +255,35 @@ impl FullyConnectedLayer {
     }
 }
 
+#[allow(clippy::unused_unit)]
 impl LearnableLayer for FullyConnectedLayer {
     fn activate(&mut self, func: activations::ActivationFunction) -> () {
+        if let activations::ActivationFunction::Softmax = func {
+            activations::softmax_into(&self.raw_values, &mut self.values);
+            return;
+        }
+
+        if let activations::ActivationFunction::PReLU = func {
+            self.prelu.activate(&self.raw_values, &mut self.values);
+            return;
+        }
+
         for i in 0..self.values.len() {
             self.values[i] = activations::eval(func, self.raw_values[i]);
         }
     }
 
     fn back_activate(&mut self, func: activations::ActivationFunction) {
+        if let activations::ActivationFunction::Softmax = func {
+            activations::softmax_derivative(&self.values, &self.value_gradients, &mut self.back_activated_values);
+            return;
+        }
+
+        if let activations::ActivationFunction::PReLU = func {
+            self.prelu.back_activate(&self.raw_values, &self.value_gradients, &mut self.back_activated_values);
+            return;
+        }
+
         for i in 0..self.values.len() {
             self.back_activated_values[i] = activations::eval_derivative(func, self.raw_values[i]) * self.value_gradients[i];
         }
@@ -145,6 +302,8 @@ impl LearnableLayer for FullyConnectedLayer {
         for i in 0..self.weight_gradients.len() {
             self.weight_gradients[i] = 0.0;
         }
+
+        self.prelu.reset_gradients();
     }
 }
 
@@ -157,6 +316,11 @@ impl LayerBase for FullyConnectedLayer {
                 layer.feed_forward(&self.values);
             }
 
+            Layer::Add(layer) => layer.feed_forward(&self.values)?,
+            Layer::MaxUnpool(layer) => layer.feed_forward(&self.values),
+            Layer::SpatialDropout(layer) => layer.feed_forward(&self.values)?,
+            Layer::Scale(layer) => layer.feed_forward(&self.values)?,
+
             _ => { return Err(Error::IncompatibleLayers) }
         }
 
@@ -184,6 +348,41 @@ impl LayerBase for FullyConnectedLayer {
 
                 self.feed_back(&layer.volume, &mut layer.volume_gradients);
             }
+
+            Layer::Add(layer) => {
+                let dim = layer.dimension;
+                if dim.0 * dim.1 * dim.2 != self.num_inputs { return Err(Error::DimensionMismatch) };
+
+                self.feed_back(&layer.volume, &mut layer.volume_gradients);
+            }
+
+            Layer::MaxUnpool(layer) => {
+                let dim = layer.dimension;
+                if dim.0 * dim.1 * dim.2 != self.num_inputs { return Err(Error::DimensionMismatch) };
+
+                self.feed_back(&layer.volume, &mut layer.volume_gradients);
+            }
+
+            Layer::SpatialDropout(layer) => {
+                let dim = layer.dimension;
+                if dim.0 * dim.1 * dim.2 != self.num_inputs { return Err(Error::DimensionMismatch) };
+
+                self.feed_back(&layer.volume, &mut layer.volume_gradients);
+            }
+
+            Layer::Scale(layer) => {
+                let dim = layer.dimension;
+                if dim.0 * dim.1 * dim.2 != self.num_inputs { return Err(Error::DimensionMismatch) };
+
+                self.feed_back(&layer.volume, &mut layer.volume_gradients);
+            }
+
+            Layer::SeparableConvolutional(layer) => {
+                let dim = layer.dimension;
+                if dim.0 * dim.1 * dim.2 != self.num_inputs { return Err(Error::DimensionMismatch) };
+
+                self.feed_back(&layer.volume, &mut layer.volume_gradients);
+            }
         }
 
         Ok(())
@@ -192,28 +391,47 @@ impl LayerBase for FullyConnectedLayer {
 
 impl Serialize for FullyConnectedLayer {
     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut state = serializer.serialize_struct("FullyConnectedLayer", 4)?;
+        let mut state = serializer.serialize_struct("FullyConnectedLayer", 6)?;
 
         state.serialize_field("num_inputs", &self.num_inputs)?;
         state.serialize_field("num_neurons", &self.num_neurons)?;
 
         state.serialize_field("weights", &self.weights)?;
         state.serialize_field("biases", &self.biases)?;
+        state.serialize_field("prelu_slopes", &self.prelu.slopes)?;
+        state.serialize_field("use_bias", &self.use_bias)?;
         
         state.end()
     }
 }
 
+const FULLY_CONNECTED_LAYER_FIELDS: &[&str] = &["num_inputs", "num_neurons", "weights", "biases", "prelu_slopes", "use_bias"];
+
 impl<'de> Deserialize<'de> for FullyConnectedLayer {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
         where
             D: serde::Deserializer<'de>,
     {
-        deserializer.deserialize_struct("FullyConnectedLayer", &["num_inputs", "num_neurons", "weights", "biases"], FullyConnectedLayerVisitor)
+        deserializer.deserialize_struct("FullyConnectedLayer", FULLY_CONNECTED_LAYER_FIELDS, FullyConnectedLayerVisitor { version: FORMAT_VERSION })
+    }
+}
+
+/// Deserializes a layer written with the given format version
+pub(crate) struct FullyConnectedLayerVisitor {
+    pub(crate) version: u32,
+}
+
+impl<'de> DeserializeSeed<'de> for FullyConnectedLayerVisitor {
+    type Value = FullyConnectedLayer;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("FullyConnectedLayer", FULLY_CONNECTED_LAYER_FIELDS, self)
     }
 }
 
-struct FullyConnectedLayerVisitor;
 impl<'de> Visitor<'de> for FullyConnectedLayerVisitor {
     type Value = FullyConnectedLayer;
 
@@ -230,6 +448,8 @@ impl<'de> Visitor<'de> for FullyConnectedLayerVisitor {
 
         let mut weights = None;
         let mut biases = None;
+        let mut prelu_slopes = None;
+        let mut use_bias = None;
 
         while let Some(key) = map.next_key::<&str>()? {
             match key {
@@ -257,7 +477,19 @@ impl<'de> Visitor<'de> for FullyConnectedLayerVisitor {
                     biases = Some(map.next_value()?);
                 }
 
-                _ => return Err(serde::de::Error::unknown_field(key, &["num_inputs", "num_neurons"])),
+                "prelu_slopes" => {
+                    if prelu_slopes.is_some() { return Err(serde::de::Error::duplicate_field("prelu_slopes")); };
+
+                    prelu_slopes = Some(map.next_value()?);
+                }
+
+                "use_bias" => {
+                    if use_bias.is_some() { return Err(serde::de::Error::duplicate_field("use_bias")); };
+
+                    use_bias = Some(map.next_value()?);
+                }
+
+                _ => return Err(serde::de::Error::unknown_field(key, FULLY_CONNECTED_LAYER_FIELDS)),
             }
         }
 
@@ -267,10 +499,26 @@ impl<'de> Visitor<'de> for FullyConnectedLayerVisitor {
         let weights = weights.ok_or_else(|| serde::de::Error::missing_field("weights"))?;
         let biases = biases.ok_or_else(|| serde::de::Error::missing_field("biases"))?;
 
+        // PReLU slopes were added in format version 4
+        let prelu_slopes = match prelu_slopes {
+            Some(prelu_slopes) => prelu_slopes,
+            None if self.version < 4 => Vec::new(),
+            None => return Err(serde::de::Error::missing_field("prelu_slopes")),
+        };
+
+        // layers without a bias were added in format version 5
+        let use_bias = match use_bias {
+            Some(use_bias) => use_bias,
+            None if self.version < 5 => true,
+            None => return Err(serde::de::Error::missing_field("use_bias")),
+        };
+
         let mut layer = FullyConnectedLayer::new(num_inputs, num_neurons);
+        layer.set_use_bias(use_bias);
 
         layer.weights = weights;
         layer.biases = biases;
+        layer.prelu.load(prelu_slopes);
 
         Ok(layer)
     }
@@ -285,10 +533,24 @@ impl<'de> Visitor<'de> for FullyConnectedLayerVisitor {
         let weights = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
         let biases = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(3, &self))?;
 
+        let prelu_slopes = if self.version < 4 {
+            Vec::new()
+        } else {
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(4, &self))?
+        };
+
+        let use_bias = if self.version < 5 {
+            true
+        } else {
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(5, &self))?
+        };
+
         let mut layer = FullyConnectedLayer::new(num_inputs, num_neurons);
+        layer.set_use_bias(use_bias);
         
         layer.weights = weights;
         layer.biases = biases;
+        layer.prelu.load(prelu_slopes);
 
         Ok(layer)
     }
@@ -20,6 +20,7 @@ pub fn eval(function_type: Initialization, inputs: usize, outputs: usize, vec: &
     }
 }
 
+#[allow(clippy::ptr_arg, clippy::needless_range_loop)]
 fn uniform_xavier_initialization(inputs: usize, outputs: usize, vec: &mut Vec<f32>) {
     let bound = (6.0 / (inputs as f32 + outputs as f32)).sqrt();
     
@@ -27,10 +28,11 @@ fn uniform_xavier_initialization(inputs: usize, outputs: usize, vec: &mut Vec<f3
     let mut rng = rand::rng();
     
     for i in 0..vec.len() {
-        vec[i] = rng.sample(&uniform);
+        vec[i] = rng.sample(uniform);
     }
 }
 
+#[allow(clippy::ptr_arg, clippy::needless_range_loop)]
 fn normal_xavier_initialization(inputs: usize, outputs: usize, vec: &mut Vec<f32>) {
     let bound = (2.0 / (inputs as f32 + outputs as f32)).sqrt();
 
@@ -38,10 +40,11 @@ fn normal_xavier_initialization(inputs: usize, outputs: usize, vec: &mut Vec<f32
     let mut rng = rand::rng();
 
     for i in 0..vec.len() {
-        vec[i] = rng.sample(&normal);
+        vec[i] = rng.sample(normal);
     }
 }
 
+#[allow(clippy::ptr_arg, clippy::needless_range_loop)]
 fn uniform_he_initialization(inputs: usize, vec: &mut Vec<f32>) {
     let bound = (6.0 / inputs as f32).sqrt();
 
@@ -49,10 +52,11 @@ fn uniform_he_initialization(inputs: usize, vec: &mut Vec<f32>) {
     let mut rng = rand::rng();
     
     for i in 0..vec.len() {
-        vec[i] = rng.sample(&uniform);
+        vec[i] = rng.sample(uniform);
     }
 }
 
+#[allow(clippy::ptr_arg, clippy::needless_range_loop)]
 fn normal_he_initialization(inputs: usize, vec: &mut Vec<f32>) {
     let bound = (2.0 / inputs as f32).sqrt();
 
@@ -60,6 +64,6 @@ fn normal_he_initialization(inputs: usize, vec: &mut Vec<f32>) {
     let mut rng = rand::rng();
 
     for i in 0..vec.len() {
-        vec[i] = rng.sample(&normal);
+        vec[i] = rng.sample(normal);
     }
 }
\ No newline at end of file
@@ -1,13 +1,19 @@
 use crate::errors::Error;
 
-use crate::convolutional_layer::ConvolutionalLayer;
-use crate::fully_connected_layer::FullyConnectedLayer;
-use crate::pooling_layer::{PoolingLayer, PoolingType};
+use crate::convolutional_layer::{ConvolutionalLayer, ConvolutionalLayerVisitor};
+use crate::fully_connected_layer::{FullyConnectedLayer, FullyConnectedLayerVisitor};
+use crate::pooling_layer::{PoolingLayer, PoolingLayerVisitor, PoolingType};
+use crate::add_layer::{AddLayer, AddLayerVisitor};
+use crate::max_unpool_layer::{MaxUnpoolLayer, MaxUnpoolLayerVisitor};
+use crate::spatial_dropout_layer::{SpatialDropoutLayer, SpatialDropoutLayerVisitor};
+use crate::scale_layer::{ScaleLayer, ScaleLayerVisitor};
+use crate::separable_convolutional_layer::{SeparableConvolutionalLayer, SeparableConvolutionalLayerVisitor};
 
 use crate::initialization;
+use crate::util::Gradient;
 use crate::activations;
 
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, de::{DeserializeSeed, EnumAccess, VariantAccess, Visitor}};
 
 pub(crate) trait LayerBase {
     fn forward_propagate(&self, next_layer: &mut Layer) -> Result<(), Error>;
@@ -24,35 +30,435 @@ pub trait LearnableLayer: LayerBase {
     fn back_activate(&mut self, func: activations::ActivationFunction) -> ();
 }
 
+/// The shape of the data a layer consumes or produces
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LayerShape {
+    /// (width, height, depth)
+    Volume(usize, usize, usize),
+    Flat(usize),
+}
+
+impl LayerShape {
+    /// the total number of values
+    pub fn size(&self) -> usize {
+        match self {
+            LayerShape::Volume(x, y, z) => x * y * z,
+            LayerShape::Flat(size) => *size,
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub enum Layer {
     Convolutional(ConvolutionalLayer),
     Pooling(PoolingLayer),
     FullyConnected(FullyConnectedLayer),
+    Add(AddLayer),
+    MaxUnpool(MaxUnpoolLayer),
+    SpatialDropout(SpatialDropoutLayer),
+    Scale(ScaleLayer),
+    SeparableConvolutional(SeparableConvolutionalLayer),
 }
 
+#[allow(clippy::unused_unit)]
 impl Layer {
+    /// a sequence like audio is a volume with a height of 1, which needs a zero padding of at least `(kernel_size - 1) / 2`
+    /// since the kernels are square, only the kernel row that overlaps the sequence is evaluated
     pub fn make_convolutional_layer(zero_padding: usize, stride: usize, kernel_size: usize, dimension: (usize, usize, usize), input_depth: usize) -> Layer {
+        Self::make_convolutional_layer_with_stride(zero_padding, (stride, stride), kernel_size, dimension, input_depth)
+    }
+
+    /// creates a convolutional layer with a separate (x, y) stride
+    pub fn make_convolutional_layer_with_stride(zero_padding: usize, stride: (usize, usize), kernel_size: usize, dimension: (usize, usize, usize), input_depth: usize) -> Layer {
         Layer::Convolutional(ConvolutionalLayer::new(zero_padding, stride, kernel_size, dimension, input_depth))
     }
 
     pub fn make_pooling_layer(pooling_type: PoolingType, zero_padding: usize, stride: usize, kernel_size: usize, dimension: (usize, usize, usize)) -> Layer {
+        Self::make_pooling_layer_with_stride(pooling_type, zero_padding, (stride, stride), kernel_size, dimension)
+    }
+
+    /// creates a pooling layer with a separate (x, y) stride
+    pub fn make_pooling_layer_with_stride(pooling_type: PoolingType, zero_padding: usize, stride: (usize, usize), kernel_size: usize, dimension: (usize, usize, usize)) -> Layer {
         Layer::Pooling(PoolingLayer::new(pooling_type, zero_padding, stride, kernel_size, dimension))
     }
 
     pub fn make_fully_connected_layer(num_inputs: usize, num_neurons: usize) -> Layer {
         Layer::FullyConnected(FullyConnectedLayer::new(num_inputs, num_neurons))
     }
+    /// creates a layer that adds the output of the layer at `source` to the output of the previous layer
+    pub fn make_add_layer(source: usize, zero_padding: usize, dimension: (usize, usize, usize)) -> Layer {
+        Layer::Add(AddLayer::new(source, zero_padding, dimension))
+    }
+
+    /// creates a layer that reverses the max pooling layer at `source`, `dimension` is the pooling layer's input dimension
+    pub fn make_max_unpool_layer(source: usize, zero_padding: usize, dimension: (usize, usize, usize)) -> Layer {
+        Layer::MaxUnpool(MaxUnpoolLayer::new(source, zero_padding, dimension))
+    }
+
+    /// creates a layer that drops whole channels of a volume with the given probability while training
+    pub fn make_spatial_dropout_layer(probability: f32, zero_padding: usize, dimension: (usize, usize, usize)) -> Layer {
+        Layer::SpatialDropout(SpatialDropoutLayer::new(probability, zero_padding, dimension))
+    }
+
+    /// creates a layer that multiplies every channel of a volume by a learnable gamma
+    pub fn make_scale_layer(zero_padding: usize, dimension: (usize, usize, usize)) -> Layer {
+        Layer::Scale(ScaleLayer::new(zero_padding, dimension))
+    }
+
+    /// creates a depthwise convolution followed by a 1x1 convolution, see `SeparableConvolutionalLayer`
+    pub fn make_separable_convolutional_layer(zero_padding: usize, stride: (usize, usize), kernel_size: usize, dimension: (usize, usize, usize), input_depth: usize) -> Layer {
+        Layer::SeparableConvolutional(SeparableConvolutionalLayer::new(zero_padding, stride, kernel_size, dimension, input_depth))
+    }
+
     // TODO: make this a separate layer for less memory consumption
     pub fn make_input_layer(zero_padding: usize, dimension: (usize, usize, usize)) -> Layer {
         Self::make_convolutional_layer(zero_padding, 0, 0, dimension, 0)
     }
 
+    pub fn output_dimension(&self) -> LayerShape {
+        match self {
+            Layer::Convolutional(layer) => {
+                let (x, y, z) = layer.dimension;
+                LayerShape::Volume(x, y, z)
+            }
+
+            Layer::Pooling(layer) => {
+                let (x, y, z) = layer.dimension;
+                LayerShape::Volume(x, y, z)
+            }
+
+            Layer::FullyConnected(layer) => LayerShape::Flat(layer.num_neurons),
+
+            Layer::Add(layer) => {
+                let (x, y, z) = layer.dimension;
+                LayerShape::Volume(x, y, z)
+            }
+
+            Layer::MaxUnpool(layer) => {
+                let (x, y, z) = layer.dimension;
+                LayerShape::Volume(x, y, z)
+            }
+
+            Layer::SpatialDropout(layer) => {
+                let (x, y, z) = layer.dimension;
+                LayerShape::Volume(x, y, z)
+            }
+
+            Layer::Scale(layer) => {
+                let (x, y, z) = layer.dimension;
+                LayerShape::Volume(x, y, z)
+            }
+
+            Layer::SeparableConvolutional(layer) => {
+                let (x, y, z) = layer.dimension;
+                LayerShape::Volume(x, y, z)
+            }
+        }
+    }
+
+    /// switches layers that behave differently during training, like stochastic pooling or dropout
+    pub(crate) fn set_training(&mut self, training: bool) -> () {
+        match self {
+            Layer::Convolutional(layer) => layer.training = training,
+            Layer::Pooling(layer) => layer.training = training,
+            Layer::SpatialDropout(layer) => layer.training = training,
+
+            _ => (),
+        }
+    }
+
+    /// true for layers that sample randomly in the forward pass, which can't be recomputed with the same result
+    pub(crate) fn is_stochastic(&self) -> bool {
+        match self {
+            Layer::Pooling(layer) => layer.training && layer.get_pooling_type() == PoolingType::Stochastic,
+            Layer::SpatialDropout(layer) => layer.training && layer.probability > 0.0,
+
+            _ => false,
+        }
+    }
+
+    /// draws a seed from the random number generator of layers that have one
+    pub(crate) fn next_seed(&mut self) -> Option<u64> {
+        match self {
+            Layer::Pooling(layer) => Some(layer.next_seed()),
+            Layer::SpatialDropout(layer) => Some(layer.next_seed()),
+
+            _ => None,
+        }
+    }
+
+    /// frees the output of the last forward pass, see `NeuralNetwork::set_gradient_checkpointing`
+    pub(crate) fn release_activations(&mut self) -> () {
+        match self {
+            Layer::Convolutional(layer) => layer.release_activations(),
+            Layer::Pooling(layer) => layer.volume = Vec::new(),
+            Layer::Add(layer) => layer.release_activations(),
+            Layer::MaxUnpool(layer) => layer.release_activations(),
+            Layer::SpatialDropout(layer) => layer.volume = Vec::new(),
+            Layer::Scale(layer) => layer.release_activations(),
+            Layer::SeparableConvolutional(layer) => layer.release_activations(),
+
+            // the outputs of a fully connected layer are small enough to keep
+            Layer::FullyConnected(_) => (),
+        }
+    }
+
+    /// reallocates the buffers freed by `release_activations` so the layer can be recomputed
+    pub(crate) fn restore_activations(&mut self, func: activations::ActivationFunction) -> () {
+        let size = self.output_dimension().size();
+
+        match self {
+            Layer::Convolutional(layer) => layer.restore_activations(func),
+            Layer::Pooling(layer) => layer.volume.resize(size, 0.0),
+            Layer::Add(layer) => layer.restore_activations(),
+            Layer::MaxUnpool(layer) => layer.volume.resize(size, 0.0),
+            Layer::SpatialDropout(layer) => layer.volume.resize(size, 0.0),
+            Layer::Scale(layer) => layer.restore_activations(),
+            Layer::SeparableConvolutional(layer) => layer.restore_activations(),
+            Layer::FullyConnected(_) => (),
+        }
+    }
+
+    /// the padding the next layer sees around this layer's output
+    pub(crate) fn zero_padding(&self) -> usize {
+        match self {
+            Layer::Convolutional(layer) => layer.zero_padding,
+            Layer::Pooling(layer) => layer.zero_padding,
+            Layer::FullyConnected(_) => 0,
+            Layer::Add(layer) => layer.zero_padding,
+            Layer::MaxUnpool(layer) => layer.zero_padding,
+            Layer::SpatialDropout(layer) => layer.zero_padding,
+            Layer::Scale(layer) => layer.zero_padding,
+            Layer::SeparableConvolutional(layer) => layer.zero_padding,
+        }
+    }
+
+    /// a fully connected layer has no spatial output to pad, so it's left unchanged
+    pub(crate) fn set_zero_padding(&mut self, zero_padding: usize) -> () {
+        match self {
+            Layer::Convolutional(layer) => layer.zero_padding = zero_padding,
+            Layer::Pooling(layer) => layer.zero_padding = zero_padding,
+            Layer::FullyConnected(_) => (),
+            Layer::Add(layer) => layer.zero_padding = zero_padding,
+            Layer::MaxUnpool(layer) => layer.zero_padding = zero_padding,
+            Layer::SpatialDropout(layer) => layer.zero_padding = zero_padding,
+            Layer::Scale(layer) => layer.zero_padding = zero_padding,
+            Layer::SeparableConvolutional(layer) => layer.zero_padding = zero_padding,
+        }
+    }
+
+    /// the gradients of the error with respect to this layer's output
+    pub(crate) fn output_gradients_mut(&mut self) -> &mut Vec<f32> {
+        match self {
+            Layer::Convolutional(layer) => &mut layer.volume_gradients,
+            Layer::Pooling(layer) => &mut layer.volume_gradients,
+            Layer::FullyConnected(layer) => &mut layer.value_gradients,
+            Layer::Add(layer) => &mut layer.volume_gradients,
+            Layer::MaxUnpool(layer) => &mut layer.volume_gradients,
+            Layer::SpatialDropout(layer) => &mut layer.volume_gradients,
+            Layer::Scale(layer) => &mut layer.volume_gradients,
+            Layer::SeparableConvolutional(layer) => &mut layer.volume_gradients,
+        }
+    }
+
+    /// returns None when the input's width and height depend on the previous layer's zero padding
+    pub fn input_dimension(&self) -> Option<LayerShape> {
+        match self {
+            Layer::Convolutional(layer) if layer.is_input_layer() => Some(self.output_dimension()),
+            Layer::FullyConnected(layer) => Some(LayerShape::Flat(layer.num_inputs)),
+
+            _ => None,
+        }
+    }
+
+    /// the number of learnable weights and biases
+    pub fn num_parameters(&self) -> usize {
+        match self {
+            Layer::Convolutional(layer) if layer.is_input_layer() => 0,
+            Layer::Convolutional(layer) => layer.get_kernel().len() + layer.get_biases().len() + layer.get_prelu_slopes().len(),
+            Layer::FullyConnected(layer) => layer.get_weights().len() + layer.get_biases().len() + layer.get_prelu_slopes().len(),
+            Layer::Scale(layer) => layer.gamma.len(),
+            Layer::SeparableConvolutional(layer) => layer.depthwise_kernel.len() + layer.pointwise_kernel.len() + layer.biases.len() + layer.prelu.slopes.len(),
+
+            _ => 0,
+        }
+    }
+
+    /// the number of multiply-adds needed to compute the layer's output
+    pub fn flops(&self) -> usize {
+        match self {
+            // every output position applies each kernel once
+            Layer::Convolutional(layer) => layer.dimension.0 * layer.dimension.1 * layer.get_kernel().len(),
+            Layer::FullyConnected(layer) => layer.get_weights().len(),
+            Layer::Scale(layer) => layer.volume_gradients.len(),
+            // every output position filters each input channel once, then combines them for each kernel
+            Layer::SeparableConvolutional(layer) => layer.dimension.0 * layer.dimension.1 * (layer.depthwise_kernel.len() + layer.pointwise_kernel.len()),
+
+            _ => 0,
+        }
+    }
+
+    /// the bytes of the buffers a forward and backward pass of a single sample write, like the output, its gradients and the raw values
+    ///
+    /// the pooled values a max unpooling layer copies from its source aren't counted
+    pub fn activation_memory(&self, func: activations::ActivationFunction) -> usize {
+        let size = self.output_dimension().size();
+        let float = std::mem::size_of::<f32>();
+
+        match self {
+            // the raw volume is only kept if the activation's derivative can't be computed from its output
+            Layer::Convolutional(_) if activations::has_output_derivative(func) => 3 * size * float,
+            Layer::Convolutional(_) => 4 * size * float,
+
+            Layer::Pooling(layer) => {
+                let indices = match layer.get_pooling_type() {
+                    PoolingType::Max | PoolingType::Stochastic => size * std::mem::size_of::<usize>(),
+
+                    _ => 0,
+                };
+
+                2 * size * float + indices
+            }
+
+            Layer::FullyConnected(_) => 4 * size * float,
+            Layer::Add(_) => 4 * size * float,
+            Layer::MaxUnpool(_) => 2 * size * float,
+            Layer::SpatialDropout(layer) => (2 * size + layer.dimension.2) * float,
+            Layer::Scale(_) => 5 * size * float,
+
+            Layer::SeparableConvolutional(layer) => {
+                let depthwise_size = layer.dimension.0 * layer.dimension.1 * layer.input_depth;
+                (4 * size + 2 * depthwise_size) * float
+            }
+        }
+    }
+
+    /// the activated values of the last forward pass, flattened
+    pub fn get_output(&self) -> &Vec<f32> {
+        match self {
+            Layer::Convolutional(layer) => &layer.volume,
+            Layer::Pooling(layer) => &layer.volume,
+            Layer::FullyConnected(layer) => &layer.values,
+            Layer::Add(layer) => &layer.volume,
+            Layer::MaxUnpool(layer) => &layer.volume,
+            Layer::SpatialDropout(layer) => &layer.volume,
+            Layer::Scale(layer) => &layer.volume,
+            Layer::SeparableConvolutional(layer) => &layer.volume,
+        }
+    }
+
+    /// returns the kernel or weights of learnable layers, which is the pointwise kernel of separable convolutional layers
+    pub fn get_weights(&self) -> Option<&Vec<f32>> {
+        match self {
+            Layer::Convolutional(layer) => Some(layer.get_kernel()),
+            Layer::FullyConnected(layer) => Some(layer.get_weights()),
+            Layer::Scale(layer) => Some(&layer.gamma),
+            Layer::SeparableConvolutional(layer) => Some(&layer.pointwise_kernel),
+
+            _ => None,
+        }
+    }
+
+    /// every kernel or weight tensor of learnable layers, i.e. `get_weights` and the depthwise kernel of separable convolutional layers
+    pub(crate) fn weight_tensors(&self) -> Vec<&Vec<f32>> {
+        match self {
+            Layer::SeparableConvolutional(layer) => vec![&layer.depthwise_kernel, &layer.pointwise_kernel],
+
+            _ => self.get_weights().into_iter().collect(),
+        }
+    }
+
+    /// the gradients of `weight_tensors`
+    pub(crate) fn weight_gradient_tensors(&self) -> Vec<&Vec<Gradient>> {
+        match self {
+            Layer::SeparableConvolutional(layer) => vec![&layer.depthwise_gradients, &layer.pointwise_gradients],
+
+            _ => self.get_weight_gradients().into_iter().collect(),
+        }
+    }
+
+    /// the kernels or weights of convolutional, separable convolutional and fully connected layers, which hold nearly every parameter
+    pub(crate) fn weights_mut(&mut self) -> Vec<&mut Vec<f32>> {
+        match self {
+            Layer::Convolutional(layer) => vec![&mut layer.kernel],
+            Layer::FullyConnected(layer) => vec![&mut layer.weights],
+            Layer::SeparableConvolutional(layer) => vec![&mut layer.depthwise_kernel, &mut layer.pointwise_kernel],
+
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn get_weight_gradients(&self) -> Option<&Vec<Gradient>> {
+        match self {
+            Layer::Convolutional(layer) => Some(&layer.kernel_gradients),
+            Layer::FullyConnected(layer) => Some(&layer.weight_gradients),
+            Layer::Scale(layer) => Some(&layer.gamma_gradients),
+            Layer::SeparableConvolutional(layer) => Some(&layer.pointwise_gradients),
+
+            _ => None,
+        }
+    }
+
+    /// compares everything except the learnable parameters
+    pub(crate) fn same_structure(&self, other: &Layer) -> bool {
+        match (self, other) {
+            (Layer::Convolutional(layer), Layer::Convolutional(other)) => layer.same_structure(other),
+            (Layer::Pooling(layer), Layer::Pooling(other)) => layer.same_structure(other),
+            (Layer::FullyConnected(layer), Layer::FullyConnected(other)) => layer.same_structure(other),
+            (Layer::Add(layer), Layer::Add(other)) => layer.same_structure(other),
+            (Layer::MaxUnpool(layer), Layer::MaxUnpool(other)) => layer.same_structure(other),
+            (Layer::SpatialDropout(layer), Layer::SpatialDropout(other)) => layer.same_structure(other),
+            (Layer::Scale(layer), Layer::Scale(other)) => layer.same_structure(other),
+            (Layer::SeparableConvolutional(layer), Layer::SeparableConvolutional(other)) => layer.same_structure(other),
+
+            _ => false,
+        }
+    }
+
+    /// copies the learnable parameters of a layer with the same structure
+    pub fn copy_weights_from(&mut self, other: &Layer) -> Result<(), Error> {
+        match (self, other) {
+            (Layer::Convolutional(layer), Layer::Convolutional(other)) => layer.copy_weights_from(other),
+            (Layer::FullyConnected(layer), Layer::FullyConnected(other)) => layer.copy_weights_from(other),
+            (Layer::Scale(layer), Layer::Scale(other)) => layer.copy_weights_from(other),
+            (Layer::SeparableConvolutional(layer), Layer::SeparableConvolutional(other)) => layer.copy_weights_from(other),
+
+            (Layer::Pooling(layer), Layer::Pooling(other)) if layer.same_structure(other) => Ok(()),
+            (Layer::Add(layer), Layer::Add(other)) if layer.same_structure(other) => Ok(()),
+            (Layer::MaxUnpool(layer), Layer::MaxUnpool(other)) if layer.same_structure(other) => Ok(()),
+            (Layer::SpatialDropout(layer), Layer::SpatialDropout(other)) if layer.same_structure(other) => Ok(()),
+
+            _ => Err(Error::IncompatibleLayers),
+        }
+    }
+
+    /// runs only this layer on `input`, a volume of `input_dimension` with `zero_padding` around it, and returns its activated output
+    ///
+    /// e.g. to time or test a layer on its own. add and max unpooling layers read other layers of the network and aren't supported
+    pub fn forward_isolated(&mut self, input: &[f32], input_dimension: (usize, usize, usize), zero_padding: usize, func: activations::ActivationFunction) -> Result<Vec<f32>, Error> {
+        if let Layer::Add(_) | Layer::MaxUnpool(_) = self { return Err(Error::IncompatibleLayers) };
+
+        // the same as `make_input_layer`
+        let mut input_layer = ConvolutionalLayer::new(zero_padding, (0, 0), 0, input_dimension, 0);
+        input_layer.set_volume(&input.to_vec())?;
+
+        self.restore_activations(func);
+        input_layer.forward_propagate(self)?;
+        self.activate(func);
+
+        Ok(self.get_output().clone())
+    }
+
     pub fn forward_propagate(&self, next_layer: &mut Layer) -> Result<(), Error> {
         match self {
             Layer::Convolutional(layer) => layer.forward_propagate(next_layer),
             Layer::Pooling(layer) => layer.forward_propagate(next_layer),
             Layer::FullyConnected(layer) => layer.forward_propagate(next_layer),
+            Layer::Add(layer) => layer.forward_propagate(next_layer),
+            Layer::MaxUnpool(layer) => layer.forward_propagate(next_layer),
+            Layer::SpatialDropout(layer) => layer.forward_propagate(next_layer),
+            Layer::Scale(layer) => layer.forward_propagate(next_layer),
+            Layer::SeparableConvolutional(layer) => layer.forward_propagate(next_layer),
         }
     }
 
@@ -61,6 +467,11 @@ impl Layer {
             Layer::Convolutional(layer) => layer.back_propagate(previous_layer),
             Layer::Pooling(layer) => layer.back_propagate(previous_layer),
             Layer::FullyConnected(layer) => layer.back_propagate(previous_layer),
+            Layer::Add(layer) => layer.back_propagate(previous_layer),
+            Layer::MaxUnpool(layer) => layer.back_propagate(previous_layer),
+            Layer::SpatialDropout(layer) => layer.back_propagate(previous_layer),
+            Layer::Scale(layer) => layer.back_propagate(previous_layer),
+            Layer::SeparableConvolutional(layer) => layer.back_propagate(previous_layer),
         }
     }
 
@@ -68,6 +479,20 @@ impl Layer {
         match self {
             Layer::Convolutional(layer) => layer.apply_gradients(learning_rate, momentum, weight_decay),
             Layer::FullyConnected(layer) => layer.apply_gradients(learning_rate, momentum, weight_decay),
+            Layer::Scale(layer) => layer.apply_gradients(learning_rate, momentum),
+            Layer::SeparableConvolutional(layer) => layer.apply_gradients(learning_rate, momentum, weight_decay),
+
+            _ => (),
+        }
+    }
+
+    /// see `ConvolutionalLayer::apply_gradients_decoupled`
+    pub fn apply_gradients_decoupled(&mut self, learning_rate: f32, momentum: f32, decay: f32) -> () {
+        match self {
+            Layer::Convolutional(layer) => layer.apply_gradients_decoupled(learning_rate, momentum, decay),
+            Layer::FullyConnected(layer) => layer.apply_gradients_decoupled(learning_rate, momentum, decay),
+            Layer::Scale(layer) => layer.apply_gradients(learning_rate, momentum),
+            Layer::SeparableConvolutional(layer) => layer.apply_gradients_decoupled(learning_rate, momentum, decay),
 
             _ => (),
         }
@@ -77,6 +502,20 @@ impl Layer {
         match self {
             Layer::Convolutional(layer) => layer.reset_gradients(),
             Layer::FullyConnected(layer) => layer.reset_gradients(),
+            Layer::Scale(layer) => layer.reset_gradients(),
+            Layer::SeparableConvolutional(layer) => layer.reset_gradients(),
+
+            _ => (),
+        }
+    }
+
+    /// zeroes the momentum of the learnable parameters
+    pub fn reset_velocity(&mut self) -> () {
+        match self {
+            Layer::Convolutional(layer) => layer.reset_velocity(),
+            Layer::FullyConnected(layer) => layer.reset_velocity(),
+            Layer::Scale(layer) => layer.reset_velocity(),
+            Layer::SeparableConvolutional(layer) => layer.reset_velocity(),
 
             _ => (),
         }
@@ -86,6 +525,9 @@ impl Layer {
         match self {
             Layer::Convolutional(layer) => layer.activate(func),
             Layer::FullyConnected(layer) => layer.activate(func),
+            Layer::Add(layer) => layer.activate(func),
+            Layer::Scale(layer) => layer.activate(func),
+            Layer::SeparableConvolutional(layer) => layer.activate(func),
 
             _ => (),
         }
@@ -95,6 +537,20 @@ impl Layer {
         match self {
             Layer::Convolutional(layer) => layer.back_activate(func),
             Layer::FullyConnected(layer) => layer.back_activate(func),
+            Layer::Add(layer) => layer.back_activate(func),
+            Layer::Scale(layer) => layer.back_activate(func),
+            Layer::SeparableConvolutional(layer) => layer.back_activate(func),
+
+            _ => (),
+        }
+    }
+
+    /// drops buffers the activation function doesn't need
+    pub fn configure_activation(&mut self, func: activations::ActivationFunction) -> () {
+        match self {
+            Layer::Convolutional(layer) => layer.configure_activation(func),
+            Layer::FullyConnected(layer) => layer.configure_activation(func),
+            Layer::SeparableConvolutional(layer) => layer.configure_activation(func),
 
             _ => (),
         }
@@ -104,8 +560,73 @@ impl Layer {
         match self {
             Layer::Convolutional(layer) => layer.initialize(func),
             Layer::FullyConnected(layer) => layer.initialize(func),
+            Layer::Scale(layer) => layer.initialize(),
+            Layer::SeparableConvolutional(layer) => layer.initialize(func),
 
             _ => (),
         }
     }
+
+    /// a learnable layer whose weights are all zero hasn't been initialized, since every neuron would compute the same thing
+    ///
+    /// the input layer has no weights and counts as initialized
+    pub fn is_initialized(&self) -> bool {
+        self.weight_tensors().iter().all(|weights| weights.is_empty() || weights.iter().any(|weight| *weight != 0.0))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier)]
+enum LayerVariant {
+    Convolutional,
+    Pooling,
+    FullyConnected,
+    Add,
+    MaxUnpool,
+    SpatialDropout,
+    Scale,
+    SeparableConvolutional,
+}
+
+/// Deserializes a layer written with the given format version
+pub(crate) struct LayerSeed {
+    pub(crate) version: u32,
+}
+
+impl<'de> DeserializeSeed<'de> for LayerSeed {
+    type Value = Layer;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_enum("Layer", &["Convolutional", "Pooling", "FullyConnected", "Add", "MaxUnpool", "SpatialDropout", "Scale", "SeparableConvolutional"], self)
+    }
+}
+
+impl<'de> Visitor<'de> for LayerSeed {
+    type Value = Layer;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a Layer enum")
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+        where
+            A: EnumAccess<'de>,
+    {
+        let version = self.version;
+        let (variant, access) = data.variant()?;
+
+        match variant {
+            LayerVariant::Convolutional => access.newtype_variant_seed(ConvolutionalLayerVisitor { version }).map(Layer::Convolutional),
+            LayerVariant::Pooling => access.newtype_variant_seed(PoolingLayerVisitor { version }).map(Layer::Pooling),
+            LayerVariant::FullyConnected => access.newtype_variant_seed(FullyConnectedLayerVisitor { version }).map(Layer::FullyConnected),
+            LayerVariant::Add => access.newtype_variant_seed(AddLayerVisitor).map(Layer::Add),
+            LayerVariant::MaxUnpool => access.newtype_variant_seed(MaxUnpoolLayerVisitor).map(Layer::MaxUnpool),
+            LayerVariant::SpatialDropout => access.newtype_variant_seed(SpatialDropoutLayerVisitor).map(Layer::SpatialDropout),
+            LayerVariant::Scale => access.newtype_variant_seed(ScaleLayerVisitor).map(Layer::Scale),
+            LayerVariant::SeparableConvolutional => access.newtype_variant_seed(SeparableConvolutionalLayerVisitor).map(Layer::SeparableConvolutional),
+        }
+    }
 }
\ No newline at end of file
@@ -1,11 +1,14 @@
 pub use initialization::Initialization;
-pub use activations::ActivationFunction;
-pub use nn_error::ErrorFunction;
+pub use activations::{ActivationFunction, Activation};
+pub use nn_error::{ErrorFunction, Loss};
 
 pub use pooling_layer::PoolingType;
-pub use layer::Layer;
+pub use layer::{Layer, LayerShape};
 
-pub use neural_network::NeuralNetwork;
+pub use neural_network::{NeuralNetwork, Hyperparameters, TrainingConfig, ProgressEvent};
+pub use early_stopping::EarlyStopping;
+pub use schedule::LearningRateSchedule;
+pub use quantization::{QuantizedNetwork, QuantizedTensor};
 
 pub use errors::Error;
 
@@ -14,12 +17,25 @@ pub mod util;
 pub mod errors;
 pub mod initialization;
 pub mod activations;
+pub mod metrics;
+pub mod augment;
+pub mod dataset;
 
 mod neural_network;
 mod layer;
 mod convolutional_layer;
 mod fully_connected_layer;
 mod pooling_layer;
+mod add_layer;
+mod max_unpool_layer;
+mod spatial_dropout_layer;
+mod scale_layer;
+mod separable_convolutional_layer;
+mod prelu;
+
+mod early_stopping;
+mod schedule;
+mod quantization;
 
 mod nn_error;
 
@@ -0,0 +1,251 @@
+use crate::layer::{Layer, LayerBase};
+use crate::errors::Error;
+use crate::util;
+
+use serde::{Serialize, Deserialize, de::{DeserializeSeed, Visitor}, ser::SerializeStruct};
+
+/// Places every value back at the position a max pooling layer took it from and zeroes the rest
+///
+/// the network hands the switches of the pooling layer at `source` to this layer during the forward pass
+#[derive(Clone)]
+pub struct MaxUnpoolLayer {
+    /// the index of the max pooling layer in the network
+    pub(crate) source: usize,
+    pub(crate) zero_padding: usize,
+
+    /// the input dimension of the pooling layer
+    pub(crate) dimension: (usize, usize, usize),
+
+    pub(crate) volume: Vec<f32>,
+    pub(crate) volume_gradients: Vec<f32>,
+
+    pooled: Vec<f32>,
+    switches: Vec<usize>,
+}
+
+#[allow(clippy::unused_unit, clippy::ptr_arg)]
+impl MaxUnpoolLayer {
+    pub fn new(source: usize, zero_padding: usize, dimension: (usize, usize, usize)) -> Self {
+        Self {
+            source,
+            zero_padding,
+
+            dimension,
+
+            volume: vec![0.0; dimension.0 * dimension.1 * dimension.2],
+            volume_gradients: vec![0.0; dimension.0 * dimension.1 * dimension.2],
+
+            pooled: Vec::new(),
+            switches: Vec::new(),
+        }
+    }
+
+    pub fn get_source(&self) -> usize {
+        self.source
+    }
+
+    /// takes the output of the previous layer, which is placed once the switches are known
+    pub(crate) fn feed_forward(&mut self, input: &[f32]) -> () {
+        self.pooled.clear();
+        self.pooled.extend_from_slice(input);
+    }
+
+    /// values whose windows overlap on the same maximum are summed
+    pub(crate) fn unpool(&mut self, switches: &[usize]) -> Result<(), Error> {
+        if switches.len() != self.pooled.len() { return Err(Error::DimensionMismatch) };
+        if switches.iter().any(|&index| index >= self.volume.len()) { return Err(Error::DimensionMismatch) };
+
+        self.switches.clear();
+        self.switches.extend_from_slice(switches);
+
+        self.volume.fill(0.0);
+
+        for (value, &index) in self.pooled.iter().zip(switches) {
+            self.volume[index] += value;
+        }
+
+        Ok(())
+    }
+
+    /// the pooled values are copied again by the next `feed_forward`
+    pub(crate) fn release_activations(&mut self) -> () {
+        self.volume = Vec::new();
+        self.pooled = Vec::new();
+    }
+
+    fn feed_back(&self, previous_gradients: &mut Vec<f32>) -> Result<(), Error> {
+        if previous_gradients.len() != self.switches.len() { return Err(Error::DimensionMismatch) };
+
+        for (gradient, &index) in previous_gradients.iter_mut().zip(&self.switches) {
+            *gradient = self.volume_gradients[index];
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn same_structure(&self, other: &MaxUnpoolLayer) -> bool {
+        self.source == other.source && self.zero_padding == other.zero_padding && self.dimension == other.dimension
+    }
+}
+
+impl LayerBase for MaxUnpoolLayer {
+    fn forward_propagate(&self, next_layer: &mut Layer) -> Result<(), Error> {
+        match next_layer {
+            Layer::Convolutional(layer) => {
+                util::check_output_dimension(self.dimension,
+                    layer.dimension,
+                    self.zero_padding,
+                    layer.num_kernels,
+                    layer.kernel_size,
+                    layer.stride,
+                    layer.rounding
+                )?;
+
+                layer.convolve(self.dimension, &self.volume, self.zero_padding);
+            }
+
+            Layer::Pooling(layer) => {
+                util::check_output_dimension(self.dimension,
+                    layer.dimension,
+                    0, // a pooling layer doesn't take padding into account
+                    layer.dimension.2,
+                    layer.kernel_size,
+                    layer.stride,
+                    layer.rounding
+                )?;
+
+                layer.convolve(self.dimension, &self.volume);
+            }
+
+            Layer::FullyConnected(layer) => {
+                let dim = self.dimension;
+                if dim.0 * dim.1 * dim.2 != layer.num_inputs { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume);
+            }
+
+            Layer::Add(layer) => {
+                if self.dimension != layer.dimension { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume)?;
+            }
+
+            Layer::MaxUnpool(layer) => layer.feed_forward(&self.volume),
+            Layer::SpatialDropout(layer) => layer.feed_forward(&self.volume)?,
+
+            Layer::Scale(layer) => {
+                if self.dimension != layer.dimension { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume)?;
+            }
+
+            Layer::SeparableConvolutional(layer) => {
+                layer.check_input(self.dimension, self.zero_padding)?;
+
+                layer.convolve(self.dimension, &self.volume, self.zero_padding);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// every pooled value receives the gradient of the position it was placed at
+    fn back_propagate(&mut self, previous_layer: &mut Layer) -> Result<(), Error> {
+        self.feed_back(previous_layer.output_gradients_mut())
+    }
+}
+
+impl Serialize for MaxUnpoolLayer {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("MaxUnpoolLayer", 3)?;
+
+        state.serialize_field("source", &self.source)?;
+        state.serialize_field("zero_padding", &self.zero_padding)?;
+        state.serialize_field("dimension", &self.dimension)?;
+
+        state.end()
+    }
+}
+
+const MAX_UNPOOL_LAYER_FIELDS: &[&str] = &["source", "zero_padding", "dimension"];
+
+impl<'de> Deserialize<'de> for MaxUnpoolLayer {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("MaxUnpoolLayer", MAX_UNPOOL_LAYER_FIELDS, MaxUnpoolLayerVisitor)
+    }
+}
+
+/// the max unpooling layer format hasn't changed since it was introduced in format version 8
+pub(crate) struct MaxUnpoolLayerVisitor;
+
+impl<'de> DeserializeSeed<'de> for MaxUnpoolLayerVisitor {
+    type Value = MaxUnpoolLayer;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("MaxUnpoolLayer", MAX_UNPOOL_LAYER_FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for MaxUnpoolLayerVisitor {
+    type Value = MaxUnpoolLayer;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a MaxUnpoolLayer struct")
+    }
+
+    fn visit_map<M>(self, mut map: M) -> Result<Self::Value, M::Error>
+        where
+            M: serde::de::MapAccess<'de>,
+    {
+        let mut source = None;
+        let mut zero_padding = None;
+        let mut dimension = None;
+
+        while let Some(key) = map.next_key::<&str>()? {
+            match key {
+                "source" => {
+                    if source.is_some() { return Err(serde::de::Error::duplicate_field("source")); };
+
+                    source = Some(map.next_value()?);
+                }
+
+                "zero_padding" => {
+                    if zero_padding.is_some() { return Err(serde::de::Error::duplicate_field("zero_padding")); };
+
+                    zero_padding = Some(map.next_value()?);
+                }
+
+                "dimension" => {
+                    if dimension.is_some() { return Err(serde::de::Error::duplicate_field("dimension")); };
+
+                    dimension = Some(map.next_value()?);
+                }
+
+                _ => return Err(serde::de::Error::unknown_field(key, MAX_UNPOOL_LAYER_FIELDS)),
+            }
+        }
+
+        let source = source.ok_or_else(|| serde::de::Error::missing_field("source"))?;
+        let zero_padding = zero_padding.ok_or_else(|| serde::de::Error::missing_field("zero_padding"))?;
+        let dimension = dimension.ok_or_else(|| serde::de::Error::missing_field("dimension"))?;
+
+        Ok(MaxUnpoolLayer::new(source, zero_padding, dimension))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let source = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+        let zero_padding = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+        let dimension = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+
+        Ok(MaxUnpoolLayer::new(source, zero_padding, dimension))
+    }
+}
@@ -0,0 +1,223 @@
+use crate::errors::Error;
+use crate::util;
+
+/// Points of a receiver operating characteristic curve
+pub struct RocCurve {
+    /// (false positive rate, true positive rate) for every threshold
+    pub points: Vec<(f32, f32)>,
+    /// an output is classified as positive if it is greater than or equal to the threshold
+    pub thresholds: Vec<f32>,
+    pub auc: f32,
+}
+
+/// computes the ROC curve of a binary classifier, targets greater than 0.5 are positive
+pub fn roc_curve(outputs: &[f32], targets: &[f32]) -> Result<RocCurve, Error> {
+    if outputs.len() != targets.len() { return Err(Error::InvalidInput) };
+
+    let positives = targets.iter().filter(|target| **target > 0.5).count();
+    let negatives = targets.len() - positives;
+
+    if positives == 0 || negatives == 0 { return Err(Error::InvalidInput) };
+
+    let mut order: Vec<usize> = (0..outputs.len()).collect();
+    order.sort_by(|a, b| outputs[*b].total_cmp(&outputs[*a]));
+
+    let mut points = vec![(0.0, 0.0)];
+    let mut thresholds = vec![f32::INFINITY];
+
+    let mut true_positives = 0;
+    let mut false_positives = 0;
+
+    for i in 0..order.len() {
+        if targets[order[i]] > 0.5 {
+            true_positives += 1;
+        } else {
+            false_positives += 1;
+        }
+
+        // samples with equal outputs can't be separated by a threshold
+        if i + 1 < order.len() && outputs[order[i + 1]] == outputs[order[i]] { continue };
+
+        points.push((false_positives as f32 / negatives as f32, true_positives as f32 / positives as f32));
+        thresholds.push(outputs[order[i]]);
+    }
+
+    let mut auc = 0.0;
+    for i in 1..points.len() {
+        let (x0, y0) = points[i - 1];
+        let (x1, y1) = points[i];
+
+        auc += (x1 - x0) * (y0 + y1) * 0.5;
+    }
+
+    Ok(RocCurve { points, thresholds, auc })
+}
+
+/// Points of a precision-recall curve
+pub struct PrecisionRecallCurve {
+    /// (precision, recall) for every threshold from the highest to the lowest
+    pub points: Vec<(f32, f32)>,
+    /// an output is classified as positive if it is greater than or equal to the threshold
+    pub thresholds: Vec<f32>,
+    /// the area under the curve as the sum of `(recall - previous recall) * precision` over the points
+    pub average_precision: f32,
+}
+
+/// computes the precision-recall curve of a binary classifier, targets greater than 0.5 are positive
+pub fn precision_recall_curve(outputs: &[f32], targets: &[f32]) -> Result<PrecisionRecallCurve, Error> {
+    if outputs.len() != targets.len() { return Err(Error::InvalidInput) };
+
+    let positives = targets.iter().filter(|target| **target > 0.5).count();
+    if positives == 0 { return Err(Error::InvalidInput) };
+
+    let mut order: Vec<usize> = (0..outputs.len()).collect();
+    order.sort_by(|a, b| outputs[*b].total_cmp(&outputs[*a]));
+
+    let mut points = Vec::new();
+    let mut thresholds = Vec::new();
+    let mut average_precision = 0.0;
+
+    let mut true_positives = 0;
+    let mut previous_recall = 0.0;
+
+    for i in 0..order.len() {
+        if targets[order[i]] > 0.5 {
+            true_positives += 1;
+        }
+
+        // samples with equal outputs can't be separated by a threshold
+        if i + 1 < order.len() && outputs[order[i + 1]] == outputs[order[i]] { continue };
+
+        let precision = true_positives as f32 / (i + 1) as f32;
+        let recall = true_positives as f32 / positives as f32;
+
+        average_precision += (recall - previous_recall) * precision;
+        previous_recall = recall;
+
+        points.push((precision, recall));
+        thresholds.push(outputs[order[i]]);
+    }
+
+    Ok(PrecisionRecallCurve { points, thresholds, average_precision })
+}
+
+/// computes the precision-recall curve of every class from the outputs and one-hot or multi-label targets, e.g. of `forward_batch`
+pub fn per_class_precision_recall(predictions: &[Vec<f32>], targets: &[Vec<f32>]) -> Result<Vec<PrecisionRecallCurve>, Error> {
+    if predictions.len() != targets.len() || predictions.is_empty() { return Err(Error::InvalidInput) };
+
+    let classes = predictions[0].len();
+    if predictions.iter().zip(targets).any(|(prediction, target)| prediction.len() != classes || target.len() != classes) {
+        return Err(Error::DimensionMismatch);
+    }
+
+    (0..classes).map(|class| {
+        let outputs: Vec<f32> = predictions.iter().map(|prediction| prediction[class]).collect();
+        let class_targets: Vec<f32> = targets.iter().map(|target| target[class]).collect();
+
+        precision_recall_curve(&outputs, &class_targets)
+    }).collect()
+}
+
+/// the fraction of samples whose true class, the argmax of the one-hot target, is among the `k` highest outputs
+///
+/// outputs tied with the true class don't push it out of the top `k`
+pub fn top_k_accuracy(predictions: &[Vec<f32>], targets: &[Vec<f32>], k: usize) -> Result<f32, Error> {
+    if predictions.len() != targets.len() || predictions.is_empty() || k == 0 { return Err(Error::InvalidInput) };
+
+    let mut correct = 0;
+
+    for (prediction, target) in predictions.iter().zip(targets) {
+        if prediction.len() != target.len() || prediction.is_empty() { return Err(Error::DimensionMismatch) };
+
+        let output = prediction[util::argmax(target)];
+        let higher = prediction.iter().filter(|value| **value > output).count();
+
+        if higher < k {
+            correct += 1;
+        }
+    }
+
+    Ok(correct as f32 / predictions.len() as f32)
+}
+
+/// Metrics of a multi-label classifier, whose outputs are independent, e.g. sigmoids
+pub struct MultiLabelMetrics {
+    /// the fraction of labels that were predicted wrong
+    pub hamming_loss: f32,
+    /// the fraction of samples whose labels were all predicted right
+    pub subset_accuracy: f32,
+    /// the F1 score of every label, 0 for a label without positive targets or predictions
+    pub f1: Vec<f32>,
+}
+
+/// evaluates every output as a separate label, which is predicted positive if it's greater than or equal to the threshold
+///
+/// unlike the multi-class metrics, the argmax isn't used. targets greater than 0.5 are positive
+pub fn multi_label_metrics(predictions: &[Vec<f32>], targets: &[Vec<f32>], threshold: f32) -> Result<MultiLabelMetrics, Error> {
+    if predictions.len() != targets.len() || predictions.is_empty() { return Err(Error::InvalidInput) };
+
+    let labels = predictions[0].len();
+    if labels == 0 { return Err(Error::InvalidInput) };
+
+    let mut wrong = 0;
+    let mut exact = 0;
+
+    // (true positives, false positives, false negatives) of every label
+    let mut counts = vec![(0, 0, 0); labels];
+
+    for (prediction, target) in predictions.iter().zip(targets) {
+        if prediction.len() != labels || target.len() != labels { return Err(Error::DimensionMismatch) };
+
+        let mut all_right = true;
+
+        for i in 0..labels {
+            let (predicted, positive) = (prediction[i] >= threshold, target[i] > 0.5);
+
+            match (predicted, positive) {
+                (true, true) => counts[i].0 += 1,
+                (true, false) => counts[i].1 += 1,
+                (false, true) => counts[i].2 += 1,
+                (false, false) => (),
+            }
+
+            if predicted != positive {
+                wrong += 1;
+                all_right = false;
+            }
+        }
+
+        if all_right {
+            exact += 1;
+        }
+    }
+
+    let f1 = counts.iter().map(|&(true_positives, false_positives, false_negatives)| {
+        let denominator = 2 * true_positives + false_positives + false_negatives;
+        if denominator == 0 { 0.0 } else { 2.0 * true_positives as f32 / denominator as f32 }
+    }).collect();
+
+    Ok(MultiLabelMetrics {
+        hamming_loss: wrong as f32 / (predictions.len() * labels) as f32,
+        subset_accuracy: exact as f32 / predictions.len() as f32,
+        f1,
+    })
+}
+
+/// counts the samples of every class, the argmax of a one-hot target or whether a single binary target is greater than 0.5
+///
+/// binary targets count as two classes, so the result has `max(target length, 2)` entries or none for no targets
+pub fn label_distribution(targets: &[Vec<f32>]) -> Vec<usize> {
+    let mut counts = Vec::new();
+
+    for target in targets {
+        let class = match target.len() {
+            1 => (target[0] > 0.5) as usize,
+            _ => util::argmax(target),
+        };
+
+        if counts.len() < target.len().max(2) { counts.resize(target.len().max(2), 0) };
+        counts[class] += 1;
+    }
+
+    counts
+}
@@ -1,105 +1,1672 @@
-use crate::{ActivationFunction, Error, ErrorFunction, Initialization, Layer};
+use crate::{ActivationFunction, Error, ErrorFunction, Initialization, Layer, LayerShape, LearningRateSchedule, PoolingType};
+use crate::util::{self, Gradient};
+use crate::nn_error;
+use crate::activations;
+use crate::layer::LayerSeed;
+use crate::dataset::{DataLoader, Dataset};
+use crate::augment::Augmentation;
+use crate::quantization::QuantizedNetwork;
 
-use serde::{Serialize, Deserialize, de::Visitor, ser::SerializeStruct};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::{SeedableRng, rngs::StdRng};
+use serde::{Serialize, Deserialize, de::{DeserializeSeed, Visitor}, ser::SerializeStruct};
+
+/// the serialization format version written by this build
+///
+/// bump it whenever a serialized field is added and read the new fields conditionally on the loaded version
+pub(crate) const FORMAT_VERSION: u32 = 12;
+
+/// written before the version so formats that aren't self describing can tell a network written before versioning,
+/// which starts with the number of layers, from a versioned one
+pub(crate) const FORMAT_MAGIC: u64 = u64::from_le_bytes(*b"CNNMODEL");
+
+/// Optimizer settings used by `end_batch`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Hyperparameters {
+    pub learning_rate: f32,
+    pub momentum: f32,
+    pub weight_decay: f32,
+}
+
+impl Default for Hyperparameters {
+    fn default() -> Self {
+        Self {
+            learning_rate: 0.01,
+            momentum: 0.9,
+            weight_decay: 5e-4,
+        }
+    }
+}
+
+/// Training statistics passed to the progress callback of `train_with_progress`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProgressEvent {
+    pub epoch: usize,
+    /// the index of the batch counted over every epoch, None at the end of an epoch
+    pub batch: Option<usize>,
+
+    /// the average error of the batch or epoch
+    pub error: f32,
+    /// the fraction of correct outputs, see `evaluate`
+    pub accuracy: f32,
+    /// the time since training started
+    pub elapsed: Duration,
+}
+
+/// Settings used by `train`
+#[derive(Clone, Debug)]
+pub struct TrainingConfig {
+    pub epochs: usize,
+    /// at most 255 samples, see `end_batch`
+    pub batch_size: usize,
+    pub shuffle: bool,
+    /// seeds the shuffling of the batches when set, otherwise every run shuffles differently. see `set_seed` for the layers
+    pub seed: Option<u64>,
+    /// ends batches with `end_batch_decoupled` instead of `end_batch`
+    pub decoupled_weight_decay: bool,
+    /// the number of worker threads `train_parallel` splits every batch across
+    pub num_threads: usize,
+    /// replaces the learning rate of the hyperparameters before every batch when set, the steps are counted over every epoch
+    pub learning_rate_schedule: Option<LearningRateSchedule>,
+
+    /// saves the network every `checkpoint_interval` batches when set
+    pub checkpoint_path: Option<PathBuf>,
+    pub checkpoint_interval: usize,
+}
+
+impl Default for TrainingConfig {
+    fn default() -> Self {
+        Self {
+            epochs: 1,
+            batch_size: 32,
+            shuffle: true,
+            seed: None,
+            decoupled_weight_decay: false,
+            num_threads: thread::available_parallelism().map(|count| count.get()).unwrap_or(1),
+            learning_rate_schedule: None,
+
+            checkpoint_path: None,
+            checkpoint_interval: 20,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct NeuralNetwork {
     layers: Vec<(Layer, ActivationFunction)>,
     error_function: ErrorFunction,
+    hyperparameters: Hyperparameters,
+
+    class_weights: Option<Vec<f32>>,
+    error_epsilon: f32,
+
+    training: bool,
+    temperature: f32,
+
+    checkpoint_segment: Option<usize>,
+
+    weight_average_decay: Option<f32>,
+    /// an exponential moving average of `collect_parameters_mut`
+    averaged_weights: Vec<f32>,
 }
 
-impl NeuralNetwork {
-    pub fn new(error_function: ErrorFunction) -> Self {
-        Self {
-            layers: Vec::new(),
-            error_function,
-        }
+#[allow(clippy::unused_unit)]
+impl NeuralNetwork {
+    pub fn new(error_function: ErrorFunction) -> Self {
+        Self {
+            layers: Vec::new(),
+            error_function,
+            hyperparameters: Hyperparameters::default(),
+
+            class_weights: None,
+            error_epsilon: nn_error::DEFAULT_EPSILON,
+
+            training: true,
+            temperature: 1.0,
+
+            checkpoint_segment: None,
+
+            weight_average_decay: None,
+            averaged_weights: Vec::new(),
+        }
+    }
+
+    pub fn get_hyperparameters(&self) -> Hyperparameters {
+        self.hyperparameters
+    }
+
+    pub fn set_hyperparameters(&mut self, hyperparameters: Hyperparameters) -> () {
+        self.hyperparameters = hyperparameters;
+    }
+
+    pub fn get_error_function(&self) -> ErrorFunction {
+        self.error_function
+    }
+
+    pub fn set_error_function(&mut self, error_function: ErrorFunction) -> () {
+        self.error_function = error_function;
+    }
+
+    pub fn get_error_epsilon(&self) -> f32 {
+        self.error_epsilon
+    }
+
+    /// clamps the outputs of the cross entropy losses to [epsilon, 1 - epsilon], which bounds their gradients
+    ///
+    /// defaults to `nn_error::DEFAULT_EPSILON` and isn't serialized with the network
+    pub fn set_error_epsilon(&mut self, epsilon: f32) -> Result<(), Error> {
+        if !(epsilon > 0.0 && epsilon < 0.5) { return Err(Error::InvalidInput) };
+
+        self.error_epsilon = epsilon;
+        Ok(())
+    }
+
+    pub fn get_class_weights(&self) -> Option<&Vec<f32>> {
+        self.class_weights.as_ref()
+    }
+
+    /// weights the error of every output index, None weights them uniformly
+    ///
+    /// the weights are a training setting and aren't serialized with the network
+    pub fn set_class_weights(&mut self, class_weights: Option<Vec<f32>>) -> () {
+        self.class_weights = class_weights;
+    }
+
+    pub fn is_training(&self) -> bool {
+        self.training
+    }
+
+    /// switches between training and inference behavior, networks start in training mode
+    pub fn set_training(&mut self, training: bool) -> () {
+        self.training = training;
+
+        for (layer, _) in &mut self.layers {
+            layer.set_training(training);
+        }
+    }
+
+    pub fn get_temperature(&self) -> f32 {
+        self.temperature
+    }
+
+    /// divides the logits of a fully connected output layer by `temperature` before its activation during inference
+    ///
+    /// a temperature above 1 softens the outputs, which calibrates overconfident classifiers
+    pub fn set_temperature(&mut self, temperature: f32) -> Result<(), Error> {
+        if !(temperature > 0.0 && temperature.is_finite()) { return Err(Error::InvalidInput) };
+
+        self.temperature = temperature;
+        Ok(())
+    }
+
+    pub fn get_gradient_checkpointing(&self) -> Option<usize> {
+        self.checkpoint_segment
+    }
+
+    /// keeps the outputs of every `segment`th layer during a training forward pass and frees the others,
+    /// which are recomputed one segment at a time during the backward pass
+    ///
+    /// trades roughly one extra forward pass for memory, None keeps every output. the intermediate outputs
+    /// are empty after a training forward pass, so `forward_with_activations` should be called in inference mode
+    pub fn set_gradient_checkpointing(&mut self, segment: Option<usize>) -> Result<(), Error> {
+        if segment == Some(0) { return Err(Error::InvalidInput) };
+
+        self.checkpoint_segment = segment;
+        Ok(())
+    }
+
+    pub fn get_weight_averaging(&self) -> Option<f32> {
+        self.weight_average_decay
+    }
+
+    /// keeps an exponential moving average of the weights, which is updated by `decay * average + (1 - decay) * weights`
+    /// at the end of every batch. None stops averaging
+    ///
+    /// the average starts at the current weights and isn't serialized with the network, see `swap_averaged_weights`
+    pub fn set_weight_averaging(&mut self, decay: Option<f32>) -> Result<(), Error> {
+        if decay.is_some_and(|decay| !(0.0..1.0).contains(&decay)) { return Err(Error::InvalidInput) };
+
+        self.weight_average_decay = decay;
+        self.averaged_weights = match decay {
+            Some(_) => self.collect_parameters_mut().into_iter().map(|weight| *weight).collect(),
+            None => Vec::new(),
+        };
+
+        Ok(())
+    }
+
+    /// exchanges the weights with their moving average, e.g. to evaluate the averaged weights, and again to swap them back
+    pub fn swap_averaged_weights(&mut self) -> Result<(), Error> {
+        if self.weight_average_decay.is_none() { return Err(Error::InvalidInput) };
+
+        let mut averaged_weights = std::mem::take(&mut self.averaged_weights);
+        let parameters = self.collect_parameters_mut();
+        if parameters.len() != averaged_weights.len() { return Err(Error::IncompatibleLayers) };
+
+        for (weight, average) in parameters.into_iter().zip(averaged_weights.iter_mut()) {
+            std::mem::swap(weight, average);
+        }
+
+        self.averaged_weights = averaged_weights;
+        Ok(())
+    }
+
+    /// moves the weight average towards the weights after a batch
+    fn update_averaged_weights(&mut self) -> () {
+        let Some(decay) = self.weight_average_decay else { return };
+
+        let mut averaged_weights = std::mem::take(&mut self.averaged_weights);
+        let parameters = self.collect_parameters_mut();
+
+        // layers were registered since averaging started
+        if parameters.len() != averaged_weights.len() {
+            averaged_weights = parameters.into_iter().map(|weight| *weight).collect();
+        } else {
+            for (weight, average) in parameters.into_iter().zip(averaged_weights.iter_mut()) {
+                *average = decay * *average + (1.0 - decay) * *weight;
+            }
+        }
+
+        self.averaged_weights = averaged_weights;
+    }
+
+    /// whether the output of every layer is kept through the backward pass, None when nothing is freed
+    ///
+    /// the outputs that later layers read directly and the outputs of stochastic layers are always kept
+    fn checkpoints(&self) -> Option<Vec<bool>> {
+        let segment = self.checkpoint_segment?;
+        if !self.training { return None };
+
+        let last = self.layers.len() - 1;
+
+        let mut checkpoints: Vec<_> = self.layers.iter().enumerate()
+            .map(|(i, (layer, _))| i == 0 || i == last || i.is_multiple_of(segment) || layer.is_stochastic())
+            .collect();
+
+        for (layer, _) in &self.layers {
+            if let Layer::Add(layer) = layer {
+                if let Some(checkpoint) = checkpoints.get_mut(layer.source) { *checkpoint = true };
+            }
+        }
+
+        Some(checkpoints)
+    }
+
+    /// seeds the random number generators of the layers, e.g. stochastic pooling or dropout, for reproducible training
+    pub fn set_seed(&mut self, seed: u64) -> () {
+        for (i, (layer, _)) in self.layers.iter_mut().enumerate() {
+            match layer {
+                Layer::Pooling(layer) => layer.set_seed(seed.wrapping_add(i as u64)),
+                Layer::SpatialDropout(layer) => layer.set_seed(seed.wrapping_add(i as u64)),
+
+                _ => (),
+            }
+        }
+    }
+
+    pub fn set_input(&mut self, input: &Vec<f32>) -> Result<(), Error> {
+        if self.layers.is_empty() { return Err(Error::IncompatibleLayers) };
+
+        let (Layer::Convolutional(layer), _) = &mut self.layers[0] else { return Err(Error::IncompatibleLayers) };
+
+        let (dimension_x, dimension_y, depth) = layer.dimension;
+        let expected = dimension_x * dimension_y * depth;
+        if input.len() != expected { return Err(Error::InputSizeMismatch { expected, provided: input.len() }) };
+
+        layer.set_volume(input)
+    }
+
+    pub fn forward_propagate(&mut self) -> Result<(), Error> {
+        // an input layer on its own has nothing to propagate into
+        if self.layers.len() < 2 { return Err(Error::IncompatibleLayers) };
+
+        let checkpoints = self.checkpoints();
+
+        for i in 0..self.layers.len() - 1 {
+            self.propagate_layer(i)?;
+
+            // the next layer was the only one reading this output
+            if let Some(checkpoints) = &checkpoints {
+                if !checkpoints[i] { self.layers[i].0.release_activations() };
+            }
+        };
+
+        Ok(())
+    }
+
+    /// propagates the output of layer `i` into layer `i + 1` and activates it
+    fn propagate_layer(&mut self, i: usize) -> Result<(), Error> {
+        let last = self.layers.len() - 1;
+        let scale_logits = !self.training && self.temperature != 1.0;
+
+        let (slice1, slice2) = self.layers.split_at_mut(i + 1);
+
+        // the output may have been freed by gradient checkpointing
+        slice2[0].0.restore_activations(slice2[0].1);
+        slice1[i].0.forward_propagate(&mut slice2[0].0)?;
+
+        match &mut slice2[0].0 {
+            Layer::FullyConnected(layer) if scale_logits && i + 1 == last => layer.divide_raw_values(self.temperature),
+
+            // residual connections can only refer to earlier layers
+            Layer::Add(layer) => {
+                let (source, _) = slice1.get(layer.source).ok_or(Error::IncompatibleLayers)?;
+                layer.add_source(source.get_output())?;
+            }
+
+            Layer::MaxUnpool(layer) => {
+                let Some((Layer::Pooling(source), _)) = slice1.get(layer.source) else { return Err(Error::IncompatibleLayers) };
+                layer.unpool(&source.switches)?;
+            }
+
+            _ => (),
+        }
+
+        slice2[0].0.activate(slice2[0].1);
+        Ok(())
+    }
+
+    pub fn back_propagate(&mut self, target_output: &Vec<f32>) -> Result<(), Error> {
+        if self.layers.len() < 2 { return Err(Error::IncompatibleLayers) };
+
+        let last = self.layers.len() - 1;
+        let (Layer::FullyConnected(_), _) = self.layers[last] else { return Err(Error::IncompatibleLayers) };
+
+        let fused = nn_error::has_fused_derivative(self.layers[last].1, self.error_function);
+
+        if let (Layer::FullyConnected(ref mut layer), _) = self.layers[last] {
+            if fused {
+                layer.calculate_fused_output_gradients(self.error_function, target_output, self.class_weights.as_deref())?;
+            } else {
+                layer.calculate_output_gradients(self.error_function, target_output, self.class_weights.as_deref(), self.error_epsilon)?;
+            }
+        }
+
+        self.propagate_gradients(fused)
+    }
+
+    /// propagates the output gradients of the last layer back to the input layer
+    #[allow(clippy::needless_range_loop)]
+    fn propagate_gradients(&mut self, fused: bool) -> Result<(), Error> {
+        let last = self.layers.len() - 1;
+        let checkpoints = self.checkpoints();
+
+        for i in (1..self.layers.len()).rev() {
+            if let Some(checkpoints) = &checkpoints {
+                // recomputes the segment ending at the previous layer from its checkpoint
+                if !checkpoints[i - 1] && self.layers[i - 1].0.get_output().is_empty() {
+                    let start = (0..i - 1).rev().find(|&j| checkpoints[j]).unwrap_or(0);
+
+                    for j in start..i - 1 {
+                        self.propagate_layer(j)?;
+                    }
+                }
+            }
+
+            let (slice1, slice2) = self.layers.split_at_mut(i);
+
+            // the fused output gradients already include the activation's derivative
+            if !(fused && i == last) { slice2[0].0.backward_activate(slice2[0].1) };
+            slice2[0].0.back_propagate(&mut slice1[i - 1].0)?;
+
+            // the later add layers have already back propagated, so their gradients are complete
+            for (layer, _) in slice2.iter() {
+                if let Layer::Add(layer) = layer {
+                    if layer.source != i - 1 { continue };
+
+                    let gradients = slice1[i - 1].0.output_gradients_mut();
+                    for j in 0..gradients.len() {
+                        gradients[j] += layer.back_activated_volume[j];
+                    }
+                }
+            }
+
+            if let Some(checkpoints) = &checkpoints {
+                if !checkpoints[i] { self.layers[i].0.release_activations() };
+            }
+        }
+
+        Ok(())
+    }
+
+    /// starts a new batch and resets gradients
+    pub fn start_batch(&mut self) -> () {
+        for (layer, _) in &mut self.layers {
+            layer.reset_gradients();
+        }
+    }
+
+    /// zeroes the momentum of every layer without changing the weights, e.g. when the learning rate schedule changes
+    pub fn reset_optimizer_state(&mut self) -> () {
+        for (layer, _) in &mut self.layers {
+            layer.reset_velocity();
+        }
+    }
+
+    /// resets the gradients of the layers in `start..end`
+    pub fn reset_gradients_range(&mut self, start: usize, end: usize) -> Result<(), Error> {
+        if start > end || end > self.layers.len() { return Err(Error::InvalidInput) };
+
+        for (layer, _) in &mut self.layers[start..end] {
+            layer.reset_gradients();
+        }
+
+        Ok(())
+    }
+
+    /// ends the batch and applies the gradients using the stored hyperparameters
+    pub fn end_batch(&mut self, sample_count: u8) -> () {
+        let Hyperparameters { learning_rate, momentum, weight_decay } = self.hyperparameters;
+
+        self.end_batch_with(sample_count, learning_rate, momentum, weight_decay);
+    }
+
+    /// ends the batch and applies the gradients using the given hyperparameters
+    pub fn end_batch_with(&mut self, sample_count: u8, learning_rate: f32, momentum: f32, weight_decay: f32) -> () {
+        let new_learning_rate = learning_rate / sample_count as f32;
+
+        for i in 1..self.layers.len() {
+            self.layers[i].0.apply_gradients(new_learning_rate, momentum, weight_decay);
+        }
+
+        self.update_averaged_weights();
+    }
+
+    /// ends the batch like `end_batch`, but decays the weights separately from the gradients as in AdamW
+    ///
+    /// the weights shrink by `learning_rate * weight_decay` once per batch, so the decay isn't accumulated in the momentum
+    pub fn end_batch_decoupled(&mut self, sample_count: u8) -> () {
+        let Hyperparameters { learning_rate, momentum, weight_decay } = self.hyperparameters;
+        let new_learning_rate = learning_rate / sample_count as f32;
+
+        for i in 1..self.layers.len() {
+            self.layers[i].0.apply_gradients_decoupled(new_learning_rate, momentum, learning_rate * weight_decay);
+        }
+
+        self.update_averaged_weights();
+    }
+
+    /// trains on a single sample as a batch of one, e.g. for online learning, and returns its error before the update
+    pub fn train_sample(&mut self, input: &[f32], target: &[f32], learning_rate: f32, momentum: f32, weight_decay: f32) -> Result<f32, Error> {
+        let (error, _) = self.accumulate_gradients(&[(input.to_vec(), target.to_vec())])?;
+        self.end_batch_with(1, learning_rate, momentum, weight_decay);
+
+        Ok(error)
+    }
+
+    /// accumulates the gradients of the triplet margin loss `max(0, |a - p| - |a - n| + margin)` between the outputs
+    /// of an anchor, a positive and a negative sample and returns the loss, e.g. to learn embeddings
+    ///
+    /// the error function isn't used. every sample is run forward again before its backward pass since the three passes share the layers,
+    /// and random layers are reseeded so both passes of a sample drop or pick the same values
+    pub fn back_propagate_triplet(&mut self, anchor: &[f32], positive: &[f32], negative: &[f32], margin: f32) -> Result<f32, Error> {
+        if self.layers.len() < 2 { return Err(Error::IncompatibleLayers) };
+        if margin.is_nan() || margin < 0.0 { return Err(Error::InvalidInput) };
+
+        let last = self.layers.len() - 1;
+        let (Layer::FullyConnected(_), _) = self.layers[last] else { return Err(Error::IncompatibleLayers) };
+
+        let samples = [anchor, positive, negative];
+        let mut embeddings = Vec::with_capacity(samples.len());
+
+        // networks without random layers don't use the seeds, spaced apart like the workers of `make_workers`
+        let base = self.layers.iter_mut().find_map(|(layer, _)| layer.next_seed()).unwrap_or_default();
+        let seeds: Vec<u64> = (0..samples.len() as u64).map(|i| base.wrapping_add(i << 32)).collect();
+
+        for (sample, &seed) in samples.into_iter().zip(&seeds) {
+            self.set_seed(seed);
+            self.set_input(&sample.to_vec())?;
+            self.forward_propagate()?;
+
+            embeddings.push(self.layers[last].0.get_output().clone());
+        }
+
+        let loss = nn_error::triplet_margin(&embeddings[0], &embeddings[1], &embeddings[2], margin);
+        if loss <= 0.0 { return Ok(loss) };
+
+        let (anchor_gradients, positive_gradients, negative_gradients) = nn_error::triplet_margin_derivative(&embeddings[0], &embeddings[1], &embeddings[2], margin);
+
+        for ((sample, gradients), &seed) in samples.into_iter().zip([anchor_gradients, positive_gradients, negative_gradients]).zip(&seeds) {
+            self.set_seed(seed);
+            self.set_input(&sample.to_vec())?;
+            self.forward_propagate()?;
+
+            self.layers[last].0.output_gradients_mut().copy_from_slice(&gradients);
+            self.propagate_gradients(false)?;
+        }
+
+        Ok(loss)
+    }
+
+    /// trains on a triplet as a batch of one with the stored hyperparameters and returns its loss before the update, see `back_propagate_triplet`
+    pub fn train_triplet(&mut self, triplet: (&[f32], &[f32], &[f32]), margin: f32) -> Result<f32, Error> {
+        self.start_batch();
+
+        let (anchor, positive, negative) = triplet;
+        let loss = self.back_propagate_triplet(anchor, positive, negative, margin)?;
+
+        // three passes contributed to the gradients, but they come from a single loss
+        self.end_batch(1);
+
+        Ok(loss)
+    }
+
+    /// trains on every sample of the dataset for the configured number of epochs and returns the average error of each epoch
+    pub fn train<D: Dataset>(&mut self, dataset: &D, config: &TrainingConfig) -> Result<Vec<f32>, Error> {
+        self.train_with_progress(dataset, config, |_| ())
+    }
+
+    /// trains like `train` and calls `progress` after every batch and epoch, e.g. to log the error
+    pub fn train_with_progress<D: Dataset, F: FnMut(&ProgressEvent)>(&mut self, dataset: &D, config: &TrainingConfig, progress: F) -> Result<Vec<f32>, Error> {
+        self.run_training(dataset, config, progress, |neural_network, batch| neural_network.accumulate_gradients(batch))
+    }
+
+    /// trains like `train`, but splits every batch across `config.num_threads` copies of the network
+    ///
+    /// the gradients of the copies are summed in a fixed order before they're applied, so the result only depends on the thread count
+    /// and the seeds, `config.seed` and `set_seed`, and one thread trains exactly like `train`
+    pub fn train_parallel<D: Dataset>(&mut self, dataset: &D, config: &TrainingConfig) -> Result<Vec<f32>, Error> {
+        self.train_parallel_with_progress(dataset, config, |_| ())
+    }
+
+    /// trains like `train_parallel` and calls `progress` after every batch and epoch
+    pub fn train_parallel_with_progress<D: Dataset, F: FnMut(&ProgressEvent)>(&mut self, dataset: &D, config: &TrainingConfig, progress: F) -> Result<Vec<f32>, Error> {
+        if config.num_threads == 0 { return Err(Error::InvalidInput) };
+
+        let mut workers = self.make_workers(config.num_threads);
+
+        self.run_training(dataset, config, progress, |neural_network, batch| neural_network.accumulate_gradients_parallel(&mut workers, batch))
+    }
+
+    /// copies of the network for `train_parallel`, which are allocated once and only have their weights refreshed every batch
+    ///
+    /// clones would share the state of the random number generators and drop the same channels for every chunk of a batch,
+    /// so every worker but the first, which continues like `train`, is reseeded from the network
+    pub(crate) fn make_workers(&mut self, count: usize) -> Vec<NeuralNetwork> {
+        let mut workers: Vec<NeuralNetwork> = (0..count).map(|_| self.clone()).collect();
+
+        // networks without random layers don't use the seed
+        let base = self.layers.iter_mut().find_map(|(layer, _)| layer.next_seed()).unwrap_or_default();
+
+        // `set_seed` adds the layer index, so the workers are spaced apart to not share a seed between layers
+        for (i, worker) in workers.iter_mut().enumerate().skip(1) {
+            worker.set_seed(base.wrapping_add((i as u64) << 32));
+        }
+
+        workers
+    }
+
+    /// runs the epochs of `train` with `accumulate` computing the gradients and the (summed error, correct count) of every batch
+    fn run_training<D, F, A>(&mut self, dataset: &D, config: &TrainingConfig, mut progress: F, mut accumulate: A) -> Result<Vec<f32>, Error>
+        where
+            D: Dataset,
+            F: FnMut(&ProgressEvent),
+            A: FnMut(&mut NeuralNetwork, &[(Vec<f32>, Vec<f32>)]) -> Result<(f32, usize), Error>,
+    {
+        if config.batch_size == 0 || config.batch_size > u8::MAX as usize || dataset.is_empty() { return Err(Error::InvalidInput) };
+        if config.checkpoint_path.is_some() && config.checkpoint_interval == 0 { return Err(Error::InvalidInput) };
+        if let Some(schedule) = &config.learning_rate_schedule { schedule.validate()? };
+
+        let start = Instant::now();
+
+        let mut rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        };
+        let mut completed_batches = 0;
+        let mut epoch_errors = Vec::with_capacity(config.epochs);
+
+        for epoch in 0..config.epochs {
+            let mut loader = DataLoader::new(dataset, config.batch_size)?;
+            if config.shuffle { loader.shuffle(&mut rng) };
+
+            let mut total_error = util::Accumulator::<f32>::default();
+            let mut total_correct = 0;
+
+            for batch in loader {
+                let batch = batch?;
+
+                let (error, correct) = accumulate(self, &batch)?;
+                total_error.add(error);
+                total_correct += correct;
+
+                completed_batches += 1;
+                self.finish_batch(config, batch.len(), completed_batches)?;
+
+                progress(&ProgressEvent {
+                    epoch,
+                    batch: Some(completed_batches - 1),
+                    error: error / batch.len() as f32,
+                    accuracy: correct as f32 / batch.len() as f32,
+                    elapsed: start.elapsed(),
+                });
+            }
+
+            let count = dataset.len() as f32;
+            epoch_errors.push(total_error.value() / count);
+
+            progress(&ProgressEvent {
+                epoch,
+                batch: None,
+                error: total_error.value() / count,
+                accuracy: total_correct as f32 / count,
+                elapsed: start.elapsed(),
+            });
+        }
+
+        Ok(epoch_errors)
+    }
+
+    /// resets the gradients, accumulates the gradients of every sample and returns the summed error and the number of correct outputs
+    fn accumulate_gradients(&mut self, samples: &[(Vec<f32>, Vec<f32>)]) -> Result<(f32, usize), Error> {
+        self.start_batch();
+
+        let mut total_error = util::Accumulator::<f32>::default();
+        let mut correct = 0;
+
+        for (input, target) in samples {
+            self.set_input(input)?;
+            self.forward_propagate()?;
+
+            total_error.add(self.get_error(target)?);
+            if is_correct(&self.get_output()?, target) { correct += 1 };
+
+            self.back_propagate(target)?;
+        }
+
+        Ok((total_error.value(), correct))
+    }
+
+    /// splits the samples across the workers and sums their gradients into this network's gradients
+    fn accumulate_gradients_parallel(&mut self, workers: &mut [NeuralNetwork], samples: &[(Vec<f32>, Vec<f32>)]) -> Result<(f32, usize), Error> {
+        let chunk_size = samples.len().div_ceil(workers.len());
+
+        // every worker writes into its own slot and the slots are summed in order,
+        // since summing in the order the workers finish makes the f32 result vary between runs
+        let mut results: Vec<_> = (0..workers.len()).map(|_| None).collect();
+
+        thread::scope(|scope| -> Result<(), Error> {
+            for ((samples, worker), slot) in samples.chunks(chunk_size).zip(workers.iter_mut()).zip(results.iter_mut()) {
+                worker.clone_weights_from(self)?;
+
+                scope.spawn(move || {
+                    *slot = Some(worker.accumulate_gradients(samples).map(|stats| (worker.collect_gradients(), stats)));
+                });
+            }
+
+            Ok(())
+        })?;
+
+        let mut combined: Vec<util::Accumulator<Gradient>> = Vec::new();
+        let mut total_error = util::Accumulator::<f32>::default();
+        let mut correct = 0;
+
+        for result in results.into_iter().flatten() {
+            let (gradients, (error, worker_correct)) = result?;
+
+            combined.resize(gradients.len(), Default::default());
+            for i in 0..gradients.len() {
+                combined[i].add(gradients[i]);
+            }
+
+            total_error.add(error);
+            correct += worker_correct;
+        }
+
+        for (gradient, value) in self.collect_gradients_mut().into_iter().zip(combined) {
+            *gradient = value.value();
+        }
+
+        Ok((total_error.value(), correct))
+    }
+
+    /// applies the accumulated gradients and saves a checkpoint when one is due
+    fn finish_batch(&mut self, config: &TrainingConfig, sample_count: usize, completed_batches: usize) -> Result<(), Error> {
+        if let Some(schedule) = &config.learning_rate_schedule {
+            self.hyperparameters.learning_rate = schedule.learning_rate(completed_batches - 1);
+        }
+
+        if config.decoupled_weight_decay {
+            self.end_batch_decoupled(sample_count as u8);
+        } else {
+            self.end_batch(sample_count as u8);
+        }
+
+        if let Some(path) = &config.checkpoint_path {
+            if completed_batches.is_multiple_of(config.checkpoint_interval) { self.save(path)? };
+        }
+
+        Ok(())
+    }
+
+    /// writes the network to `path` through a temporary file, so an interrupted save leaves the previous file intact
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        bincode::serde::encode_into_std_write(self, &mut writer, bincode::config::standard())?;
+        writer.flush()?;
+
+        let file = writer.into_inner().map_err(|error| error.into_error())?;
+        file.sync_all()?;
+
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    /// reads a network written by `save`
+    ///
+    /// the file is read before decoding, so a truncated file is a serialization error like any other corrupt model
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// decodes a network encoded with bincode's standard configuration
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let (neural_network, _) = bincode::serde::decode_from_slice(bytes, bincode::config::standard())?;
+        Ok(neural_network)
+    }
+
+    /// encodes only the learnable parameters as little endian f32s, without the architecture
+    ///
+    /// the parameters are ordered by layer as the kernel or weights, the biases and the PReLU slopes, or the scale gammas
+    pub fn export_weights(&self) -> Vec<u8> {
+        self.collect_parameters().iter().flat_map(|weight| weight.to_le_bytes()).collect()
+    }
+
+    /// loads parameters written by `export_weights` into a network with the same architecture
+    pub fn import_weights(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let mut parameters = self.collect_parameters_mut();
+        if bytes.len() != parameters.len() * 4 { return Err(Error::DimensionMismatch) };
+
+        for (weight, chunk) in parameters.iter_mut().zip(bytes.chunks_exact(4)) {
+            **weight = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+
+        Ok(())
+    }
+
+    /// stores the kernels and weights as int8, which is roughly a quarter of the size when serialized
+    pub fn quantize_int8(&self) -> QuantizedNetwork {
+        QuantizedNetwork::new(self)
+    }
+
+    pub fn get_error(&self, target_output: &Vec<f32>) -> Result<f32, Error> {
+        if self.layers.is_empty() { return Err(Error::IncompatibleLayers) };
+
+        let last = self.layers.len() - 1;
+        if let (Layer::FullyConnected(ref layer), _) = self.layers[last] {
+            return layer.get_error(self.error_function, target_output, self.class_weights.as_deref(), self.error_epsilon);
+        };
+
+        Err(Error::InvalidInput)
+    }
+
+    pub fn get_output(&self) -> Result<Vec<f32>, Error> {
+        if self.layers.is_empty() { return Err(Error::IncompatibleLayers) };
+
+        let last = self.layers.len() - 1;
+        if let (Layer::FullyConnected(ref layer), _) = self.layers[last] {
+            return Ok(layer.get_outputs());
+        };
+
+        Err(Error::InvalidInput)
+    }
+
+    /// runs a forward pass and returns the output together with its error
+    pub fn forward_and_error(&mut self, input: &[f32], target: &[f32]) -> Result<(Vec<f32>, f32), Error> {
+        self.set_input(&input.to_vec())?;
+        self.forward_propagate()?;
+
+        let (Layer::FullyConnected(layer), _) = &self.layers[self.layers.len() - 1] else { return Err(Error::InvalidInput) };
+
+        let error = layer.get_error(self.error_function, &target.to_vec(), self.class_weights.as_deref(), self.error_epsilon)?;
+        Ok((layer.get_outputs(), error))
+    }
+
+    /// runs every model on the input and returns the average of their outputs weighted by `weights`
+    ///
+    /// the weights don't have to sum to 1, they are normalized by their sum
+    pub fn ensemble_predict(models: &mut [NeuralNetwork], weights: &[f32], input: &[f32]) -> Result<Vec<f32>, Error> {
+        if models.is_empty() || weights.len() != models.len() { return Err(Error::InvalidInput) };
+        if weights.iter().any(|weight| *weight < 0.0 || weight.is_nan()) { return Err(Error::InvalidInput) };
+
+        let total_weight: f32 = weights.iter().sum();
+        if total_weight == 0.0 { return Err(Error::InvalidInput) };
+
+        let mut result: Vec<f32> = Vec::new();
+
+        for (model, weight) in models.iter_mut().zip(weights) {
+            model.set_input(&input.to_vec())?;
+            model.forward_propagate()?;
+            let output = model.get_output()?;
+
+            if result.is_empty() {
+                result = vec![0.0; output.len()];
+            } else if output.len() != result.len() {
+                return Err(Error::DimensionMismatch);
+            }
+
+            for i in 0..output.len() {
+                result[i] += weight * output[i];
+            }
+        }
+
+        for value in &mut result {
+            *value /= total_weight;
+        }
+
+        Ok(result)
+    }
+
+    /// runs every augmentation of the input through the network and returns the average of the outputs, i.e. test-time augmentation
+    pub fn predict_tta(&mut self, input: &[f32], dimension: (usize, usize, usize), augmentations: &[Augmentation]) -> Result<Vec<f32>, Error> {
+        if augmentations.is_empty() { return Err(Error::InvalidInput) };
+
+        let input = input.to_vec();
+        let mut result: Vec<f32> = Vec::new();
+
+        for augmentation in augmentations {
+            self.set_input(&augmentation.apply(&input, dimension)?)?;
+            self.forward_propagate()?;
+            let output = self.get_output()?;
+
+            if result.is_empty() { result = vec![0.0; output.len()] };
+
+            for i in 0..output.len() {
+                result[i] += output[i];
+            }
+        }
+
+        for value in &mut result {
+            *value /= augmentations.len() as f32;
+        }
+
+        Ok(result)
+    }
+
+    /// returns the average error and the accuracy over a dataset
+    ///
+    /// accuracy compares the argmax of the output and target, or thresholds at 0.5 for a single output
+    pub fn evaluate(&mut self, inputs: &[Vec<f32>], targets: &[Vec<f32>]) -> Result<(f32, f32), Error> {
+        if inputs.len() != targets.len() || inputs.is_empty() { return Err(Error::InvalidInput) };
+
+        let mut total_error = util::Accumulator::<f32>::default();
+        let mut correct = 0;
+
+        for i in 0..inputs.len() {
+            self.set_input(&inputs[i])?;
+            self.forward_propagate()?;
+
+            total_error.add(self.get_error(&targets[i])?);
+            if is_correct(&self.get_output()?, &targets[i]) { correct += 1 };
+        }
+
+        let count = inputs.len() as f32;
+        Ok((total_error.value() / count, correct as f32 / count))
+    }
+
+    /// returns the average error over a batch like `evaluate`, without computing the accuracy
+    pub fn batch_loss(&mut self, inputs: &[Vec<f32>], targets: &[Vec<f32>]) -> Result<f32, Error> {
+        if inputs.len() != targets.len() || inputs.is_empty() { return Err(Error::InvalidInput) };
+
+        let mut total_error = util::Accumulator::<f32>::default();
+
+        for i in 0..inputs.len() {
+            self.set_input(&inputs[i])?;
+            self.forward_propagate()?;
+
+            total_error.add(self.get_error(&targets[i])?);
+        }
+
+        Ok(total_error.value() / inputs.len() as f32)
+    }
+
+    pub fn initialize(&mut self, layer_index: usize, initialization_function: Initialization) -> Result<(), Error> {
+        if layer_index >= self.layers.len() { return Err(Error::InvalidInput) };
+
+        self.layers[layer_index].0.initialize(initialization_function);
+        Ok(())
+    }
+
+    /// initializes every learnable layer that hasn't been initialized yet, layers initialized with `initialize` are kept
+    pub fn initialize_all(&mut self, default: Initialization) -> () {
+        for (layer, _) in &mut self.layers {
+            if !layer.is_initialized() {
+                layer.initialize(default);
+            }
+        }
+    }
+
+    /// compares the layer types and shapes of both networks, but not their weights or activations
+    pub fn same_architecture(&self, other: &NeuralNetwork) -> bool {
+        self.layers.len() == other.layers.len() &&
+            self.layers.iter().zip(&other.layers).all(|((layer, _), (other_layer, _))| layer.same_structure(other_layer))
+    }
+
+    /// copies the weights and biases of a network with the same architecture without reallocating
+    pub fn clone_weights_from(&mut self, source: &NeuralNetwork) -> Result<(), Error> {
+        // check every layer first so a mismatch doesn't leave the network partially copied
+        if !self.same_architecture(source) { return Err(Error::IncompatibleLayers) };
+
+        for ((layer, _), (source_layer, _)) in self.layers.iter_mut().zip(&source.layers) {
+            layer.copy_weights_from(source_layer)?;
+        }
+
+        Ok(())
+    }
+
+    /// the L2 norm of the difference between every learnable parameter of both networks, e.g. to check how far fine-tuning moved a model
+    pub fn weight_diff(&self, other: &NeuralNetwork) -> Result<f32, Error> {
+        if !self.same_architecture(other) { return Err(Error::IncompatibleLayers) };
+
+        let parameters = self.collect_parameters();
+        let other_parameters = other.collect_parameters();
+
+        // only one of the networks may use PReLU
+        if parameters.len() != other_parameters.len() { return Err(Error::IncompatibleLayers) };
+
+        let squared: f32 = parameters.iter().zip(&other_parameters).map(|(a, b)| (a - b) * (a - b)).sum();
+        Ok(squared.sqrt())
+    }
+
+    /// copies the weights of every layer whose structure matches the layer at the same index of `source`, e.g. a pretrained backbone
+    ///
+    /// mismatched layers like a resized head are left as they are, returns the indices of the copied layers
+    pub fn load_matching_layers(&mut self, source: &NeuralNetwork) -> Vec<usize> {
+        let mut copied = Vec::new();
+
+        for (i, ((layer, _), (source_layer, _))) in self.layers.iter_mut().zip(&source.layers).enumerate() {
+            // the structure is checked before anything is copied
+            if layer.copy_weights_from(source_layer).is_ok() {
+                copied.push(i);
+            }
+        }
+
+        copied
+    }
+
+    pub fn register_layer(&mut self, activation_function: ActivationFunction, mut layer: Layer) -> () {
+        layer.configure_activation(activation_function);
+        layer.set_training(self.training);
+        self.layers.push((layer, activation_function));
+    }
+
+    /// registers the first layer, which only holds the input and isn't activated
+    pub fn register_input_layer(&mut self, layer: Layer) -> Result<(), Error> {
+        if !self.layers.is_empty() { return Err(Error::IncompatibleLayers) };
+        let Layer::Convolutional(ref input_layer) = layer else { return Err(Error::IncompatibleLayers) };
+        if !input_layer.is_input_layer() { return Err(Error::IncompatibleLayers) };
+
+        self.register_layer(ActivationFunction::None, layer);
+        Ok(())
+    }
+
+    /// checks that the network ends in a fully connected layer whose activation suits the error function
+    ///
+    /// probability losses like binary cross entropy produce meaningless errors for unbounded outputs
+    /// and a learnable layer left with all-zero weights never learns
+    pub fn validate(&self) -> Result<(), Error> {
+        let Some((Layer::FullyConnected(_), activation_function)) = self.layers.last() else { return Err(Error::IncompatibleLayers) };
+
+        if nn_error::expects_probabilities(self.error_function) && !activations::is_bounded_to_unit_interval(*activation_function) {
+            return Err(Error::IncompatibleErrorFunction);
+        }
+
+        if let Some(layer_index) = self.layers.iter().position(|(layer, _)| !layer.is_initialized()) {
+            return Err(Error::UninitializedLayer(layer_index));
+        }
+
+        Ok(())
+    }
+
+    /// checks every layer against the output of the layer before it and returns all the incompatible ones
+    ///
+    /// the same checks the forward pass makes, but without running it and without stopping at the first error
+    pub fn validate_dimensions(&self) -> Result<(), Vec<(usize, Error)>> {
+        let errors: Vec<(usize, Error)> = (1..self.layers.len())
+            .filter_map(|i| self.check_adjacent(i).err().map(|error| (i, error)))
+            .collect();
+
+        if !errors.is_empty() { return Err(errors) };
+
+        Ok(())
+    }
+
+    /// checks that layer `i` accepts the output of layer `i - 1`
+    fn check_adjacent(&self, i: usize) -> Result<(), Error> {
+        let (layer, _) = &self.layers[i];
+        let previous = &self.layers[i - 1].0;
+
+        let size = layer.output_dimension().size();
+        let previous_size = previous.output_dimension().size();
+
+        match (layer, previous.output_dimension()) {
+            (Layer::Convolutional(layer), LayerShape::Volume(x, y, z)) => {
+                util::check_output_dimension((x, y, z),
+                    layer.dimension,
+                    previous.zero_padding(),
+                    layer.num_kernels,
+                    layer.kernel_size,
+                    layer.stride,
+                    layer.rounding
+                )?;
+            }
+
+            (Layer::Pooling(layer), LayerShape::Volume(x, y, z)) => {
+                util::check_output_dimension((x, y, z),
+                    layer.dimension,
+                    0, // a pooling layer doesn't take padding into account
+                    layer.dimension.2,
+                    layer.kernel_size,
+                    layer.stride,
+                    layer.rounding
+                )?;
+            }
+
+            (Layer::SeparableConvolutional(layer), LayerShape::Volume(x, y, z)) => layer.check_input((x, y, z), previous.zero_padding())?,
+
+            (Layer::FullyConnected(layer), _) => {
+                if previous_size != layer.num_inputs { return Err(Error::DimensionMismatch) };
+            }
+
+            // a volume has to match exactly while a flat output only has to have the same size
+            (Layer::Add(_) | Layer::Scale(_), shape @ LayerShape::Volume(..)) if shape != layer.output_dimension() => return Err(Error::DimensionMismatch),
+
+            (Layer::Add(layer), _) => {
+                let (source, _) = self.layers[..i].get(layer.source).ok_or(Error::IncompatibleLayers)?;
+                if previous_size != size || source.output_dimension().size() != size { return Err(Error::DimensionMismatch) };
+            }
+
+            (Layer::MaxUnpool(layer), _) => {
+                let Some((source @ Layer::Pooling(_), _)) = self.layers[..i].get(layer.source) else { return Err(Error::IncompatibleLayers) };
+                if previous_size != source.output_dimension().size() { return Err(Error::DimensionMismatch) };
+            }
+
+            (Layer::SpatialDropout(_) | Layer::Scale(_), _) => {
+                if previous_size != size { return Err(Error::DimensionMismatch) };
+            }
+
+            _ => return Err(Error::IncompatibleLayers),
+        }
+
+        Ok(())
+    }
+
+    pub fn get_activation(&self, layer_index: usize) -> Option<ActivationFunction> {
+        self.layers.get(layer_index).map(|(_, activation_function)| *activation_function)
+    }
+
+    /// replaces the activation function of a layer, e.g. to swap the head of a loaded model
+    pub fn set_activation(&mut self, layer_index: usize, activation_function: ActivationFunction) -> Result<(), Error> {
+        if layer_index >= self.layers.len() { return Err(Error::InvalidInput) };
+
+        let (layer, current) = &mut self.layers[layer_index];
+        layer.configure_activation(activation_function);
+        *current = activation_function;
+
+        Ok(())
+    }
+
+    /// registers a convolutional layer whose output dimension and input depth are inferred from the last layer
+    pub fn register_conv(&mut self, activation_function: ActivationFunction, zero_padding: usize, stride: usize, kernel_size: usize, num_kernels: usize) -> Result<(), Error> {
+        let (previous_dimension, previous_padding) = self.last_volume()?;
+
+        let dimension = util::get_output_dimension(previous_dimension, previous_padding, num_kernels, kernel_size, (stride, stride))
+            .ok_or(Error::ImpossibleOutputDimension)?;
+
+        self.register_layer(activation_function, Layer::make_convolutional_layer(zero_padding, stride, kernel_size, dimension, previous_dimension.2));
+        Ok(())
+    }
+
+    /// registers a convolutional layer with a stride of 1 whose output has the same width and height as the last layer
+    ///
+    /// a layer's padding is seen by the next layer, so the padding of the last layer is set to `util::same_padding(kernel_size)`.
+    /// a different padding the last layer already has isn't replaced and returns `Error::IncompatibleLayers`.
+    /// `zero_padding` is the padding of the new layer's output like in `register_conv`, not the same padding
+    ///
+    /// an even kernel can't be centered, so its output is 1 smaller, see `util::same_padding`
+    pub fn register_conv_same(&mut self, activation_function: ActivationFunction, zero_padding: usize, kernel_size: usize, num_kernels: usize) -> Result<(), Error> {
+        let (previous_dimension, previous_padding) = self.last_volume()?;
+        let padding = util::same_padding(kernel_size);
+
+        if previous_padding != 0 && previous_padding != padding { return Err(Error::IncompatibleLayers) };
+
+        // checked before the last layer is changed
+        util::get_output_dimension(previous_dimension, padding, num_kernels, kernel_size, (1, 1)).ok_or(Error::ImpossibleOutputDimension)?;
+
+        let last = self.layers.len() - 1;
+        self.layers[last].0.set_zero_padding(padding);
+
+        self.register_conv(activation_function, zero_padding, 1, kernel_size, num_kernels)
+    }
+
+    /// registers a depthwise separable convolutional layer whose output dimension and input depth are inferred from the last layer
+    pub fn register_separable_conv(&mut self, activation_function: ActivationFunction, zero_padding: usize, stride: usize, kernel_size: usize, num_kernels: usize) -> Result<(), Error> {
+        let (previous_dimension, previous_padding) = self.last_volume()?;
+
+        let dimension = util::get_output_dimension(previous_dimension, previous_padding, num_kernels, kernel_size, (stride, stride))
+            .ok_or(Error::ImpossibleOutputDimension)?;
+
+        self.register_layer(activation_function, Layer::make_separable_convolutional_layer(zero_padding, (stride, stride), kernel_size, dimension, previous_dimension.2));
+        Ok(())
+    }
+
+    /// registers a pooling layer whose output dimension is inferred from the last layer
+    pub fn register_pooling(&mut self, activation_function: ActivationFunction, pooling_type: PoolingType, zero_padding: usize, stride: usize, kernel_size: usize) -> Result<(), Error> {
+        let (previous_dimension, _) = self.last_volume()?;
+
+        // a pooling layer doesn't take padding into account
+        let dimension = util::get_output_dimension(previous_dimension, 0, previous_dimension.2, kernel_size, (stride, stride))
+            .ok_or(Error::ImpossibleOutputDimension)?;
+
+        self.register_layer(activation_function, Layer::make_pooling_layer(pooling_type, zero_padding, stride, kernel_size, dimension));
+        Ok(())
+    }
+
+    /// registers a fully connected layer whose number of inputs is inferred from the last layer
+    pub fn register_fully_connected(&mut self, activation_function: ActivationFunction, num_neurons: usize) -> Result<(), Error> {
+        let (previous_layer, _) = self.layers.last().ok_or(Error::IncompatibleLayers)?;
+        if num_neurons == 0 { return Err(Error::ImpossibleOutputDimension) };
+
+        let num_inputs = previous_layer.output_dimension().size();
+
+        self.register_layer(activation_function, Layer::make_fully_connected_layer(num_inputs, num_neurons));
+        Ok(())
+    }
+
+    /// registers an add layer that sums the output of the last layer with the output of the layer at `source`
+    pub fn register_add(&mut self, activation_function: ActivationFunction, source: usize, zero_padding: usize) -> Result<(), Error> {
+        let (previous_layer, _) = self.layers.last().ok_or(Error::IncompatibleLayers)?;
+        let (source_layer, _) = self.layers.get(source).ok_or(Error::InvalidInput)?;
+
+        // the source has to come before the layer whose output it is added to
+        if source + 1 == self.layers.len() { return Err(Error::InvalidInput) };
+        if source_layer.output_dimension() != previous_layer.output_dimension() { return Err(Error::DimensionMismatch) };
+
+        let dimension = match previous_layer.output_dimension() {
+            LayerShape::Volume(x, y, z) => (x, y, z),
+            LayerShape::Flat(size) => (1, 1, size),
+        };
+
+        self.register_layer(activation_function, Layer::make_add_layer(source, zero_padding, dimension));
+        Ok(())
+    }
+
+    /// registers a layer that places the output of the last layer back at the maxima found by the max pooling layer at `source`
+    pub fn register_max_unpool(&mut self, source: usize, zero_padding: usize) -> Result<(), Error> {
+        let (previous_layer, _) = self.layers.last().ok_or(Error::IncompatibleLayers)?;
+        if source == 0 || source >= self.layers.len() { return Err(Error::InvalidInput) };
+
+        let (Layer::Pooling(pooling_layer), _) = &self.layers[source] else { return Err(Error::IncompatibleLayers) };
+        if pooling_layer.get_pooling_type() != PoolingType::Max { return Err(Error::IncompatibleLayers) };
+        if previous_layer.output_dimension() != self.layers[source].0.output_dimension() { return Err(Error::DimensionMismatch) };
+
+        let LayerShape::Volume(x, y, z) = self.layers[source - 1].0.output_dimension() else { return Err(Error::IncompatibleLayers) };
+
+        self.register_layer(ActivationFunction::None, Layer::make_max_unpool_layer(source, zero_padding, (x, y, z)));
+        Ok(())
+    }
+
+    /// registers a layer that drops whole channels of the last layer's output with the given probability while training
+    pub fn register_spatial_dropout(&mut self, probability: f32, zero_padding: usize) -> Result<(), Error> {
+        if !(0.0..1.0).contains(&probability) { return Err(Error::InvalidInput) };
+
+        let (dimension, _) = self.last_volume()?;
+
+        self.register_layer(ActivationFunction::None, Layer::make_spatial_dropout_layer(probability, zero_padding, dimension));
+        Ok(())
+    }
+
+    /// registers a layer that multiplies every channel of the last layer's output by a learnable gamma before the activation
+    pub fn register_scale(&mut self, activation_function: ActivationFunction, zero_padding: usize) -> Result<(), Error> {
+        let (dimension, _) = self.last_volume()?;
+
+        self.register_layer(activation_function, Layer::make_scale_layer(zero_padding, dimension));
+        Ok(())
+    }
+
+    /// the output dimension and zero padding of the last layer if it produces a volume
+    fn last_volume(&self) -> Result<((usize, usize, usize), usize), Error> {
+        let (previous_layer, _) = self.layers.last().ok_or(Error::IncompatibleLayers)?;
+
+        match previous_layer.output_dimension() {
+            LayerShape::Volume(x, y, z) => Ok(((x, y, z), previous_layer.zero_padding())),
+            LayerShape::Flat(_) => Err(Error::IncompatibleLayers),
+        }
+    }
+
+    /// returns the (width, height) of the input region that influences a single value of a layer
+    pub fn receptive_field(&self, layer_index: usize) -> Result<(usize, usize), Error> {
+        if layer_index >= self.layers.len() { return Err(Error::InvalidInput) };
+
+        let mut field = (1, 1);
+        let mut jump = (1, 1);
+
+        for (layer, _) in &self.layers[1..=layer_index] {
+            let (kernel_size, stride) = match layer {
+                Layer::Convolutional(layer) => (layer.kernel_size, layer.stride),
+                Layer::Pooling(layer) => (layer.kernel_size, layer.stride),
+                Layer::SeparableConvolutional(layer) => (layer.kernel_size, layer.stride),
+
+                // the main path sees at least as much as the skipped one
+                Layer::Add(_) => (1, (1, 1)),
+                // every value comes from a single pooled value
+                Layer::MaxUnpool(_) => (1, (1, 1)),
+                Layer::SpatialDropout(_) => (1, (1, 1)),
+                Layer::Scale(_) => (1, (1, 1)),
+
+                // every output of a fully connected layer sees the whole input
+                Layer::FullyConnected(_) => {
+                    return match self.layers[0].0.output_dimension() {
+                        LayerShape::Volume(x, y, _) => Ok((x, y)),
+                        LayerShape::Flat(size) => Ok((size, 1)),
+                    };
+                }
+            };
+
+            field.0 += (kernel_size - 1) * jump.0;
+            field.1 += (kernel_size - 1) * jump.1;
+
+            jump.0 *= stride.0;
+            jump.1 *= stride.1;
+        }
+
+        Ok(field)
+    }
+
+    /// buckets the weights of a learnable layer, both kernels of a separable convolutional layer together, see `util::histogram`
+    pub fn weight_histogram(&self, layer_index: usize, bins: usize) -> Result<Vec<(f32, usize)>, Error> {
+        let layer = self.get_layer(layer_index).ok_or(Error::InvalidInput)?;
+
+        let tensors = layer.weight_tensors();
+        if tensors.is_empty() { return Err(Error::IncompatibleLayers) };
+
+        let weights: Vec<f32> = tensors.into_iter().flatten().copied().collect();
+        util::histogram(&weights, bins)
+    }
+
+    /// buckets the weight gradients of a learnable layer like `weight_histogram`, see `util::histogram`
+    pub fn gradient_histogram(&self, layer_index: usize, bins: usize) -> Result<Vec<(f32, usize)>, Error> {
+        let layer = self.get_layer(layer_index).ok_or(Error::InvalidInput)?;
+
+        let tensors = layer.weight_gradient_tensors();
+        if tensors.is_empty() { return Err(Error::IncompatibleLayers) };
+
+        let gradients: Vec<f32> = tensors.into_iter().flatten().map(|gradient| util::from_gradient(*gradient)).collect();
+
+        util::histogram(&gradients, bins)
     }
 
-    pub fn set_input(&mut self, input: &Vec<f32>) -> Result<(), Error> {
-        if self.layers.len() == 0 { return Err(Error::IncompatibleLayers) };
+    /// the total number of learnable weights and biases
+    pub fn num_parameters(&self) -> usize {
+        self.layers.iter().map(|(layer, _)| layer.num_parameters()).sum()
+    }
+
+    /// the number of multiply-adds of a single forward pass
+    pub fn flops_per_forward(&self) -> usize {
+        self.layers.iter().map(|(layer, _)| layer.flops()).sum()
+    }
+
+    /// the bytes of the activation buffers one forward and backward pass needs per sample, see `Layer::activation_memory`
+    ///
+    /// dividing a memory budget by it gives a rough upper bound on the batch size, the parameters and their gradients are extra
+    pub fn estimate_memory_per_sample(&self) -> usize {
+        self.layers.iter().map(|(layer, activation_function)| layer.activation_memory(*activation_function)).sum()
+    }
+
+    /// describes the layers as a Graphviz DOT graph, e.g. for `dot -Tsvg`
+    ///
+    /// every node shows a layer's type, output shape and activation, residual connections and
+    /// max unpooling switches are drawn as dashed edges from their source
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph NeuralNetwork {\n    node [shape=box];\n");
+
+        for (i, (layer, activation_function)) in self.layers.iter().enumerate() {
+            let name = match layer {
+                Layer::Convolutional(layer) if layer.is_input_layer() => "Input",
+                Layer::Convolutional(_) => "Convolutional",
+                Layer::Pooling(_) => "Pooling",
+                Layer::FullyConnected(_) => "FullyConnected",
+                Layer::Add(_) => "Add",
+                Layer::MaxUnpool(_) => "MaxUnpool",
+                Layer::SpatialDropout(_) => "SpatialDropout",
+                Layer::Scale(_) => "Scale",
+                Layer::SeparableConvolutional(_) => "SeparableConvolutional",
+            };
+
+            let shape = match layer.output_dimension() {
+                LayerShape::Volume(x, y, z) => format!("{}x{}x{}", x, y, z),
+                LayerShape::Flat(size) => size.to_string(),
+            };
 
-        match &mut self.layers[0] {
-            (Layer::Convolutional(layer), _) => layer.set_volume(input),
-            _ => Err(Error::IncompatibleLayers),
+            dot.push_str(&format!("    layer{} [label=\"{}: {}\\n{}\\n{:?}\"];\n", i, i, name, shape, activation_function));
+
+            if i > 0 { dot.push_str(&format!("    layer{} -> layer{};\n", i - 1, i)) };
+
+            match layer {
+                Layer::Add(layer) => dot.push_str(&format!("    layer{} -> layer{} [style=dashed];\n", layer.source, i)),
+                Layer::MaxUnpool(layer) => dot.push_str(&format!("    layer{} -> layer{} [style=dashed];\n", layer.source, i)),
+
+                _ => (),
+            }
         }
+
+        dot.push_str("}\n");
+        dot
     }
 
-    pub fn forward_propagate(&mut self) -> Result<(), Error> {
-        for i in 0..(self.layers.len() - 1) {
-            let (slice1, slice2) = self.layers.split_at_mut(i + 1);
+    /// runs a forward pass and returns the activated output of every layer in order
+    pub fn forward_with_activations(&mut self, input: &[f32]) -> Result<Vec<Vec<f32>>, Error> {
+        self.set_input(&input.to_vec())?;
+        self.forward_propagate()?;
 
-            slice1[i].0.forward_propagate(&mut slice2[0].0)?;
-            slice2[0].0.activate(slice2[0].1);
-        };
+        Ok(self.layers.iter().map(|(layer, _)| layer.get_output().clone()).collect())
+    }
 
-        Ok(())
+    /// runs a forward and backward pass and returns the gradient of the error with respect to the input, i.e. a saliency map
+    ///
+    /// the weight gradients accumulate like in `back_propagate`, so start a new batch before training
+    pub fn input_gradient(&mut self, input: &[f32], target: &[f32]) -> Result<Vec<f32>, Error> {
+        self.set_input(&input.to_vec())?;
+        self.forward_propagate()?;
+        self.back_propagate(&target.to_vec())?;
+
+        Ok(self.layers[0].0.output_gradients_mut().clone())
     }
 
-    pub fn back_propagate(&mut self, target_output: &Vec<f32>) -> Result<(), Error> {
+    /// returns the gradient of every output with respect to the input, one row per output
+    ///
+    /// backpropagates a unit vector for every output, so it costs a backward pass per output.
+    /// the weight gradients accumulate like in `back_propagate`, so start a new batch before training
+    pub fn jacobian(&mut self, input: &[f32]) -> Result<Vec<Vec<f32>>, Error> {
+        if self.layers.len() < 2 { return Err(Error::IncompatibleLayers) };
+
         let last = self.layers.len() - 1;
         let (Layer::FullyConnected(_), _) = self.layers[last] else { return Err(Error::IncompatibleLayers) };
 
-        if let (Layer::FullyConnected(ref mut layer), _) = self.layers[last] {
-            layer.calculate_output_gradients(self.error_function, target_output)?;
-        }
+        self.set_input(&input.to_vec())?;
+        self.forward_propagate()?;
 
-        for i in (1..self.layers.len()).rev() {
-            let (slice1, slice2) = self.layers.split_at_mut(i);
+        let num_outputs = self.layers[last].0.get_output().len();
 
-            slice2[0].0.backward_activate(slice2[0].1);
-            slice2[0].0.back_propagate(&mut slice1[i - 1].0)?;
-        }
+        (0..num_outputs).map(|output| {
+            let gradients = self.layers[last].0.output_gradients_mut();
+            gradients.fill(0.0);
+            gradients[output] = 1.0;
 
+            self.propagate_gradients(false)?;
+            Ok(self.layers[0].0.output_gradients_mut().clone())
+        }).collect()
+    }
+
+    /// keeps the first `keep` layers, e.g. to reuse a trained network without its head as a feature extractor
+    pub fn truncate(&mut self, keep: usize) -> Result<(), Error> {
+        if keep == 0 || keep > self.layers.len() { return Err(Error::InvalidInput) };
+
+        self.layers.truncate(keep);
         Ok(())
     }
 
-    /// starts a new batch and resets gradients
-    pub fn start_batch(&mut self) -> () {
+    /// runs a forward pass and returns the flattened output of the last layer
+    pub fn extract_features(&mut self, input: &[f32]) -> Result<Vec<f32>, Error> {
+        self.set_input(&input.to_vec())?;
+        if self.layers.len() > 1 { self.forward_propagate()? };
+
+        let (layer, _) = self.layers.last().ok_or(Error::IncompatibleLayers)?;
+        Ok(layer.get_output().clone())
+    }
+
+    pub fn num_layers(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn get_layer(&self, layer_index: usize) -> Option<&Layer> {
+        self.layers.get(layer_index).map(|(layer, _)| layer)
+    }
+
+    /// multiplies every gradient by `factor`
+    pub fn scale_gradients(&mut self, factor: f32) -> () {
+        let factor = factor as Gradient;
+
         for (layer, _) in &mut self.layers {
-            layer.reset_gradients();
+            match layer {
+                Layer::Convolutional(layer) => {
+                    layer.kernel_gradients.iter_mut().for_each(|gradient| *gradient *= factor);
+                    layer.bias_gradients.iter_mut().for_each(|gradient| *gradient *= factor);
+                    layer.prelu.gradients.iter_mut().for_each(|gradient| *gradient *= factor);
+                },
+
+                Layer::FullyConnected(layer) => {
+                    layer.weight_gradients.iter_mut().for_each(|gradient| *gradient *= factor);
+                    layer.bias_gradients.iter_mut().for_each(|gradient| *gradient *= factor);
+                    layer.prelu.gradients.iter_mut().for_each(|gradient| *gradient *= factor);
+                }
+
+                Layer::Scale(layer) => layer.gamma_gradients.iter_mut().for_each(|gradient| *gradient *= factor),
+
+                Layer::SeparableConvolutional(layer) => {
+                    layer.depthwise_gradients.iter_mut().for_each(|gradient| *gradient *= factor);
+                    layer.pointwise_gradients.iter_mut().for_each(|gradient| *gradient *= factor);
+                    layer.bias_gradients.iter_mut().for_each(|gradient| *gradient *= factor);
+                    layer.prelu.gradients.iter_mut().for_each(|gradient| *gradient *= factor);
+                }
+
+                _ => (),
+            }
         }
     }
 
-    /// ends the batch and applies the gradients
-    pub fn end_batch(&mut self, sample_count: u8, learning_rate: f32, momentum: f32, weight_decay: f32) -> () {
-        let new_learning_rate = learning_rate / sample_count as f32;
+    /// subtracts the mean of the weight gradients of every convolutional kernel and fully connected neuron from them, see gradient centralization
+    ///
+    /// call it after back propagating and before applying the gradients. biases, PReLU slopes and scale gammas are left as they are
+    pub fn centralize_gradients(&mut self) -> () {
+        for (layer, _) in &mut self.layers {
+            match layer {
+                Layer::Convolutional(layer) => {
+                    let kernel_size = layer.kernel_gradients.len() / layer.num_kernels;
+                    centralize(&mut layer.kernel_gradients, kernel_size);
+                }
 
-        for i in 1..self.layers.len() {
-            self.layers[i].0.apply_gradients(new_learning_rate, momentum, weight_decay);
+                Layer::FullyConnected(layer) => centralize(&mut layer.weight_gradients, layer.num_inputs),
+
+                Layer::SeparableConvolutional(layer) => {
+                    centralize(&mut layer.depthwise_gradients, layer.kernel_size * layer.kernel_size);
+                    centralize(&mut layer.pointwise_gradients, layer.input_depth);
+                }
+
+                _ => (),
+            }
         }
     }
 
-    pub fn get_error(&self, target_output: &Vec<f32>) -> Result<f32, Error> {
-        let last = self.layers.len() - 1;
-        if let (Layer::FullyConnected(ref layer), _) = self.layers[last] {
-            return layer.get_error(self.error_function, target_output);
-        };
+    /// returns the L2 norm of the weight, bias and PReLU slope gradients of every layer, 0 for layers without parameters
+    pub fn gradient_norms(&self) -> Vec<f32> {
+        self.layers.iter().map(|(layer, _)| {
+            let (weight_gradients, bias_gradients, slope_gradients) = match layer {
+                Layer::Convolutional(layer) => (&layer.kernel_gradients, &layer.bias_gradients, &layer.prelu.gradients),
+                Layer::FullyConnected(layer) => (&layer.weight_gradients, &layer.bias_gradients, &layer.prelu.gradients),
+                Layer::Scale(layer) => (&layer.gamma_gradients, &Vec::new(), &Vec::new()),
+                Layer::SeparableConvolutional(layer) => (&layer.pointwise_gradients, &layer.bias_gradients, &layer.prelu.gradients),
 
-        Err(Error::InvalidInput)
+                _ => return 0.0,
+            };
+
+            // the depthwise kernel is a second set of weights
+            let depthwise_gradients = match layer {
+                Layer::SeparableConvolutional(layer) => &layer.depthwise_gradients,
+
+                _ => &Vec::new(),
+            };
+
+            util::from_gradient(weight_gradients.iter().chain(depthwise_gradients).chain(bias_gradients).chain(slope_gradients).map(|gradient| gradient * gradient).sum::<Gradient>().sqrt())
+        }).collect()
     }
 
-    pub fn get_output(&self) -> Result<Vec<f32>, Error> {
-        let last = self.layers.len() - 1;
-        if let (Layer::FullyConnected(ref layer), _) = self.layers[last] {
-            return Ok(layer.get_outputs());
-        };
+    /// returns the fraction of every layer's outputs that were exactly 0 after the last forward pass, e.g. after a ReLU
+    ///
+    /// layers without outputs, like ones freed by gradient checkpointing, count as 0
+    pub fn activation_sparsity(&self) -> Vec<f32> {
+        self.layers.iter().map(|(layer, _)| {
+            let output = layer.get_output();
+            if output.is_empty() { return 0.0 };
 
-        Err(Error::InvalidInput)
+            output.iter().filter(|value| **value == 0.0).count() as f32 / output.len() as f32
+        }).collect()
     }
 
-    pub fn initialize(&mut self, layer_index: usize, initialization_function: Initialization) -> Result<(), Error> {
-        if layer_index >= self.layers.len() { return Err(Error::InvalidInput) };
+    /// the kernels and weights of the convolutional, separable convolutional and fully connected layers in order
+    pub(crate) fn weights_mut(&mut self) -> Vec<&mut Vec<f32>> {
+        self.layers.iter_mut().flat_map(|(layer, _)| layer.weights_mut()).collect()
+    }
 
-        self.layers[layer_index].0.initialize(initialization_function);
-        Ok(())
+    /// returns the kernels of a convolutional layer whose largest output of the last forward pass is below `threshold`
+    ///
+    /// e.g. kernels that a ReLU never activates, which are candidates for pruning or reinitialization
+    pub fn dead_filters(&self, layer_index: usize, threshold: f32) -> Result<Vec<usize>, Error> {
+        let Some((Layer::Convolutional(layer), _)) = self.layers.get(layer_index) else { return Err(Error::InvalidInput) };
+        if layer.is_input_layer() { return Err(Error::IncompatibleLayers) };
+
+        // the output may have been freed by gradient checkpointing
+        if layer.volume.is_empty() { return Err(Error::InvalidInput) };
+
+        let mut maxima = vec![f32::NEG_INFINITY; layer.num_kernels];
+
+        // the kernel is the innermost index of a volume
+        for (i, value) in layer.volume.iter().enumerate() {
+            let kernel = i % layer.num_kernels;
+            maxima[kernel] = maxima[kernel].max(*value);
+        }
+
+        Ok((0..layer.num_kernels).filter(|&kernel| maxima[kernel] < threshold).collect())
     }
 
-    pub fn register_layer(&mut self, activation_function: ActivationFunction, layer: Layer) -> () {
-        self.layers.push((layer, activation_function));
+    /// returns the outputs of a convolutional layer after the last forward pass as a grayscale image and its (width, height)
+    ///
+    /// every channel is scaled to [0, 1] and tiled row by row into a grid of `ceil(sqrt(num_kernels))` columns.
+    /// channels with constant outputs and the unused tiles of the last row are 0
+    pub fn feature_maps_as_grid(&self, layer_index: usize) -> Result<(Vec<f32>, (usize, usize)), Error> {
+        let Some((Layer::Convolutional(layer), _)) = self.layers.get(layer_index) else { return Err(Error::InvalidInput) };
+        if layer.is_input_layer() { return Err(Error::IncompatibleLayers) };
+
+        // the output may have been freed by gradient checkpointing
+        if layer.volume.is_empty() { return Err(Error::InvalidInput) };
+
+        let (tile_width, tile_height, depth) = layer.dimension;
+
+        let columns = (depth as f32).sqrt().ceil() as usize;
+        let rows = depth.div_ceil(columns);
+
+        let width = columns * tile_width;
+        let height = rows * tile_height;
+
+        let mut result = vec![0.0; width * height];
+
+        for z in 0..depth {
+            let mut min = f32::INFINITY;
+            let mut max = f32::NEG_INFINITY;
+
+            for y in 0..tile_height {
+                for x in 0..tile_width {
+                    let value = layer.volume[util::get_index((x, y, z), layer.dimension)];
+
+                    min = min.min(value);
+                    max = max.max(value);
+                }
+            }
+
+            let range = max - min;
+            let (offset_x, offset_y) = ((z % columns) * tile_width, (z / columns) * tile_height);
+
+            for y in 0..tile_height {
+                for x in 0..tile_width {
+                    let value = layer.volume[util::get_index((x, y, z), layer.dimension)];
+
+                    result[(offset_y + y) * width + offset_x + x] = if range > 0.0 { (value - min) / range } else { 0.0 };
+                }
+            }
+        }
+
+        Ok((result, (width, height)))
+    }
+
+    /// every learnable parameter in the same order as `collect_gradients_mut`
+    fn collect_parameters(&self) -> Vec<f32> {
+        let mut result = Vec::new();
+
+        for (layer, _) in &self.layers {
+            match layer {
+                Layer::Convolutional(layer) => {
+                    result.extend(&layer.kernel);
+                    result.extend(&layer.biases);
+                    result.extend(&layer.prelu.slopes);
+                },
+
+                Layer::FullyConnected(layer) => {
+                    result.extend(&layer.weights);
+                    result.extend(&layer.biases);
+                    result.extend(&layer.prelu.slopes);
+                }
+
+                Layer::Scale(layer) => result.extend(&layer.gamma),
+
+                Layer::SeparableConvolutional(layer) => {
+                    result.extend(&layer.depthwise_kernel);
+                    result.extend(&layer.pointwise_kernel);
+                    result.extend(&layer.biases);
+                    result.extend(&layer.prelu.slopes);
+                }
+
+                _ => (),
+            }
+        }
+
+        result
+    }
+
+    /// see `collect_parameters`
+    fn collect_parameters_mut(&mut self) -> Vec<&mut f32> {
+        let mut result = Vec::new();
+
+        for (layer, _) in &mut self.layers {
+            match layer {
+                Layer::Convolutional(layer) => {
+                    result.extend(layer.kernel.iter_mut());
+                    result.extend(layer.biases.iter_mut());
+                    result.extend(layer.prelu.slopes.iter_mut());
+                },
+
+                Layer::FullyConnected(layer) => {
+                    result.extend(layer.weights.iter_mut());
+                    result.extend(layer.biases.iter_mut());
+                    result.extend(layer.prelu.slopes.iter_mut());
+                }
+
+                Layer::Scale(layer) => result.extend(layer.gamma.iter_mut()),
+
+                Layer::SeparableConvolutional(layer) => {
+                    result.extend(layer.depthwise_kernel.iter_mut());
+                    result.extend(layer.pointwise_kernel.iter_mut());
+                    result.extend(layer.biases.iter_mut());
+                    result.extend(layer.prelu.slopes.iter_mut());
+                }
+
+                _ => (),
+            }
+        }
+
+        result
     }
 
-    pub fn collect_gradients_mut(&mut self) -> Vec<&mut f32> {
+    pub fn collect_gradients_mut(&mut self) -> Vec<&mut Gradient> {
         let mut result = Vec::new();
 
         for (layer, _) in &mut self.layers {
@@ -107,21 +1674,32 @@ impl NeuralNetwork {
                 Layer::Convolutional(layer) => {
                     result.extend(layer.kernel_gradients.iter_mut());
                     result.extend(layer.bias_gradients.iter_mut());
+                    result.extend(layer.prelu.gradients.iter_mut());
                 },
 
                 Layer::FullyConnected(layer) => {
                     result.extend(layer.weight_gradients.iter_mut());
                     result.extend(layer.bias_gradients.iter_mut());
+                    result.extend(layer.prelu.gradients.iter_mut());
+                }
+
+                Layer::Scale(layer) => result.extend(layer.gamma_gradients.iter_mut()),
+
+                Layer::SeparableConvolutional(layer) => {
+                    result.extend(layer.depthwise_gradients.iter_mut());
+                    result.extend(layer.pointwise_gradients.iter_mut());
+                    result.extend(layer.bias_gradients.iter_mut());
+                    result.extend(layer.prelu.gradients.iter_mut());
                 }
 
                 _ => (),
             }
         }
 
-        return result;
+        result
     }
 
-    pub fn collect_gradients(&self) -> Vec<f32> {
+    pub fn collect_gradients(&self) -> Vec<Gradient> {
         let mut result = Vec::new();
 
         for (layer, _) in &self.layers {
@@ -129,38 +1707,171 @@ impl NeuralNetwork {
                 Layer::Convolutional(layer) => {
                     result.extend(layer.kernel_gradients.iter());
                     result.extend(layer.bias_gradients.iter());
+                    result.extend(layer.prelu.gradients.iter());
                 },
 
                 Layer::FullyConnected(layer) => {
                     result.extend(layer.weight_gradients.iter());
                     result.extend(layer.bias_gradients.iter());
+                    result.extend(layer.prelu.gradients.iter());
+                }
+
+                Layer::Scale(layer) => result.extend(layer.gamma_gradients.iter()),
+
+                Layer::SeparableConvolutional(layer) => {
+                    result.extend(layer.depthwise_gradients.iter());
+                    result.extend(layer.pointwise_gradients.iter());
+                    result.extend(layer.bias_gradients.iter());
+                    result.extend(layer.prelu.gradients.iter());
                 }
 
                 _ => (),
             }
         }
 
-        return result;
+        result
     }
 }
 
 impl Serialize for NeuralNetwork {
     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut state = serializer.serialize_struct("NeuralNetwork", 2)?;
+        let mut state = serializer.serialize_struct("NeuralNetwork", 6)?;
         
+        state.serialize_field("magic", &FORMAT_MAGIC)?;
+        state.serialize_field("version", &FORMAT_VERSION)?;
         state.serialize_field("layers", &self.layers)?;
         state.serialize_field("error_function", &self.error_function)?;
+        state.serialize_field("hyperparameters", &self.hyperparameters)?;
+        state.serialize_field("temperature", &self.temperature)?;
 
         state.end()
     }
 }
 
+const NEURAL_NETWORK_FIELDS: &[&str] = &["magic", "version", "layers", "error_function", "hyperparameters", "temperature"];
+
 impl<'de> Deserialize<'de> for NeuralNetwork {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
         where
             D: serde::Deserializer<'de>,
     {
-        deserializer.deserialize_struct("NeuralNetwork", &["layers", "error_function"], NeuralNetworkVisitor)
+        deserializer.deserialize_struct("NeuralNetwork", NEURAL_NETWORK_FIELDS, NeuralNetworkVisitor)
+    }
+}
+
+/// compares the argmax of the output and target, or thresholds at 0.5 for a single output
+fn is_correct(output: &[f32], target: &[f32]) -> bool {
+    if output.len() == 1 {
+        (output[0] > 0.5) == (target[0] > 0.5)
+    } else {
+        util::argmax(output) == util::argmax(target)
+    }
+}
+
+/// subtracts the mean of every group of `group_size` consecutive gradients
+///
+/// a group of a single weight would always be zeroed, so it's left as it is
+#[allow(clippy::unused_unit)]
+fn centralize(gradients: &mut [Gradient], group_size: usize) -> () {
+    if group_size < 2 { return };
+
+    for group in gradients.chunks_mut(group_size) {
+        let mean = group.iter().sum::<Gradient>() / group.len() as Gradient;
+        group.iter_mut().for_each(|gradient| *gradient -= mean);
+    }
+}
+
+fn check_version<E: serde::de::Error>(version: u32) -> Result<u32, E> {
+    if version > FORMAT_VERSION {
+        return Err(E::custom(format!("model format version {} is newer than the supported version {}", version, FORMAT_VERSION)));
+    }
+
+    Ok(version)
+}
+
+/// Deserializes the layers of a network written before versioning, whose number of layers was already read
+struct LegacyLayersSeed {
+    len: usize,
+}
+
+impl<'de> DeserializeSeed<'de> for LegacyLayersSeed {
+    type Value = Vec<(Layer, ActivationFunction)>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_tuple(self.len, LayersSeed { version: 1 })
+    }
+}
+
+/// Deserializes the layers of a network written with the given format version
+struct LayersSeed {
+    version: u32,
+}
+
+impl<'de> DeserializeSeed<'de> for LayersSeed {
+    type Value = Vec<(Layer, ActivationFunction)>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for LayersSeed {
+    type Value = Vec<(Layer, ActivationFunction)>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a sequence of layers and activation functions")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let mut layers = Vec::new();
+
+        while let Some(layer) = seq.next_element_seed(LayerEntrySeed { version: self.version })? {
+            layers.push(layer);
+        }
+
+        Ok(layers)
+    }
+}
+
+struct LayerEntrySeed {
+    version: u32,
+}
+
+impl<'de> DeserializeSeed<'de> for LayerEntrySeed {
+    type Value = (Layer, ActivationFunction);
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_tuple(2, self)
+    }
+}
+
+impl<'de> Visitor<'de> for LayerEntrySeed {
+    type Value = (Layer, ActivationFunction);
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a layer and its activation function")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let layer = seq.next_element_seed(LayerSeed { version: self.version })?.ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+        let activation_function = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+
+        Ok((layer, activation_function))
     }
 }
 
@@ -176,15 +1887,29 @@ impl<'de> Visitor<'de> for NeuralNetworkVisitor {
         where
             M: serde::de::MapAccess<'de>,
     {
+        let mut version = None;
         let mut layers = None;
         let mut error_function = None;
+        let mut hyperparameters = None;
+        let mut temperature = None;
         
         while let Some(key) = map.next_key::<&str>()? {
             match key {
+                "magic" => {
+                    if map.next_value::<u64>()? != FORMAT_MAGIC { return Err(serde::de::Error::custom("not a serialized neural network")) };
+                },
+
+                "version" => {
+                    if version.is_some() { return Err(serde::de::Error::duplicate_field("version")); };
+
+                    version = Some(check_version(map.next_value()?)?);
+                },
+
                 "layers" => {
                     if layers.is_some() { return Err(serde::de::Error::duplicate_field("layers")); };
 
-                    layers = Some(map.next_value()?);
+                    // the version is serialized first, formats without it predate versioning
+                    layers = Some(map.next_value_seed(LayersSeed { version: version.unwrap_or(1) })?);
                 },
 
                 "error_function" => {
@@ -193,15 +1918,46 @@ impl<'de> Visitor<'de> for NeuralNetworkVisitor {
                     error_function = Some(map.next_value()?);
                 }
 
-                _ => return Err(serde::de::Error::unknown_field(key, &["layers", "error_function"])),
+                "hyperparameters" => {
+                    if hyperparameters.is_some() { return Err(serde::de::Error::duplicate_field("hyperparameters")); };
+
+                    hyperparameters = Some(map.next_value()?);
+                }
+
+                "temperature" => {
+                    if temperature.is_some() { return Err(serde::de::Error::duplicate_field("temperature")); };
+
+                    temperature = Some(map.next_value()?);
+                }
+
+                _ => return Err(serde::de::Error::unknown_field(key, NEURAL_NETWORK_FIELDS)),
             }
         }
 
+        // self describing formats written before versioning have no version field
+        let version = version.unwrap_or(1);
+
         let layers = layers.ok_or_else(|| serde::de::Error::missing_field("layers"))?;
         let error_function = error_function.ok_or_else(|| serde::de::Error::missing_field("error_function"))?;
 
+        let hyperparameters = match version {
+            1 => Hyperparameters::default(),
+            _ => hyperparameters.ok_or_else(|| serde::de::Error::missing_field("hyperparameters"))?,
+        };
+
+        // the temperature was added in format version 6
+        let temperature = match temperature {
+            Some(temperature) => temperature,
+            None if version < 6 => 1.0,
+            None => return Err(serde::de::Error::missing_field("temperature")),
+        };
+
         let mut neural_network = NeuralNetwork::new(error_function);
-        neural_network.layers = layers;
+        neural_network.hyperparameters = hyperparameters;
+        neural_network.temperature = temperature;
+        for (layer, activation_function) in layers {
+            neural_network.register_layer(activation_function, layer);
+        }
 
         Ok(neural_network)
     }
@@ -210,11 +1966,44 @@ impl<'de> Visitor<'de> for NeuralNetworkVisitor {
     where
         A: serde::de::SeqAccess<'de>,
     {
-        let layers = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
-        let error_function = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+        let header: u64 = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+
+        // networks written before versioning are (layers, error_function), so the header is the number of layers
+        if header != FORMAT_MAGIC {
+            let layers = seq.next_element_seed(LegacyLayersSeed { len: header as usize })?.ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+            let error_function = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+
+            let mut neural_network = NeuralNetwork::new(error_function);
+            for (layer, activation_function) in layers {
+                neural_network.register_layer(activation_function, layer);
+            }
+
+            return Ok(neural_network);
+        }
+
+        let version: u32 = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+        let version = check_version(version)?;
+
+        let layers = seq.next_element_seed(LayersSeed { version })?.ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+        let error_function = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(3, &self))?;
+
+        let hyperparameters = match version {
+            1 => Hyperparameters::default(),
+            _ => seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(4, &self))?,
+        };
+
+        let temperature = if version < 6 {
+            1.0
+        } else {
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(5, &self))?
+        };
 
         let mut neural_network = NeuralNetwork::new(error_function);
-        neural_network.layers = layers;
+        neural_network.hyperparameters = hyperparameters;
+        neural_network.temperature = temperature;
+        for (layer, activation_function) in layers {
+            neural_network.register_layer(activation_function, layer);
+        }
 
         Ok(neural_network)
     }
@@ -1,53 +1,275 @@
+use crate::ActivationFunction;
+use crate::util;
+
 use serde::{Serialize, Deserialize};
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ErrorFunction {
     HalfMeanSquaredError,
     BinaryCrossEntropy,
+    LogCosh,
+    /// expects the outputs to be a probability distribution, usually from a softmax
+    CategoricalCrossEntropy,
+    /// 1 - the cosine similarity between the outputs and the target embedding
+    ///
+    /// the loss doesn't decompose over the outputs, so it ignores class weights
+    CosineEmbedding,
+    /// an objective supplied by the user, e.g. a `static` implementing `Loss`
+    ///
+    /// it ignores class weights, but outputs masked with a NaN target still get no gradient, see `mask_targets`.
+    /// it can't be serialized, so a network using it can't be saved
+    #[serde(skip)]
+    Custom(&'static dyn Loss),
 }
 
-pub fn eval(function_type: ErrorFunction, values: &Vec<f32>, expected: &Vec<f32>) -> f32 {
-    match function_type {
-        ErrorFunction::HalfMeanSquaredError => half_mean_squared(values, expected),
-        ErrorFunction::BinaryCrossEntropy => binary_cross_entropy(values, expected),
+/// A user defined error function for `ErrorFunction::Custom`
+///
+/// it has to be `Sync` since networks are trained on several threads
+pub trait Loss: Sync {
+    fn eval(&self, values: &[f32], expected: &[f32]) -> f32;
+
+    /// the derivative of the error with respect to the output at `i`
+    fn eval_derivative(&self, i: usize, values: &[f32], expected: &[f32]) -> f32;
+}
+
+impl std::fmt::Debug for dyn Loss {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("Loss")
     }
 }
 
-pub fn eval_derivative(function_type: ErrorFunction, i: usize, values: &Vec<f32>, expected: &Vec<f32>) -> f32 {
+/// two custom losses are only equal if they're the same object
+impl PartialEq for dyn Loss {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self, other)
+    }
+}
+
+/// the default distance the cross entropy losses keep the outputs from 0 and 1
+///
+/// smaller values round to 1.0 in f32 and no longer bound the gradient
+pub const DEFAULT_EPSILON: f32 = 1e-7;
+
+/// `weights` scales the contribution of every output index, None weights them uniformly
+///
+/// `epsilon` clamps the outputs of the cross entropy losses to [epsilon, 1 - epsilon]. a NaN target masks its output, see `mask_targets`
+#[allow(clippy::ptr_arg)]
+pub fn eval(function_type: ErrorFunction, values: &Vec<f32>, expected: &Vec<f32>, weights: Option<&[f32]>, epsilon: f32) -> f32 {
+    if let Some((values, expected, weights)) = mask_targets(values, expected, weights) {
+        return eval(function_type, &values, &expected, Some(&weights), epsilon);
+    }
+
     match function_type {
+        ErrorFunction::HalfMeanSquaredError => half_mean_squared(values, expected, weights),
+        ErrorFunction::BinaryCrossEntropy => binary_cross_entropy(values, expected, weights, epsilon),
+        ErrorFunction::LogCosh => log_cosh(values, expected, weights),
+        ErrorFunction::CategoricalCrossEntropy => categorical_cross_entropy(values, expected, weights, epsilon),
+        ErrorFunction::CosineEmbedding => cosine_embedding(values, expected),
+        ErrorFunction::Custom(loss) => loss.eval(values, expected),
+    }
+}
+
+#[allow(clippy::ptr_arg)]
+pub fn eval_derivative(function_type: ErrorFunction, i: usize, values: &Vec<f32>, expected: &Vec<f32>, weights: Option<&[f32]>, epsilon: f32) -> f32 {
+    let derivative = match function_type {
         ErrorFunction::HalfMeanSquaredError => half_mean_squared_derivative(i, values, expected),
-        ErrorFunction::BinaryCrossEntropy => binary_cross_entropy_derivative(i, values, expected),
+        ErrorFunction::BinaryCrossEntropy => binary_cross_entropy_derivative(i, values, expected, epsilon),
+        ErrorFunction::LogCosh => log_cosh_derivative(i, values, expected),
+        ErrorFunction::CategoricalCrossEntropy => categorical_cross_entropy_derivative(i, values, expected, epsilon),
+        ErrorFunction::CosineEmbedding => return cosine_embedding_derivative(i, values, expected),
+        ErrorFunction::Custom(loss) => return loss.eval_derivative(i, values, expected),
+    };
+
+    weight(weights, i) * derivative
+}
+
+/// returns true if the error function is only defined for outputs in [0, 1]
+pub fn expects_probabilities(function_type: ErrorFunction) -> bool {
+    matches!(function_type, ErrorFunction::BinaryCrossEntropy | ErrorFunction::CategoricalCrossEntropy)
+}
+
+/// returns true if the derivative of the error with respect to the raw outputs simplifies for this activation
+pub fn has_fused_derivative(activation_function: ActivationFunction, function_type: ErrorFunction) -> bool {
+    matches!((activation_function, function_type),
+        (ActivationFunction::Sigmoid, ErrorFunction::BinaryCrossEntropy) |
+        (ActivationFunction::Softmax, ErrorFunction::CategoricalCrossEntropy))
+}
+
+/// the derivative of the error with respect to the raw (pre-activation) outputs, see `has_fused_derivative`
+///
+/// it doesn't divide by a clamped output, so it stays finite when the activation saturates
+#[allow(clippy::unused_unit, clippy::ptr_arg)]
+pub fn eval_fused_derivative(function_type: ErrorFunction, values: &Vec<f32>, expected: &Vec<f32>, weights: Option<&[f32]>, result: &mut Vec<f32>) -> () {
+    match function_type {
+        ErrorFunction::CategoricalCrossEntropy => {
+            let total_weight: f32 = (0..expected.len()).map(|i| weight(weights, i) * expected[i]).sum();
+
+            for i in 0..values.len() {
+                result[i] = total_weight * values[i] - weight(weights, i) * expected[i];
+            }
+        }
+
+        _ => {
+            for i in 0..values.len() {
+                result[i] = weight(weights, i) * (values[i] - expected[i]) / values.len() as f32;
+            }
+        }
+    }
+}
+
+/// a NaN target marks a missing label, whose output adds nothing to the error and gets no gradient
+///
+/// returns the values, targets and weights with the masked outputs and targets zeroed and their weights set to 0,
+/// or None if no target is masked. the averaging losses still divide by the number of outputs, so the other outputs are unaffected
+#[allow(clippy::ptr_arg)]
+pub(crate) fn mask_targets(values: &Vec<f32>, expected: &Vec<f32>, weights: Option<&[f32]>) -> Option<(Vec<f32>, Vec<f32>, Vec<f32>)> {
+    if !expected.iter().any(|target| target.is_nan()) { return None };
+
+    let mut masked_values = values.clone();
+    let mut masked_expected = expected.clone();
+    let mut masked_weights: Vec<f32> = (0..expected.len()).map(|i| weight(weights, i)).collect();
+
+    for i in 0..expected.len() {
+        if expected[i].is_nan() {
+            masked_values[i] = 0.0;
+            masked_expected[i] = 0.0;
+            masked_weights[i] = 0.0;
+        }
+    }
+
+    Some((masked_values, masked_expected, masked_weights))
+}
+
+/// max(0, |anchor - positive| - |anchor - negative| + margin) with euclidean distances
+pub(crate) fn triplet_margin(anchor: &[f32], positive: &[f32], negative: &[f32], margin: f32) -> f32 {
+    (distance(anchor, positive) - distance(anchor, negative) + margin).max(0.0)
+}
+
+/// returns the gradients of `triplet_margin` with respect to the (anchor, positive, negative) embeddings
+///
+/// a distance of 0 has no direction, so it contributes no gradient
+pub(crate) fn triplet_margin_derivative(anchor: &[f32], positive: &[f32], negative: &[f32], margin: f32) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+    let mut anchor_gradients = vec![0.0; anchor.len()];
+    let mut positive_gradients = vec![0.0; anchor.len()];
+    let mut negative_gradients = vec![0.0; anchor.len()];
+
+    if triplet_margin(anchor, positive, negative, margin) <= 0.0 {
+        return (anchor_gradients, positive_gradients, negative_gradients);
+    }
+
+    let (positive_distance, negative_distance) = (distance(anchor, positive), distance(anchor, negative));
+
+    for i in 0..anchor.len() {
+        let towards_positive = if positive_distance > 0.0 { (anchor[i] - positive[i]) / positive_distance } else { 0.0 };
+        let towards_negative = if negative_distance > 0.0 { (anchor[i] - negative[i]) / negative_distance } else { 0.0 };
+
+        anchor_gradients[i] = towards_positive - towards_negative;
+        positive_gradients[i] = -towards_positive;
+        negative_gradients[i] = towards_negative;
     }
+
+    (anchor_gradients, positive_gradients, negative_gradients)
+}
+
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt()
+}
+
+fn weight(weights: Option<&[f32]>, i: usize) -> f32 {
+    weights.map_or(1.0, |weights| weights[i])
 }
 
-fn half_mean_squared(values: &Vec<f32>, expected: &Vec<f32>) -> f32 {
-    let mut result: f32 = 0.0;
+fn half_mean_squared(values: &[f32], expected: &[f32], weights: Option<&[f32]>) -> f32 {
+    let mut result = util::Accumulator::<f32>::default();
 
     for i in 0..values.len() {
         let diff = values[i] - expected[i];
-        result += diff * diff;
+        result.add(weight(weights, i) * diff * diff);
     }
 
-    result / values.len() as f32 * 0.5
+    result.value() / values.len() as f32 * 0.5
 }
 
-fn binary_cross_entropy(values: &Vec<f32>, expected: &Vec<f32>) -> f32 {
-    let mut result: f32 = 0.0;
+fn binary_cross_entropy(values: &[f32], expected: &[f32], weights: Option<&[f32]>, epsilon: f32) -> f32 {
+    let mut result = util::Accumulator::<f32>::default();
 
     for i in 0..values.len() {
-        let clamped_value = values[i].clamp(1e-12, 1.0 - 1e-12);
-        result += expected[i] * clamped_value.ln() + (1.0 - expected[i]) * (1.0 - clamped_value).ln();
+        let clamped_value = values[i].clamp(epsilon, 1.0 - epsilon);
+        result.add(weight(weights, i) * (expected[i] * clamped_value.ln() + (1.0 - expected[i]) * (1.0 - clamped_value).ln()));
     }
 
-    -result / values.len() as f32
+    -result.value() / values.len() as f32
 }
 
 
-fn half_mean_squared_derivative(i: usize, values: &Vec<f32>, expected: &Vec<f32>) -> f32 {
+fn log_cosh(values: &[f32], expected: &[f32], weights: Option<&[f32]>) -> f32 {
+    let mut result = util::Accumulator::<f32>::default();
+
+    for i in 0..values.len() {
+        // ln(cosh(x)) = |x| + ln(1 + e^(-2|x|)) - ln(2) doesn't overflow for large differences
+        let diff = (values[i] - expected[i]).abs() as f64;
+        result.add(weight(weights, i) * (diff + (-2.0 * diff).exp().ln_1p() - std::f64::consts::LN_2) as f32);
+    }
+
+    result.value() / values.len() as f32
+}
+
+/// summed instead of averaged over the outputs since only the expected class contributes
+fn categorical_cross_entropy(values: &[f32], expected: &[f32], weights: Option<&[f32]>, epsilon: f32) -> f32 {
+    let mut result = util::Accumulator::<f32>::default();
+
+    for i in 0..values.len() {
+        result.add(weight(weights, i) * expected[i] * values[i].max(epsilon).ln());
+    }
+
+    -result.value()
+}
+
+/// returns (values . expected, |values|, |expected|)
+fn cosine_terms(values: &[f32], expected: &[f32]) -> (f32, f32, f32) {
+    let mut dot: f32 = 0.0;
+    let mut values_norm: f32 = 0.0;
+    let mut expected_norm: f32 = 0.0;
+
+    for i in 0..values.len() {
+        dot += values[i] * expected[i];
+        values_norm += values[i] * values[i];
+        expected_norm += expected[i] * expected[i];
+    }
+
+    (dot, values_norm.sqrt(), expected_norm.sqrt())
+}
+
+/// a zero vector has no direction, so its similarity is treated as 0
+fn cosine_embedding(values: &[f32], expected: &[f32]) -> f32 {
+    let (dot, values_norm, expected_norm) = cosine_terms(values, expected);
+    if values_norm == 0.0 || expected_norm == 0.0 { return 1.0 };
+
+    1.0 - dot / (values_norm * expected_norm)
+}
+
+fn half_mean_squared_derivative(i: usize, values: &[f32], expected: &[f32]) -> f32 {
     (values[i] - expected[i]) / values.len() as f32
 }
 
-fn binary_cross_entropy_derivative(i: usize, values: &Vec<f32>, expected: &Vec<f32>) -> f32 {
-    let clamped_value = values[i].clamp(1e-12, 1.0 - 1e-12);
+fn binary_cross_entropy_derivative(i: usize, values: &[f32], expected: &[f32], epsilon: f32) -> f32 {
+    let clamped_value = values[i].clamp(epsilon, 1.0 - epsilon);
     -(expected[i] / clamped_value - (1.0 - expected[i]) / (1.0 - clamped_value)) / values.len() as f32
-}
\ No newline at end of file
+}
+
+fn log_cosh_derivative(i: usize, values: &[f32], expected: &[f32]) -> f32 {
+    (values[i] - expected[i]).tanh() / values.len() as f32
+}
+
+fn categorical_cross_entropy_derivative(i: usize, values: &[f32], expected: &[f32], epsilon: f32) -> f32 {
+    -expected[i] / values[i].max(epsilon)
+}
+
+/// only the part of the target perpendicular to the outputs changes the angle
+fn cosine_embedding_derivative(i: usize, values: &[f32], expected: &[f32]) -> f32 {
+    let (dot, values_norm, expected_norm) = cosine_terms(values, expected);
+    if values_norm == 0.0 || expected_norm == 0.0 { return 0.0 };
+
+    let similarity = dot / (values_norm * expected_norm);
+    -(expected[i] / (values_norm * expected_norm) - similarity * values[i] / (values_norm * values_norm))
+}
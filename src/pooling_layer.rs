@@ -1,80 +1,230 @@
 use crate::layer::{Layer, LayerBase};
 use crate::errors::Error;
-use crate::util;
+use crate::util::{self, OutputRounding};
+use crate::neural_network::FORMAT_VERSION;
 
-use serde::{Serialize, Deserialize, de::Visitor, ser::SerializeStruct};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::{Serialize, Deserialize, de::{DeserializeSeed, Visitor}, ser::SerializeStruct};
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum PoolingType {
     Max,
     Average,
+
+    // new variants are appended so the serialized variant indices stay stable
+
+    /// blends max and average pooling, 1.0 is max pooling and 0.0 is average pooling
+    Mixed(f32),
+    /// samples a value proportionally to its activation while training and takes the weighted average otherwise
+    ///
+    /// expects non-negative inputs, negative values are treated as 0
+    Stochastic,
 }
 
 #[derive(Clone)]
 pub struct PoolingLayer {
     pub(crate) zero_padding: usize,
-    pub(crate) stride: usize,
+    /// (x, y)
+    pub(crate) stride: (usize, usize),
     pub(crate) kernel_size: usize,
+    pub(crate) rounding: OutputRounding,
     
     pub(crate) dimension: (usize, usize, usize),
 
     pub(crate) volume: Vec<f32>,
     pub(crate) volume_gradients: Vec<f32>,
 
+    pub(crate) training: bool,
+
     pooling_type: PoolingType,
+
+    /// the input index sampled for every output by stochastic pooling
+    sampled_indices: Vec<usize>,
+    /// the input index of the maximum of every output for max pooling, used by max unpooling
+    pub(crate) switches: Vec<usize>,
+    rng: StdRng,
 }
 
+#[allow(clippy::unused_unit, clippy::ptr_arg)]
 impl PoolingLayer {
-    pub fn new(pooling_type: PoolingType, zero_padding: usize, stride: usize, kernel_size: usize, dimension: (usize, usize, usize)) -> Self {
+    pub fn new(pooling_type: PoolingType, zero_padding: usize, stride: (usize, usize), kernel_size: usize, dimension: (usize, usize, usize)) -> Self {
+        let sampled_indices = match pooling_type {
+            PoolingType::Stochastic => vec![0; dimension.0 * dimension.1 * dimension.2],
+
+            _ => Vec::new(),
+        };
+
+        let switches = match pooling_type {
+            PoolingType::Max => vec![0; dimension.0 * dimension.1 * dimension.2],
+
+            _ => Vec::new(),
+        };
+
         Self {
             pooling_type,
 
             zero_padding,
             stride,
             kernel_size,
+            rounding: OutputRounding::Floor,
             
             dimension,
             volume: vec![0.0; dimension.0 * dimension.1 * dimension.2],
             volume_gradients: vec![0.0; dimension.0 * dimension.1 * dimension.2],
+
+            training: true,
+
+            sampled_indices,
+            switches,
+            rng: StdRng::from_rng(&mut rand::rng()),
+        }
+    }
+
+    pub fn get_pooling_type(&self) -> PoolingType {
+        self.pooling_type
+    }
+
+    pub fn get_rounding(&self) -> OutputRounding {
+        self.rounding
+    }
+
+    /// sets how the output dimension is rounded, which has to match the dimension the layer was created with
+    pub fn set_rounding(&mut self, rounding: OutputRounding) -> () {
+        self.rounding = rounding;
+    }
+
+    /// seeds the random number generator used by stochastic pooling
+    pub fn set_seed(&mut self, seed: u64) -> () {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// draws a seed for a copy of the layer from the random number generator
+    pub(crate) fn next_seed(&mut self) -> u64 {
+        self.rng.random()
+    }
+
+    pub(crate) fn same_structure(&self, other: &PoolingLayer) -> bool {
+        self.dimension == other.dimension &&
+            self.kernel_size == other.kernel_size &&
+            self.stride == other.stride &&
+            self.rounding == other.rounding &&
+            self.zero_padding == other.zero_padding &&
+            self.pooling_type == other.pooling_type
+    }
+
+    /// the window is clipped to the input, which only shortens the last window with `OutputRounding::Ceil`
+    fn window_indices(&self, position: (usize, usize, usize), input_dimension: (usize, usize, usize)) -> impl Iterator<Item = usize> + '_ {
+        let (x, y, z) = position;
+        let (columns, rows) = self.window_size(position, input_dimension);
+
+        (0..rows).flat_map(move |kernel_y| {
+            (0..columns).map(move |kernel_x| util::get_index((x + kernel_x, y + kernel_y, z), input_dimension))
+        })
+    }
+
+    /// the (columns, rows) of the window that lie inside the input
+    fn window_size(&self, position: (usize, usize, usize), input_dimension: (usize, usize, usize)) -> (usize, usize) {
+        (self.kernel_size.min(input_dimension.0 - position.0), self.kernel_size.min(input_dimension.1 - position.1))
+    }
+
+    /// the reciprocal of the number of values an average is taken over
+    fn window_scale(&self, position: (usize, usize, usize), input_dimension: (usize, usize, usize)) -> f32 {
+        let (columns, rows) = self.window_size(position, input_dimension);
+
+        1.0 / (columns as f32 * rows as f32)
+    }
+
+    fn window_max(&self, position: (usize, usize, usize), input_dimension: (usize, usize, usize), volume: &Vec<f32>) -> f32 {
+        self.window_indices(position, input_dimension).fold(0.0, |value, index| value.max(volume[index]))
+    }
+
+    fn window_max_index(&self, position: (usize, usize, usize), input_dimension: (usize, usize, usize), volume: &Vec<f32>) -> usize {
+        let mut max_index = util::get_index(position, input_dimension);
+        let mut max_value = volume[max_index];
+
+        for index in self.window_indices(position, input_dimension) {
+            if volume[index] > max_value {
+                max_index = index;
+                max_value = volume[index];
+            }
+        }
+
+        max_index
+    }
+
+    fn window_average(&self, position: (usize, usize, usize), input_dimension: (usize, usize, usize), volume: &Vec<f32>) -> f32 {
+        self.window_indices(position, input_dimension).map(|index| volume[index]).sum::<f32>() * self.window_scale(position, input_dimension)
+    }
+
+    /// returns the sum and the sum of squares of the non-negative values of a window
+    fn window_sums(&self, position: (usize, usize, usize), input_dimension: (usize, usize, usize), volume: &Vec<f32>) -> (f32, f32) {
+        self.window_indices(position, input_dimension)
+            .map(|index| volume[index].max(0.0))
+            .fold((0.0, 0.0), |(sum, squares), value| (sum + value, squares + value * value))
+    }
+
+    fn sample_window(&mut self, position: (usize, usize, usize), input_dimension: (usize, usize, usize), volume: &Vec<f32>) -> usize {
+        let (sum, _) = self.window_sums(position, input_dimension, volume);
+        let first = util::get_index(position, input_dimension);
+
+        if sum <= 0.0 { return first };
+
+        let mut threshold = self.rng.random::<f32>() * sum;
+        let mut sampled = first;
+
+        for index in self.window_indices(position, input_dimension) {
+            let value = volume[index].max(0.0);
+            if value == 0.0 { continue };
+
+            sampled = index;
+            threshold -= value;
+
+            if threshold < 0.0 { break };
         }
+
+        sampled
     }
 
+    #[allow(clippy::explicit_counter_loop)]
     pub(crate) fn convolve(&mut self, input_dimension: (usize, usize, usize), volume: &Vec<f32>) -> () {
         let mut o_x = 0;
 
-        let kernel_volume = 1.0 / (self.kernel_size as f32 * self.kernel_size as f32);
-
         // TODO: use zero padding?
-        for x in (0..input_dimension.0 - self.kernel_size + 1).step_by(self.stride) {
+        for x in (0..self.dimension.0 * self.stride.0).step_by(self.stride.0) {
             let mut o_y = 0;
 
-            for y in (0..input_dimension.1 - self.kernel_size + 1).step_by(self.stride) {
+            for y in (0..self.dimension.1 * self.stride.1).step_by(self.stride.1) {
                 for z in 0..input_dimension.2 {
-                    let mut value: f32 = 0.0;
+                    let position = (x, y, z);
+                    let output_index = util::get_index((o_x, o_y, z), self.dimension);
 
-                    match self.pooling_type {
+                    let value = match self.pooling_type {
                         PoolingType::Max => {
-                            for kernel_y in 0..self.kernel_size {
-                                for kernel_x in 0..self.kernel_size {
-                                    let val = volume[util::get_index((x + kernel_x, y + kernel_y, z), input_dimension)];
-                                    value = value.max(val);
-                                }
-                            }
+                            self.switches[output_index] = self.window_max_index(position, input_dimension, volume);
+                            self.window_max(position, input_dimension, volume)
                         },
 
-                        PoolingType::Average => {
-                            for kernel_y in 0..self.kernel_size {
-                                for kernel_x in 0..self.kernel_size {
-                                    let val = volume[util::get_index((x + kernel_x, y + kernel_y, z), input_dimension)];
-                                    value += val;
-                                }
-                            }
+                        PoolingType::Average => self.window_average(position, input_dimension, volume),
 
-                            value *= kernel_volume;
-                        }
-                    }
+                        PoolingType::Mixed(ratio) => {
+                            ratio * self.window_max(position, input_dimension, volume) + (1.0 - ratio) * self.window_average(position, input_dimension, volume)
+                        },
+
+                        PoolingType::Stochastic if self.training => {
+                            let index = self.sample_window(position, input_dimension, volume);
+                            self.sampled_indices[output_index] = index;
+
+                            volume[index].max(0.0)
+                        },
 
-                    self.volume[util::get_index((o_x, o_y, z), self.dimension)] = value;
+                        PoolingType::Stochastic => {
+                            let (sum, squares) = self.window_sums(position, input_dimension, volume);
+
+                            if sum > 0.0 { squares / sum } else { 0.0 }
+                        },
+                    };
+
+                    self.volume[output_index] = value;
                 }
 
                 o_y += 1;
@@ -87,45 +237,51 @@ impl PoolingLayer {
     fn convolve_back(&mut self, input_dimension: (usize, usize, usize), volume: &Vec<f32>, volume_gradients: &mut Vec<f32>) {
         volume_gradients.fill(0.0);
 
-        let kernel_volume = 1.0 / (self.kernel_size as f32 * self.kernel_size as f32);
-
         let mut o_x = 0;
 
-        for x in (0..input_dimension.0 - self.kernel_size + 1).step_by(self.stride) {
+        for x in (0..self.dimension.0 * self.stride.0).step_by(self.stride.0) {
             let mut o_y = 0;
 
-            for y in (0..input_dimension.1 - self.kernel_size + 1).step_by(self.stride) {
+            for y in (0..self.dimension.1 * self.stride.1).step_by(self.stride.1) {
                 for z in 0..input_dimension.2 {
+                    let position = (x, y, z);
                     let output_index = util::get_index((o_x, o_y, z), self.dimension);
+                    let gradient = self.volume_gradients[output_index];
+                    let kernel_volume = self.window_scale(position, input_dimension);
                     
                     match self.pooling_type {
                         PoolingType::Max => {
-                            let mut max_index = util::get_index((x, y, z), input_dimension);
-                            let mut max_value = volume[max_index];
+                            volume_gradients[self.switches[output_index]] += gradient;
+                        },
 
-                            for kernel_y in 0..self.kernel_size {
-                                for kernel_x in 0..self.kernel_size {
-                                    let index = util::get_index((x + kernel_x, y + kernel_y, z), input_dimension);
+                        PoolingType::Average => {
+                            for index in self.window_indices(position, input_dimension) {
+                                volume_gradients[index] += gradient * kernel_volume;
+                            }
+                        },
 
-                                    let val = volume[index];
+                        PoolingType::Mixed(ratio) => {
+                            volume_gradients[self.window_max_index(position, input_dimension, volume)] += gradient * ratio;
 
-                                    if val > max_value {
-                                        max_index = index;
-                                        max_value = val;
-                                    }
-                                }
+                            for index in self.window_indices(position, input_dimension) {
+                                volume_gradients[index] += gradient * (1.0 - ratio) * kernel_volume;
                             }
+                        },
 
-                            volume_gradients[max_index] += self.volume_gradients[output_index];
+                        PoolingType::Stochastic if self.training => {
+                            let index = self.sampled_indices[output_index];
+                            if volume[index] > 0.0 { volume_gradients[index] += gradient };
                         },
 
-                        PoolingType::Average => {
-                            for kernel_y in 0..self.kernel_size {
-                                for kernel_x in 0..self.kernel_size {
-                                    let index = util::get_index((x + kernel_x, y + kernel_y, z), input_dimension);
+                        PoolingType::Stochastic => {
+                            let (sum, squares) = self.window_sums(position, input_dimension, volume);
+                            if sum <= 0.0 { continue };
 
-                                    volume_gradients[index] += self.volume_gradients[output_index] * kernel_volume;
-                                }
+                            // d/da (sum of a^2 / sum of a) = (2a * sum - squares) / sum^2
+                            for index in self.window_indices(position, input_dimension) {
+                                if volume[index] <= 0.0 { continue };
+
+                                volume_gradients[index] += gradient * (2.0 * volume[index] * sum - squares) / (sum * sum);
                             }
                         },
                     }
@@ -148,7 +304,8 @@ impl LayerBase for PoolingLayer {
                     self.zero_padding,
                     layer.num_kernels,
                     layer.kernel_size,
-                    layer.stride
+                    layer.stride,
+                    layer.rounding
                 )?;
 
                 layer.convolve(self.dimension, &self.volume, self.zero_padding);
@@ -160,7 +317,8 @@ impl LayerBase for PoolingLayer {
                     0, // a pooling layer doesn't take padding into account
                     layer.dimension.2,
                     layer.kernel_size,
-                    layer.stride
+                    layer.stride,
+                    layer.rounding
                 )?;
 
                 layer.convolve(self.dimension, &self.volume);
@@ -172,6 +330,27 @@ impl LayerBase for PoolingLayer {
 
                 layer.feed_forward(&self.volume);
             }
+
+            Layer::Add(layer) => {
+                if self.dimension != layer.dimension { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume)?;
+            }
+
+            Layer::MaxUnpool(layer) => layer.feed_forward(&self.volume),
+            Layer::SpatialDropout(layer) => layer.feed_forward(&self.volume)?,
+
+            Layer::Scale(layer) => {
+                if self.dimension != layer.dimension { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume)?;
+            }
+
+            Layer::SeparableConvolutional(layer) => {
+                layer.check_input(self.dimension, self.zero_padding)?;
+
+                layer.convolve(self.dimension, &self.volume, self.zero_padding);
+            }
         }
 
         Ok(())
@@ -185,7 +364,8 @@ impl LayerBase for PoolingLayer {
                     0,
                     self.dimension.2,
                     self.kernel_size,
-                    self.stride
+                    self.stride,
+                    self.rounding
                 )?;
 
                 self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients);
@@ -197,7 +377,73 @@ impl LayerBase for PoolingLayer {
                     0,
                     self.dimension.2,
                     self.kernel_size,
-                    self.stride
+                    self.stride,
+                    self.rounding
+                )?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients);
+            }
+
+            Layer::Add(layer) => {
+                util::check_output_dimension(layer.dimension,
+                    self.dimension,
+                    0,
+                    self.dimension.2,
+                    self.kernel_size,
+                    self.stride,
+                    self.rounding
+                )?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients);
+            }
+
+            Layer::MaxUnpool(layer) => {
+                util::check_output_dimension(layer.dimension,
+                    self.dimension,
+                    0,
+                    self.dimension.2,
+                    self.kernel_size,
+                    self.stride,
+                    self.rounding
+                )?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients);
+            }
+
+            Layer::SpatialDropout(layer) => {
+                util::check_output_dimension(layer.dimension,
+                    self.dimension,
+                    0,
+                    self.dimension.2,
+                    self.kernel_size,
+                    self.stride,
+                    self.rounding
+                )?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients);
+            }
+
+            Layer::Scale(layer) => {
+                util::check_output_dimension(layer.dimension,
+                    self.dimension,
+                    0,
+                    self.dimension.2,
+                    self.kernel_size,
+                    self.stride,
+                    self.rounding
+                )?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients);
+            }
+
+            Layer::SeparableConvolutional(layer) => {
+                util::check_output_dimension(layer.dimension,
+                    self.dimension,
+                    0,
+                    self.dimension.2,
+                    self.kernel_size,
+                    self.stride,
+                    self.rounding
                 )?;
 
                 self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients);
@@ -212,13 +458,14 @@ impl LayerBase for PoolingLayer {
 
 impl Serialize for PoolingLayer {
     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut state = serializer.serialize_struct("PoolingLayer", 5)?;
+        let mut state = serializer.serialize_struct("PoolingLayer", 6)?;
 
         state.serialize_field("pooling_type", &self.pooling_type)?;
         state.serialize_field("zero_padding", &self.zero_padding)?;
         state.serialize_field("stride", &self.stride)?;
         state.serialize_field("kernel_size", &self.kernel_size)?;
         state.serialize_field("dimension", &self.dimension)?;
+        state.serialize_field("rounding", &self.rounding)?;
 
         state.end()
     }
@@ -229,11 +476,27 @@ impl<'de> Deserialize<'de> for PoolingLayer {
         where
             D: serde::Deserializer<'de>,
     {
-        deserializer.deserialize_struct("PoolingLayer", &["pooling_type", "zero_padding", "stride", "kernel_size", "dimension"], PoolingLayerVisitor)
+        deserializer.deserialize_struct("PoolingLayer", &["pooling_type", "zero_padding", "stride", "kernel_size", "dimension", "rounding"], PoolingLayerVisitor { version: FORMAT_VERSION })
+    }
+}
+
+/// Deserializes a layer written with the given format version
+pub(crate) struct PoolingLayerVisitor {
+    pub(crate) version: u32,
+}
+
+impl<'de> DeserializeSeed<'de> for PoolingLayerVisitor {
+    type Value = PoolingLayer;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        let fields = &["pooling_type", "zero_padding", "stride", "kernel_size", "dimension", "rounding"];
+        deserializer.deserialize_struct("PoolingLayer", fields, self)
     }
 }
 
-struct PoolingLayerVisitor;
 impl<'de> Visitor<'de> for PoolingLayerVisitor {
     type Value = PoolingLayer;
 
@@ -250,6 +513,7 @@ impl<'de> Visitor<'de> for PoolingLayerVisitor {
         let mut stride = None;
         let mut kernel_size = None;
         let mut dimension = None;
+        let mut rounding = None;
         
         while let Some(key) = map.next_key::<&str>()? {
             match key {
@@ -268,7 +532,7 @@ impl<'de> Visitor<'de> for PoolingLayerVisitor {
                 "stride" => {
                     if stride.is_some() { return Err(serde::de::Error::duplicate_field("stride")); };
 
-                    stride = Some(map.next_value()?);
+                    stride = Some(if self.version < 3 { util::scalar_stride(map.next_value()?) } else { map.next_value()? });
                 },
 
                 "kernel_size" => {
@@ -283,6 +547,12 @@ impl<'de> Visitor<'de> for PoolingLayerVisitor {
                     dimension = Some(map.next_value()?);
                 },
 
+                "rounding" => {
+                    if rounding.is_some() { return Err(serde::de::Error::duplicate_field("rounding")); };
+
+                    rounding = Some(map.next_value()?);
+                },
+
                 _ => return Err(serde::de::Error::unknown_field(key, &["zero_padding", "stride", "kernel_size", "dimension", "input_depth", "kernel", "biases"])),
             }
         }
@@ -293,7 +563,17 @@ impl<'de> Visitor<'de> for PoolingLayerVisitor {
         let kernel_size = kernel_size.ok_or_else(|| serde::de::Error::missing_field("kernel_size"))?;
         let dimension = dimension.ok_or_else(|| serde::de::Error::missing_field("dimension"))?;
 
-        Ok(PoolingLayer::new(pooling_type, zero_padding, stride, kernel_size, dimension))
+        // the output rounding was added in format version 11
+        let rounding = match rounding {
+            Some(rounding) => rounding,
+            None if self.version < 11 => OutputRounding::Floor,
+            None => return Err(serde::de::Error::missing_field("rounding")),
+        };
+
+        let mut layer = PoolingLayer::new(pooling_type, zero_padding, stride, kernel_size, dimension);
+        layer.set_rounding(rounding);
+
+        Ok(layer)
     }
 
     fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
@@ -302,16 +582,30 @@ impl<'de> Visitor<'de> for PoolingLayerVisitor {
     {
         let pooling_type = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
         let zero_padding = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
-        let stride = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+        let stride = if self.version < 3 {
+            seq.next_element()?.map(util::scalar_stride)
+        } else {
+            seq.next_element()?
+        };
+        let stride = stride.ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
         let kernel_size = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(3, &self))?;
         let dimension = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(4, &self))?;
 
-        Ok(PoolingLayer::new(
+        let rounding = if self.version < 11 {
+            OutputRounding::Floor
+        } else {
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(5, &self))?
+        };
+
+        let mut layer = PoolingLayer::new(
             pooling_type,
             zero_padding,
             stride,
             kernel_size,
             dimension,
-        ))
+        );
+        layer.set_rounding(rounding);
+
+        Ok(layer)
     }
 }
\ No newline at end of file
@@ -0,0 +1,92 @@
+use crate::activations::{self, ActivationFunction};
+use crate::errors::Error;
+use crate::util::{self, Gradient};
+
+/// The learnable negative slopes of a layer using `ActivationFunction::PReLU`, one per channel
+///
+/// the buffers are empty for every other activation
+#[derive(Clone, Default)]
+pub(crate) struct PReluSlopes {
+    pub(crate) slopes: Vec<f32>,
+    pub(crate) gradients: Vec<Gradient>,
+    velocity: Vec<f32>,
+}
+
+#[allow(clippy::unused_unit)]
+impl PReluSlopes {
+    /// allocates the slopes for PReLU and keeps existing (e.g. loaded) slopes of the right size
+    pub(crate) fn configure(&mut self, func: ActivationFunction, channels: usize) -> () {
+        let ActivationFunction::PReLU = func else {
+            *self = Self::default();
+            return;
+        };
+
+        if self.slopes.len() != channels {
+            self.slopes = vec![activations::PRELU_INITIAL_SLOPE; channels];
+        }
+
+        self.gradients = vec![0.0; channels];
+        self.velocity = vec![0.0; channels];
+    }
+
+    /// restores slopes from a saved model, they are resized when the activation is configured
+    pub(crate) fn load(&mut self, slopes: Vec<f32>) -> () {
+        self.slopes = slopes;
+    }
+
+    pub(crate) fn set_slopes(&mut self, slopes: Vec<f32>) -> Result<(), Error> {
+        if self.slopes.len() != slopes.len() { return Err(Error::InvalidInput) };
+
+        self.slopes = slopes;
+        Ok(())
+    }
+
+    /// the channel of a value is `index % channels`
+    pub(crate) fn activate(&self, raw: &[f32], output: &mut [f32]) -> () {
+        let channels = self.slopes.len();
+
+        for i in 0..raw.len() {
+            output[i] = if raw[i] > 0.0 { raw[i] } else { self.slopes[i % channels] * raw[i] };
+        }
+    }
+
+    /// multiplies the gradients by the derivative and accumulates the gradients of the slopes
+    pub(crate) fn back_activate(&mut self, raw: &[f32], gradients: &[f32], result: &mut [f32]) -> () {
+        let channels = self.slopes.len();
+
+        for i in 0..raw.len() {
+            let channel = i % channels;
+
+            if raw[i] > 0.0 {
+                result[i] = gradients[i];
+            } else {
+                result[i] = self.slopes[channel] * gradients[i];
+                self.gradients[channel] += (raw[i] * gradients[i]) as Gradient;
+            }
+        }
+    }
+
+    /// the slopes aren't weight decayed since that would push them towards ReLU
+    pub(crate) fn apply_gradients(&mut self, learning_rate: f32, momentum: f32) -> () {
+        for i in 0..self.slopes.len() {
+            let vel = self.velocity[i] * momentum + learning_rate * util::from_gradient(self.gradients[i]);
+            self.velocity[i] = vel;
+            self.slopes[i] -= vel;
+        }
+    }
+
+    pub(crate) fn reset_gradients(&mut self) -> () {
+        self.gradients.fill(0.0);
+    }
+
+    pub(crate) fn reset_velocity(&mut self) -> () {
+        self.velocity.fill(0.0);
+    }
+
+    /// copies the slopes of a layer configured with the same activation
+    pub(crate) fn copy_from(&mut self, other: &PReluSlopes) -> () {
+        if self.slopes.len() == other.slopes.len() {
+            self.slopes.copy_from_slice(&other.slopes);
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::neural_network::NeuralNetwork;
+
+use serde::{Serialize, Deserialize};
+
+/// A vector of f32 values stored as int8, which are recovered as `(value - zero_point) * scale`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QuantizedTensor {
+    pub scale: f32,
+    pub zero_point: i8,
+    pub values: Vec<i8>,
+}
+
+impl QuantizedTensor {
+    /// maps the range of the values, extended to include 0 so it stays exact, onto [-128, 127]
+    pub fn new(values: &[f32]) -> Self {
+        let min = values.iter().cloned().fold(0.0, f32::min);
+        let max = values.iter().cloned().fold(0.0, f32::max);
+
+        // every value is 0
+        if max - min <= 0.0 {
+            return Self { scale: 1.0, zero_point: 0, values: vec![0; values.len()] };
+        }
+
+        let scale = (max - min) / 255.0;
+        let zero_point = (-128.0 - min / scale).round().clamp(-128.0, 127.0);
+
+        Self {
+            scale,
+            zero_point: zero_point as i8,
+            values: values.iter().map(|value| (value / scale + zero_point).round().clamp(-128.0, 127.0) as i8).collect(),
+        }
+    }
+
+    pub fn dequantize(&self) -> Vec<f32> {
+        self.values.iter().map(|&value| (value as f32 - self.zero_point as f32) * self.scale).collect()
+    }
+}
+
+/// A network whose kernels and weights are stored as int8 with a scale and zero point per kernel or weight tensor
+///
+/// the biases, PReLU slopes, scale gammas and everything else stay f32 since they're a small part of the parameters.
+/// `dequantize` recovers an f32 network for inference
+#[derive(Clone, Serialize, Deserialize)]
+pub struct QuantizedNetwork {
+    /// the network without its kernels and weights
+    network: NeuralNetwork,
+    /// the kernels and weights of the convolutional, separable convolutional and fully connected layers in order
+    tensors: Vec<QuantizedTensor>,
+}
+
+impl QuantizedNetwork {
+    pub(crate) fn new(neural_network: &NeuralNetwork) -> Self {
+        let mut network = neural_network.clone();
+
+        let tensors = network.weights_mut().into_iter()
+            .map(|weights| QuantizedTensor::new(&std::mem::take(weights)))
+            .collect();
+
+        Self { network, tensors }
+    }
+
+    /// the quantized kernels and weights of the convolutional, separable convolutional and fully connected layers in order,
+    /// the depthwise kernel of a separable layer before its pointwise kernel
+    pub fn get_tensors(&self) -> &Vec<QuantizedTensor> {
+        &self.tensors
+    }
+
+    /// returns the network with every kernel and weight replaced by its dequantized approximation
+    pub fn dequantize(&self) -> NeuralNetwork {
+        let mut network = self.network.clone();
+
+        for (weights, tensor) in network.weights_mut().into_iter().zip(&self.tensors) {
+            *weights = tensor.dequantize();
+        }
+
+        network
+    }
+}
@@ -0,0 +1,337 @@
+use crate::layer::{Layer, LayerBase};
+use crate::errors::Error;
+use crate::{activations, util};
+use crate::util::Gradient;
+
+use serde::{Serialize, Deserialize, de::{DeserializeSeed, Visitor}, ser::SerializeStruct};
+
+/// Multiplies every channel of the previous layer's output by a learnable `gamma`, then applies its activation
+///
+/// a cheap learnable rescaling between a convolution and its activation, the gammas start at 1
+#[derive(Clone)]
+pub struct ScaleLayer {
+    pub(crate) zero_padding: usize,
+
+    pub(crate) dimension: (usize, usize, usize),
+
+    /// one per channel
+    pub(crate) gamma: Vec<f32>,
+    pub(crate) gamma_gradients: Vec<Gradient>,
+    gamma_velocity: Vec<f32>,
+
+    pub(crate) volume: Vec<f32>,
+    pub(crate) volume_gradients: Vec<f32>,
+
+    /// the unscaled input of the last forward pass, which the gamma gradients are computed from
+    input: Vec<f32>,
+    raw_volume: Vec<f32>,
+    back_activated_volume: Vec<f32>,
+}
+
+#[allow(clippy::unused_unit, clippy::ptr_arg)]
+impl ScaleLayer {
+    pub fn new(zero_padding: usize, dimension: (usize, usize, usize)) -> Self {
+        let (dimension_x, dimension_y, depth) = dimension;
+
+        Self {
+            zero_padding,
+
+            dimension,
+
+            gamma: vec![1.0; depth],
+            gamma_gradients: vec![0.0; depth],
+            gamma_velocity: vec![0.0; depth],
+
+            volume: vec![0.0; dimension_x * dimension_y * depth],
+            volume_gradients: vec![0.0; dimension_x * dimension_y * depth],
+
+            input: vec![0.0; dimension_x * dimension_y * depth],
+            raw_volume: vec![0.0; dimension_x * dimension_y * depth],
+            back_activated_volume: vec![0.0; dimension_x * dimension_y * depth],
+        }
+    }
+
+    pub fn get_gamma(&self) -> &Vec<f32> {
+        &self.gamma
+    }
+
+    pub fn set_gamma(&mut self, gamma: Vec<f32>) -> Result<(), Error> {
+        if gamma.len() != self.gamma.len() { return Err(Error::InvalidInput) };
+
+        self.gamma = gamma;
+        Ok(())
+    }
+
+    /// takes the output of the previous layer and scales every channel
+    #[allow(clippy::needless_range_loop)]
+    pub(crate) fn feed_forward(&mut self, input: &[f32]) -> Result<(), Error> {
+        if input.len() != self.raw_volume.len() { return Err(Error::DimensionMismatch) };
+
+        self.input.copy_from_slice(input);
+
+        // the depth is the innermost index of a volume
+        let depth = self.dimension.2;
+
+        for i in 0..input.len() {
+            self.raw_volume[i] = input[i] * self.gamma[i % depth];
+        }
+
+        Ok(())
+    }
+
+    /// accumulates the gamma gradients and passes the scaled gradients to the previous layer
+    #[allow(clippy::needless_range_loop)]
+    fn feed_back(&mut self, previous_gradients: &mut Vec<f32>) -> Result<(), Error> {
+        if previous_gradients.len() != self.back_activated_volume.len() { return Err(Error::DimensionMismatch) };
+
+        let depth = self.dimension.2;
+
+        for i in 0..previous_gradients.len() {
+            self.gamma_gradients[i % depth] += (self.input[i] * self.back_activated_volume[i]) as Gradient;
+            previous_gradients[i] = self.gamma[i % depth] * self.back_activated_volume[i];
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn activate(&mut self, func: activations::ActivationFunction) -> () {
+        if let activations::ActivationFunction::Softmax = func {
+            activations::softmax_into(&self.raw_volume, &mut self.volume);
+            return;
+        }
+
+        for i in 0..self.volume.len() {
+            self.volume[i] = activations::eval(func, self.raw_volume[i]);
+        }
+    }
+
+    pub(crate) fn back_activate(&mut self, func: activations::ActivationFunction) -> () {
+        if let activations::ActivationFunction::Softmax = func {
+            activations::softmax_derivative(&self.volume, &self.volume_gradients, &mut self.back_activated_volume);
+            return;
+        }
+
+        for i in 0..self.volume.len() {
+            self.back_activated_volume[i] = activations::eval_derivative(func, self.raw_volume[i]) * self.volume_gradients[i];
+        }
+    }
+
+    /// the gammas aren't weight decayed since that would pull them towards 0 instead of 1
+    pub(crate) fn apply_gradients(&mut self, learning_rate: f32, momentum: f32) -> () {
+        for i in 0..self.gamma.len() {
+            let vel = self.gamma_velocity[i] * momentum + learning_rate * util::from_gradient(self.gamma_gradients[i]);
+            self.gamma_velocity[i] = vel;
+            self.gamma[i] -= vel;
+        }
+    }
+
+    pub(crate) fn reset_gradients(&mut self) -> () {
+        self.gamma_gradients.fill(0.0);
+    }
+
+    pub(crate) fn reset_velocity(&mut self) -> () {
+        self.gamma_velocity.fill(0.0);
+    }
+
+    /// resets every gamma to 1
+    pub(crate) fn initialize(&mut self) -> () {
+        self.gamma.fill(1.0);
+    }
+
+    pub(crate) fn release_activations(&mut self) -> () {
+        self.volume = Vec::new();
+        self.input = Vec::new();
+        self.raw_volume = Vec::new();
+    }
+
+    pub(crate) fn restore_activations(&mut self) -> () {
+        let (dimension_x, dimension_y, depth) = self.dimension;
+        let size = dimension_x * dimension_y * depth;
+
+        self.volume.resize(size, 0.0);
+        self.input.resize(size, 0.0);
+        self.raw_volume.resize(size, 0.0);
+    }
+
+    pub(crate) fn same_structure(&self, other: &ScaleLayer) -> bool {
+        self.zero_padding == other.zero_padding && self.dimension == other.dimension
+    }
+
+    pub(crate) fn copy_weights_from(&mut self, other: &ScaleLayer) -> Result<(), Error> {
+        if !self.same_structure(other) { return Err(Error::IncompatibleLayers) };
+
+        self.gamma.copy_from_slice(&other.gamma);
+        Ok(())
+    }
+}
+
+impl LayerBase for ScaleLayer {
+    fn forward_propagate(&self, next_layer: &mut Layer) -> Result<(), Error> {
+        match next_layer {
+            Layer::Convolutional(layer) => {
+                util::check_output_dimension(self.dimension,
+                    layer.dimension,
+                    self.zero_padding,
+                    layer.num_kernels,
+                    layer.kernel_size,
+                    layer.stride,
+                    layer.rounding
+                )?;
+
+                layer.convolve(self.dimension, &self.volume, self.zero_padding);
+            }
+
+            Layer::Pooling(layer) => {
+                util::check_output_dimension(self.dimension,
+                    layer.dimension,
+                    0, // a pooling layer doesn't take padding into account
+                    layer.dimension.2,
+                    layer.kernel_size,
+                    layer.stride,
+                    layer.rounding
+                )?;
+
+                layer.convolve(self.dimension, &self.volume);
+            }
+
+            Layer::FullyConnected(layer) => {
+                let dim = self.dimension;
+                if dim.0 * dim.1 * dim.2 != layer.num_inputs { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume);
+            }
+
+            Layer::Add(layer) => {
+                if self.dimension != layer.dimension { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume)?;
+            }
+
+            Layer::MaxUnpool(layer) => layer.feed_forward(&self.volume),
+            Layer::SpatialDropout(layer) => layer.feed_forward(&self.volume)?,
+
+            Layer::Scale(layer) => {
+                if self.dimension != layer.dimension { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume)?;
+            }
+
+            Layer::SeparableConvolutional(layer) => {
+                layer.check_input(self.dimension, self.zero_padding)?;
+
+                layer.convolve(self.dimension, &self.volume, self.zero_padding);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn back_propagate(&mut self, previous_layer: &mut Layer) -> Result<(), Error> {
+        self.feed_back(previous_layer.output_gradients_mut())
+    }
+}
+
+impl Serialize for ScaleLayer {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("ScaleLayer", 3)?;
+
+        state.serialize_field("zero_padding", &self.zero_padding)?;
+        state.serialize_field("dimension", &self.dimension)?;
+        state.serialize_field("gamma", &self.gamma)?;
+
+        state.end()
+    }
+}
+
+const SCALE_LAYER_FIELDS: &[&str] = &["zero_padding", "dimension", "gamma"];
+
+impl<'de> Deserialize<'de> for ScaleLayer {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("ScaleLayer", SCALE_LAYER_FIELDS, ScaleLayerVisitor)
+    }
+}
+
+/// the scale layer format hasn't changed since it was introduced in format version 10
+pub(crate) struct ScaleLayerVisitor;
+
+impl ScaleLayerVisitor {
+    fn build<E: serde::de::Error>(zero_padding: usize, dimension: (usize, usize, usize), gamma: Vec<f32>) -> Result<ScaleLayer, E> {
+        let mut layer = ScaleLayer::new(zero_padding, dimension);
+        if gamma.len() != dimension.2 { return Err(E::invalid_length(gamma.len(), &"one gamma per channel")) };
+
+        layer.gamma = gamma;
+        Ok(layer)
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for ScaleLayerVisitor {
+    type Value = ScaleLayer;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("ScaleLayer", SCALE_LAYER_FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for ScaleLayerVisitor {
+    type Value = ScaleLayer;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a ScaleLayer struct")
+    }
+
+    fn visit_map<M>(self, mut map: M) -> Result<Self::Value, M::Error>
+        where
+            M: serde::de::MapAccess<'de>,
+    {
+        let mut zero_padding = None;
+        let mut dimension = None;
+        let mut gamma = None;
+
+        while let Some(key) = map.next_key::<&str>()? {
+            match key {
+                "zero_padding" => {
+                    if zero_padding.is_some() { return Err(serde::de::Error::duplicate_field("zero_padding")); };
+
+                    zero_padding = Some(map.next_value()?);
+                }
+
+                "dimension" => {
+                    if dimension.is_some() { return Err(serde::de::Error::duplicate_field("dimension")); };
+
+                    dimension = Some(map.next_value()?);
+                }
+
+                "gamma" => {
+                    if gamma.is_some() { return Err(serde::de::Error::duplicate_field("gamma")); };
+
+                    gamma = Some(map.next_value()?);
+                }
+
+                _ => return Err(serde::de::Error::unknown_field(key, SCALE_LAYER_FIELDS)),
+            }
+        }
+
+        let zero_padding = zero_padding.ok_or_else(|| serde::de::Error::missing_field("zero_padding"))?;
+        let dimension = dimension.ok_or_else(|| serde::de::Error::missing_field("dimension"))?;
+        let gamma = gamma.ok_or_else(|| serde::de::Error::missing_field("gamma"))?;
+
+        Self::build(zero_padding, dimension, gamma)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let zero_padding = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+        let dimension = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+        let gamma = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+
+        Self::build(zero_padding, dimension, gamma)
+    }
+}
@@ -0,0 +1,63 @@
+use crate::errors::Error;
+
+use std::f32::consts::PI;
+
+/// Computes the learning rate of every batch from the number of batches trained so far
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LearningRateSchedule {
+    /// rises linearly from `base` to `max` over `step_size` steps, falls back over the next `step_size` steps and repeats
+    Triangular { base: f32, max: f32, step_size: usize },
+
+    /// rises linearly from `max / div_factor` to `max` over the first `warmup_fraction` of `total_steps`,
+    /// then anneals along a cosine to `max / (div_factor * final_div_factor)` and stays there after `total_steps`
+    OneCycle { max: f32, total_steps: usize, warmup_fraction: f32, div_factor: f32, final_div_factor: f32 },
+}
+
+impl LearningRateSchedule {
+    /// a one cycle schedule with the usual 30% warmup, a starting rate of `max / 25` and a final rate of `max / 25e4`
+    pub fn one_cycle(max: f32, total_steps: usize) -> Self {
+        Self::OneCycle { max, total_steps, warmup_fraction: 0.3, div_factor: 25.0, final_div_factor: 1e4 }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        match *self {
+            Self::Triangular { base, max, step_size } => {
+                if step_size == 0 || !(0.0..).contains(&base) || !(base..).contains(&max) { return Err(Error::InvalidInput) };
+            }
+
+            Self::OneCycle { max, total_steps, warmup_fraction, div_factor, final_div_factor } => {
+                if total_steps == 0 || max.is_nan() || max <= 0.0 || !(0.0..=1.0).contains(&warmup_fraction) { return Err(Error::InvalidInput) };
+                if !(1.0..).contains(&div_factor) || !(1.0..).contains(&final_div_factor) { return Err(Error::InvalidInput) };
+            }
+        }
+
+        Ok(())
+    }
+
+    /// the learning rate of the batch at `step`, counted from 0
+    pub fn learning_rate(&self, step: usize) -> f32 {
+        match *self {
+            Self::Triangular { base, max, step_size } => {
+                let position = (step % (2 * step_size)) as f32 / step_size as f32;
+
+                // 0 at the start of a cycle, 1 at its peak
+                let height = 1.0 - (position - 1.0).abs();
+                base + (max - base) * height
+            }
+
+            Self::OneCycle { max, total_steps, warmup_fraction, div_factor, final_div_factor } => {
+                let initial = max / div_factor;
+                let last = initial / final_div_factor;
+
+                let warmup_steps = (total_steps as f32 * warmup_fraction).round() as usize;
+
+                if step < warmup_steps {
+                    return initial + (max - initial) * step as f32 / warmup_steps as f32;
+                }
+
+                let progress = ((step - warmup_steps) as f32 / (total_steps - warmup_steps).max(1) as f32).min(1.0);
+                last + (max - last) * (1.0 + (PI * progress).cos()) * 0.5
+            }
+        }
+    }
+}
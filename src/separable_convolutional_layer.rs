@@ -0,0 +1,655 @@
+use crate::layer::{Layer, LayerBase, LearnableLayer};
+use crate::errors::Error;
+use crate::{activations, util};
+use crate::initialization;
+use crate::prelu::PReluSlopes;
+use crate::util::{Gradient, OutputRounding};
+
+use serde::{Serialize, Deserialize, de::{DeserializeSeed, Visitor}, ser::SerializeStruct};
+
+/// A depthwise convolution, which filters every input channel on its own, followed by a 1x1 pointwise convolution
+///
+/// computes the same as a convolution with one kernel per input channel followed by a 1x1 convolution,
+/// with `kernel_size^2 * input_depth + input_depth * num_kernels` weights instead of `kernel_size^2 * input_depth * num_kernels`.
+/// only the pointwise convolution has biases
+#[derive(Clone)]
+pub struct SeparableConvolutionalLayer {
+    /// (x, y)
+    pub(crate) stride: (usize, usize),
+    pub(crate) kernel_size: usize,
+    pub(crate) num_kernels: usize,
+    pub(crate) input_depth: usize,
+
+    pub(crate) zero_padding: usize,
+    pub(crate) dimension: (usize, usize, usize),
+
+    /// a (kernel_size, kernel_size) filter per input channel, laid out like a kernel of a convolutional layer with an input depth of 1
+    pub(crate) depthwise_kernel: Vec<f32>,
+    /// `input_depth` weights per output channel, laid out like the kernel of a 1x1 convolutional layer
+    pub(crate) pointwise_kernel: Vec<f32>,
+    pub(crate) biases: Vec<f32>,
+
+    pub(crate) depthwise_gradients: Vec<Gradient>,
+    pub(crate) pointwise_gradients: Vec<Gradient>,
+    pub(crate) bias_gradients: Vec<Gradient>,
+
+    depthwise_velocity: Vec<f32>,
+    pointwise_velocity: Vec<f32>,
+    bias_velocity: Vec<f32>,
+
+    /// the output of the depthwise convolution, a (dimension.0, dimension.1, input_depth) volume, and its gradients
+    depthwise_volume: Vec<f32>,
+    depthwise_volume_gradients: Vec<f32>,
+
+    pub(crate) volume: Vec<f32>,
+    pub(crate) volume_gradients: Vec<f32>,
+
+    raw_volume: Vec<f32>,
+    back_activated_volume: Vec<f32>,
+
+    pub(crate) prelu: PReluSlopes,
+}
+
+#[allow(clippy::unused_unit, clippy::ptr_arg)]
+impl SeparableConvolutionalLayer {
+    pub fn new(zero_padding: usize, stride: (usize, usize), kernel_size: usize, dimension: (usize, usize, usize), input_depth: usize) -> Self {
+        let (dimension_x, dimension_y, depth) = dimension;
+
+        Self {
+            stride,
+            kernel_size,
+            num_kernels: depth,
+            input_depth,
+
+            zero_padding,
+            dimension,
+
+            depthwise_kernel: vec![0.0; kernel_size * kernel_size * input_depth],
+            pointwise_kernel: vec![0.0; input_depth * depth],
+            biases: vec![0.0; depth],
+
+            depthwise_gradients: vec![0.0; kernel_size * kernel_size * input_depth],
+            pointwise_gradients: vec![0.0; input_depth * depth],
+            bias_gradients: vec![0.0; depth],
+
+            depthwise_velocity: vec![0.0; kernel_size * kernel_size * input_depth],
+            pointwise_velocity: vec![0.0; input_depth * depth],
+            bias_velocity: vec![0.0; depth],
+
+            depthwise_volume: vec![0.0; dimension_x * dimension_y * input_depth],
+            depthwise_volume_gradients: vec![0.0; dimension_x * dimension_y * input_depth],
+
+            volume: vec![0.0; dimension_x * dimension_y * depth],
+            volume_gradients: vec![0.0; dimension_x * dimension_y * depth],
+
+            raw_volume: vec![0.0; dimension_x * dimension_y * depth],
+            back_activated_volume: vec![0.0; dimension_x * dimension_y * depth],
+
+            prelu: PReluSlopes::default(),
+        }
+    }
+
+    pub(crate) fn configure_activation(&mut self, func: activations::ActivationFunction) -> () {
+        self.prelu.configure(func, self.num_kernels);
+    }
+
+    pub(crate) fn release_activations(&mut self) -> () {
+        self.volume = Vec::new();
+        self.raw_volume = Vec::new();
+        self.depthwise_volume = Vec::new();
+    }
+
+    pub(crate) fn restore_activations(&mut self) -> () {
+        let (dimension_x, dimension_y, depth) = self.dimension;
+
+        self.volume.resize(dimension_x * dimension_y * depth, 0.0);
+        self.raw_volume.resize(dimension_x * dimension_y * depth, 0.0);
+        self.depthwise_volume.resize(dimension_x * dimension_y * self.input_depth, 0.0);
+    }
+
+    pub fn get_depthwise_kernel(&self) -> &Vec<f32> {
+        &self.depthwise_kernel
+    }
+
+    pub fn set_depthwise_kernel(&mut self, kernel: Vec<f32>) -> Result<(), Error> {
+        if self.depthwise_kernel.len() != kernel.len() { return Err(Error::InvalidInput) };
+
+        self.depthwise_kernel = kernel;
+        Ok(())
+    }
+
+    pub fn get_pointwise_kernel(&self) -> &Vec<f32> {
+        &self.pointwise_kernel
+    }
+
+    pub fn set_pointwise_kernel(&mut self, kernel: Vec<f32>) -> Result<(), Error> {
+        if self.pointwise_kernel.len() != kernel.len() { return Err(Error::InvalidInput) };
+
+        self.pointwise_kernel = kernel;
+        Ok(())
+    }
+
+    pub fn get_biases(&self) -> &Vec<f32> {
+        &self.biases
+    }
+
+    pub fn set_biases(&mut self, biases: Vec<f32>) -> Result<(), Error> {
+        if self.biases.len() != biases.len() { return Err(Error::InvalidInput) };
+
+        self.biases = biases;
+        Ok(())
+    }
+
+    /// the learned negative slope of every kernel when using PReLU, empty otherwise
+    pub fn get_prelu_slopes(&self) -> &Vec<f32> {
+        &self.prelu.slopes
+    }
+
+    pub fn set_prelu_slopes(&mut self, slopes: Vec<f32>) -> Result<(), Error> {
+        self.prelu.set_slopes(slopes)
+    }
+
+    pub(crate) fn same_structure(&self, other: &SeparableConvolutionalLayer) -> bool {
+        self.dimension == other.dimension &&
+            self.kernel_size == other.kernel_size &&
+            self.stride == other.stride &&
+            self.zero_padding == other.zero_padding &&
+            self.input_depth == other.input_depth
+    }
+
+    pub(crate) fn copy_weights_from(&mut self, other: &SeparableConvolutionalLayer) -> Result<(), Error> {
+        if !self.same_structure(other) { return Err(Error::IncompatibleLayers) };
+
+        self.depthwise_kernel.copy_from_slice(&other.depthwise_kernel);
+        self.pointwise_kernel.copy_from_slice(&other.pointwise_kernel);
+        self.biases.copy_from_slice(&other.biases);
+        self.prelu.copy_from(&other.prelu);
+
+        Ok(())
+    }
+
+    pub fn apply_gradients(&mut self, learning_rate: f32, momentum: f32, weight_decay: f32) -> () {
+        for i in 0..self.biases.len() {
+            let vel = self.bias_velocity[i] * momentum + learning_rate * util::from_gradient(self.bias_gradients[i]);
+            self.bias_velocity[i] = vel;
+            self.biases[i] -= vel;
+        }
+
+        for i in 0..self.depthwise_kernel.len() {
+            let gradient = util::from_gradient(self.depthwise_gradients[i]) + weight_decay * self.depthwise_kernel[i];
+            let vel = self.depthwise_velocity[i] * momentum + learning_rate * gradient;
+            self.depthwise_velocity[i] = vel;
+
+            self.depthwise_kernel[i] -= vel;
+        }
+
+        for i in 0..self.pointwise_kernel.len() {
+            let gradient = util::from_gradient(self.pointwise_gradients[i]) + weight_decay * self.pointwise_kernel[i];
+            let vel = self.pointwise_velocity[i] * momentum + learning_rate * gradient;
+            self.pointwise_velocity[i] = vel;
+
+            self.pointwise_kernel[i] -= vel;
+        }
+
+        self.prelu.apply_gradients(learning_rate, momentum);
+    }
+
+    /// applies the gradients without weight decay, then shrinks both kernels by `decay` times themselves as in AdamW
+    pub fn apply_gradients_decoupled(&mut self, learning_rate: f32, momentum: f32, decay: f32) -> () {
+        self.apply_gradients(learning_rate, momentum, 0.0);
+
+        for weight in self.depthwise_kernel.iter_mut().chain(self.pointwise_kernel.iter_mut()) {
+            *weight -= decay * *weight;
+        }
+    }
+
+    /// zeroes the momentum of the kernels, biases and PReLU slopes without changing them
+    pub fn reset_velocity(&mut self) -> () {
+        self.depthwise_velocity.fill(0.0);
+        self.pointwise_velocity.fill(0.0);
+        self.bias_velocity.fill(0.0);
+        self.prelu.reset_velocity();
+    }
+
+    pub(crate) fn convolve(&mut self, input_dimension: (usize, usize, usize), volume: &Vec<f32>, zero_padding: usize) -> () {
+        let depthwise_dimension = (self.dimension.0, self.dimension.1, self.input_depth);
+
+        for z in 0..self.input_depth {
+            for (o_x, x) in (0..self.dimension.0 * self.stride.0).step_by(self.stride.0).enumerate() {
+                let columns = util::kernel_range(x, zero_padding, input_dimension.0, self.kernel_size);
+
+                for (o_y, y) in (0..self.dimension.1 * self.stride.1).step_by(self.stride.1).enumerate() {
+                    let rows = util::kernel_range(y, zero_padding, input_dimension.1, self.kernel_size);
+                    let mut value: f32 = 0.0;
+
+                    // the padding is skipped instead of multiplied by zero
+                    for kernel_y in rows.clone() {
+                        for kernel_x in columns.clone() {
+                            let ind = util::get_index((x + kernel_x - zero_padding, y + kernel_y - zero_padding, z), input_dimension);
+
+                            value += volume[ind] * self.depthwise_kernel[util::get_kernel_index((kernel_x, kernel_y, 0, z), self.kernel_size, 1)];
+                        }
+                    }
+
+                    self.depthwise_volume[util::get_index((o_x, o_y, z), depthwise_dimension)] = value;
+                }
+            }
+        }
+
+        // a 1x1 convolution is a linear combination of the channels at every position
+        for pixel in 0..self.dimension.0 * self.dimension.1 {
+            let channels = &self.depthwise_volume[pixel * self.input_depth..(pixel + 1) * self.input_depth];
+
+            for k in 0..self.num_kernels {
+                let weights = &self.pointwise_kernel[k * self.input_depth..(k + 1) * self.input_depth];
+                let value: f32 = channels.iter().zip(weights).map(|(channel, weight)| channel * weight).sum();
+
+                self.raw_volume[pixel * self.num_kernels + k] = value + self.biases[k];
+            }
+        }
+    }
+
+    fn convolve_back(&mut self, input_dimension: (usize, usize, usize), volume: &Vec<f32>, volume_gradients: &mut Vec<f32>, zero_padding: usize) -> () {
+        self.depthwise_volume_gradients.fill(0.0);
+
+        for pixel in 0..self.dimension.0 * self.dimension.1 {
+            for k in 0..self.num_kernels {
+                let derivative = self.back_activated_volume[pixel * self.num_kernels + k];
+                if derivative == 0.0 { continue };
+
+                self.bias_gradients[k] += derivative as Gradient;
+
+                for z in 0..self.input_depth {
+                    let (channel, weight) = (pixel * self.input_depth + z, k * self.input_depth + z);
+
+                    self.pointwise_gradients[weight] += (self.depthwise_volume[channel] * derivative) as Gradient;
+                    self.depthwise_volume_gradients[channel] += self.pointwise_kernel[weight] * derivative;
+                }
+            }
+        }
+
+        volume_gradients.fill(0.0);
+        let depthwise_dimension = (self.dimension.0, self.dimension.1, self.input_depth);
+
+        for z in 0..self.input_depth {
+            for (o_x, x) in (0..self.dimension.0 * self.stride.0).step_by(self.stride.0).enumerate() {
+                let columns = util::kernel_range(x, zero_padding, input_dimension.0, self.kernel_size);
+
+                for (o_y, y) in (0..self.dimension.1 * self.stride.1).step_by(self.stride.1).enumerate() {
+                    let derivative = self.depthwise_volume_gradients[util::get_index((o_x, o_y, z), depthwise_dimension)];
+                    if derivative == 0.0 { continue };
+
+                    let rows = util::kernel_range(y, zero_padding, input_dimension.1, self.kernel_size);
+
+                    for kernel_y in rows.clone() {
+                        for kernel_x in columns.clone() {
+                            let kernel_index = util::get_kernel_index((kernel_x, kernel_y, 0, z), self.kernel_size, 1);
+                            let ind = util::get_index((x + kernel_x - zero_padding, y + kernel_y - zero_padding, z), input_dimension);
+
+                            self.depthwise_gradients[kernel_index] += (volume[ind] * derivative) as Gradient;
+                            volume_gradients[ind] += self.depthwise_kernel[kernel_index] * derivative;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// the previous layer's output is a volume of `input_dimension` seen with `zero_padding`
+    pub(crate) fn check_input(&self, input_dimension: (usize, usize, usize), zero_padding: usize) -> Result<(), Error> {
+        if input_dimension.2 != self.input_depth { return Err(Error::DimensionMismatch) };
+
+        util::check_output_dimension(input_dimension,
+            self.dimension,
+            zero_padding,
+            self.num_kernels,
+            self.kernel_size,
+            self.stride,
+            OutputRounding::Floor
+        )
+    }
+}
+
+impl LayerBase for SeparableConvolutionalLayer {
+    fn forward_propagate(&self, next_layer: &mut Layer) -> Result<(), Error> {
+        match next_layer {
+            Layer::Convolutional(layer) => {
+                util::check_output_dimension(self.dimension,
+                    layer.dimension,
+                    self.zero_padding,
+                    layer.num_kernels,
+                    layer.kernel_size,
+                    layer.stride,
+                    layer.rounding
+                )?;
+
+                layer.convolve(self.dimension, &self.volume, self.zero_padding);
+            }
+
+            Layer::Pooling(layer) => {
+                util::check_output_dimension(self.dimension,
+                    layer.dimension,
+                    0, // a pooling layer doesn't take padding into account
+                    layer.dimension.2,
+                    layer.kernel_size,
+                    layer.stride,
+                    layer.rounding
+                )?;
+
+                layer.convolve(self.dimension, &self.volume);
+            }
+
+            Layer::FullyConnected(layer) => {
+                let dim = self.dimension;
+                if dim.0 * dim.1 * dim.2 != layer.num_inputs { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume);
+            }
+
+            Layer::Add(layer) => {
+                if self.dimension != layer.dimension { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume)?;
+            }
+
+            Layer::MaxUnpool(layer) => layer.feed_forward(&self.volume),
+            Layer::SpatialDropout(layer) => layer.feed_forward(&self.volume)?,
+
+            Layer::Scale(layer) => {
+                if self.dimension != layer.dimension { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume)?;
+            }
+
+            Layer::SeparableConvolutional(layer) => {
+                layer.check_input(self.dimension, self.zero_padding)?;
+
+                layer.convolve(self.dimension, &self.volume, self.zero_padding);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn back_propagate(&mut self, previous_layer: &mut Layer) -> Result<(), Error> {
+        match previous_layer {
+            Layer::Convolutional(layer) => {
+                self.check_input(layer.dimension, layer.zero_padding)?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients, layer.zero_padding);
+            }
+
+            Layer::Pooling(layer) => {
+                self.check_input(layer.dimension, layer.zero_padding)?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients, layer.zero_padding);
+            }
+
+            Layer::Add(layer) => {
+                self.check_input(layer.dimension, layer.zero_padding)?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients, layer.zero_padding);
+            }
+
+            Layer::MaxUnpool(layer) => {
+                self.check_input(layer.dimension, layer.zero_padding)?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients, layer.zero_padding);
+            }
+
+            Layer::SpatialDropout(layer) => {
+                self.check_input(layer.dimension, layer.zero_padding)?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients, layer.zero_padding);
+            }
+
+            Layer::Scale(layer) => {
+                self.check_input(layer.dimension, layer.zero_padding)?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients, layer.zero_padding);
+            }
+
+            Layer::SeparableConvolutional(layer) => {
+                self.check_input(layer.dimension, layer.zero_padding)?;
+
+                self.convolve_back(layer.dimension, &layer.volume, &mut layer.volume_gradients, layer.zero_padding);
+            }
+
+            _ => ()
+        }
+
+        Ok(())
+    }
+}
+
+#[allow(clippy::unused_unit)]
+impl LearnableLayer for SeparableConvolutionalLayer {
+    fn initialize(&mut self, func: initialization::Initialization) -> () {
+        let window = self.kernel_size * self.kernel_size;
+
+        // every depthwise output only sees one channel
+        initialization::eval(func, window, window, &mut self.depthwise_kernel);
+        initialization::eval(func, self.input_depth, self.num_kernels, &mut self.pointwise_kernel);
+        initialization::eval(func, self.input_depth, self.num_kernels, &mut self.biases);
+    }
+
+    fn activate(&mut self, func: activations::ActivationFunction) -> () {
+        if let activations::ActivationFunction::Softmax = func {
+            activations::softmax_into(&self.raw_volume, &mut self.volume);
+            return;
+        }
+
+        if let activations::ActivationFunction::PReLU = func {
+            self.prelu.activate(&self.raw_volume, &mut self.volume);
+            return;
+        }
+
+        for i in 0..self.volume.len() {
+            self.volume[i] = activations::eval(func, self.raw_volume[i]);
+        }
+    }
+
+    fn back_activate(&mut self, func: activations::ActivationFunction) -> () {
+        if let activations::ActivationFunction::Softmax = func {
+            activations::softmax_derivative(&self.volume, &self.volume_gradients, &mut self.back_activated_volume);
+            return;
+        }
+
+        if let activations::ActivationFunction::PReLU = func {
+            self.prelu.back_activate(&self.raw_volume, &self.volume_gradients, &mut self.back_activated_volume);
+            return;
+        }
+
+        for i in 0..self.volume.len() {
+            self.back_activated_volume[i] = activations::eval_derivative(func, self.raw_volume[i]) * self.volume_gradients[i];
+        }
+    }
+
+    fn reset_gradients(&mut self) -> () {
+        self.depthwise_gradients.fill(0.0);
+        self.pointwise_gradients.fill(0.0);
+        self.bias_gradients.fill(0.0);
+
+        self.prelu.reset_gradients();
+    }
+}
+
+impl Serialize for SeparableConvolutionalLayer {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("SeparableConvolutionalLayer", 9)?;
+
+        state.serialize_field("zero_padding", &self.zero_padding)?;
+        state.serialize_field("stride", &self.stride)?;
+        state.serialize_field("kernel_size", &self.kernel_size)?;
+        state.serialize_field("dimension", &self.dimension)?;
+        state.serialize_field("input_depth", &self.input_depth)?;
+
+        state.serialize_field("depthwise_kernel", &self.depthwise_kernel)?;
+        state.serialize_field("pointwise_kernel", &self.pointwise_kernel)?;
+        state.serialize_field("biases", &self.biases)?;
+        state.serialize_field("prelu_slopes", &self.prelu.slopes)?;
+
+        state.end()
+    }
+}
+
+const SEPARABLE_CONVOLUTIONAL_LAYER_FIELDS: &[&str] = &[
+    "zero_padding", "stride", "kernel_size", "dimension", "input_depth", "depthwise_kernel", "pointwise_kernel", "biases", "prelu_slopes"
+];
+
+impl<'de> Deserialize<'de> for SeparableConvolutionalLayer {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("SeparableConvolutionalLayer", SEPARABLE_CONVOLUTIONAL_LAYER_FIELDS, SeparableConvolutionalLayerVisitor)
+    }
+}
+
+/// the separable convolutional layer format hasn't changed since it was introduced in format version 12
+pub(crate) struct SeparableConvolutionalLayerVisitor;
+
+/// the fields of a serialized layer in order
+type SeparableConvolutionalFields = (usize, (usize, usize), usize, (usize, usize, usize), usize, Vec<f32>, Vec<f32>, Vec<f32>, Vec<f32>);
+
+impl SeparableConvolutionalLayerVisitor {
+    fn build<E: serde::de::Error>(fields: SeparableConvolutionalFields) -> Result<SeparableConvolutionalLayer, E> {
+        let (zero_padding, stride, kernel_size, dimension, input_depth, depthwise_kernel, pointwise_kernel, biases, prelu_slopes) = fields;
+        let mut layer = SeparableConvolutionalLayer::new(zero_padding, stride, kernel_size, dimension, input_depth);
+
+        if depthwise_kernel.len() != layer.depthwise_kernel.len() { return Err(E::invalid_length(depthwise_kernel.len(), &"a filter per input channel")) };
+        if pointwise_kernel.len() != layer.pointwise_kernel.len() { return Err(E::invalid_length(pointwise_kernel.len(), &"a weight per input and output channel")) };
+        if biases.len() != layer.biases.len() { return Err(E::invalid_length(biases.len(), &"a bias per output channel")) };
+
+        layer.depthwise_kernel = depthwise_kernel;
+        layer.pointwise_kernel = pointwise_kernel;
+        layer.biases = biases;
+        layer.prelu.load(prelu_slopes);
+
+        Ok(layer)
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for SeparableConvolutionalLayerVisitor {
+    type Value = SeparableConvolutionalLayer;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("SeparableConvolutionalLayer", SEPARABLE_CONVOLUTIONAL_LAYER_FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for SeparableConvolutionalLayerVisitor {
+    type Value = SeparableConvolutionalLayer;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a SeparableConvolutionalLayer struct")
+    }
+
+    fn visit_map<M>(self, mut map: M) -> Result<Self::Value, M::Error>
+        where
+            M: serde::de::MapAccess<'de>,
+    {
+        let mut zero_padding = None;
+        let mut stride = None;
+        let mut kernel_size = None;
+        let mut dimension = None;
+        let mut input_depth = None;
+
+        let mut depthwise_kernel = None;
+        let mut pointwise_kernel = None;
+        let mut biases = None;
+        let mut prelu_slopes = None;
+
+        while let Some(key) = map.next_key::<&str>()? {
+            match key {
+                "zero_padding" => {
+                    if zero_padding.is_some() { return Err(serde::de::Error::duplicate_field("zero_padding")); };
+
+                    zero_padding = Some(map.next_value()?);
+                }
+
+                "stride" => {
+                    if stride.is_some() { return Err(serde::de::Error::duplicate_field("stride")); };
+
+                    stride = Some(map.next_value()?);
+                }
+
+                "kernel_size" => {
+                    if kernel_size.is_some() { return Err(serde::de::Error::duplicate_field("kernel_size")); };
+
+                    kernel_size = Some(map.next_value()?);
+                }
+
+                "dimension" => {
+                    if dimension.is_some() { return Err(serde::de::Error::duplicate_field("dimension")); };
+
+                    dimension = Some(map.next_value()?);
+                }
+
+                "input_depth" => {
+                    if input_depth.is_some() { return Err(serde::de::Error::duplicate_field("input_depth")); };
+
+                    input_depth = Some(map.next_value()?);
+                }
+
+                "depthwise_kernel" => {
+                    if depthwise_kernel.is_some() { return Err(serde::de::Error::duplicate_field("depthwise_kernel")); };
+
+                    depthwise_kernel = Some(map.next_value()?);
+                }
+
+                "pointwise_kernel" => {
+                    if pointwise_kernel.is_some() { return Err(serde::de::Error::duplicate_field("pointwise_kernel")); };
+
+                    pointwise_kernel = Some(map.next_value()?);
+                }
+
+                "biases" => {
+                    if biases.is_some() { return Err(serde::de::Error::duplicate_field("biases")); };
+
+                    biases = Some(map.next_value()?);
+                }
+
+                "prelu_slopes" => {
+                    if prelu_slopes.is_some() { return Err(serde::de::Error::duplicate_field("prelu_slopes")); };
+
+                    prelu_slopes = Some(map.next_value()?);
+                }
+
+                _ => return Err(serde::de::Error::unknown_field(key, SEPARABLE_CONVOLUTIONAL_LAYER_FIELDS)),
+            }
+        }
+
+        Self::build((
+            zero_padding.ok_or_else(|| serde::de::Error::missing_field("zero_padding"))?,
+            stride.ok_or_else(|| serde::de::Error::missing_field("stride"))?,
+            kernel_size.ok_or_else(|| serde::de::Error::missing_field("kernel_size"))?,
+            dimension.ok_or_else(|| serde::de::Error::missing_field("dimension"))?,
+            input_depth.ok_or_else(|| serde::de::Error::missing_field("input_depth"))?,
+            depthwise_kernel.ok_or_else(|| serde::de::Error::missing_field("depthwise_kernel"))?,
+            pointwise_kernel.ok_or_else(|| serde::de::Error::missing_field("pointwise_kernel"))?,
+            biases.ok_or_else(|| serde::de::Error::missing_field("biases"))?,
+            prelu_slopes.ok_or_else(|| serde::de::Error::missing_field("prelu_slopes"))?,
+        ))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        Self::build((
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(0, &self))?,
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(1, &self))?,
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(2, &self))?,
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(3, &self))?,
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(4, &self))?,
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(5, &self))?,
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(6, &self))?,
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(7, &self))?,
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(8, &self))?,
+        ))
+    }
+}
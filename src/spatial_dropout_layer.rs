@@ -0,0 +1,263 @@
+use crate::layer::{Layer, LayerBase};
+use crate::errors::Error;
+use crate::util;
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::{Serialize, Deserialize, de::{DeserializeSeed, Visitor}, ser::SerializeStruct};
+
+/// Zeroes whole channels of the previous layer's output while training and passes it through unchanged otherwise
+///
+/// the kept channels are scaled by `1 / (1 - probability)` so the expected output doesn't change between training and inference
+#[derive(Clone)]
+pub struct SpatialDropoutLayer {
+    /// the probability of dropping a channel
+    pub(crate) probability: f32,
+    pub(crate) zero_padding: usize,
+
+    pub(crate) dimension: (usize, usize, usize),
+
+    pub(crate) volume: Vec<f32>,
+    pub(crate) volume_gradients: Vec<f32>,
+
+    pub(crate) training: bool,
+
+    /// the factor every channel was multiplied by in the last forward pass
+    mask: Vec<f32>,
+    rng: StdRng,
+}
+
+#[allow(clippy::unused_unit, clippy::ptr_arg)]
+impl SpatialDropoutLayer {
+    pub fn new(probability: f32, zero_padding: usize, dimension: (usize, usize, usize)) -> Self {
+        let (dimension_x, dimension_y, depth) = dimension;
+
+        Self {
+            probability,
+            zero_padding,
+
+            dimension,
+
+            volume: vec![0.0; dimension_x * dimension_y * depth],
+            volume_gradients: vec![0.0; dimension_x * dimension_y * depth],
+
+            training: true,
+
+            mask: vec![1.0; depth],
+            rng: StdRng::from_rng(&mut rand::rng()),
+        }
+    }
+
+    pub fn get_probability(&self) -> f32 {
+        self.probability
+    }
+
+    /// seeds the random number generator that picks the dropped channels
+    pub fn set_seed(&mut self, seed: u64) -> () {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// draws a seed for a copy of the layer from the random number generator
+    pub(crate) fn next_seed(&mut self) -> u64 {
+        self.rng.random()
+    }
+
+    /// takes the output of the previous layer and drops a new set of channels when training
+    #[allow(clippy::needless_range_loop)]
+    pub(crate) fn feed_forward(&mut self, input: &[f32]) -> Result<(), Error> {
+        if input.len() != self.volume.len() { return Err(Error::DimensionMismatch) };
+
+        let scale = 1.0 / (1.0 - self.probability);
+
+        for factor in self.mask.iter_mut() {
+            *factor = if !self.training { 1.0 } else if self.rng.random::<f32>() < self.probability { 0.0 } else { scale };
+        }
+
+        // the depth is the innermost index of a volume
+        let depth = self.dimension.2;
+
+        for i in 0..input.len() {
+            self.volume[i] = input[i] * self.mask[i % depth];
+        }
+
+        Ok(())
+    }
+
+    /// the gradients of the dropped channels are zeroed with the same mask
+    #[allow(clippy::needless_range_loop)]
+    fn feed_back(&self, previous_gradients: &mut Vec<f32>) -> Result<(), Error> {
+        if previous_gradients.len() != self.volume_gradients.len() { return Err(Error::DimensionMismatch) };
+
+        let depth = self.dimension.2;
+
+        for i in 0..previous_gradients.len() {
+            previous_gradients[i] = self.volume_gradients[i] * self.mask[i % depth];
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn same_structure(&self, other: &SpatialDropoutLayer) -> bool {
+        self.probability == other.probability && self.zero_padding == other.zero_padding && self.dimension == other.dimension
+    }
+}
+
+impl LayerBase for SpatialDropoutLayer {
+    fn forward_propagate(&self, next_layer: &mut Layer) -> Result<(), Error> {
+        match next_layer {
+            Layer::Convolutional(layer) => {
+                util::check_output_dimension(self.dimension,
+                    layer.dimension,
+                    self.zero_padding,
+                    layer.num_kernels,
+                    layer.kernel_size,
+                    layer.stride,
+                    layer.rounding
+                )?;
+
+                layer.convolve(self.dimension, &self.volume, self.zero_padding);
+            }
+
+            Layer::Pooling(layer) => {
+                util::check_output_dimension(self.dimension,
+                    layer.dimension,
+                    0, // a pooling layer doesn't take padding into account
+                    layer.dimension.2,
+                    layer.kernel_size,
+                    layer.stride,
+                    layer.rounding
+                )?;
+
+                layer.convolve(self.dimension, &self.volume);
+            }
+
+            Layer::FullyConnected(layer) => {
+                let dim = self.dimension;
+                if dim.0 * dim.1 * dim.2 != layer.num_inputs { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume);
+            }
+
+            Layer::Add(layer) => {
+                if self.dimension != layer.dimension { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume)?;
+            }
+
+            Layer::MaxUnpool(layer) => layer.feed_forward(&self.volume),
+            Layer::SpatialDropout(layer) => layer.feed_forward(&self.volume)?,
+
+            Layer::Scale(layer) => {
+                if self.dimension != layer.dimension { return Err(Error::DimensionMismatch) };
+
+                layer.feed_forward(&self.volume)?;
+            }
+
+            Layer::SeparableConvolutional(layer) => {
+                layer.check_input(self.dimension, self.zero_padding)?;
+
+                layer.convolve(self.dimension, &self.volume, self.zero_padding);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn back_propagate(&mut self, previous_layer: &mut Layer) -> Result<(), Error> {
+        self.feed_back(previous_layer.output_gradients_mut())
+    }
+}
+
+impl Serialize for SpatialDropoutLayer {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("SpatialDropoutLayer", 3)?;
+
+        state.serialize_field("probability", &self.probability)?;
+        state.serialize_field("zero_padding", &self.zero_padding)?;
+        state.serialize_field("dimension", &self.dimension)?;
+
+        state.end()
+    }
+}
+
+const SPATIAL_DROPOUT_LAYER_FIELDS: &[&str] = &["probability", "zero_padding", "dimension"];
+
+impl<'de> Deserialize<'de> for SpatialDropoutLayer {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("SpatialDropoutLayer", SPATIAL_DROPOUT_LAYER_FIELDS, SpatialDropoutLayerVisitor)
+    }
+}
+
+/// the spatial dropout layer format hasn't changed since it was introduced in format version 9
+pub(crate) struct SpatialDropoutLayerVisitor;
+
+impl<'de> DeserializeSeed<'de> for SpatialDropoutLayerVisitor {
+    type Value = SpatialDropoutLayer;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("SpatialDropoutLayer", SPATIAL_DROPOUT_LAYER_FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for SpatialDropoutLayerVisitor {
+    type Value = SpatialDropoutLayer;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a SpatialDropoutLayer struct")
+    }
+
+    fn visit_map<M>(self, mut map: M) -> Result<Self::Value, M::Error>
+        where
+            M: serde::de::MapAccess<'de>,
+    {
+        let mut probability = None;
+        let mut zero_padding = None;
+        let mut dimension = None;
+
+        while let Some(key) = map.next_key::<&str>()? {
+            match key {
+                "probability" => {
+                    if probability.is_some() { return Err(serde::de::Error::duplicate_field("probability")); };
+
+                    probability = Some(map.next_value()?);
+                }
+
+                "zero_padding" => {
+                    if zero_padding.is_some() { return Err(serde::de::Error::duplicate_field("zero_padding")); };
+
+                    zero_padding = Some(map.next_value()?);
+                }
+
+                "dimension" => {
+                    if dimension.is_some() { return Err(serde::de::Error::duplicate_field("dimension")); };
+
+                    dimension = Some(map.next_value()?);
+                }
+
+                _ => return Err(serde::de::Error::unknown_field(key, SPATIAL_DROPOUT_LAYER_FIELDS)),
+            }
+        }
+
+        let probability = probability.ok_or_else(|| serde::de::Error::missing_field("probability"))?;
+        let zero_padding = zero_padding.ok_or_else(|| serde::de::Error::missing_field("zero_padding"))?;
+        let dimension = dimension.ok_or_else(|| serde::de::Error::missing_field("dimension"))?;
+
+        Ok(SpatialDropoutLayer::new(probability, zero_padding, dimension))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let probability = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+        let zero_padding = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+        let dimension = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+
+        Ok(SpatialDropoutLayer::new(probability, zero_padding, dimension))
+    }
+}
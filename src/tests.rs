@@ -23,20 +23,3574 @@ fn convolutional_layer_forward_propagate()
         assert_eq!(conv.volume, vec![
             6.5,
             18.5,
-            12.5,
-            0.5,
+            21.5,
+            14.0,
             21.5,
             45.5,
-            24.5,
-            0.5,
-            15.5,
+            51.5,
             27.5,
-            12.5,
-            0.5,
-            0.5,
-            0.5,
-            0.5,
-            0.5,
+            30.5,
+            63.5,
+            69.5,
+            36.5,
+            20.0,
+            36.5,
+            39.5,
+            18.5,
         ]);
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn early_stopping_triggers_after_patience()
+{
+    let mut early_stopping = EarlyStopping::new(3, 0.02);
+    let losses = [1.0, 0.8, 0.7, 0.71, 0.69, 0.75, 0.5];
+
+    let mut stopped_at = None;
+    for (epoch, loss) in losses.iter().enumerate() {
+        if early_stopping.update(*loss) {
+            stopped_at = Some(epoch);
+            break;
+        }
+    }
+
+    // 0.69 is not an improvement over 0.7 by more than min_delta
+    assert_eq!(stopped_at, Some(5));
+    assert_eq!(early_stopping.get_best_loss(), 0.7);
+
+    early_stopping.reset();
+    assert!(!early_stopping.should_stop());
+}
+
+fn make_identity_network(error_function: ErrorFunction) -> NeuralNetwork
+{
+    let mut output_layer = Layer::make_fully_connected_layer(2, 2);
+
+    if let Layer::FullyConnected(ref mut fc) = output_layer {
+        fc.set_weights(vec![1.0, 0.0, 0.0, 1.0]).expect("Set weights");
+        fc.set_biases(vec![0.0, 0.0]).expect("Set biases");
+    }
+
+    let mut neural_network = NeuralNetwork::new(error_function);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (1, 1, 2)));
+    neural_network.register_layer(ActivationFunction::None, output_layer);
+
+    neural_network
+}
+
+#[test]
+fn neural_network_evaluate()
+{
+    let mut neural_network = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+
+    let inputs = vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![0.2, 0.8]];
+    let targets = vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 0.0]];
+
+    let (loss, accuracy) = neural_network.evaluate(&inputs, &targets).expect("Evaluate");
+
+    assert!((loss - 0.32 / 3.0).abs() < 1e-6);
+    assert!((accuracy - 2.0 / 3.0).abs() < 1e-6);
+
+    assert!(neural_network.evaluate(&inputs, &targets[..2]).is_err());
+    assert!(neural_network.evaluate(&inputs, &vec![vec![1.0]; 3]).is_err());
+    assert!(neural_network.evaluate(&vec![vec![1.0]; 3], &targets).is_err());
+}
+
+#[test]
+fn neural_network_reset_gradients_range()
+{
+    let mut neural_network = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_fully_connected_layer(2, 2));
+    neural_network.initialize(2, Initialization::UniformXavier).expect("Initialize");
+
+    neural_network.start_batch();
+    neural_network.set_input(&vec![1.0, 2.0]).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagation");
+    neural_network.back_propagate(&vec![0.0, 0.0]).expect("Back propagation");
+
+    let before = neural_network.collect_gradients();
+    neural_network.reset_gradients_range(2, 3).expect("Reset gradients");
+    let after = neural_network.collect_gradients();
+
+    // the input layer has 2 biases and the first fully connected layer has 4 weights and 2 biases
+    assert_eq!(before[..8], after[..8]);
+    assert!(before[..8].iter().any(|gradient| *gradient != 0.0));
+    assert!(after[8..].iter().all(|gradient| *gradient == 0.0));
+
+    assert!(neural_network.reset_gradients_range(2, 4).is_err());
+    assert!(neural_network.reset_gradients_range(2, 1).is_err());
+}
+
+#[test]
+fn hard_sigmoid_activation()
+{
+    let func = ActivationFunction::HardSigmoid;
+
+    assert_eq!(activations::eval(func, -2.5), 0.0);
+    assert_eq!(activations::eval(func, -10.0), 0.0);
+    assert_eq!(activations::eval(func, 0.0), 0.5);
+    assert_eq!(activations::eval(func, 2.5), 1.0);
+    assert_eq!(activations::eval(func, 10.0), 1.0);
+
+    assert_eq!(activations::eval_derivative(func, 0.0), 0.2);
+    assert_eq!(activations::eval_derivative(func, -2.6), 0.0);
+    assert_eq!(activations::eval_derivative(func, 2.6), 0.0);
+}
+
+#[test]
+fn softsign_activation()
+{
+    let func = ActivationFunction::Softsign;
+
+    assert_eq!(activations::eval(func, 0.0), 0.0);
+    assert_eq!(activations::eval(func, 1.0), 0.5);
+    assert!(activations::eval(func, 1000.0) > 0.99);
+    assert!(activations::eval(func, -1000.0) < -0.99);
+
+    assert_eq!(activations::eval_derivative(func, 0.0), 1.0);
+    assert_eq!(activations::eval_derivative(func, 1.0), 0.25);
+    assert!(activations::eval_derivative(func, -10.0) < activations::eval_derivative(func, -1.0));
+    assert!(activations::eval_derivative(func, 100.0) < 1e-3);
+}
+
+#[test]
+fn neural_network_error_function_accessors()
+{
+    let mut neural_network = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+    assert_eq!(neural_network.get_error_function(), ErrorFunction::HalfMeanSquaredError);
+
+    neural_network.set_error_function(ErrorFunction::BinaryCrossEntropy);
+    assert_eq!(neural_network.get_error_function(), ErrorFunction::BinaryCrossEntropy);
+
+    let bytes = bincode::serde::encode_to_vec(&neural_network, bincode::config::standard()).expect("Serialize");
+    let (loaded, _): (NeuralNetwork, usize) = bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).expect("Deserialize");
+
+    assert_eq!(loaded.get_error_function(), ErrorFunction::BinaryCrossEntropy);
+}
+
+#[test]
+fn convolutional_layer_pointwise_matches_generic()
+{
+    let input_dimension = (5, 3, 3);
+    let mut input_layer = Layer::make_input_layer(0, input_dimension);
+    let mut layer = Layer::make_convolutional_layer(0, 2, 1, (3, 2, 4), 3);
+
+    let input: Vec<f32> = (0..45).map(|i| (i as f32 * 0.37).sin()).collect();
+    if let Layer::Convolutional(ref mut conv) = input_layer {
+        conv.set_volume(&input).expect("Set volume");
+    }
+
+    if let Layer::Convolutional(ref mut conv) = layer {
+        conv.set_kernel((0..12).map(|i| (i as f32 * 0.53).cos()).collect()).expect("Set kernel");
+        conv.set_biases(vec![0.1, -0.2, 0.3, -0.4]).expect("Set biases");
+    }
+
+    input_layer.forward_propagate(&mut layer).expect("Forward propagation");
+
+    if let Layer::Convolutional(ref mut conv) = layer {
+        let mut generic = vec![0.0; conv.volume.len()];
+        conv.convolve_generic(input_dimension, &input, 0, conv.dimension, &mut generic, None);
+
+        assert_eq!(conv.volume, generic);
+    }
+}
+
+/// the architecture from the cat/dog classification example
+fn make_example_network() -> NeuralNetwork
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::BinaryCrossEntropy);
+
+    neural_network.register_layer(ActivationFunction::ReLU, Layer::make_input_layer(1, (128, 128, 3)));
+    neural_network.register_layer(ActivationFunction::ReLU, Layer::make_convolutional_layer(0, 1, 3, (128, 128, 32), 3));
+    neural_network.register_layer(ActivationFunction::ReLU, Layer::make_pooling_layer(PoolingType::Max, 1, 2, 2, (64, 64, 32)));
+    neural_network.register_layer(ActivationFunction::ReLU, Layer::make_convolutional_layer(0, 1, 3, (64, 64, 64), 32));
+    neural_network.register_layer(ActivationFunction::ReLU, Layer::make_pooling_layer(PoolingType::Max, 1, 2, 2, (32, 32, 64)));
+    neural_network.register_layer(ActivationFunction::ReLU, Layer::make_convolutional_layer(0, 1, 3, (32, 32, 128), 64));
+    neural_network.register_layer(ActivationFunction::ReLU, Layer::make_pooling_layer(PoolingType::Max, 0, 2, 2, (16, 16, 128)));
+    neural_network.register_layer(ActivationFunction::ReLU, Layer::make_fully_connected_layer(32768, 512));
+    neural_network.register_layer(ActivationFunction::Sigmoid, Layer::make_fully_connected_layer(512, 1));
+
+    neural_network
+}
+
+#[test]
+fn layer_dimensions()
+{
+    let neural_network = make_example_network();
+    let layer = |index: usize| neural_network.get_layer(index).expect("Get layer");
+
+    assert_eq!(neural_network.num_layers(), 9);
+
+    assert_eq!(layer(0).input_dimension(), Some(LayerShape::Volume(128, 128, 3)));
+    assert_eq!(layer(0).output_dimension(), LayerShape::Volume(128, 128, 3));
+
+    assert_eq!(layer(1).input_dimension(), None);
+    assert_eq!(layer(1).output_dimension(), LayerShape::Volume(128, 128, 32));
+
+    assert_eq!(layer(2).input_dimension(), None);
+    assert_eq!(layer(2).output_dimension(), LayerShape::Volume(64, 64, 32));
+
+    assert_eq!(layer(7).input_dimension(), Some(LayerShape::Flat(32768)));
+    assert_eq!(layer(7).output_dimension(), LayerShape::Flat(512));
+    assert_eq!(layer(6).output_dimension().size(), 32768);
+
+    assert_eq!(layer(8).input_dimension(), Some(LayerShape::Flat(512)));
+    assert_eq!(layer(8).output_dimension(), LayerShape::Flat(1));
+}
+
+/// mirrors of the layer layout before strides became (x, y) in format version 3
+#[derive(serde::Serialize)]
+struct ConvolutionalLayerV1 {
+    zero_padding: usize,
+    stride: usize,
+    kernel_size: usize,
+    dimension: (usize, usize, usize),
+    input_depth: usize,
+    kernel: Vec<f32>,
+    biases: Vec<f32>,
+}
+
+#[derive(serde::Serialize)]
+struct PoolingLayerV1 {
+    pooling_type: PoolingType,
+    zero_padding: usize,
+    stride: usize,
+    kernel_size: usize,
+    dimension: (usize, usize, usize),
+}
+
+#[derive(serde::Serialize)]
+struct FullyConnectedLayerV1 {
+    num_inputs: usize,
+    num_neurons: usize,
+    weights: Vec<f32>,
+    biases: Vec<f32>,
+}
+
+#[derive(serde::Serialize)]
+enum LayerV1 {
+    Convolutional(ConvolutionalLayerV1),
+    Pooling(PoolingLayerV1),
+    FullyConnected(FullyConnectedLayerV1),
+}
+
+#[test]
+fn neural_network_version_tagging()
+{
+    let layers = vec![
+        (LayerV1::Convolutional(ConvolutionalLayerV1 { zero_padding: 0, stride: 0, kernel_size: 0, dimension: (4, 4, 1), input_depth: 0, kernel: vec![], biases: vec![0.0] }), ActivationFunction::None),
+        (LayerV1::Pooling(PoolingLayerV1 { pooling_type: PoolingType::Max, zero_padding: 0, stride: 2, kernel_size: 2, dimension: (2, 2, 1) }), ActivationFunction::None),
+        (LayerV1::FullyConnected(FullyConnectedLayerV1 { num_inputs: 4, num_neurons: 1, weights: vec![1.0; 4], biases: vec![0.0] }), ActivationFunction::None),
+    ];
+
+    // networks written before versioning have no magic number or version
+    let v1_blob = bincode::serde::encode_to_vec((&layers, ErrorFunction::BinaryCrossEntropy), bincode::config::standard()).expect("Serialize");
+    let mut loaded = NeuralNetwork::from_bytes(&v1_blob).expect("Deserialize");
+
+    assert_eq!(loaded.num_layers(), 3);
+    assert_eq!(loaded.get_error_function(), ErrorFunction::BinaryCrossEntropy);
+    assert_eq!(loaded.get_hyperparameters(), Hyperparameters::default());
+
+    // the maxima of the 2x2 windows are 5, 7, 13 and 15
+    loaded.set_input(&(0..16).map(|i| i as f32).collect()).expect("Set input");
+    loaded.forward_propagate().expect("Forward propagation");
+    assert_eq!(loaded.get_output().expect("Get output"), vec![40.0]);
+
+    let future_blob = bincode::serde::encode_to_vec((neural_network::FORMAT_MAGIC, u32::MAX, &layers, ErrorFunction::BinaryCrossEntropy), bincode::config::standard()).expect("Serialize");
+    let result: Result<(NeuralNetwork, usize), _> = bincode::serde::decode_from_slice(&future_blob, bincode::config::standard());
+
+    assert!(result.is_err_and(|error| error.to_string().contains("newer than the supported version")));
+}
+
+#[test]
+fn neural_network_loads_a_model_saved_before_versioning()
+{
+    // written by the unversioned format: a 4x4 input, a 2x2 convolution with a stride of 2 and a kernel of [1, 0, 0, 1]
+    // and a bias of 0.5 followed by a ReLU, and a fully connected layer with weights [1, 2, 3, 4] and a bias of 0.25
+    let bytes: [u8; 74] = [
+        3, 0, 0, 0, 0, 4, 4, 1, 0, 0, 1, 0, 0, 0, 0, 3, 0, 0, 2, 2, 2, 2, 1, 1, 4, 0, 0, 128, 63, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 128, 63, 1, 0, 0, 0, 63, 1, 2, 4, 1, 4, 0, 0, 128, 63, 0, 0, 0, 64, 0, 0, 64, 64, 0, 0, 128, 64, 1, 0,
+        0, 128, 62, 3, 1,
+    ];
+
+    let mut loaded = NeuralNetwork::from_bytes(&bytes).expect("Deserialize");
+
+    assert_eq!(loaded.num_layers(), 3);
+    assert_eq!(loaded.get_error_function(), ErrorFunction::BinaryCrossEntropy);
+
+    // the diagonals of the 2x2 windows sum to 5, 9, 21 and 25
+    loaded.set_input(&(0..16).map(|i| i as f32).collect()).expect("Set input");
+    loaded.forward_propagate().expect("Forward propagation");
+    assert_eq!(loaded.get_output().expect("Get output"), vec![191.25]);
+
+    // a network saved now round trips through the magic number
+    let bytes = bincode::serde::encode_to_vec(&loaded, bincode::config::standard()).expect("Serialize");
+    let reloaded = NeuralNetwork::from_bytes(&bytes).expect("Deserialize");
+    assert_eq!(reloaded.get_layer(1).expect("Layer").get_weights(), loaded.get_layer(1).expect("Layer").get_weights());
+}
+
+#[test]
+fn neural_network_receptive_field()
+{
+    let neural_network = make_example_network();
+
+    // 3x3 conv: 3, 2x2/2 pool: 3 + 1 = 4, 3x3 conv: 4 + 2 * 2 = 8, 2x2/2 pool: 8 + 1 * 2 = 10, 3x3 conv: 10 + 2 * 4 = 18
+    assert_eq!(neural_network.receptive_field(0).expect("Receptive field"), (1, 1));
+    assert_eq!(neural_network.receptive_field(1).expect("Receptive field"), (3, 3));
+    assert_eq!(neural_network.receptive_field(5).expect("Receptive field"), (18, 18));
+    assert_eq!(neural_network.receptive_field(8).expect("Receptive field"), (128, 128));
+
+    assert!(neural_network.receptive_field(9).is_err());
+}
+
+#[test]
+fn convolutional_layer_output_derivative_matches_raw()
+{
+    let run = |func: ActivationFunction, release_raw_volume: bool| -> Vec<util::Gradient> {
+        let mut input_layer = Layer::make_input_layer(1, (4, 4, 2));
+        let mut conv_layer = Layer::make_convolutional_layer(0, 1, 3, (4, 4, 3), 2);
+        let mut output_layer = Layer::make_fully_connected_layer(48, 2);
+
+        if let Layer::Convolutional(ref mut conv) = input_layer {
+            conv.set_volume(&(0..32).map(|i| (i as f32 * 0.71).sin()).collect()).expect("Set volume");
+        }
+
+        if let Layer::Convolutional(ref mut conv) = conv_layer {
+            conv.set_kernel((0..54).map(|i| (i as f32 * 0.29).cos() * 0.5).collect()).expect("Set kernel");
+            conv.set_biases(vec![0.1, -0.1, 0.05]).expect("Set biases");
+        }
+
+        if let Layer::FullyConnected(ref mut fc) = output_layer {
+            fc.set_weights((0..96).map(|i| (i as f32 * 0.13).sin() * 0.2).collect()).expect("Set weights");
+        }
+
+        if release_raw_volume {
+            conv_layer.configure_activation(func);
+        }
+
+        input_layer.forward_propagate(&mut conv_layer).expect("Forward propagation");
+        conv_layer.activate(func);
+        conv_layer.forward_propagate(&mut output_layer).expect("Forward propagation");
+
+        if let Layer::FullyConnected(ref mut fc) = output_layer {
+            fc.calculate_output_gradients(ErrorFunction::HalfMeanSquaredError, &vec![1.0, -1.0], None, nn_error::DEFAULT_EPSILON).expect("Output gradients");
+        }
+
+        output_layer.backward_activate(ActivationFunction::None);
+        output_layer.back_propagate(&mut conv_layer).expect("Back propagation");
+        conv_layer.backward_activate(func);
+        conv_layer.back_propagate(&mut input_layer).expect("Back propagation");
+
+        match conv_layer {
+            Layer::Convolutional(conv) => conv.kernel_gradients.clone(),
+            _ => unreachable!(),
+        }
+    };
+
+    for func in [ActivationFunction::Sigmoid, ActivationFunction::ReLU, ActivationFunction::LeakyReLU(0.1)] {
+        assert!(activations::has_output_derivative(func));
+        assert_eq!(run(func, true), run(func, false));
+    }
+
+    assert!(!activations::has_output_derivative(ActivationFunction::Softsign));
+}
+
+#[test]
+fn metrics_roc_curve()
+{
+    let outputs = [0.1, 0.4, 0.35, 0.8];
+    let targets = [0.0, 0.0, 1.0, 1.0];
+
+    let roc = metrics::roc_curve(&outputs, &targets).expect("ROC curve");
+
+    assert_eq!(roc.points, vec![(0.0, 0.0), (0.0, 0.5), (0.5, 0.5), (0.5, 1.0), (1.0, 1.0)]);
+    assert_eq!(roc.thresholds[1..], [0.8, 0.4, 0.35, 0.1]);
+    assert!((roc.auc - 0.75).abs() < 1e-6);
+
+    assert!(metrics::roc_curve(&outputs, &[1.0; 4]).is_err());
+    assert!(metrics::roc_curve(&outputs, &targets[..3]).is_err());
+}
+
+#[test]
+fn metrics_top_k_accuracy()
+{
+    let predictions = vec![
+        vec![0.1, 0.6, 0.2, 0.1],
+        vec![0.4, 0.3, 0.2, 0.1],
+        vec![0.1, 0.2, 0.3, 0.4],
+    ];
+
+    let targets = vec![
+        vec![0.0, 1.0, 0.0, 0.0],
+        // third highest output, wrong at top-1 but correct at top-3
+        vec![0.0, 0.0, 1.0, 0.0],
+        vec![1.0, 0.0, 0.0, 0.0],
+    ];
+
+    let top_1 = metrics::top_k_accuracy(&predictions, &targets, 1).expect("top-1 accuracy");
+    let top_3 = metrics::top_k_accuracy(&predictions, &targets, 3).expect("top-3 accuracy");
+    let top_4 = metrics::top_k_accuracy(&predictions, &targets, 4).expect("top-4 accuracy");
+
+    assert!((top_1 - 1.0 / 3.0).abs() < 1e-6);
+    assert!((top_3 - 2.0 / 3.0).abs() < 1e-6);
+    assert_eq!(top_4, 1.0);
+
+    assert!(metrics::top_k_accuracy(&predictions, &targets, 0).is_err());
+    assert!(metrics::top_k_accuracy(&predictions, &targets[..2], 1).is_err());
+}
+
+#[test]
+fn log_cosh_error()
+{
+    let func = ErrorFunction::LogCosh;
+
+    // behaves like half the squared error for small differences
+    let small = nn_error::eval(func, &vec![0.01], &vec![0.0], None, nn_error::DEFAULT_EPSILON);
+    let squared = nn_error::eval(ErrorFunction::HalfMeanSquaredError, &vec![0.01], &vec![0.0], None, nn_error::DEFAULT_EPSILON);
+    assert!((small - squared).abs() / squared < 1e-3);
+
+    // and like the absolute error for large differences
+    let large = nn_error::eval(func, &vec![100.0, 0.0], &vec![0.0, 0.0], None, nn_error::DEFAULT_EPSILON);
+    assert!(large.is_finite());
+    assert!((large - (100.0 - std::f32::consts::LN_2) / 2.0).abs() < 1e-4);
+
+    let slope = nn_error::eval(func, &vec![101.0], &vec![0.0], None, nn_error::DEFAULT_EPSILON) - nn_error::eval(func, &vec![100.0], &vec![0.0], None, nn_error::DEFAULT_EPSILON);
+    assert!((slope - 1.0).abs() < 1e-4);
+
+    assert!((nn_error::eval_derivative(func, 0, &vec![100.0, 0.0], &vec![0.0, 0.0], None, nn_error::DEFAULT_EPSILON) - 0.5).abs() < 1e-6);
+    assert!((nn_error::eval_derivative(func, 0, &vec![0.5], &vec![0.0], None, nn_error::DEFAULT_EPSILON) - 0.5f32.tanh()).abs() < 1e-6);
+    assert_eq!(nn_error::eval_derivative(func, 1, &vec![100.0, 0.0], &vec![0.0, 0.0], None, nn_error::DEFAULT_EPSILON), 0.0);
+}
+
+#[test]
+fn neural_network_weight_histogram()
+{
+    let mut output_layer = Layer::make_fully_connected_layer(4, 2);
+    if let Layer::FullyConnected(ref mut fc) = output_layer {
+        fc.set_weights(vec![0.0, 1.0, 1.0, 2.0, 3.0, 5.0, 8.0, 8.0]).expect("Set weights");
+    }
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (2, 2, 1)));
+    neural_network.register_layer(ActivationFunction::None, output_layer);
+
+    let histogram = neural_network.weight_histogram(1, 4).expect("Weight histogram");
+    assert_eq!(histogram, vec![(0.0, 3), (2.0, 2), (4.0, 1), (6.0, 2)]);
+
+    let gradient_histogram = neural_network.gradient_histogram(1, 2).expect("Gradient histogram");
+    assert_eq!(gradient_histogram, vec![(0.0, 8), (0.0, 0)]);
+
+    assert!(neural_network.weight_histogram(1, 0).is_err());
+    assert!(neural_network.weight_histogram(2, 4).is_err());
+}
+
+#[test]
+fn neural_network_scale_gradients()
+{
+    let mut neural_network = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+
+    neural_network.start_batch();
+    neural_network.set_input(&vec![1.0, 2.0]).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagation");
+    neural_network.back_propagate(&vec![0.0, 0.0]).expect("Back propagation");
+
+    let before = neural_network.collect_gradients();
+    neural_network.scale_gradients(0.5);
+    let after = neural_network.collect_gradients();
+
+    assert!(before.iter().any(|gradient| *gradient != 0.0));
+    for i in 0..before.len() {
+        assert_eq!(after[i], before[i] * 0.5);
+    }
+}
+
+#[test]
+fn neural_network_stored_hyperparameters()
+{
+    let hyperparameters = Hyperparameters { learning_rate: 0.5, momentum: 0.0, weight_decay: 0.0 };
+
+    let train = |use_stored: bool| -> Vec<f32> {
+        let mut neural_network = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+        neural_network.set_hyperparameters(hyperparameters);
+
+        neural_network.start_batch();
+        neural_network.set_input(&vec![1.0, 2.0]).expect("Set input");
+        neural_network.forward_propagate().expect("Forward propagation");
+        neural_network.back_propagate(&vec![0.0, 0.0]).expect("Back propagation");
+
+        if use_stored {
+            neural_network.end_batch(1);
+        } else {
+            neural_network.end_batch_with(1, 0.5, 0.0, 0.0);
+        }
+
+        neural_network.get_layer(1).expect("Get layer").get_weights().expect("Get weights").clone()
+    };
+
+    let stored = train(true);
+    assert_eq!(stored, train(false));
+    assert_ne!(stored, vec![1.0, 0.0, 0.0, 1.0]);
+
+    let mut neural_network = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+    neural_network.set_hyperparameters(hyperparameters);
+
+    let bytes = bincode::serde::encode_to_vec(&neural_network, bincode::config::standard()).expect("Serialize");
+    let (loaded, _): (NeuralNetwork, usize) = bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).expect("Deserialize");
+
+    assert_eq!(loaded.get_hyperparameters(), hyperparameters);
+}
+
+#[test]
+fn neural_network_clone_weights_from()
+{
+    let build = || {
+        let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+        neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(1, (4, 4, 2)));
+        neural_network.register_layer(ActivationFunction::ReLU, Layer::make_convolutional_layer(0, 1, 3, (4, 4, 3), 2));
+        neural_network.register_layer(ActivationFunction::None, Layer::make_pooling_layer(PoolingType::Max, 0, 2, 2, (2, 2, 3)));
+        neural_network.register_layer(ActivationFunction::Sigmoid, Layer::make_fully_connected_layer(12, 2));
+
+        neural_network
+    };
+
+    let mut source = build();
+    source.initialize(1, Initialization::NormalHe).expect("Initialize");
+    source.initialize(3, Initialization::NormalXavier).expect("Initialize");
+
+    let mut target = build();
+    target.clone_weights_from(&source).expect("Clone weights");
+
+    let input: Vec<f32> = (0..32).map(|i| (i as f32 * 0.41).sin()).collect();
+    for neural_network in [&mut source, &mut target] {
+        neural_network.set_input(&input).expect("Set input");
+        neural_network.forward_propagate().expect("Forward propagation");
+    }
+
+    assert_eq!(source.get_output().expect("Get output"), target.get_output().expect("Get output"));
+
+    let mut different = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+    assert!(different.clone_weights_from(&source).is_err());
+}
+
+#[test]
+fn asymmetric_stride()
+{
+    assert_eq!(util::get_output_dimension((5, 4, 1), 0, 2, 1, (2, 1)), Some((3, 4, 2)));
+
+    let mut identity = vec![0.0; 36];
+    for i in 0..6 {
+        identity[i * 6 + i] = 1.0;
+    }
+
+    let mut output_layer = Layer::make_fully_connected_layer(6, 6);
+    if let Layer::FullyConnected(ref mut fc) = output_layer {
+        fc.set_weights(identity).expect("Set weights");
+    }
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (4, 4, 1)));
+    neural_network.register_layer(ActivationFunction::None, Layer::make_pooling_layer_with_stride(PoolingType::Max, 0, (2, 1), 2, (2, 3, 1)));
+    neural_network.register_layer(ActivationFunction::None, output_layer);
+
+    // the input value at (x, y) is y + 4x, so a window's maximum is at its bottom right
+    neural_network.set_input(&(0..16).map(|i| i as f32).collect()).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagation");
+
+    assert_eq!(neural_network.get_output().expect("Get output"), vec![5.0, 6.0, 7.0, 13.0, 14.0, 15.0]);
+
+    let bytes = bincode::serde::encode_to_vec(&neural_network, bincode::config::standard()).expect("Serialize");
+    let (mut loaded, _): (NeuralNetwork, usize) = bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).expect("Deserialize");
+
+    loaded.set_input(&(0..16).map(|i| i as f32).collect()).expect("Set input");
+    loaded.forward_propagate().expect("Forward propagation");
+
+    assert_eq!(loaded.get_output().expect("Get output"), vec![5.0, 6.0, 7.0, 13.0, 14.0, 15.0]);
+}
+
+#[test]
+fn parameter_and_flop_counts()
+{
+    let neural_network = make_example_network();
+
+    let conv_parameters = (3 * 3 * 3 * 32 + 32) + (3 * 3 * 32 * 64 + 64) + (3 * 3 * 64 * 128 + 128);
+    let fully_connected_parameters = (32768 * 512 + 512) + (512 + 1);
+    assert_eq!(neural_network.num_parameters(), conv_parameters + fully_connected_parameters);
+    assert_eq!(neural_network.num_parameters(), 16_871_489);
+
+    let conv_flops = 128 * 128 * (3 * 3 * 3 * 32) + 64 * 64 * (3 * 3 * 32 * 64) + 32 * 32 * (3 * 3 * 64 * 128);
+    let fully_connected_flops = 32768 * 512 + 512;
+    assert_eq!(neural_network.flops_per_forward(), conv_flops + fully_connected_flops);
+    assert_eq!(neural_network.flops_per_forward(), 181_928_448);
+}
+
+#[test]
+fn horizontal_flip_is_its_own_inverse()
+{
+    use rand::SeedableRng;
+
+    let dimension = (4, 3, 2);
+    let volume: Vec<f32> = (0..24).map(|i| i as f32).collect();
+
+    let flipped = augment::horizontal_flip(&volume, dimension).expect("Flip volume");
+    assert_ne!(flipped, volume);
+    assert_eq!(flipped[util::get_index((0, 1, 1), dimension)], volume[util::get_index((3, 1, 1), dimension)]);
+    assert_eq!(augment::horizontal_flip(&flipped, dimension).expect("Flip volume"), volume);
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    assert_eq!(augment::random_horizontal_flip(&volume, dimension, 1.0, &mut rng).expect("Flip volume"), flipped);
+    assert_eq!(augment::random_horizontal_flip(&volume, dimension, 0.0, &mut rng).expect("Flip volume"), volume);
+}
+
+#[test]
+fn erasing_zeroes_the_region()
+{
+    use rand::SeedableRng;
+
+    let dimension = (5, 4, 3);
+    let volume = vec![1.0; 5 * 4 * 3];
+
+    let erased = augment::erase(&volume, dimension, (1, 2, 3, 2)).expect("Erase region");
+    for x in 0..5 {
+        for y in 0..4 {
+            for z in 0..3 {
+                let inside = (1..4).contains(&x) && (2..4).contains(&y);
+                assert_eq!(erased[util::get_index((x, y, z), dimension)], if inside { 0.0 } else { 1.0 });
+            }
+        }
+    }
+
+    assert!(augment::erase(&volume, dimension, (3, 0, 3, 1)).is_err());
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let erased = augment::random_erasing(&volume, dimension, (2, 3), &mut rng).expect("Erase region");
+    assert_eq!(erased.iter().filter(|&&value| value == 0.0).count(), 2 * 3 * 3);
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    assert_eq!(augment::random_erasing(&volume, dimension, (2, 3), &mut rng).expect("Erase region"), erased);
+}
+
+#[test]
+fn class_weights_scale_gradients()
+{
+    let input = vec![0.5, 0.5];
+    let target = vec![1.0, 0.0];
+
+    let run = |class_weights: Option<Vec<f32>>| {
+        let mut neural_network = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+        neural_network.set_class_weights(class_weights);
+
+        neural_network.set_input(&input).expect("Set input");
+        neural_network.forward_propagate().expect("Forward propagate");
+        neural_network.start_batch();
+        neural_network.back_propagate(&target).expect("Back propagate");
+
+        (neural_network.get_error(&target).expect("Get error"), neural_network.collect_gradients())
+    };
+
+    let (error, gradients) = run(None);
+    let (uniform_error, uniform_gradients) = run(Some(vec![1.0, 1.0]));
+    let (weighted_error, weighted_gradients) = run(Some(vec![3.0, 1.0]));
+
+    assert_eq!(error, uniform_error);
+    assert_eq!(gradients, uniform_gradients);
+
+    // the output biases are the last gradients and only depend on their own output
+    let bias = gradients.len() - 2;
+    assert!((weighted_gradients[bias] - 3.0 * gradients[bias]).abs() < 1e-6);
+    assert_eq!(weighted_gradients[bias + 1], gradients[bias + 1]);
+
+    // (3 * 0.5^2 + 0.5^2) / 2 * 0.5
+    assert!((weighted_error - 0.25).abs() < 1e-6);
+
+    let mut neural_network = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+    neural_network.set_class_weights(Some(vec![1.0]));
+    neural_network.set_input(&input).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagate");
+    assert!(neural_network.back_propagate(&target).is_err());
+}
+
+#[test]
+fn convolutional_layer_apply_matches_forward_pass()
+{
+    let input_dimension = (6, 5, 2);
+    let mut input_layer = Layer::make_input_layer(0, input_dimension);
+    let mut layer = Layer::make_convolutional_layer_with_stride(0, (1, 2), 3, (4, 2, 3), 2);
+
+    let input: Vec<f32> = (0..60).map(|i| (i as f32 * 0.41).sin()).collect();
+    if let Layer::Convolutional(ref mut conv) = input_layer {
+        conv.set_volume(&input).expect("Set volume");
+    }
+
+    if let Layer::Convolutional(ref mut conv) = layer {
+        conv.set_kernel((0..54).map(|i| (i as f32 * 0.29).cos()).collect()).expect("Set kernel");
+        conv.set_biases(vec![0.2, -0.1, 0.05]).expect("Set biases");
+    }
+
+    input_layer.forward_propagate(&mut layer).expect("Forward propagation");
+
+    if let Layer::Convolutional(ref conv) = layer {
+        assert_eq!(conv.apply(&input, input_dimension).expect("Apply"), conv.volume);
+
+        assert!(conv.apply(&input[1..], input_dimension).is_err());
+        assert!(conv.apply(&input, (5, 6, 2)).is_ok());
+        assert!(conv.apply(&input, (10, 3, 2)).is_ok());
+        assert!(conv.apply(&input, (6, 10, 1)).is_err());
+    }
+}
+
+#[test]
+fn register_layers_with_inferred_dimensions()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::BinaryCrossEntropy);
+
+    assert!(neural_network.register_conv(ActivationFunction::ReLU, 0, 1, 3, 32).is_err());
+
+    neural_network.register_layer(ActivationFunction::ReLU, Layer::make_input_layer(1, (128, 128, 3)));
+    neural_network.register_conv(ActivationFunction::ReLU, 0, 1, 3, 32).expect("Register conv");
+    neural_network.register_pooling(ActivationFunction::ReLU, PoolingType::Max, 1, 2, 2).expect("Register pooling");
+    neural_network.register_conv(ActivationFunction::ReLU, 0, 1, 3, 64).expect("Register conv");
+    neural_network.register_pooling(ActivationFunction::ReLU, PoolingType::Max, 1, 2, 2).expect("Register pooling");
+    neural_network.register_conv(ActivationFunction::ReLU, 0, 1, 3, 128).expect("Register conv");
+    neural_network.register_pooling(ActivationFunction::ReLU, PoolingType::Max, 0, 2, 2).expect("Register pooling");
+    neural_network.register_fully_connected(ActivationFunction::ReLU, 512).expect("Register fully connected");
+    neural_network.register_fully_connected(ActivationFunction::Sigmoid, 1).expect("Register fully connected");
+
+    let example = make_example_network();
+    assert_eq!(neural_network.num_layers(), example.num_layers());
+
+    for i in 0..example.num_layers() {
+        let layer = neural_network.get_layer(i).expect("Get layer");
+        let expected = example.get_layer(i).expect("Get layer");
+
+        assert!(layer.same_structure(expected));
+        assert_eq!(layer.output_dimension(), expected.output_dimension());
+    }
+
+    assert!(neural_network.register_conv(ActivationFunction::ReLU, 0, 1, 3, 8).is_err());
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::BinaryCrossEntropy);
+    neural_network.register_layer(ActivationFunction::ReLU, Layer::make_input_layer(0, (4, 4, 1)));
+    assert!(matches!(neural_network.register_conv(ActivationFunction::ReLU, 0, 1, 5, 2), Err(Error::ImpossibleOutputDimension)));
+}
+
+fn make_output_network(activation_function: ActivationFunction, error_function: ErrorFunction, biases: Vec<f32>) -> NeuralNetwork
+{
+    let num_neurons = biases.len();
+    let mut output_layer = Layer::make_fully_connected_layer(3, num_neurons);
+
+    if let Layer::FullyConnected(ref mut fc) = output_layer {
+        fc.set_weights((0..3 * num_neurons).map(|i| (i as f32 * 0.7).sin()).collect()).expect("Set weights");
+        fc.set_biases(biases).expect("Set biases");
+    }
+
+    let mut neural_network = NeuralNetwork::new(error_function);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (1, 1, 3)));
+    neural_network.register_layer(activation_function, output_layer);
+    neural_network.set_input(&vec![0.5, -1.0, 2.0]).expect("Set input");
+
+    neural_network
+}
+
+#[test]
+fn fused_output_gradients_match_finite_differences()
+{
+    let cases = [
+        (ActivationFunction::Softmax, ErrorFunction::CategoricalCrossEntropy, vec![0.1, -0.3, 0.2, 0.4], vec![0.0, 0.0, 1.0, 0.0]),
+        (ActivationFunction::Sigmoid, ErrorFunction::BinaryCrossEntropy, vec![0.1, -0.3], vec![1.0, 0.0]),
+    ];
+
+    for (activation_function, error_function, biases, target) in cases {
+        assert!(nn_error::has_fused_derivative(activation_function, error_function));
+
+        let mut neural_network = make_output_network(activation_function, error_function, biases.clone());
+        neural_network.forward_propagate().expect("Forward propagate");
+        neural_network.start_batch();
+        neural_network.back_propagate(&target).expect("Back propagate");
+
+        let gradients = neural_network.collect_gradients();
+        let bias_gradients = &gradients[gradients.len() - biases.len()..];
+
+        let epsilon = 1e-3;
+        for i in 0..biases.len() {
+            let error = |offset: f32| {
+                let mut biases = biases.clone();
+                biases[i] += offset;
+
+                let mut neural_network = make_output_network(activation_function, error_function, biases);
+                neural_network.forward_propagate().expect("Forward propagate");
+                neural_network.get_error(&target).expect("Get error")
+            };
+
+            let numerical = (error(epsilon) - error(-epsilon)) / (2.0 * epsilon);
+            assert!((numerical - util::from_gradient(bias_gradients[i])).abs() < 1e-3, "{} != {}", numerical, bias_gradients[i]);
+        }
+    }
+}
+
+#[test]
+fn fused_output_gradients_are_stable_when_saturated()
+{
+    let target = vec![0.0];
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::BinaryCrossEntropy);
+    let mut output_layer = Layer::make_fully_connected_layer(2, 1);
+    if let Layer::FullyConnected(ref mut fc) = output_layer {
+        fc.set_biases(vec![40.0]).expect("Set biases");
+    }
+
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (1, 1, 2)));
+    neural_network.register_layer(ActivationFunction::Sigmoid, output_layer.clone());
+    neural_network.set_input(&vec![0.0, 0.0]).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagate");
+    neural_network.start_batch();
+    neural_network.back_propagate(&target).expect("Back propagate");
+
+    let fused = *neural_network.collect_gradients().last().expect("Bias gradient");
+    assert!((fused - 1.0).abs() < 1e-6);
+
+    // multiplying the clamped error derivative by the saturated sigmoid derivative loses the gradient
+    let mut input_layer = Layer::make_input_layer(0, (1, 1, 2));
+    input_layer.forward_propagate(&mut output_layer).expect("Forward propagation");
+    output_layer.activate(ActivationFunction::Sigmoid);
+
+    if let Layer::FullyConnected(ref mut fc) = output_layer {
+        fc.calculate_output_gradients(ErrorFunction::BinaryCrossEntropy, &target, None, nn_error::DEFAULT_EPSILON).expect("Output gradients");
+    }
+
+    output_layer.backward_activate(ActivationFunction::Sigmoid);
+    output_layer.back_propagate(&mut input_layer).expect("Back propagation");
+
+    if let Layer::FullyConnected(ref fc) = output_layer {
+        assert_eq!(fc.bias_gradients[0], 0.0);
+    }
+}
+
+#[test]
+fn softmax_outputs_a_distribution()
+{
+    let mut neural_network = make_output_network(ActivationFunction::Softmax, ErrorFunction::CategoricalCrossEntropy, vec![100.0, 0.0, -100.0]);
+    neural_network.forward_propagate().expect("Forward propagate");
+
+    let output = neural_network.get_output().expect("Get output");
+    assert!((output.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+    assert!(output.iter().all(|value| value.is_finite() && *value >= 0.0));
+    assert_eq!(util::argmax(&output), 0);
+}
+
+#[test]
+fn normalized_filters()
+{
+    let mut layer = Layer::make_convolutional_layer(0, 1, 2, (3, 3, 2), 2);
+
+    if let Layer::Convolutional(ref mut conv) = layer {
+        // kernel 1 has a constant first channel and the values -2, 0, 2, 6 in its second channel
+        let mut kernel: Vec<f32> = (0..8).map(|i| i as f32).collect();
+        kernel.extend([3.0, 3.0, 3.0, 3.0, -2.0, 0.0, 2.0, 6.0]);
+        conv.set_kernel(kernel).expect("Set kernel");
+
+        let filter = conv.filter_as_normalized(1).expect("Normalize filter");
+        let dimension = (2, 2, 2);
+        assert_eq!(filter.len(), 2 * 2 * 2);
+
+        assert_eq!(filter[util::get_index((0, 0, 0), dimension)], 0.0);
+        assert_eq!(filter[util::get_index((1, 1, 0), dimension)], 0.0);
+
+        assert_eq!(filter[util::get_index((0, 0, 1), dimension)], 0.0);
+        assert_eq!(filter[util::get_index((1, 0, 1), dimension)], 0.25);
+        assert_eq!(filter[util::get_index((0, 1, 1), dimension)], 0.5);
+        assert_eq!(filter[util::get_index((1, 1, 1), dimension)], 1.0);
+
+        let filter = conv.filter_as_normalized(0).expect("Normalize filter");
+        assert!(filter.iter().all(|value| (0.0..=1.0).contains(value)));
+
+        assert!(conv.filter_as_normalized(2).is_err());
+    }
+}
+
+#[test]
+fn file_dataset_matches_in_memory_dataset()
+{
+    use dataset::{DataLoader, Dataset, FileDataset, InMemoryDataset};
+    use rand::SeedableRng;
+
+    let inputs: Vec<Vec<f32>> = (0..7).map(|i| (0..4).map(|j| (i * 4 + j) as f32 * 0.5).collect()).collect();
+    let targets: Vec<Vec<f32>> = (0..7).map(|i| vec![(i % 2) as f32, -(i as f32)]).collect();
+    let memory = InMemoryDataset::new(inputs, targets).expect("Create dataset");
+
+    let path = std::env::temp_dir().join(format!("convolutional_neural_network_dataset_{}.bin", std::process::id()));
+    FileDataset::write(&path, &memory).expect("Write dataset");
+    let file = FileDataset::open(&path, 4, 2).expect("Open dataset");
+
+    assert_eq!(file.len(), memory.len());
+    assert!(file.get(7).is_err());
+
+    let mut memory_loader = DataLoader::new(&memory, 3).expect("Create loader");
+    let mut file_loader = DataLoader::new(&file, 3).expect("Create loader");
+    assert_eq!(memory_loader.num_batches(), 3);
+
+    memory_loader.shuffle(&mut rand::rngs::StdRng::seed_from_u64(1));
+    file_loader.shuffle(&mut rand::rngs::StdRng::seed_from_u64(1));
+
+    let memory_batches: Vec<_> = memory_loader.map(|batch| batch.expect("Load batch")).collect();
+    let file_batches: Vec<_> = file_loader.map(|batch| batch.expect("Load batch")).collect();
+
+    assert_eq!(memory_batches.iter().map(|batch| batch.len()).collect::<Vec<_>>(), vec![3, 3, 1]);
+    assert_eq!(memory_batches, file_batches);
+
+    // reads from several threads don't move each other's cursor
+    std::thread::scope(|scope| {
+        for offset in 0..8 {
+            let (file, memory) = (&file, &memory);
+
+            scope.spawn(move || {
+                for i in 0..2000 {
+                    let index = (i * 3 + offset) % memory.len();
+                    assert_eq!(file.get(index).expect("Get sample"), memory.get(index).expect("Get sample"));
+                }
+            });
+        }
+    });
+
+    assert!(FileDataset::open(&path, 3, 2).is_err());
+    std::fs::remove_file(&path).expect("Remove dataset");
+}
+
+#[test]
+fn gradient_norms_per_layer()
+{
+    let mut neural_network_pooled = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network_pooled.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (2, 2, 1)));
+    neural_network_pooled.register_layer(ActivationFunction::None, Layer::make_pooling_layer(PoolingType::Max, 0, 2, 2, (1, 1, 1)));
+    assert_eq!(neural_network_pooled.gradient_norms(), vec![0.0, 0.0]);
+
+    let mut neural_network = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+    neural_network.set_input(&vec![0.5, 0.5]).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagate");
+    neural_network.start_batch();
+    neural_network.back_propagate(&vec![1.0, 0.0]).expect("Back propagate");
+
+    // the output gradients are (0.5 - 1.0) / 2 and (0.5 - 0.0) / 2, the weight gradients multiply them by the inputs
+    let norms = neural_network.gradient_norms();
+    assert_eq!(norms.len(), 2);
+    assert_eq!(norms[0], 0.0);
+    assert!((norms[1] - (2.0f32 * 0.25 * 0.25 + 4.0 * 0.125 * 0.125).sqrt()).abs() < 1e-6);
+}
+
+#[test]
+fn set_activation_changes_output()
+{
+    let mut neural_network = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+    let input = vec![2.0, -1.0];
+
+    neural_network.set_input(&input).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagate");
+    assert_eq!(neural_network.get_output().expect("Get output"), input);
+
+    neural_network.set_activation(1, ActivationFunction::ReLU).expect("Set activation");
+    assert!(matches!(neural_network.get_activation(1), Some(ActivationFunction::ReLU)));
+
+    neural_network.forward_propagate().expect("Forward propagate");
+    assert_eq!(neural_network.get_output().expect("Get output"), vec![2.0, 0.0]);
+
+    neural_network.set_activation(1, ActivationFunction::Softmax).expect("Set activation");
+    neural_network.forward_propagate().expect("Forward propagate");
+
+    let output = neural_network.get_output().expect("Get output");
+    let expected = 1.0 / (1.0 + (-3.0f32).exp());
+    assert!((output[0] - expected).abs() < 1e-6);
+    assert!((output[0] + output[1] - 1.0).abs() < 1e-6);
+
+    assert!(neural_network.set_activation(2, ActivationFunction::ReLU).is_err());
+    assert!(neural_network.get_activation(2).is_none());
+}
+
+#[test]
+fn validate_flags_unbounded_outputs_with_probability_losses()
+{
+    assert!(matches!(make_example_network().validate(), Err(Error::UninitializedLayer(1))));
+    assert!(make_identity_network(ErrorFunction::HalfMeanSquaredError).validate().is_ok());
+    assert!(matches!(make_identity_network(ErrorFunction::BinaryCrossEntropy).validate(), Err(Error::IncompatibleErrorFunction)));
+    assert!(matches!(make_identity_network(ErrorFunction::CategoricalCrossEntropy).validate(), Err(Error::IncompatibleErrorFunction)));
+
+    let mut neural_network = make_identity_network(ErrorFunction::CategoricalCrossEntropy);
+    neural_network.set_activation(1, ActivationFunction::Softmax).expect("Set activation");
+    assert!(neural_network.validate().is_ok());
+
+    neural_network.set_error_function(ErrorFunction::BinaryCrossEntropy);
+    neural_network.set_activation(1, ActivationFunction::LeakyReLU(0.1)).expect("Set activation");
+    assert!(neural_network.validate().is_err());
+
+    assert!(matches!(NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError).validate(), Err(Error::IncompatibleLayers)));
+}
+
+#[test]
+fn training_saves_checkpoints()
+{
+    use dataset::InMemoryDataset;
+
+    let inputs: Vec<Vec<f32>> = (0..6).map(|i| vec![(i as f32 * 0.3).sin(), (i as f32 * 0.7).cos()]).collect();
+    let targets: Vec<Vec<f32>> = (0..6).map(|i| vec![(i % 2) as f32, 1.0 - (i % 2) as f32]).collect();
+    let dataset = InMemoryDataset::new(inputs.clone(), targets.clone()).expect("Create dataset");
+
+    let path = std::env::temp_dir().join(format!("convolutional_neural_network_checkpoint_{}.bin", std::process::id()));
+    let load = || -> NeuralNetwork {
+        let mut reader = std::io::BufReader::new(std::fs::File::open(&path).expect("Open checkpoint"));
+        bincode::serde::decode_from_std_read(&mut reader, bincode::config::standard()).expect("Load checkpoint")
+    };
+    let weights = |neural_network: &NeuralNetwork| neural_network.get_layer(1).and_then(|layer| layer.get_weights()).expect("Get weights").clone();
+
+    let mut config = TrainingConfig {
+        epochs: 1,
+        batch_size: 2,
+        shuffle: false,
+        seed: None,
+        decoupled_weight_decay: false,
+        num_threads: 1,
+        learning_rate_schedule: None,
+
+        checkpoint_path: Some(path.clone()),
+        checkpoint_interval: 2,
+    };
+
+    // the checkpoint after the second of three batches only saw the first four samples
+    let mut neural_network = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+    let errors = neural_network.train(&dataset, &config).expect("Train");
+    assert_eq!(errors.len(), 1);
+
+    let mut expected = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+    let first_batches = InMemoryDataset::new(inputs[..4].to_vec(), targets[..4].to_vec()).expect("Create dataset");
+    expected.train(&first_batches, &TrainingConfig { checkpoint_path: None, ..config.clone() }).expect("Train");
+
+    assert_eq!(weights(&load()), weights(&expected));
+    assert_ne!(weights(&load()), weights(&neural_network));
+
+    config.checkpoint_interval = 1;
+    neural_network.train(&dataset, &config).expect("Train");
+    assert_eq!(weights(&load()), weights(&neural_network));
+
+    let mut temp_path = path.clone().into_os_string();
+    temp_path.push(".tmp");
+    assert!(!std::path::Path::new(&temp_path).exists());
+
+    config.checkpoint_interval = 0;
+    assert!(neural_network.train(&dataset, &config).is_err());
+
+    std::fs::remove_file(&path).expect("Remove checkpoint");
+}
+
+#[test]
+fn empty_and_single_layer_networks_return_errors()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+
+    assert!(matches!(neural_network.set_input(&vec![0.0]), Err(Error::IncompatibleLayers)));
+    assert!(matches!(neural_network.forward_propagate(), Err(Error::IncompatibleLayers)));
+    assert!(matches!(neural_network.back_propagate(&vec![0.0]), Err(Error::IncompatibleLayers)));
+    assert!(matches!(neural_network.get_error(&vec![0.0]), Err(Error::IncompatibleLayers)));
+    assert!(matches!(neural_network.get_output(), Err(Error::IncompatibleLayers)));
+
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (1, 1, 2)));
+    neural_network.set_input(&vec![1.0, 2.0]).expect("Set input");
+
+    assert!(matches!(neural_network.forward_propagate(), Err(Error::IncompatibleLayers)));
+    assert!(matches!(neural_network.back_propagate(&vec![0.0, 0.0]), Err(Error::IncompatibleLayers)));
+    assert!(neural_network.get_error(&vec![0.0, 0.0]).is_err());
+    assert!(neural_network.get_output().is_err());
+}
+
+fn pool_window(pooling_type: PoolingType, training: bool, input: &Vec<f32>) -> (Layer, Layer)
+{
+    let mut input_layer = Layer::make_input_layer(0, (2, 2, 1));
+    let mut layer = Layer::make_pooling_layer(pooling_type, 0, 2, 2, (1, 1, 1));
+
+    if let Layer::Convolutional(ref mut conv) = input_layer {
+        conv.set_volume(input).expect("Set volume");
+    }
+
+    layer.set_training(training);
+    input_layer.forward_propagate(&mut layer).expect("Forward propagation");
+
+    (input_layer, layer)
+}
+
+#[test]
+fn mixed_pooling_blends_max_and_average()
+{
+    let input = vec![0.5, 3.0, 1.5, 2.0];
+    let output = |pooling_type: PoolingType| match pool_window(pooling_type, true, &input).1 {
+        Layer::Pooling(pool) => pool.volume[0],
+        _ => unreachable!(),
+    };
+
+    assert_eq!(output(PoolingType::Mixed(1.0)), output(PoolingType::Max));
+    assert_eq!(output(PoolingType::Mixed(0.0)), output(PoolingType::Average));
+    assert!((output(PoolingType::Mixed(0.25)) - (0.25 * 3.0 + 0.75 * 1.75)).abs() < 1e-6);
+
+    let (mut input_layer, mut layer) = pool_window(PoolingType::Mixed(0.5), true, &input);
+    if let Layer::Pooling(ref mut pool) = layer {
+        pool.volume_gradients[0] = 1.0;
+    }
+
+    layer.back_propagate(&mut input_layer).expect("Back propagation");
+    if let Layer::Convolutional(conv) = input_layer {
+        assert_eq!(conv.volume_gradients, vec![0.125, 0.625, 0.125, 0.125]);
+    }
+}
+
+#[test]
+#[allow(clippy::needless_range_loop)]
+fn stochastic_pooling_samples_proportionally()
+{
+    let input = vec![1.0, 2.0, 3.0, 4.0];
+    let (mut input_layer, mut layer) = pool_window(PoolingType::Stochastic, true, &input);
+
+    if let Layer::Pooling(ref mut pool) = layer {
+        pool.set_seed(7);
+    }
+
+    let draws = 20000;
+    let mut counts = [0; 4];
+
+    for _ in 0..draws {
+        input_layer.forward_propagate(&mut layer).expect("Forward propagation");
+
+        let Layer::Pooling(ref mut pool) = layer else { unreachable!() };
+        let sampled = pool.volume[0];
+        counts[sampled as usize - 1] += 1;
+
+        // the gradient only reaches the sampled value
+        pool.volume_gradients[0] = 1.0;
+        layer.back_propagate(&mut input_layer).expect("Back propagation");
+
+        let Layer::Convolutional(ref conv) = input_layer else { unreachable!() };
+        for i in 0..4 {
+            assert_eq!(conv.volume_gradients[i], if input[i] == sampled { 1.0 } else { 0.0 });
+        }
+    }
+
+    for i in 0..4 {
+        let proportion = counts[i] as f32 / draws as f32;
+        assert!((proportion - input[i] / 10.0).abs() < 0.02, "{} sampled {} times", input[i], counts[i]);
+    }
+
+    // inference takes the activation weighted average
+    let (_, layer) = pool_window(PoolingType::Stochastic, false, &input);
+    if let Layer::Pooling(pool) = layer {
+        assert!((pool.volume[0] - 3.0).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn pooling_type_serializes_mixed_ratio()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (2, 2, 1)));
+    neural_network.register_layer(ActivationFunction::None, Layer::make_pooling_layer(PoolingType::Mixed(0.3), 0, 2, 2, (1, 1, 1)));
+    neural_network.register_layer(ActivationFunction::None, Layer::make_fully_connected_layer(1, 1));
+
+    let bytes = bincode::serde::encode_to_vec(&neural_network, bincode::config::standard()).expect("Serialize");
+    let (loaded, _): (NeuralNetwork, usize) = bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).expect("Deserialize");
+
+    let layer = loaded.get_layer(1).expect("Get layer");
+    assert!(layer.same_structure(neural_network.get_layer(1).expect("Get layer")));
+    assert!(!layer.same_structure(&Layer::make_pooling_layer(PoolingType::Mixed(0.4), 0, 2, 2, (1, 1, 1))));
+}
+
+#[test]
+fn truncated_network_extracts_features()
+{
+    let mut neural_network = make_example_network();
+
+    assert!(neural_network.truncate(0).is_err());
+    assert!(neural_network.truncate(10).is_err());
+
+    // the first convolution and pooling layers form the backbone
+    neural_network.truncate(3).expect("Truncate");
+    assert_eq!(neural_network.num_layers(), 3);
+
+    let input: Vec<f32> = (0..128 * 128 * 3).map(|i| (i % 255) as f32 / 255.0).collect();
+    let features = neural_network.extract_features(&input).expect("Extract features");
+    assert_eq!(features.len(), 64 * 64 * 32);
+
+    neural_network.truncate(1).expect("Truncate");
+    assert_eq!(neural_network.extract_features(&input).expect("Extract features"), input);
+    assert!(neural_network.extract_features(&input[1..]).is_err());
+}
+
+#[test]
+fn affine_augmentation()
+{
+    use augment::{AffineRanges, AffineTransform, FillMode};
+    use rand::SeedableRng;
+
+    let dimension = (3, 3, 2);
+    let volume: Vec<f32> = (0..18).map(|i| i as f32 + 1.0).collect();
+
+    for fill in [FillMode::Zero, FillMode::Clamp] {
+        assert_eq!(augment::affine(&volume, dimension, AffineTransform::default(), fill).expect("Transform volume"), volume);
+    }
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+    let ranges = AffineRanges { rotation: 0.0, translation: (0.0, 0.0), scale: (1.0, 1.0) };
+    assert_eq!(augment::random_affine(&volume, dimension, ranges, FillMode::Zero, &mut rng).expect("Transform volume"), volume);
+
+    // a quarter turn moves every corner to the next one
+    let rotation = AffineTransform { rotation: std::f32::consts::FRAC_PI_2, ..AffineTransform::default() };
+    let rotated = augment::affine(&volume, dimension, rotation, FillMode::Zero).expect("Transform volume");
+
+    for ((from_x, from_y), (to_x, to_y)) in [((0, 0), (2, 0)), ((2, 0), (2, 2)), ((2, 2), (0, 2)), ((0, 2), (0, 0)), ((1, 1), (1, 1))] {
+        for z in 0..2 {
+            let expected = volume[util::get_index((from_x, from_y, z), dimension)];
+            assert!((rotated[util::get_index((to_x, to_y, z), dimension)] - expected).abs() < 1e-4);
+        }
+    }
+
+    // translating by a pixel fills the uncovered column
+    let translation = AffineTransform { translation: (1.0, 0.0), ..AffineTransform::default() };
+    let zero = augment::affine(&volume, dimension, translation, FillMode::Zero).expect("Transform volume");
+    let clamp = augment::affine(&volume, dimension, translation, FillMode::Clamp).expect("Transform volume");
+
+    assert_eq!(zero[util::get_index((0, 1, 0), dimension)], 0.0);
+    assert_eq!(clamp[util::get_index((0, 1, 0), dimension)], volume[util::get_index((0, 1, 0), dimension)]);
+    assert_eq!(zero[util::get_index((2, 1, 1), dimension)], volume[util::get_index((1, 1, 1), dimension)]);
+}
+
+#[test]
+fn error_epsilon_bounds_cross_entropy_gradients()
+{
+    let saturated = vec![1.0];
+    let target = vec![0.0];
+
+    let gradient = |epsilon: f32| nn_error::eval_derivative(ErrorFunction::BinaryCrossEntropy, 0, &saturated, &target, None, epsilon);
+
+    assert!(gradient(nn_error::DEFAULT_EPSILON).is_finite());
+    assert!((gradient(1e-2) - 100.0).abs() < 1e-3);
+    assert!(gradient(1e-2) < gradient(nn_error::DEFAULT_EPSILON));
+    assert!(nn_error::eval(ErrorFunction::CategoricalCrossEntropy, &vec![0.0, 1.0], &vec![1.0, 0.0], None, 1e-2) <= -(1e-2f32).ln() + 1e-6);
+
+    // an unfused output layer uses the network's epsilon
+    let mut neural_network = make_identity_network(ErrorFunction::BinaryCrossEntropy);
+    assert_eq!(neural_network.get_error_epsilon(), nn_error::DEFAULT_EPSILON);
+    assert!(neural_network.set_error_epsilon(0.0).is_err());
+    assert!(neural_network.set_error_epsilon(0.5).is_err());
+
+    neural_network.set_error_epsilon(1e-2).expect("Set epsilon");
+    neural_network.set_input(&vec![1.0, 1.0]).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagate");
+    neural_network.start_batch();
+    neural_network.back_propagate(&vec![0.0, 0.0]).expect("Back propagate");
+
+    let gradients = neural_network.collect_gradients();
+    assert!(gradients.iter().all(|gradient| gradient.abs() <= 100.0 / 2.0 + 1e-3));
+    assert!((neural_network.get_error(&vec![0.0, 0.0]).expect("Get error") + (1e-2f32).ln()).abs() < 1e-5);
+}
+
+#[test]
+fn forward_with_activations_returns_every_layer()
+{
+    let mut neural_network = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::Sigmoid, Layer::make_fully_connected_layer(2, 3));
+    neural_network.initialize(2, Initialization::NormalXavier).expect("Initialize");
+
+    let input = vec![0.25, -0.5];
+    let activations = neural_network.forward_with_activations(&input).expect("Forward propagate");
+
+    assert_eq!(activations.len(), 3);
+    assert_eq!(activations[0], input);
+    assert_eq!(activations[1], input);
+    assert_eq!(activations[2].len(), 3);
+    assert_eq!(*activations.last().expect("Last activation"), neural_network.get_output().expect("Get output"));
+}
+
+fn make_prelu_network(slopes: Vec<f32>) -> NeuralNetwork
+{
+    let mut conv_layer = Layer::make_convolutional_layer(0, 1, 2, (2, 2, 2), 1);
+    let mut output_layer = Layer::make_fully_connected_layer(8, 1);
+
+    conv_layer.configure_activation(ActivationFunction::PReLU);
+    if let Layer::Convolutional(ref mut conv) = conv_layer {
+        conv.set_kernel((0..8).map(|i| (i as f32 * 1.3).sin()).collect()).expect("Set kernel");
+        conv.set_biases(vec![-0.2, 0.1]).expect("Set biases");
+        conv.set_prelu_slopes(slopes).expect("Set slopes");
+    }
+
+    if let Layer::FullyConnected(ref mut fc) = output_layer {
+        fc.set_weights((0..8).map(|i| (i as f32 * 0.7).cos()).collect()).expect("Set weights");
+    }
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (3, 3, 1)));
+    neural_network.register_layer(ActivationFunction::PReLU, conv_layer);
+    neural_network.register_layer(ActivationFunction::None, output_layer);
+    neural_network.set_input(&(0..9).map(|i| (i as f32 * 0.9).cos()).collect()).expect("Set input");
+
+    neural_network
+}
+
+#[test]
+fn prelu_slope_gradients_match_finite_differences()
+{
+    let slopes = vec![0.25, -0.1];
+    let target = vec![0.5];
+
+    let mut neural_network = make_prelu_network(slopes.clone());
+    neural_network.forward_propagate().expect("Forward propagate");
+    neural_network.start_batch();
+    neural_network.back_propagate(&target).expect("Back propagate");
+
+    let slope_gradients = match neural_network.get_layer(1).expect("Get layer") {
+        Layer::Convolutional(conv) => conv.prelu.gradients.clone(),
+        _ => unreachable!(),
+    };
+
+    let epsilon = 1e-3;
+    for i in 0..slopes.len() {
+        let error = |offset: f32| {
+            let mut slopes = slopes.clone();
+            slopes[i] += offset;
+
+            let mut neural_network = make_prelu_network(slopes);
+            neural_network.forward_propagate().expect("Forward propagate");
+            neural_network.get_error(&target).expect("Get error")
+        };
+
+        let numerical = (error(epsilon) - error(-epsilon)) / (2.0 * epsilon);
+        assert!(numerical.abs() > 1e-3);
+        assert!((numerical - util::from_gradient(slope_gradients[i])).abs() < 1e-3, "{} != {}", numerical, slope_gradients[i]);
+    }
+
+    // a step moves the slopes, and they survive serialization
+    neural_network.end_batch(1);
+    let trained = match neural_network.get_layer(1).expect("Get layer") {
+        Layer::Convolutional(conv) => conv.get_prelu_slopes().clone(),
+        _ => unreachable!(),
+    };
+    assert_ne!(trained, slopes);
+    assert_eq!(neural_network.num_parameters(), 8 + 2 + 2 + 8 + 1);
+
+    let bytes = bincode::serde::encode_to_vec(&neural_network, bincode::config::standard()).expect("Serialize");
+    let (mut loaded, _): (NeuralNetwork, usize) = bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).expect("Deserialize");
+
+    match loaded.get_layer(1).expect("Get layer") {
+        Layer::Convolutional(conv) => assert_eq!(*conv.get_prelu_slopes(), trained),
+        _ => unreachable!(),
+    }
+
+    let input: Vec<f32> = (0..9).map(|i| (i as f32 * 0.4).sin()).collect();
+    assert_eq!(loaded.extract_features(&input).expect("Forward propagate"), neural_network.extract_features(&input).expect("Forward propagate"));
+}
+
+#[test]
+fn no_bias_layers_skip_the_bias_term()
+{
+    let kernel: Vec<f32> = (0..24).map(|i| (i as f32 * 0.61).cos()).collect();
+    let biases = vec![0.5, -1.0, 2.0];
+
+    let mut input_layer = Layer::make_input_layer(1, (4, 4, 2));
+    let mut biased = Layer::make_convolutional_layer(1, 1, 2, (5, 5, 3), 2);
+    let mut unbiased = Layer::make_convolutional_layer(1, 1, 2, (5, 5, 3), 2);
+
+    if let Layer::Convolutional(ref mut conv) = input_layer {
+        conv.set_volume(&(0..32).map(|i| (i as f32 * 0.37).sin()).collect()).expect("Set volume");
+    }
+
+    if let Layer::Convolutional(ref mut conv) = biased {
+        conv.set_kernel(kernel.clone()).expect("Set kernel");
+        conv.set_biases(biases.clone()).expect("Set biases");
+    }
+
+    if let Layer::Convolutional(ref mut conv) = unbiased {
+        conv.set_use_bias(false);
+        conv.set_kernel(kernel).expect("Set kernel");
+
+        assert!(!conv.uses_bias());
+        assert!(conv.get_biases().is_empty());
+        assert!(conv.set_biases(vec![0.0; 3]).is_err());
+    }
+
+    input_layer.forward_propagate(&mut biased).expect("Forward propagation");
+    input_layer.forward_propagate(&mut unbiased).expect("Forward propagation");
+
+    let (biased, unbiased) = (biased.get_output(), unbiased.get_output());
+    for i in 0..biased.len() {
+        assert!((biased[i] - biases[i % 3] - unbiased[i]).abs() < 1e-5);
+    }
+
+    // dense layers without a bias keep the flag through training and serialization
+    let mut output_layer = Layer::make_fully_connected_layer(2, 2);
+    if let Layer::FullyConnected(ref mut fc) = output_layer {
+        fc.set_use_bias(false);
+        fc.set_weights(vec![1.0, 0.0, 0.0, 1.0]).expect("Set weights");
+    }
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (1, 1, 2)));
+    neural_network.register_layer(ActivationFunction::None, output_layer);
+    assert_eq!(neural_network.num_parameters(), 4);
+
+    neural_network.set_input(&vec![0.5, -0.5]).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagate");
+    assert_eq!(neural_network.get_output().expect("Get output"), vec![0.5, -0.5]);
+
+    neural_network.start_batch();
+    neural_network.back_propagate(&vec![1.0, 1.0]).expect("Back propagate");
+    neural_network.end_batch(1);
+
+    let bytes = bincode::serde::encode_to_vec(&neural_network, bincode::config::standard()).expect("Serialize");
+    let (loaded, _): (NeuralNetwork, usize) = bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).expect("Deserialize");
+
+    match loaded.get_layer(1).expect("Get layer") {
+        Layer::FullyConnected(fc) => assert!(!fc.uses_bias() && fc.get_biases().is_empty()),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn decoupled_weight_decay_is_kept_out_of_the_momentum()
+{
+    let hyperparameters = Hyperparameters { learning_rate: 0.1, momentum: 0.9, weight_decay: 0.1 };
+    let weight = |neural_network: &NeuralNetwork| neural_network.get_layer(1).and_then(|layer| layer.get_weights()).expect("Get weights")[0];
+
+    let mut coupled = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+    let mut decoupled = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+    coupled.set_hyperparameters(hyperparameters);
+    decoupled.set_hyperparameters(hyperparameters);
+
+    // the identity network already fits the target, so only the weight decay moves the weights
+    let sample = vec![0.0, 0.0];
+
+    for step in 1..=5 {
+        for neural_network in [&mut coupled, &mut decoupled] {
+            neural_network.set_input(&sample).expect("Set input");
+            neural_network.forward_propagate().expect("Forward propagate");
+            neural_network.start_batch();
+            neural_network.back_propagate(&sample).expect("Back propagate");
+        }
+
+        coupled.end_batch(1);
+        decoupled.end_batch_decoupled(1);
+
+        // the decay builds up velocity when it is part of the gradient
+        assert!((weight(&decoupled) - 0.99f32.powi(step)).abs() < 1e-6);
+        if step > 1 { assert!(weight(&coupled) < weight(&decoupled) - 1e-3) };
+    }
+}
+
+#[test]
+fn temperature_softens_the_output_distribution()
+{
+    let mut neural_network = make_output_network(ActivationFunction::Softmax, ErrorFunction::CategoricalCrossEntropy, vec![0.1, -0.3, 0.2, 0.4]);
+    assert_eq!(neural_network.get_temperature(), 1.0);
+    assert!(neural_network.set_temperature(0.0).is_err());
+
+    neural_network.forward_propagate().expect("Forward propagate");
+    let calibrated = neural_network.get_output().expect("Get output");
+
+    // the temperature only applies during inference
+    neural_network.set_temperature(2.0).expect("Set temperature");
+    neural_network.forward_propagate().expect("Forward propagate");
+    assert_eq!(neural_network.get_output().expect("Get output"), calibrated);
+
+    neural_network.set_training(false);
+    neural_network.forward_propagate().expect("Forward propagate");
+    let softened = neural_network.get_output().expect("Get output");
+
+    let max = |values: &Vec<f32>| values.iter().cloned().fold(f32::MIN, f32::max);
+    let min = |values: &Vec<f32>| values.iter().cloned().fold(f32::MAX, f32::min);
+
+    assert!((softened.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+    assert!(max(&softened) < max(&calibrated));
+    assert!(min(&softened) > min(&calibrated));
+
+    let bytes = bincode::serde::encode_to_vec(&neural_network, bincode::config::standard()).expect("Serialize");
+    let (loaded, _): (NeuralNetwork, usize) = bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).expect("Deserialize");
+    assert_eq!(loaded.get_temperature(), 2.0);
+}
+
+fn make_fully_connected(num_inputs: usize, weights: Vec<f32>) -> Layer
+{
+    let mut layer = Layer::make_fully_connected_layer(num_inputs, weights.len() / num_inputs);
+
+    if let Layer::FullyConnected(ref mut fc) = layer {
+        fc.set_weights(weights).expect("Set weights");
+    }
+
+    layer
+}
+
+#[test]
+fn add_layer_sums_paths_and_splits_gradients()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (1, 1, 2)));
+    neural_network.register_layer(ActivationFunction::None, make_fully_connected(2, vec![1.0, 0.0, 0.0, 1.0]));
+    neural_network.register_layer(ActivationFunction::None, make_fully_connected(2, vec![2.0, 0.0, 0.0, 3.0]));
+
+    assert!(matches!(neural_network.register_add(ActivationFunction::None, 0, 0), Err(Error::DimensionMismatch)));
+    assert!(neural_network.register_add(ActivationFunction::None, 2, 0).is_err());
+
+    // the output of the first dense layer skips the second one
+    neural_network.register_add(ActivationFunction::None, 1, 0).expect("Register add");
+    neural_network.register_layer(ActivationFunction::None, make_fully_connected(2, vec![1.0, 1.0]));
+
+    neural_network.set_input(&vec![1.0, 2.0]).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagate");
+
+    assert_eq!(*neural_network.get_layer(3).expect("Get layer").get_output(), vec![1.0 + 2.0, 2.0 + 6.0]);
+    assert_eq!(neural_network.get_output().expect("Get output"), vec![11.0]);
+
+    neural_network.start_batch();
+    neural_network.back_propagate(&vec![10.0]).expect("Back propagate");
+
+    let bias_gradients = |layer_index: usize| match neural_network.get_layer(layer_index).expect("Get layer") {
+        Layer::FullyConnected(fc) => fc.bias_gradients.clone(),
+        _ => unreachable!(),
+    };
+
+    // the first layer receives the gradient through the second layer plus the gradient of the skip
+    let output_gradient = bias_gradients(4)[0];
+    assert!(output_gradient != 0.0);
+    assert_eq!(bias_gradients(2), vec![output_gradient, output_gradient]);
+    assert_eq!(bias_gradients(1), vec![(2.0 + 1.0) * output_gradient, (3.0 + 1.0) * output_gradient]);
+
+    let bytes = bincode::serde::encode_to_vec(&neural_network, bincode::config::standard()).expect("Serialize");
+    let (mut loaded, _): (NeuralNetwork, usize) = bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).expect("Deserialize");
+
+    assert_eq!(loaded.extract_features(&[1.0, 2.0]).expect("Forward propagate"), vec![11.0]);
+}
+
+#[test]
+fn input_gradient_matches_finite_differences()
+{
+    let input: Vec<f32> = (0..9).map(|i| (i as f32 * 0.9).cos()).collect();
+    let target = vec![0.5];
+
+    let mut neural_network = make_prelu_network(vec![0.25, 0.1]);
+    neural_network.start_batch();
+    let saliency = neural_network.input_gradient(&input, &target).expect("Input gradient");
+
+    assert_eq!(saliency.len(), input.len());
+    assert!(saliency.iter().any(|gradient| *gradient != 0.0));
+
+    let epsilon = 1e-3;
+    for i in 0..input.len() {
+        let mut error = |offset: f32| {
+            let mut input = input.clone();
+            input[i] += offset;
+
+            neural_network.set_input(&input).expect("Set input");
+            neural_network.forward_propagate().expect("Forward propagate");
+            neural_network.get_error(&target).expect("Get error")
+        };
+
+        let numerical = (error(epsilon) - error(-epsilon)) / (2.0 * epsilon);
+        assert!((numerical - saliency[i]).abs() < 1e-3, "{} != {}", numerical, saliency[i]);
+    }
+}
+
+#[test]
+fn overlapping_average_pooling_matches_finite_differences()
+{
+    let input: Vec<f32> = (0..32).map(|i| (i as f32 * 0.83).sin()).collect();
+
+    // every input of an overlapping window is shared by up to kernel_size^2 outputs
+    for (kernel_size, dimension) in [(3, (2, 2, 2)), (2, (3, 3, 2))] {
+        let num_outputs = dimension.0 * dimension.1 * dimension.2;
+        let target = vec![0.25];
+
+        let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+        neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (4, 4, 2)));
+        neural_network.register_layer(ActivationFunction::None, Layer::make_pooling_layer(PoolingType::Average, 0, 1, kernel_size, dimension));
+        neural_network.register_layer(ActivationFunction::None, make_fully_connected(num_outputs, (0..num_outputs).map(|i| (i as f32 * 1.7).cos()).collect()));
+
+        neural_network.start_batch();
+        let gradients = neural_network.input_gradient(&input, &target).expect("Input gradient");
+
+        let epsilon = 1e-2;
+        for i in 0..input.len() {
+            let mut error = |offset: f32| {
+                let mut input = input.clone();
+                input[i] += offset;
+
+                neural_network.set_input(&input).expect("Set input");
+                neural_network.forward_propagate().expect("Forward propagate");
+                neural_network.get_error(&target).expect("Get error")
+            };
+
+            let numerical = (error(epsilon) - error(-epsilon)) / (2.0 * epsilon);
+            assert!((numerical - gradients[i]).abs() < 1e-3, "{} != {}", numerical, gradients[i]);
+        }
+    }
+}
+
+#[test]
+fn max_unpool_restores_maxima_at_their_positions()
+{
+    let input = vec![
+        1.0, 5.0, 2.0, 0.5,
+        3.0, 4.0, 8.0, 7.0,
+        9.0, 0.0, 1.0, 6.0,
+        2.0, 1.0, 3.0, 2.0,
+    ];
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (4, 4, 1)));
+    neural_network.register_pooling(ActivationFunction::None, PoolingType::Max, 0, 2, 2).expect("Register pooling");
+    neural_network.register_pooling(ActivationFunction::None, PoolingType::Average, 0, 1, 1).expect("Register pooling");
+
+    assert!(matches!(neural_network.register_max_unpool(2, 0), Err(Error::IncompatibleLayers)));
+    neural_network.register_max_unpool(1, 0).expect("Register max unpool");
+    neural_network.register_fully_connected(ActivationFunction::None, 1).expect("Register fully connected");
+    neural_network.initialize(4, Initialization::NormalXavier).expect("Initialize");
+
+    let activations = neural_network.forward_with_activations(&input).expect("Forward propagate");
+    let maxima: Vec<usize> = (0..16).filter(|&i| [5.0, 8.0, 9.0, 6.0].contains(&input[i])).collect();
+
+    assert_eq!(activations[3].len(), input.len());
+    for i in 0..input.len() {
+        let expected = if maxima.contains(&i) { input[i] } else { 0.0 };
+        assert_eq!(activations[3][i], expected);
+    }
+
+    // only the maxima receive a gradient, and it's the one of the position they were restored to
+    neural_network.start_batch();
+    let gradients = neural_network.input_gradient(&input, &[0.0]).expect("Input gradient");
+    let weights = neural_network.get_layer(4).and_then(|layer| layer.get_weights()).expect("Get weights").clone();
+    let output_gradient = gradients[maxima[0]] / weights[maxima[0]];
+
+    for i in 0..input.len() {
+        let expected = if maxima.contains(&i) { output_gradient * weights[i] } else { 0.0 };
+        assert!((gradients[i] - expected).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn initialize_all_leaves_no_layer_uninitialized()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (4, 4, 1)));
+    neural_network.register_conv(ActivationFunction::ReLU, 0, 1, 3, 2).expect("Register convolutional layer");
+    neural_network.register_pooling(ActivationFunction::None, PoolingType::Max, 0, 2, 2).expect("Register pooling layer");
+    neural_network.register_fully_connected(ActivationFunction::ReLU, 4).expect("Register fully connected layer");
+    neural_network.register_fully_connected(ActivationFunction::None, 1).expect("Register fully connected layer");
+
+    neural_network.initialize(4, Initialization::NormalXavier).expect("Initialize");
+    let output_weights = neural_network.get_layer(4).and_then(Layer::get_weights).expect("Get weights").clone();
+
+    assert!(matches!(neural_network.validate(), Err(Error::UninitializedLayer(1))));
+
+    neural_network.initialize_all(Initialization::NormalHe);
+
+    for layer_index in 0..neural_network.num_layers() {
+        assert!(neural_network.get_layer(layer_index).expect("Get layer").is_initialized());
+    }
+
+    // layers that were already initialized are kept
+    assert_eq!(neural_network.get_layer(4).and_then(Layer::get_weights), Some(&output_weights));
+    assert!(neural_network.validate().is_ok());
+}
+
+fn make_parallel_training_setup() -> (NeuralNetwork, dataset::InMemoryDataset)
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (4, 4, 1)));
+    neural_network.register_conv(ActivationFunction::ReLU, 0, 1, 3, 2).expect("Register convolutional layer");
+    neural_network.register_fully_connected(ActivationFunction::Sigmoid, 2).expect("Register fully connected layer");
+    neural_network.initialize_all(Initialization::NormalXavier);
+
+    let inputs: Vec<Vec<f32>> = (0..10).map(|i| (0..16).map(|j| ((i * 16 + j) as f32 * 0.37).sin()).collect()).collect();
+    let targets: Vec<Vec<f32>> = (0..10).map(|i| vec![(i % 2) as f32, 1.0 - (i % 2) as f32]).collect();
+    let dataset = dataset::InMemoryDataset::new(inputs, targets).expect("Create dataset");
+
+    (neural_network, dataset)
+}
+
+#[test]
+fn parallel_training_matches_for_any_thread_count()
+{
+    let (neural_network, dataset) = make_parallel_training_setup();
+
+    let config = TrainingConfig {
+        epochs: 2,
+        batch_size: 5,
+        shuffle: false,
+        num_threads: 1,
+        ..TrainingConfig::default()
+    };
+
+    let weights = |neural_network: &NeuralNetwork| -> Vec<f32> {
+        [1, 2].iter().flat_map(|&i| neural_network.get_layer(i).and_then(Layer::get_weights).expect("Get weights").clone()).collect()
+    };
+
+    let train = |num_threads: usize| -> Vec<f32> {
+        let mut neural_network = neural_network.clone();
+        neural_network.train_parallel(&dataset, &TrainingConfig { num_threads, ..config.clone() }).expect("Train");
+
+        weights(&neural_network)
+    };
+
+    let mut serial = neural_network.clone();
+    serial.train(&dataset, &config).expect("Train");
+
+    // a single worker accumulates the gradients exactly like `train`
+    assert_eq!(train(1), weights(&serial));
+
+    // more workers only change the order the gradients are summed in
+    for (one, four) in train(1).iter().zip(train(4)) {
+        assert!((one - four).abs() < 1e-5);
+    }
+
+    assert!(neural_network.clone().train_parallel(&dataset, &TrainingConfig { num_threads: 0, ..config }).is_err());
+}
+
+#[test]
+fn parallel_training_is_reproducible()
+{
+    let (mut neural_network, dataset) = make_parallel_training_setup();
+    neural_network.set_seed(7);
+
+    let config = TrainingConfig {
+        epochs: 3,
+        batch_size: 5,
+        shuffle: false,
+        num_threads: 4,
+        ..TrainingConfig::default()
+    };
+
+    let train = |config: &TrainingConfig| -> Vec<u32> {
+        let mut neural_network = neural_network.clone();
+        neural_network.train_parallel(&dataset, config).expect("Train");
+
+        [1, 2].iter()
+            .flat_map(|&i| neural_network.get_layer(i).and_then(Layer::get_weights).expect("Get weights").clone())
+            .map(f32::to_bits)
+            .collect()
+    };
+
+    let first = train(&config);
+
+    for _ in 0..5 {
+        assert_eq!(train(&config), first);
+    }
+
+    // the shuffled batch order only depends on the seed
+    let shuffled = TrainingConfig { shuffle: true, seed: Some(3), ..config.clone() };
+    let first = train(&shuffled);
+
+    for _ in 0..5 {
+        assert_eq!(train(&shuffled), first);
+    }
+
+    assert!((4..12).any(|seed| train(&TrainingConfig { seed: Some(seed), ..shuffled.clone() }) != first));
+}
+
+#[test]
+fn same_architecture_ignores_weights()
+{
+    let build = |kernel_size: usize| {
+        let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+        neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (6, 6, 1)));
+        neural_network.register_conv(ActivationFunction::ReLU, 0, 1, kernel_size, 2).expect("Register convolutional layer");
+        neural_network.register_pooling(ActivationFunction::None, PoolingType::Max, 0, 2, 2).expect("Register pooling layer");
+        neural_network.register_fully_connected(ActivationFunction::Sigmoid, 2).expect("Register fully connected layer");
+
+        neural_network
+    };
+
+    let mut first = build(3);
+    first.initialize_all(Initialization::NormalHe);
+
+    assert!(first.same_architecture(&build(3)));
+    assert!(build(3).same_architecture(&first));
+
+    assert!(!first.same_architecture(&build(1)));
+    assert!(!first.same_architecture(&make_identity_network(ErrorFunction::HalfMeanSquaredError)));
+
+    let mut truncated = build(3);
+    truncated.truncate(3).expect("Truncate");
+    assert!(!first.same_architecture(&truncated));
+}
+
+#[test]
+fn load_matching_layers_keeps_a_resized_head()
+{
+    let build = |num_outputs: usize| {
+        let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+        neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (6, 6, 1)));
+        neural_network.register_conv(ActivationFunction::ReLU, 0, 1, 3, 2).expect("Register convolutional layer");
+        neural_network.register_pooling(ActivationFunction::None, PoolingType::Max, 0, 2, 2).expect("Register pooling layer");
+        neural_network.register_fully_connected(ActivationFunction::ReLU, 4).expect("Register fully connected layer");
+        neural_network.register_fully_connected(ActivationFunction::Sigmoid, num_outputs).expect("Register fully connected layer");
+        neural_network.initialize_all(Initialization::NormalXavier);
+
+        neural_network
+    };
+
+    let weights = |neural_network: &NeuralNetwork, layer_index: usize| {
+        neural_network.get_layer(layer_index).and_then(Layer::get_weights).expect("Get weights").clone()
+    };
+
+    let pretrained = build(2);
+    let bytes = bincode::serde::encode_to_vec(&pretrained, bincode::config::standard()).expect("Serialize");
+    let (checkpoint, _): (NeuralNetwork, usize) = bincode::serde::decode_from_slice(&bytes, bincode::config::standard()).expect("Deserialize");
+
+    let mut neural_network = build(3);
+    let head = weights(&neural_network, 4);
+
+    assert_eq!(neural_network.load_matching_layers(&checkpoint), vec![0, 1, 2, 3]);
+
+    assert_eq!(weights(&neural_network, 1), weights(&pretrained, 1));
+    assert_eq!(weights(&neural_network, 3), weights(&pretrained, 3));
+    assert_eq!(weights(&neural_network, 4), head);
+}
+
+#[test]
+fn spatial_dropout_drops_whole_channels()
+{
+    let depth = 8;
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (3, 3, depth)));
+    neural_network.register_spatial_dropout(0.5, 0).expect("Register spatial dropout layer");
+    neural_network.register_fully_connected(ActivationFunction::None, 1).expect("Register fully connected layer");
+    neural_network.initialize_all(Initialization::NormalXavier);
+    neural_network.set_seed(3);
+
+    assert!(neural_network.register_spatial_dropout(1.0, 0).is_err());
+
+    let input: Vec<f32> = (0..9 * depth).map(|i| 1.0 + (i as f32 * 0.3).sin().abs()).collect();
+    let input_gradient = neural_network.input_gradient(&input, &[1.0]).expect("Input gradient");
+    let output = neural_network.get_layer(1).expect("Get layer").get_output().clone();
+
+    let mut dropped = 0;
+
+    for channel in 0..depth {
+        let indices: Vec<usize> = (channel..input.len()).step_by(depth).collect();
+
+        if output[channel] == 0.0 {
+            dropped += 1;
+
+            assert!(indices.iter().all(|&i| output[i] == 0.0 && input_gradient[i] == 0.0));
+        } else {
+            assert!(indices.iter().all(|&i| (output[i] - input[i] * 2.0).abs() < 1e-6 && input_gradient[i] != 0.0));
+        }
+    }
+
+    assert!(dropped > 0 && dropped < depth);
+
+    // nothing is dropped or scaled at inference
+    neural_network.set_training(false);
+    neural_network.set_input(&input).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagation");
+
+    assert_eq!(neural_network.get_layer(1).expect("Get layer").get_output(), &input);
+}
+
+#[test]
+fn set_input_reports_the_expected_size()
+{
+    let mut neural_network = make_identity_network(ErrorFunction::HalfMeanSquaredError);
+
+    let error = neural_network.set_input(&vec![1.0, 2.0, 3.0]).expect_err("Set input");
+
+    assert!(matches!(error, Error::InputSizeMismatch { expected: 2, provided: 3 }));
+    assert_eq!(error.to_string(), "Input has 3 values but the input layer expects 2");
+}
+
+#[test]
+fn train_sample_drives_the_error_down()
+{
+    let (mut neural_network, _) = make_parallel_training_setup();
+
+    let input: Vec<f32> = (0..16).map(|i| (i as f32 * 0.61).cos()).collect();
+    let target = [1.0, 0.0];
+
+    let errors: Vec<f32> = (0..50)
+        .map(|_| neural_network.train_sample(&input, &target, 0.5, 0.5, 0.0).expect("Train sample"))
+        .collect();
+
+    assert!(errors[49] < errors[0] * 0.5);
+
+    // the returned error is the error before the update
+    let current = neural_network.train_sample(&input, &target, 0.0, 0.0, 0.0).expect("Train sample");
+    assert!((current - neural_network.get_error(&target.to_vec()).expect("Get error")).abs() < 1e-6);
+
+    assert!(neural_network.train_sample(&input[..4], &target, 0.5, 0.5, 0.0).is_err());
+}
+
+#[test]
+fn forward_and_error_matches_separate_calls()
+{
+    let (mut neural_network, _) = make_parallel_training_setup();
+
+    let input: Vec<f32> = (0..16).map(|i| (i as f32 * 0.29).sin()).collect();
+    let target = vec![0.0, 1.0];
+
+    let (output, error) = neural_network.forward_and_error(&input, &target).expect("Forward and error");
+
+    neural_network.set_input(&input).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagation");
+
+    assert_eq!(output, neural_network.get_output().expect("Get output"));
+    assert_eq!(error, neural_network.get_error(&target).expect("Get error"));
+
+    assert!(neural_network.forward_and_error(&input, &[0.0]).is_err());
+}
+
+#[test]
+fn sequence_convolution_matches_padded_2d()
+{
+    let (length, depth, num_kernels) = (8, 2, 2);
+
+    let kernel: Vec<f32> = (0..9 * depth * num_kernels).map(|i| (i as f32 * 0.83).sin()).collect();
+    let signal: Vec<f32> = (0..length * depth).map(|i| (i as f32 * 0.47).cos()).collect();
+
+    let convolve = |height: usize, input: &Vec<f32>| -> Vec<f32> {
+        let mut input_layer = Layer::make_input_layer(1, (length, height, depth));
+        let mut layer = Layer::make_convolutional_layer(0, 1, 3, (length, height, num_kernels), depth);
+
+        if let Layer::Convolutional(ref mut input_layer) = input_layer {
+            input_layer.set_volume(input).expect("Set volume");
+        }
+
+        if let Layer::Convolutional(ref mut layer) = layer {
+            layer.set_kernel(kernel.clone()).expect("Set kernel");
+        }
+
+        input_layer.forward_propagate(&mut layer).expect("Forward propagation");
+        layer.get_output().clone()
+    };
+
+    let sequence = convolve(1, &signal);
+
+    // the same signal as the middle row of a volume with a height of 3
+    let mut volume = vec![0.0; length * 3 * depth];
+    for x in 0..length {
+        for z in 0..depth {
+            volume[util::get_index((x, 1, z), (length, 3, depth))] = signal[util::get_index((x, 0, z), (length, 1, depth))];
+        }
+    }
+
+    let padded = convolve(3, &volume);
+
+    for x in 0..length {
+        for k in 0..num_kernels {
+            let value = sequence[util::get_index((x, 0, k), (length, 1, num_kernels))];
+            assert!((value - padded[util::get_index((x, 1, k), (length, 3, num_kernels))]).abs() < 1e-6);
+
+            // only the middle kernel row overlaps the sequence
+            let mut expected = 0.0;
+            for z in 0..depth {
+                for kernel_x in 0..3 {
+                    if x + kernel_x < 1 || x + kernel_x > length { continue };
+
+                    let input = signal[util::get_index((x + kernel_x - 1, 0, z), (length, 1, depth))];
+                    expected += input * kernel[k * 9 * depth + z * 9 + 3 + kernel_x];
+                }
+            }
+
+            assert!((value - expected).abs() < 1e-5);
+        }
+    }
+}
+
+#[test]
+fn train_val_split_partitions_every_sample()
+{
+    let data: Vec<u32> = (0..10).collect();
+
+    let (train, validation) = util::train_val_split(&data, 0.3, 5).expect("Split");
+    assert_eq!((train.len(), validation.len()), (7, 3));
+
+    let mut all: Vec<usize> = train.iter().chain(&validation).cloned().collect();
+    all.sort();
+    assert_eq!(all, (0..10).collect::<Vec<usize>>());
+
+    assert_eq!(util::train_val_split(&data, 0.3, 5).expect("Split"), (train, validation));
+
+    assert_eq!(util::train_val_split(&data, 0.0, 5).expect("Split").1.len(), 0);
+    assert_eq!(util::train_val_split(&data, 1.0, 5).expect("Split").0.len(), 0);
+    assert_eq!(util::train_val_split(&data[..1], 0.2, 5).expect("Split"), (vec![0], vec![]));
+    assert_eq!(util::train_val_split::<u32>(&[], 0.5, 5).expect("Split"), (vec![], vec![]));
+
+    assert!(util::train_val_split(&data, 1.5, 5).is_err());
+    assert!(util::train_val_split(&data, f32::NAN, 5).is_err());
+}
+
+#[test]
+fn cosine_embedding_error()
+{
+    let func = ErrorFunction::CosineEmbedding;
+    let eval = |values: &Vec<f32>, expected: &Vec<f32>| nn_error::eval(func, values, expected, None, nn_error::DEFAULT_EPSILON);
+    let derivative = |i: usize, values: &Vec<f32>, expected: &Vec<f32>| nn_error::eval_derivative(func, i, values, expected, None, nn_error::DEFAULT_EPSILON);
+
+    // aligned embeddings have no loss regardless of their length, so there's nothing to correct
+    let aligned = (vec![2.0, 4.0, -2.0], vec![1.0, 2.0, -1.0]);
+    assert!(eval(&aligned.0, &aligned.1).abs() < 1e-6);
+    assert!((0..3).all(|i| derivative(i, &aligned.0, &aligned.1).abs() < 1e-6));
+
+    // orthogonal embeddings are pulled towards the target
+    let orthogonal = (vec![2.0, 0.0], vec![0.0, 3.0]);
+    assert!((eval(&orthogonal.0, &orthogonal.1) - 1.0).abs() < 1e-6);
+    assert!(derivative(0, &orthogonal.0, &orthogonal.1).abs() < 1e-6);
+    assert!((derivative(1, &orthogonal.0, &orthogonal.1) + 0.5).abs() < 1e-6);
+
+    let opposite = (vec![1.0, 1.0], vec![-1.0, -1.0]);
+    assert!((eval(&opposite.0, &opposite.1) - 2.0).abs() < 1e-6);
+
+    // matches finite differences for an arbitrary pair
+    let values = vec![0.3, -1.2, 0.8];
+    let expected = vec![1.0, 0.5, -0.4];
+    let h = 1e-3;
+
+    for i in 0..values.len() {
+        let mut plus = values.clone();
+        let mut minus = values.clone();
+        plus[i] += h;
+        minus[i] -= h;
+
+        let numerical = (eval(&plus, &expected) - eval(&minus, &expected)) / (2.0 * h);
+        assert!((numerical - derivative(i, &values, &expected)).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn training_reports_progress()
+{
+    let (mut neural_network, dataset) = make_parallel_training_setup();
+
+    let config = TrainingConfig {
+        epochs: 2,
+        batch_size: 4,
+        shuffle: false,
+        num_threads: 2,
+        ..TrainingConfig::default()
+    };
+
+    let mut events = Vec::new();
+    let epoch_errors = neural_network.train_with_progress(&dataset, &config, |event| events.push(*event)).expect("Train");
+
+    // three batches of 4, 4 and 2 samples per epoch followed by the epoch summary
+    assert_eq!(events.len(), 8);
+
+    let batches: Vec<usize> = events.iter().filter_map(|event| event.batch).collect();
+    assert_eq!(batches, (0..6).collect::<Vec<usize>>());
+
+    let epochs: Vec<&ProgressEvent> = events.iter().filter(|event| event.batch.is_none()).collect();
+    assert_eq!(epochs.iter().map(|event| event.epoch).collect::<Vec<usize>>(), vec![0, 1]);
+    assert_eq!(epochs.iter().map(|event| event.error).collect::<Vec<f32>>(), epoch_errors);
+
+    assert!(events.iter().all(|event| (0.0..=1.0).contains(&event.accuracy)));
+    assert!(events.windows(2).all(|pair| pair[0].elapsed <= pair[1].elapsed));
+
+    let mut parallel_events = 0;
+    neural_network.train_parallel_with_progress(&dataset, &config, |_| parallel_events += 1).expect("Train");
+    assert_eq!(parallel_events, 8);
+}
+
+#[test]
+fn gradient_checkpointing_matches_full_backprop()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::CategoricalCrossEntropy);
+
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (8, 8, 1)));
+    neural_network.register_conv(ActivationFunction::PReLU, 0, 1, 3, 3).expect("Register convolutional layer");
+    neural_network.register_conv(ActivationFunction::Softsign, 0, 1, 1, 3).expect("Register convolutional layer");
+    neural_network.register_add(ActivationFunction::ReLU, 1, 0).expect("Register add");
+    neural_network.register_conv(ActivationFunction::Sigmoid, 0, 1, 3, 2).expect("Register convolutional layer");
+    neural_network.register_pooling(ActivationFunction::None, PoolingType::Max, 0, 2, 2).expect("Register pooling layer");
+    neural_network.register_conv(ActivationFunction::LeakyReLU(0.1), 0, 1, 1, 2).expect("Register convolutional layer");
+    neural_network.register_fully_connected(ActivationFunction::Softmax, 3).expect("Register fully connected layer");
+    neural_network.initialize_all(Initialization::NormalXavier);
+
+    let samples: Vec<(Vec<f32>, Vec<f32>)> = (0..2)
+        .map(|i| ((0..64).map(|j| ((i * 64 + j) as f32 * 0.53).sin()).collect(), vec![0.0, (i % 2) as f32, 1.0 - (i % 2) as f32]))
+        .collect();
+
+    let mut run = |segment: Option<usize>| -> (Vec<f32>, Vec<util::Gradient>) {
+        neural_network.set_gradient_checkpointing(segment).expect("Set gradient checkpointing");
+        neural_network.start_batch();
+
+        for (input, target) in &samples {
+            neural_network.set_input(input).expect("Set input");
+            neural_network.forward_propagate().expect("Forward propagate");
+
+            if segment.is_some() {
+                // the add layer's source and every third layer are kept
+                assert!(!neural_network.get_layer(1).expect("Get layer").get_output().is_empty());
+                assert!(!neural_network.get_layer(3).expect("Get layer").get_output().is_empty());
+                assert!(neural_network.get_layer(4).expect("Get layer").get_output().is_empty());
+                assert!(neural_network.get_layer(5).expect("Get layer").get_output().is_empty());
+            }
+
+            neural_network.back_propagate(target).expect("Back propagate");
+        }
+
+        (neural_network.get_output().expect("Get output"), neural_network.collect_gradients())
+    };
+
+    let (output, gradients) = run(None);
+    let (checkpointed_output, checkpointed_gradients) = run(Some(3));
+
+    assert_eq!(output, checkpointed_output);
+    assert_eq!(gradients, checkpointed_gradients);
+
+    assert!(matches!(neural_network.set_gradient_checkpointing(Some(0)), Err(Error::InvalidInput)));
+}
+
+#[test]
+fn metrics_label_distribution()
+{
+    let one_hot: Vec<Vec<f32>> = [0, 2, 2, 1, 2, 0].iter().map(|&class| {
+        let mut target = vec![0.0; 3];
+        target[class] = 1.0;
+        target
+    }).collect();
+
+    assert_eq!(metrics::label_distribution(&one_hot), vec![2, 1, 3]);
+
+    let binary = vec![vec![1.0], vec![0.0], vec![0.9], vec![0.2], vec![1.0]];
+    assert_eq!(metrics::label_distribution(&binary), vec![2, 3]);
+
+    assert!(metrics::label_distribution(&[]).is_empty());
+}
+
+#[test]
+fn loading_corrupt_bytes_is_a_serialization_error()
+{
+    let (neural_network, _) = make_parallel_training_setup();
+
+    let path = std::env::temp_dir().join(format!("convolutional_neural_network_corrupt_{}.bin", std::process::id()));
+    neural_network.save(&path).expect("Save");
+
+    let loaded = NeuralNetwork::load(&path).expect("Load");
+    assert!(loaded.same_architecture(&neural_network));
+
+    let bytes = std::fs::read(&path).expect("Read model");
+
+    // a truncated model and bytes that were never a model
+    assert!(matches!(NeuralNetwork::from_bytes(&bytes[..bytes.len() / 2]), Err(Error::Serialization(_))));
+    assert!(matches!(NeuralNetwork::from_bytes(&[9, 3, 0xff, 0xff]), Err(Error::Serialization(_))));
+
+    // a checkpoint that was cut off while it was written
+    std::fs::write(&path, &bytes[..bytes.len() / 2]).expect("Write truncated model");
+    assert!(matches!(NeuralNetwork::load(&path), Err(Error::Serialization(_))));
+
+    std::fs::write(&path, [0xff; 16]).expect("Write corrupt model");
+    let result = NeuralNetwork::load(&path);
+    std::fs::remove_file(&path).expect("Remove model");
+
+    assert!(matches!(result, Err(Error::Serialization(message)) if !message.is_empty()));
+    assert!(matches!(NeuralNetwork::load(&path), Err(Error::Io(std::io::ErrorKind::NotFound))));
+}
+
+fn make_scale_network(gamma: Vec<f32>) -> NeuralNetwork
+{
+    let mut scale_layer = Layer::make_scale_layer(0, (2, 2, 2));
+    let mut output_layer = Layer::make_fully_connected_layer(8, 1);
+
+    if let Layer::Scale(ref mut scale) = scale_layer {
+        scale.set_gamma(gamma).expect("Set gamma");
+    }
+
+    if let Layer::FullyConnected(ref mut fc) = output_layer {
+        fc.set_weights((0..8).map(|i| (i as f32 * 0.7).cos()).collect()).expect("Set weights");
+    }
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (2, 2, 2)));
+    neural_network.register_layer(ActivationFunction::Sigmoid, scale_layer);
+    neural_network.register_layer(ActivationFunction::None, output_layer);
+    neural_network.set_input(&(0..8).map(|i| (i as f32 * 1.3).sin()).collect()).expect("Set input");
+
+    neural_network
+}
+
+#[test]
+fn scale_gamma_gradients_match_finite_differences()
+{
+    let gamma = vec![0.8, -1.2];
+    let target = vec![0.3];
+
+    let mut neural_network = make_scale_network(gamma.clone());
+    neural_network.forward_propagate().expect("Forward propagate");
+    neural_network.start_batch();
+    neural_network.back_propagate(&target).expect("Back propagate");
+
+    let gamma_gradients = neural_network.get_layer(1).and_then(Layer::get_weight_gradients).expect("Get gradients").clone();
+
+    let epsilon = 1e-3;
+    for i in 0..gamma.len() {
+        let error = |offset: f32| {
+            let mut gamma = gamma.clone();
+            gamma[i] += offset;
+
+            let mut neural_network = make_scale_network(gamma);
+            neural_network.forward_propagate().expect("Forward propagate");
+            neural_network.get_error(&target).expect("Get error")
+        };
+
+        let numerical = (error(epsilon) - error(-epsilon)) / (2.0 * epsilon);
+        assert!(numerical.abs() > 1e-3);
+        assert!((numerical - util::from_gradient(gamma_gradients[i])).abs() < 1e-3, "{} != {}", numerical, gamma_gradients[i]);
+    }
+
+    // a step moves the gammas, and they survive serialization
+    neural_network.end_batch(1);
+    let trained = neural_network.get_layer(1).and_then(Layer::get_weights).expect("Get gamma").clone();
+    assert_ne!(trained, gamma);
+
+    let bytes = bincode::serde::encode_to_vec(&neural_network, bincode::config::standard()).expect("Serialize");
+    let loaded = NeuralNetwork::from_bytes(&bytes).expect("Deserialize");
+    assert_eq!(loaded.get_layer(1).and_then(Layer::get_weights).expect("Get gamma"), &trained);
+    assert_eq!(loaded.get_layer(1).expect("Get layer").num_parameters(), 2);
+}
+
+struct CustomCrossEntropy;
+
+impl Loss for CustomCrossEntropy {
+    fn eval(&self, values: &[f32], expected: &[f32]) -> f32 {
+        values.iter().zip(expected).map(|(&value, &target)| {
+            let value = value.clamp(1e-7, 1.0 - 1e-7);
+            -(target * value.ln() + (1.0 - target) * (1.0 - value).ln())
+        }).sum()
+    }
+
+    // 1 for the zeroed output and target of a masked output, unlike the built in losses
+    fn eval_derivative(&self, i: usize, values: &[f32], expected: &[f32]) -> f32 {
+        let value = values[i].clamp(1e-7, 1.0 - 1e-7);
+        -expected[i] / value + (1.0 - expected[i]) / (1.0 - value)
+    }
+}
+
+static CUSTOM_CROSS_ENTROPY: CustomCrossEntropy = CustomCrossEntropy;
+
+#[test]
+fn masked_targets_have_no_error_or_gradient()
+{
+    let cases = [
+        (ActivationFunction::Sigmoid, ErrorFunction::HalfMeanSquaredError, vec![0.2, 0.7, 0.9]),
+        (ActivationFunction::Sigmoid, ErrorFunction::LogCosh, vec![0.2, 0.7, 0.9]),
+        // fused with the activation's derivative
+        (ActivationFunction::Sigmoid, ErrorFunction::BinaryCrossEntropy, vec![0.0, 1.0, 1.0]),
+        (ActivationFunction::Softmax, ErrorFunction::CategoricalCrossEntropy, vec![1.0, 0.0, 0.0]),
+        // ignores the weights the mask is applied with
+        (ActivationFunction::Sigmoid, ErrorFunction::Custom(&CUSTOM_CROSS_ENTROPY), vec![0.0, 1.0, 1.0]),
+    ];
+
+    for (activation_function, error_function, target) in cases {
+        let mut neural_network = NeuralNetwork::new(error_function);
+        neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (1, 1, 2)));
+        neural_network.register_fully_connected(activation_function, 3).expect("Register fully connected layer");
+        neural_network.initialize_all(Initialization::NormalXavier);
+
+        let mut masked_target = target.clone();
+        masked_target[1] = f32::NAN;
+
+        let mut run = |target: &Vec<f32>| -> (f32, Vec<util::Gradient>) {
+            neural_network.set_input(&vec![0.4, -0.8]).expect("Set input");
+            neural_network.forward_propagate().expect("Forward propagate");
+            neural_network.start_batch();
+            neural_network.back_propagate(target).expect("Back propagate");
+
+            let Some(Layer::FullyConnected(layer)) = neural_network.get_layer(1) else { unreachable!() };
+            (neural_network.get_error(target).expect("Get error"), layer.bias_gradients.clone())
+        };
+
+        let (error, gradients) = run(&target);
+        let (masked_error, masked_gradients) = run(&masked_target);
+
+        // only the masked output stops contributing, which a cross entropy target of 0 doesn't do anyway
+        assert!(masked_error.is_finite() && masked_error <= error);
+        assert_eq!(masked_gradients[1], 0.0);
+        assert_eq!(masked_gradients[0], gradients[0]);
+        assert_eq!(masked_gradients[2], gradients[2]);
+    }
+}
+
+#[test]
+fn isolated_layer_forward_matches_the_network()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (5, 5, 2)));
+    neural_network.register_conv(ActivationFunction::ReLU, 1, 1, 3, 3).expect("Register convolutional layer");
+    neural_network.register_conv(ActivationFunction::Sigmoid, 0, 1, 3, 2).expect("Register convolutional layer");
+    neural_network.register_fully_connected(ActivationFunction::Sigmoid, 2).expect("Register fully connected layer");
+    neural_network.initialize_all(Initialization::NormalXavier);
+
+    let input: Vec<f32> = (0..50).map(|i| (i as f32 * 0.31).sin()).collect();
+    let activations = neural_network.forward_with_activations(&input).expect("Forward propagate");
+
+    // the second convolution sees the first one's output with its zero padding of 1
+    let mut conv = neural_network.get_layer(2).expect("Get layer").clone();
+    let output = conv.forward_isolated(&activations[1], (3, 3, 3), 1, ActivationFunction::Sigmoid).expect("Forward isolated");
+    assert_eq!(output, activations[2]);
+
+    let mut fc = neural_network.get_layer(3).expect("Get layer").clone();
+    let output = fc.forward_isolated(&activations[2], (3, 3, 2), 0, ActivationFunction::Sigmoid).expect("Forward isolated");
+    assert_eq!(output, activations[3]);
+
+    assert!(matches!(conv.forward_isolated(&activations[1], (3, 3, 2), 1, ActivationFunction::Sigmoid), Err(Error::DimensionMismatch)));
+    assert!(matches!(conv.forward_isolated(&input, (3, 3, 3), 1, ActivationFunction::Sigmoid), Err(Error::DimensionMismatch)));
+
+    // during inference the first convolution fuses its ReLU, which mustn't replace the activation that is passed in
+    neural_network.set_training(false);
+    let activations = neural_network.forward_with_activations(&input).expect("Forward propagate");
+
+    let mut conv = neural_network.get_layer(1).expect("Get layer").clone();
+    assert!(matches!(&conv, Layer::Convolutional(layer) if layer.fuses_activation()));
+
+    let output = conv.forward_isolated(&input, (5, 5, 2), 0, ActivationFunction::ReLU).expect("Forward isolated");
+    assert_eq!(output, activations[1]);
+
+    let output = conv.forward_isolated(&input, (5, 5, 2), 0, ActivationFunction::Sigmoid).expect("Forward isolated");
+    assert!(output.iter().all(|&value| value > 0.0 && value < 1.0));
+
+    let mut raw = neural_network.get_layer(1).expect("Get layer").clone();
+    let raw = raw.forward_isolated(&input, (5, 5, 2), 0, ActivationFunction::None).expect("Forward isolated");
+    let expected: Vec<f32> = raw.iter().map(|&value| activations::eval(ActivationFunction::Sigmoid, value)).collect();
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn sigmoid_is_stable_at_extremes()
+{
+    let func = ActivationFunction::Sigmoid;
+
+    assert_eq!(activations::eval(func, 100.0), 1.0);
+    assert_eq!(activations::eval_derivative(func, 100.0), 0.0);
+
+    // e^-100 is a subnormal f32 instead of 1 / (1 + inf)
+    let low = activations::eval(func, -100.0);
+    assert!(low > 0.0 && ((low - (-100.0f32).exp()) / (-100.0f32).exp()).abs() < 1e-3);
+
+    let derivative = activations::eval_derivative(func, -100.0);
+    assert!(derivative.is_finite() && derivative > 0.0);
+
+    for x in [-5.0f32, -0.5, 0.0, 0.5, 5.0] {
+        assert!((activations::eval(func, x) - 1.0 / (1.0 + (-x).exp())).abs() < 1e-6);
+        assert!((activations::eval(func, x) + activations::eval(func, -x) - 1.0).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn ensemble_averages_the_model_outputs()
+{
+    let (first, _) = make_parallel_training_setup();
+    let (second, _) = make_parallel_training_setup();
+    let mut models = vec![first, second];
+
+    let input: Vec<f32> = (0..16).map(|i| (i as f32 * 0.23).cos()).collect();
+
+    let outputs: Vec<Vec<f32>> = models.iter_mut().map(|model| {
+        model.set_input(&input).expect("Set input");
+        model.forward_propagate().expect("Forward propagate");
+        model.get_output().expect("Get output")
+    }).collect();
+
+    let ensemble = NeuralNetwork::ensemble_predict(&mut models, &[0.5, 0.5], &input).expect("Ensemble predict");
+    for i in 0..ensemble.len() {
+        assert!((ensemble[i] - (outputs[0][i] + outputs[1][i]) / 2.0).abs() < 1e-6);
+    }
+
+    // the weights are normalized
+    let weighted = NeuralNetwork::ensemble_predict(&mut models, &[3.0, 1.0], &input).expect("Ensemble predict");
+    for i in 0..weighted.len() {
+        assert!((weighted[i] - (0.75 * outputs[0][i] + 0.25 * outputs[1][i])).abs() < 1e-6);
+    }
+
+    assert!(matches!(NeuralNetwork::ensemble_predict(&mut models, &[1.0], &input), Err(Error::InvalidInput)));
+    assert!(matches!(NeuralNetwork::ensemble_predict(&mut models, &[0.0, 0.0], &input), Err(Error::InvalidInput)));
+
+    let mut wider = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    wider.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (4, 4, 1)));
+    wider.register_fully_connected(ActivationFunction::Sigmoid, 3).expect("Register fully connected layer");
+    models.push(wider);
+
+    assert!(matches!(NeuralNetwork::ensemble_predict(&mut models, &[1.0, 1.0, 1.0], &input), Err(Error::DimensionMismatch)));
+}
+
+#[test]
+fn activation_sparsity_counts_zero_outputs()
+{
+    let mut conv_layer = Layer::make_convolutional_layer(0, 1, 1, (2, 2, 2), 1);
+
+    // the second kernel negates the input, so the ReLU zeroes exactly one of the two channels at every position
+    if let Layer::Convolutional(ref mut conv) = conv_layer {
+        conv.set_kernel(vec![1.0, -1.0]).expect("Set kernel");
+    }
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (2, 2, 1)));
+    neural_network.register_layer(ActivationFunction::ReLU, conv_layer);
+    neural_network.register_fully_connected(ActivationFunction::ReLU, 2).expect("Register fully connected layer");
+
+    neural_network.set_input(&vec![0.5, -1.0, 0.0, 2.0]).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagate");
+
+    // the input has a single zero and the uninitialized fully connected layer outputs only zeros
+    assert_eq!(neural_network.activation_sparsity(), vec![0.25, 0.625, 1.0]);
+}
+
+#[test]
+fn large_batch_gradients_accumulate_precisely()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (1, 1, 1)));
+    neural_network.register_fully_connected(ActivationFunction::None, 1).expect("Register fully connected layer");
+
+    // the output stays 0, so the bias gradient of every sample is exactly -target
+    let targets: Vec<f32> = (0..100_000).map(|i| -(0.1 + 0.001 * (i as f32 * 0.37).sin())).collect();
+
+    neural_network.start_batch();
+    neural_network.set_input(&vec![0.0]).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagate");
+
+    for target in &targets {
+        neural_network.back_propagate(&vec![*target]).expect("Back propagate");
+    }
+
+    let exact: f64 = targets.iter().map(|target| -*target as f64).sum();
+    let naive: f32 = targets.iter().map(|target| -*target).sum();
+
+    let Some(Layer::FullyConnected(layer)) = neural_network.get_layer(1) else { unreachable!() };
+    // the gradients are already f64 with the feature
+    #[allow(clippy::useless_conversion)]
+    let accumulated = f64::from(layer.bias_gradients[0]);
+
+    let naive_error = (naive as f64 - exact).abs();
+    let accumulated_error = (accumulated - exact).abs();
+
+    assert!(accumulated_error <= naive_error);
+
+    if cfg!(feature = "f64-gradients") {
+        assert!(naive_error > 1e-3);
+        assert!(accumulated_error < 1e-6);
+    }
+}
+
+#[test]
+fn validate_dimensions_reports_every_incompatible_layer()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (6, 6, 1)));
+    neural_network.register_conv(ActivationFunction::ReLU, 0, 1, 3, 2).expect("Register convolutional layer");
+    assert!(neural_network.validate_dimensions().is_ok());
+
+    // a 2x2 pooling of the 4x4 convolution outputs 2x2, not 3x3
+    neural_network.register_layer(ActivationFunction::None, Layer::make_pooling_layer(PoolingType::Max, 0, 2, 2, (3, 3, 2)));
+    neural_network.register_layer(ActivationFunction::ReLU, Layer::make_fully_connected_layer(18, 4));
+    // the previous layer outputs 4 values
+    neural_network.register_layer(ActivationFunction::None, Layer::make_fully_connected_layer(5, 1));
+
+    let errors = neural_network.validate_dimensions().expect_err("Invalid dimensions");
+
+    assert_eq!(errors.len(), 2);
+    assert!(matches!(errors[0], (2, Error::DimensionMismatch)));
+    assert!(matches!(errors[1], (4, Error::DimensionMismatch)));
+}
+
+#[test]
+fn fitted_normalization_standardizes_the_features()
+{
+    let mut data: Vec<Vec<f32>> = (0..200)
+        .map(|i| vec![(i as f32 * 1.3).sin() * 40.0 + 128.0, i as f32 * 0.5 - 7.0, 3.0])
+        .collect();
+
+    let (mean, std) = util::fit_normalization(&data).expect("Fit normalization");
+    assert_eq!(std[2], 1.0);
+
+    for sample in &mut data {
+        util::apply_normalization(sample, &mean, &std).expect("Apply normalization");
+    }
+
+    for feature in 0..2 {
+        let values: Vec<f32> = data.iter().map(|sample| sample[feature]).collect();
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f32>() / values.len() as f32;
+
+        assert!(mean.abs() < 1e-4);
+        assert!((variance - 1.0).abs() < 1e-4);
+    }
+
+    // the constant feature is only centered
+    assert!(data.iter().all(|sample| sample[2] == 0.0));
+    assert!(matches!(util::apply_normalization(&mut [0.0; 2], &mean, &std), Err(Error::DimensionMismatch)));
+
+    // samples of different lengths are rejected instead of truncated
+    assert!(matches!(util::fit_normalization(&[vec![1.0, 2.0], vec![3.0]]), Err(Error::DimensionMismatch)));
+    assert!(matches!(util::fit_normalization(&[vec![1.0], vec![3.0, 4.0]]), Err(Error::DimensionMismatch)));
+}
+
+#[test]
+fn output_rounding_on_a_non_divisible_input()
+{
+    use util::OutputRounding;
+
+    assert_eq!(util::get_output_dimension_rounded((5, 5, 1), 0, 1, 2, (2, 2), OutputRounding::Floor), Some((2, 2, 1)));
+    assert_eq!(util::get_output_dimension_rounded((5, 5, 1), 0, 1, 2, (2, 2), OutputRounding::Ceil), Some((3, 3, 1)));
+    // the last window would start in the padding
+    assert_eq!(util::get_output_dimension_rounded((4, 4, 1), 1, 1, 3, (3, 3), OutputRounding::Ceil), Some((2, 2, 1)));
+
+    let make_layers = |rounding: OutputRounding, dimension: (usize, usize, usize)| {
+        let mut pooling = Layer::make_pooling_layer(PoolingType::Max, 0, 2, 2, dimension);
+        if let Layer::Pooling(ref mut layer) = pooling { layer.set_rounding(rounding) };
+
+        let mut conv = Layer::make_convolutional_layer(0, 2, 2, dimension, 1);
+        if let Layer::Convolutional(ref mut layer) = conv {
+            layer.set_rounding(rounding);
+            layer.set_kernel(vec![1.0; 4]).expect("Set kernel");
+        }
+
+        (pooling, conv)
+    };
+
+    let make_network = |layer: Layer| {
+        let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+        neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (5, 5, 1)));
+        neural_network.register_layer(ActivationFunction::None, layer);
+
+        // the input value at (x, y) is y + 5x
+        neural_network.set_input(&(0..25).map(|i| i as f32).collect()).expect("Set input");
+        neural_network
+    };
+
+    let output = |layer: Layer| {
+        let mut neural_network = make_network(layer);
+        neural_network.forward_propagate().expect("Forward propagate");
+
+        neural_network.get_layer(1).expect("Get layer").get_output().clone()
+    };
+
+    let (pooling, conv) = make_layers(OutputRounding::Floor, (2, 2, 1));
+    assert_eq!(output(pooling), vec![6.0, 8.0, 16.0, 18.0]);
+    assert_eq!(output(conv), vec![12.0, 20.0, 52.0, 60.0]);
+
+    // the last row and column of windows only cover the last row and column of the input
+    let (pooling, conv) = make_layers(OutputRounding::Ceil, (3, 3, 1));
+    assert_eq!(output(pooling.clone()), vec![6.0, 8.0, 9.0, 16.0, 18.0, 19.0, 21.0, 23.0, 24.0]);
+    assert_eq!(output(conv), vec![12.0, 20.0, 13.0, 52.0, 60.0, 33.0, 41.0, 45.0, 24.0]);
+
+    let bytes = bincode::serde::encode_to_vec(make_network(pooling), bincode::config::standard()).expect("Serialize");
+    let loaded = NeuralNetwork::from_bytes(&bytes).expect("Deserialize");
+    let Some(Layer::Pooling(layer)) = loaded.get_layer(1) else { unreachable!() };
+    assert_eq!(layer.get_rounding(), OutputRounding::Ceil);
+
+    // a layer sized for one rounding doesn't accept the input with the other
+    let (pooling, conv) = make_layers(OutputRounding::Floor, (3, 3, 1));
+    assert!(make_network(pooling).forward_propagate().is_err());
+    assert!(make_network(conv).forward_propagate().is_err());
+}
+
+#[test]
+fn int8_quantization_round_trips_within_the_quantization_error()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (8, 8, 2)));
+    neural_network.register_conv(ActivationFunction::ReLU, 0, 1, 3, 8).expect("Register convolutional layer");
+    neural_network.register_fully_connected(ActivationFunction::ReLU, 16).expect("Register fully connected layer");
+    neural_network.register_fully_connected(ActivationFunction::None, 3).expect("Register fully connected layer");
+    neural_network.initialize_all(Initialization::UniformXavier);
+
+    let quantized = neural_network.quantize_int8();
+    let mut dequantized = quantized.dequantize();
+
+    let weights: Vec<&Vec<f32>> = (0..neural_network.num_layers()).filter_map(|i| neural_network.get_layer(i).expect("Get layer").get_weights()).collect();
+    let restored: Vec<&Vec<f32>> = (0..dequantized.num_layers()).filter_map(|i| dequantized.get_layer(i).expect("Get layer").get_weights()).collect();
+
+    for ((weights, restored), tensor) in weights.iter().zip(&restored).zip(quantized.get_tensors()) {
+        assert_eq!(weights.len(), restored.len());
+        assert!(weights.iter().zip(restored.iter()).all(|(weight, restored)| (weight - restored).abs() <= tensor.scale * 0.5 + 1e-6));
+    }
+
+    let input: Vec<f32> = (0..128).map(|i| (i as f32 * 0.3).sin()).collect();
+
+    let expected = neural_network.forward_with_activations(&input).expect("Forward").pop().expect("Output");
+    let output = dequantized.forward_with_activations(&input).expect("Forward").pop().expect("Output");
+
+    for (expected, output) in expected.iter().zip(&output) {
+        assert!((expected - output).abs() < 0.05 * (1.0 + expected.abs()));
+    }
+
+    let size = bincode::serde::encode_to_vec(&neural_network, bincode::config::standard()).expect("Serialize").len();
+    let quantized_size = bincode::serde::encode_to_vec(&quantized, bincode::config::standard()).expect("Serialize").len();
+    assert!(quantized_size * 3 < size);
+}
+
+#[test]
+fn averaged_weights_lie_between_the_initial_and_current_weights()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (1, 1, 1)));
+    neural_network.register_layer(ActivationFunction::None, make_fully_connected(1, vec![0.5]));
+
+    assert!(neural_network.swap_averaged_weights().is_err());
+    assert!(neural_network.set_weight_averaging(Some(1.0)).is_err());
+    neural_network.set_weight_averaging(Some(0.9)).expect("Set weight averaging");
+
+    let parameters = |neural_network: &NeuralNetwork| {
+        let Some(Layer::FullyConnected(layer)) = neural_network.get_layer(1) else { unreachable!() };
+        (layer.get_weights()[0], layer.get_biases()[0])
+    };
+
+    let initial = parameters(&neural_network);
+
+    // without momentum the weights move steadily towards the target
+    for _ in 0..20 {
+        neural_network.train_sample(&[1.0], &[4.0], 0.05, 0.0, 0.0).expect("Train sample");
+    }
+
+    let current = parameters(&neural_network);
+
+    neural_network.swap_averaged_weights().expect("Swap averaged weights");
+    let averaged = parameters(&neural_network);
+
+    assert!(initial.0 < averaged.0 && averaged.0 < current.0);
+    assert!(initial.1 < averaged.1 && averaged.1 < current.1);
+
+    neural_network.swap_averaged_weights().expect("Swap averaged weights");
+    assert_eq!(parameters(&neural_network), current);
+}
+
+#[test]
+fn jacobian_of_a_linear_network_is_its_weight_matrix()
+{
+    let weights = vec![0.5, -1.0, 2.0, 0.25, 3.0, -0.75];
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (1, 1, 3)));
+    neural_network.register_layer(ActivationFunction::None, make_fully_connected(3, weights.clone()));
+
+    let jacobian = neural_network.jacobian(&[1.0, -2.0, 0.5]).expect("Jacobian");
+
+    // the weights of every neuron are stored contiguously
+    assert_eq!(jacobian, vec![weights[0..3].to_vec(), weights[3..6].to_vec()]);
+}
+
+#[test]
+fn mixup_blends_inputs_and_targets()
+{
+    use rand::SeedableRng;
+
+    let a = (vec![1.0, 2.0, 3.0], vec![1.0, 0.0]);
+    let b = (vec![3.0, -2.0, 0.0], vec![0.0, 1.0]);
+
+    let (input, target) = augment::blend((&a.0, &a.1), (&b.0, &b.1), 0.5).expect("Blend samples");
+    assert_eq!(input, vec![2.0, 0.0, 1.5]);
+    assert_eq!(target, vec![0.5, 0.5]);
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+
+    // Beta(alpha, alpha) concentrates around 0.5 for a large alpha
+    let (input, target) = augment::mixup((&a.0, &a.1), (&b.0, &b.1), 1e6, &mut rng).expect("Mixup");
+    assert!(input.iter().zip(&[2.0, 0.0, 1.5]).all(|(value, expected)| (value - expected).abs() < 0.01));
+    assert!((target[0] + target[1] - 1.0).abs() < 1e-6 && (target[0] - 0.5).abs() < 0.01);
+
+    assert!(matches!(augment::mixup((&a.0, &a.1), (&b.0, &b.1), 0.0, &mut rng), Err(Error::InvalidInput)));
+    assert!(matches!(augment::blend((&a.0, &a.1), (&b.0[..2], &b.1), 0.5), Err(Error::DimensionMismatch)));
+}
+
+#[test]
+fn dead_filters_reports_a_zeroed_kernel()
+{
+    let mut conv_layer = Layer::make_convolutional_layer(0, 1, 2, (2, 2, 3), 1);
+
+    if let Layer::Convolutional(ref mut conv) = conv_layer {
+        // the second kernel is zeroed and the third only sees negative sums
+        conv.set_kernel(vec![1.0, 0.5, 0.25, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0, -1.0, -1.0, -1.0]).expect("Set kernel");
+        conv.set_biases(vec![0.0, 0.0, 0.0]).expect("Set biases");
+    }
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (3, 3, 1)));
+    neural_network.register_layer(ActivationFunction::ReLU, conv_layer);
+
+    neural_network.set_input(&(1..10).map(|i| i as f32).collect()).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagate");
+
+    assert_eq!(neural_network.dead_filters(1, 1e-6).expect("Dead filters"), vec![1, 2]);
+    assert!(matches!(neural_network.dead_filters(0, 1e-6), Err(Error::IncompatibleLayers)));
+    assert!(matches!(neural_network.dead_filters(2, 1e-6), Err(Error::InvalidInput)));
+}
+
+#[test]
+fn to_dot_describes_every_layer()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(1, (4, 4, 2)));
+    neural_network.register_conv(ActivationFunction::ReLU, 1, 1, 3, 2).expect("Register convolutional layer");
+    neural_network.register_add(ActivationFunction::ReLU, 0, 0).expect("Register add layer");
+    neural_network.register_fully_connected(ActivationFunction::Sigmoid, 3).expect("Register fully connected layer");
+
+    let dot = neural_network.to_dot();
+
+    assert!(dot.starts_with("digraph"));
+    assert_eq!(dot.matches("[label=").count(), 4);
+
+    assert!(dot.contains("layer0 [label=\"0: Input\\n4x4x2\\nNone\"]"));
+    assert!(dot.contains("layer1 [label=\"1: Convolutional\\n4x4x2\\nReLU\"]"));
+    assert!(dot.contains("layer3 [label=\"3: FullyConnected\\n3\\nSigmoid\"]"));
+
+    assert!(dot.contains("layer2 -> layer3;"));
+    assert!(dot.contains("layer0 -> layer2 [style=dashed];"));
+}
+
+#[test]
+fn batch_loss_is_the_average_sample_error()
+{
+    let mut neural_network = make_output_network(ActivationFunction::Sigmoid, ErrorFunction::BinaryCrossEntropy, vec![0.1, -0.3]);
+
+    let inputs = vec![vec![0.5, -1.0, 2.0], vec![1.0, 0.0, -0.5], vec![-2.0, 0.25, 1.0]];
+    let targets = vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 1.0]];
+
+    let mut expected = 0.0;
+    for (input, target) in inputs.iter().zip(&targets) {
+        neural_network.set_input(input).expect("Set input");
+        neural_network.forward_propagate().expect("Forward propagate");
+
+        expected += neural_network.get_error(target).expect("Get error") / 3.0;
+    }
+
+    let loss = neural_network.batch_loss(&inputs, &targets).expect("Batch loss");
+    assert!((loss - expected).abs() < 1e-6);
+
+    assert!(matches!(neural_network.batch_loss(&inputs, &targets[..2]), Err(Error::InvalidInput)));
+    assert!(matches!(neural_network.batch_loss(&[], &[]), Err(Error::InvalidInput)));
+}
+
+#[test]
+fn input_layer_is_registered_without_an_activation()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    assert!(neural_network.register_input_layer(Layer::make_convolutional_layer(0, 1, 1, (2, 2, 1), 1)).is_err());
+
+    neural_network.register_input_layer(Layer::make_input_layer(0, (2, 2, 1))).expect("Register input layer");
+    assert!(matches!(neural_network.get_activation(0), Some(ActivationFunction::None)));
+    assert!(neural_network.register_input_layer(Layer::make_input_layer(0, (2, 2, 1))).is_err());
+
+    let input = vec![-1.0, 0.5, -0.25, 2.0];
+    neural_network.set_input(&input).expect("Set input");
+    assert_eq!(neural_network.get_layer(0).expect("Input layer").get_output(), &input);
+}
+
+#[test]
+fn triplet_loss_decreases_as_the_anchor_nears_the_positive()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_input_layer(Layer::make_input_layer(0, (1, 1, 2))).expect("Register input layer");
+    neural_network.register_layer(ActivationFunction::None, make_fully_connected(2, vec![1.0, 0.0, 0.0, 1.0]));
+
+    let (positive, negative) = ([1.0, 0.0], [0.0, 1.0]);
+
+    let mut previous = f32::INFINITY;
+    for anchor in [[0.0, 1.0], [0.5, 0.5], [1.0, 0.0]] {
+        neural_network.start_batch();
+
+        let loss = neural_network.back_propagate_triplet(&anchor, &positive, &negative, 0.5).expect("Back propagate triplet");
+        assert!(loss < previous);
+
+        previous = loss;
+    }
+
+    // the anchor is on the positive, so only the margin remains
+    assert!(previous == 0.0);
+    assert!(matches!(neural_network.back_propagate_triplet(&positive, &positive, &negative, -1.0), Err(Error::InvalidInput)));
+
+    // the shared weights are pulled so the anchor's embedding moves towards the positive's
+    let anchor = [0.25, 0.75];
+    neural_network.set_hyperparameters(Hyperparameters { learning_rate: 0.05, momentum: 0.0, weight_decay: 0.0 });
+
+    let before = neural_network.train_triplet((&anchor, &positive, &negative), 0.5).expect("Train triplet");
+
+    let mut after = before;
+    for _ in 0..20 {
+        after = neural_network.train_triplet((&anchor, &positive, &negative), 0.5).expect("Train triplet");
+    }
+
+    assert!(after < before);
+}
+
+#[test]
+fn same_padded_convolution_preserves_the_input_size()
+{
+    assert_eq!(util::same_padding(1), 0);
+    assert_eq!(util::same_padding(3), 1);
+    assert_eq!(util::same_padding(5), 2);
+    assert_eq!(util::same_padding(4), 1);
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    assert!(neural_network.register_conv_same(ActivationFunction::ReLU, 0, 3, 4).is_err());
+
+    neural_network.register_input_layer(Layer::make_input_layer(0, (7, 5, 3))).expect("Register input layer");
+    neural_network.register_conv_same(ActivationFunction::ReLU, 0, 3, 4).expect("Register conv");
+    neural_network.register_conv_same(ActivationFunction::ReLU, 0, 5, 2).expect("Register conv");
+    assert!(matches!(neural_network.register_conv_same(ActivationFunction::ReLU, 0, 0, 2), Err(Error::ImpossibleOutputDimension)));
+
+    assert!(matches!(neural_network.get_layer(1).map(Layer::output_dimension), Some(LayerShape::Volume(7, 5, 4))));
+    assert!(matches!(neural_network.get_layer(2).map(Layer::output_dimension), Some(LayerShape::Volume(7, 5, 2))));
+
+    neural_network.initialize_all(Initialization::NormalXavier);
+    neural_network.set_input(&vec![0.5; 7 * 5 * 3]).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagate");
+    assert_eq!(neural_network.get_layer(2).expect("Last layer").get_output().len(), 7 * 5 * 2);
+
+    // an even kernel can't be centered
+    neural_network.register_conv_same(ActivationFunction::ReLU, 0, 4, 1).expect("Register conv");
+    assert!(matches!(neural_network.get_layer(3).map(Layer::output_dimension), Some(LayerShape::Volume(6, 4, 1))));
+
+    // the padding the last layer already has is kept when it matches and not replaced otherwise
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_input_layer(Layer::make_input_layer(2, (7, 5, 3))).expect("Register input layer");
+    assert!(matches!(neural_network.register_conv_same(ActivationFunction::ReLU, 0, 3, 4), Err(Error::IncompatibleLayers)));
+    assert_eq!(neural_network.num_layers(), 1);
+
+    neural_network.register_conv_same(ActivationFunction::ReLU, 0, 5, 4).expect("Register conv");
+    assert!(matches!(neural_network.get_layer(1).map(Layer::output_dimension), Some(LayerShape::Volume(7, 5, 4))));
+}
+
+#[test]
+fn metrics_precision_recall_curve()
+{
+    let predictions = vec![vec![0.9, 0.1], vec![0.8, 0.3], vec![0.7, 0.3], vec![0.6, 0.2]];
+    let targets = vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 0.0], vec![0.0, 1.0]];
+
+    let curves = metrics::per_class_precision_recall(&predictions, &targets).expect("Precision-recall curves");
+    assert_eq!(curves.len(), 2);
+
+    assert_eq!(curves[0].points, vec![(1.0, 0.5), (0.5, 0.5), (2.0 / 3.0, 1.0), (0.5, 1.0)]);
+    assert_eq!(curves[0].thresholds, [0.9, 0.8, 0.7, 0.6]);
+    // 0.5 * 1 + 0.5 * 2 / 3
+    assert!((curves[0].average_precision - 5.0 / 6.0).abs() < 1e-6);
+
+    // the tied outputs are a single point
+    assert_eq!(curves[1].points, vec![(0.5, 0.5), (2.0 / 3.0, 1.0), (0.5, 1.0)]);
+    assert!((curves[1].average_precision - (0.25 + 1.0 / 3.0)).abs() < 1e-6);
+
+    assert!(metrics::precision_recall_curve(&[0.1, 0.2], &[0.0, 0.0]).is_err());
+    assert!(metrics::per_class_precision_recall(&predictions, &targets[..3]).is_err());
+}
+
+#[test]
+fn metrics_multi_label()
+{
+    let predictions = vec![
+        vec![0.9, 0.8, 0.1],
+        vec![0.2, 0.6, 0.7],
+        vec![0.7, 0.3, 0.4],
+        vec![0.1, 0.2, 0.3],
+    ];
+
+    // several labels can be positive at once
+    let targets = vec![
+        vec![1.0, 1.0, 0.0],
+        vec![0.0, 1.0, 0.0],
+        vec![1.0, 1.0, 0.0],
+        vec![0.0, 0.0, 0.0],
+    ];
+
+    let metrics = metrics::multi_label_metrics(&predictions, &targets, 0.5).expect("Multi-label metrics");
+
+    // a false positive of the third label and a false negative of the second label
+    assert!((metrics.hamming_loss - 2.0 / 12.0).abs() < 1e-6);
+    assert!((metrics.subset_accuracy - 0.5).abs() < 1e-6);
+
+    assert_eq!(metrics.f1[0], 1.0);
+    assert!((metrics.f1[1] - 0.8).abs() < 1e-6);
+    assert_eq!(metrics.f1[2], 0.0);
+
+    assert!(metrics::multi_label_metrics(&predictions, &targets[..3], 0.5).is_err());
+    assert!(matches!(metrics::multi_label_metrics(&predictions, &vec![vec![1.0]; 4], 0.5), Err(Error::DimensionMismatch)));
+}
+
+#[test]
+fn reset_optimizer_state_discards_the_momentum()
+{
+    let (input, target) = (vec![0.5, -1.0, 2.0], vec![0.25, -0.5]);
+    let mut trained = make_output_network(ActivationFunction::PReLU, ErrorFunction::HalfMeanSquaredError, vec![0.1, -0.3]);
+
+    for _ in 0..3 {
+        trained.train_sample(&input, &target, 0.1, 0.9, 0.0).expect("Train sample");
+    }
+
+    trained.reset_optimizer_state();
+
+    // the velocities aren't serialized, so a loaded copy has the same weights and slopes but no momentum
+    let bytes = bincode::serde::encode_to_vec(&trained, bincode::config::standard()).expect("Serialize");
+    let mut fresh = NeuralNetwork::from_bytes(&bytes).expect("Deserialize");
+    let weights = trained.get_layer(1).and_then(Layer::get_weights).expect("Get weights").clone();
+
+    fresh.train_sample(&input, &target, 0.1, 0.9, 0.0).expect("Train sample");
+    trained.train_sample(&input, &target, 0.1, 0.9, 0.0).expect("Train sample");
+
+    assert_eq!(fresh.get_layer(1).and_then(Layer::get_weights), trained.get_layer(1).and_then(Layer::get_weights));
+    assert_ne!(fresh.get_layer(1).and_then(Layer::get_weights), Some(&weights));
+    assert_eq!(fresh.get_output().expect("Get output"), trained.get_output().expect("Get output"));
+}
+
+#[test]
+fn exported_weights_import_into_the_same_architecture()
+{
+    let mut source = make_prelu_network(vec![0.3, 0.05]);
+    source.initialize_all(Initialization::NormalXavier);
+
+    let bytes = source.export_weights();
+
+    // the architecture is defined in code, so a new network only needs the parameters
+    let mut destination = make_prelu_network(vec![0.25, 0.25]);
+    destination.import_weights(&bytes).expect("Import weights");
+
+    for i in 0..source.num_layers() {
+        assert_eq!(source.get_layer(i).and_then(Layer::get_weights), destination.get_layer(i).and_then(Layer::get_weights));
+    }
+
+    assert_eq!(destination.export_weights(), bytes);
+
+    let full = bincode::serde::encode_to_vec(&source, bincode::config::standard()).expect("Serialize");
+    assert!(bytes.len() < full.len());
+
+    assert!(matches!(destination.import_weights(&bytes[..bytes.len() - 4]), Err(Error::DimensionMismatch)));
+    assert!(matches!(make_identity_network(ErrorFunction::HalfMeanSquaredError).import_weights(&bytes), Err(Error::DimensionMismatch)));
+}
+
+#[test]
+fn triangular_learning_rate_rises_then_falls()
+{
+    let schedule = LearningRateSchedule::Triangular { base: 0.001, max: 0.005, step_size: 4 };
+    let rates: Vec<f32> = (0..=8).map(|step| schedule.learning_rate(step)).collect();
+
+    for (rate, expected) in rates.iter().zip([0.001, 0.002, 0.003, 0.004, 0.005, 0.004, 0.003, 0.002, 0.001]) {
+        assert!((rate - expected).abs() < 1e-6);
+    }
+
+    // the next cycle repeats the first
+    assert_eq!(schedule.learning_rate(11), schedule.learning_rate(3));
+}
+
+#[test]
+fn one_cycle_learning_rate_warms_up_then_anneals()
+{
+    let schedule = LearningRateSchedule::OneCycle { max: 0.1, total_steps: 100, warmup_fraction: 0.3, div_factor: 10.0, final_div_factor: 100.0 };
+    let rates: Vec<f32> = (0..=100).map(|step| schedule.learning_rate(step)).collect();
+
+    assert!((rates[0] - 0.01).abs() < 1e-6);
+    assert!((rates[30] - 0.1).abs() < 1e-6);
+    assert!((rates[100] - 1e-4).abs() < 1e-7);
+    assert_eq!(schedule.learning_rate(250), rates[100]);
+
+    assert!(rates[..=30].windows(2).all(|pair| pair[1] > pair[0]));
+    assert!(rates[30..].windows(2).all(|pair| pair[1] < pair[0]));
+
+    // the cosine is halfway down in the middle of the annealing
+    assert!((rates[65] - (0.1 + 1e-4) / 2.0).abs() < 1e-6);
+}
+
+#[test]
+fn training_follows_the_learning_rate_schedule()
+{
+    let (mut neural_network, dataset) = make_parallel_training_setup();
+
+    let config = TrainingConfig {
+        epochs: 2,
+        batch_size: 5,
+        num_threads: 1,
+        learning_rate_schedule: Some(LearningRateSchedule::one_cycle(0.05, 4)),
+        ..TrainingConfig::default()
+    };
+
+    neural_network.train(&dataset, &config).expect("Train");
+
+    // the last of the four batches set the final rate
+    let expected = LearningRateSchedule::one_cycle(0.05, 4).learning_rate(3);
+    assert_eq!(neural_network.get_hyperparameters().learning_rate, expected);
+
+    let invalid = TrainingConfig { learning_rate_schedule: Some(LearningRateSchedule::Triangular { base: 0.1, max: 0.01, step_size: 2 }), ..config };
+    assert!(matches!(neural_network.train(&dataset, &invalid), Err(Error::InvalidInput)));
+}
+
+#[test]
+fn tta_with_the_identity_is_a_single_forward_pass()
+{
+    let dimension = (3, 2, 1);
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_input_layer(Layer::make_input_layer(0, dimension)).expect("Register input layer");
+    neural_network.register_conv(ActivationFunction::ReLU, 0, 1, 2, 2).expect("Register conv");
+    neural_network.register_fully_connected(ActivationFunction::Sigmoid, 2).expect("Register fully connected");
+    neural_network.initialize_all(Initialization::NormalXavier);
+
+    let input = vec![0.3, -0.7, 1.2, 0.5, -0.1, 0.9];
+    neural_network.set_input(&input).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagate");
+    let output = neural_network.get_output().expect("Get output");
+
+    let identity = neural_network.predict_tta(&input, dimension, &[augment::Augmentation::Identity]).expect("Predict");
+    assert_eq!(identity, output);
+
+    let flipped = augment::horizontal_flip(&input, dimension).expect("Flip volume");
+    neural_network.set_input(&flipped).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagate");
+    let flipped_output = neural_network.get_output().expect("Get output");
+
+    let averaged = neural_network.predict_tta(&input, dimension, &[augment::Augmentation::Identity, augment::Augmentation::HorizontalFlip]).expect("Predict");
+    for i in 0..averaged.len() {
+        assert!((averaged[i] - (output[i] + flipped_output[i]) / 2.0).abs() < 1e-6);
+    }
+
+    assert!(neural_network.predict_tta(&input, dimension, &[]).is_err());
+    assert!(neural_network.predict_tta(&input, (2, 2, 1), &[augment::Augmentation::Identity]).is_err());
+}
+
+fn make_separable_network(depthwise: Vec<f32>, pointwise: Vec<f32>, biases: Vec<f32>) -> NeuralNetwork
+{
+    let mut separable_layer = Layer::make_separable_convolutional_layer(0, (1, 1), 3, (5, 5, 3), 2);
+    let mut output_layer = Layer::make_fully_connected_layer(75, 1);
+
+    if let Layer::SeparableConvolutional(ref mut separable) = separable_layer {
+        separable.set_depthwise_kernel(depthwise).expect("Set depthwise kernel");
+        separable.set_pointwise_kernel(pointwise).expect("Set pointwise kernel");
+        separable.set_biases(biases).expect("Set biases");
+    }
+
+    if let Layer::FullyConnected(ref mut fc) = output_layer {
+        fc.set_weights((0..75).map(|i| (i as f32 * 0.3).cos() * 0.2).collect()).expect("Set weights");
+    }
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(1, (5, 5, 2)));
+    neural_network.register_layer(ActivationFunction::Sigmoid, separable_layer);
+    neural_network.register_layer(ActivationFunction::None, output_layer);
+    neural_network.set_input(&(0..50).map(|i| (i as f32 * 0.9).sin()).collect()).expect("Set input");
+
+    neural_network
+}
+
+#[test]
+fn separable_convolution_matches_a_depthwise_and_pointwise_convolution()
+{
+    let depthwise: Vec<f32> = (0..18).map(|i| (i as f32 * 0.4).sin()).collect();
+    let pointwise = vec![0.5, -1.0, 0.25, 0.75, -0.5, 1.5];
+    let biases = vec![0.1, -0.2, 0.3];
+
+    let mut neural_network = make_separable_network(depthwise.clone(), pointwise.clone(), biases.clone());
+    neural_network.forward_propagate().expect("Forward propagate");
+    let output = neural_network.get_layer(1).expect("Get layer").get_output().clone();
+
+    // the input padded by 1 on every side, since `apply` doesn't pad
+    let input: Vec<f32> = (0..50).map(|i| (i as f32 * 0.9).sin()).collect();
+    let channel = |z: usize| -> Vec<f32> {
+        let mut padded = vec![0.0; 7 * 7];
+        for x in 0..5 {
+            for y in 0..5 {
+                padded[util::get_index((x + 1, y + 1, 0), (7, 7, 1))] = input[util::get_index((x, y, z), (5, 5, 2))];
+            }
+        }
+
+        padded
+    };
+
+    // a convolution with a single kernel per input channel
+    let mut depthwise_volume = vec![0.0; 5 * 5 * 2];
+    for z in 0..2 {
+        let mut layer = Layer::make_convolutional_layer(0, 1, 3, (5, 5, 1), 1);
+        let Layer::Convolutional(ref mut conv) = layer else { unreachable!() };
+        conv.set_kernel(depthwise[z * 9..(z + 1) * 9].to_vec()).expect("Set kernel");
+
+        let filtered = conv.apply(&channel(z), (7, 7, 1)).expect("Apply depthwise");
+        for i in 0..25 {
+            depthwise_volume[i * 2 + z] = filtered[i];
+        }
+    }
+
+    let mut layer = Layer::make_convolutional_layer(0, 1, 1, (5, 5, 3), 2);
+    let Layer::Convolutional(ref mut conv) = layer else { unreachable!() };
+    conv.set_kernel(pointwise.clone()).expect("Set kernel");
+    let combined = conv.apply(&depthwise_volume, (5, 5, 2)).expect("Apply pointwise");
+
+    for i in 0..output.len() {
+        let expected = activations::eval(ActivationFunction::Sigmoid, combined[i] + biases[i % 3]);
+        assert!((output[i] - expected).abs() < 1e-5, "{} != {}", output[i], expected);
+    }
+
+    assert_eq!(neural_network.get_layer(1).expect("Get layer").num_parameters(), 18 + 6 + 3);
+
+    // the gradients of both kernels and the biases match finite differences
+    let target = vec![0.4];
+    neural_network.start_batch();
+    neural_network.back_propagate(&target).expect("Back propagate");
+
+    let Some(Layer::SeparableConvolutional(layer)) = neural_network.get_layer(1) else { unreachable!() };
+    let gradients: Vec<util::Gradient> = layer.depthwise_gradients.iter().chain(&layer.pointwise_gradients).chain(&layer.bias_gradients).cloned().collect();
+
+    let epsilon = 1e-2;
+    for (parameter, gradient) in gradients.into_iter().enumerate() {
+        let error = |offset: f32| {
+            let (mut depthwise, mut pointwise, mut biases) = (depthwise.clone(), pointwise.clone(), biases.clone());
+            match parameter {
+                0..18 => depthwise[parameter] += offset,
+                18..24 => pointwise[parameter - 18] += offset,
+                _ => biases[parameter - 24] += offset,
+            }
+
+            let mut neural_network = make_separable_network(depthwise, pointwise, biases);
+            neural_network.forward_propagate().expect("Forward propagate");
+            neural_network.get_error(&target).expect("Get error")
+        };
+
+        let numerical = (error(epsilon) - error(-epsilon)) / (2.0 * epsilon);
+        let gradient = util::from_gradient(gradient);
+        assert!((numerical - gradient).abs() < 1e-3, "{} != {}", numerical, gradient);
+    }
+
+    // both kernels survive serialization
+    let bytes = bincode::serde::encode_to_vec(&neural_network, bincode::config::standard()).expect("Serialize");
+    let mut loaded = NeuralNetwork::from_bytes(&bytes).expect("Deserialize");
+    loaded.set_input(&input).expect("Set input");
+    loaded.forward_propagate().expect("Forward propagate");
+    assert_eq!(loaded.get_layer(1).expect("Get layer").get_output(), &output);
+}
+
+#[test]
+fn memory_per_sample_counts_the_activation_buffers()
+{
+    let neural_network = make_example_network();
+
+    // ReLU layers keep their output, its gradients and the back activated gradients, max pooling also keeps a switch per value
+    let convolutions = (128 * 128 * 3 + 128 * 128 * 32 + 64 * 64 * 64 + 32 * 32 * 128) * 3 * 4;
+    let pooling = (64 * 64 * 32 + 32 * 32 * 64 + 16 * 16 * 128) * (2 * 4 + std::mem::size_of::<usize>());
+    let fully_connected = (512 + 1) * 4 * 4;
+
+    assert_eq!(neural_network.estimate_memory_per_sample(), convolutions + pooling + fully_connected);
+
+    let mut softsign_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    softsign_network.register_input_layer(Layer::make_input_layer(0, (4, 4, 1))).expect("Register input layer");
+    softsign_network.register_conv(ActivationFunction::Softsign, 0, 1, 3, 2).expect("Register conv");
+
+    // the raw volume is needed for the derivative of Softsign
+    assert_eq!(softsign_network.estimate_memory_per_sample(), 16 * 3 * 4 + 2 * 2 * 2 * 4 * 4);
+}
+
+#[test]
+fn softmax_temperature_flattens_the_distribution()
+{
+    let logits = vec![2.0, 1.0, -0.5];
+
+    let standard = activations::softmax(&logits, 1.0).expect("Softmax");
+    let sum: f32 = logits.iter().map(|logit| logit.exp()).sum();
+    for i in 0..logits.len() {
+        assert!((standard[i] - logits[i].exp() / sum).abs() < 1e-6);
+    }
+
+    let soft = activations::softmax(&logits, 4.0).expect("Softmax");
+    assert!((soft.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+    assert!(soft[0] < standard[0] && soft[2] > standard[2]);
+
+    // large logits don't overflow
+    let large = activations::softmax(&[1000.0, 999.0], 1.0).expect("Softmax");
+    assert!(large.iter().all(|probability| probability.is_finite()));
+
+    assert!(activations::softmax(&logits, 0.0).is_err());
+}
+
+#[test]
+fn weight_diff_is_the_norm_of_the_parameter_difference()
+{
+    let make_network = |weights: Vec<f32>| {
+        let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+        neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (1, 1, 2)));
+        neural_network.register_layer(ActivationFunction::Sigmoid, make_fully_connected(2, weights));
+
+        neural_network
+    };
+
+    let neural_network = make_network(vec![0.5, -1.0, 0.25, 2.0]);
+    assert_eq!(neural_network.weight_diff(&neural_network).expect("Weight diff"), 0.0);
+
+    let perturbed = make_network(vec![0.5, -1.0 + 3.0, 0.25, 2.0 - 4.0]);
+    assert!((neural_network.weight_diff(&perturbed).expect("Weight diff") - 5.0).abs() < 1e-6);
+
+    let wider = make_network(vec![0.5, -1.0, 0.25, 2.0, 1.0, 1.0]);
+    assert!(matches!(neural_network.weight_diff(&wider), Err(Error::IncompatibleLayers)));
+}
+
+#[test]
+fn kahan_summation_is_closer_to_the_f64_sum()
+{
+    let values: Vec<f32> = std::iter::once(1.0).chain(std::iter::repeat_n(1e-8, 1_000_000)).collect();
+    let reference: f64 = values.iter().map(|value| *value as f64).sum();
+
+    let naive = values.iter().fold(0.0f32, |sum, value| sum + value);
+    let compensated = util::kahan_sum(values.iter().cloned());
+
+    // every small value is lost when it's added to 1 on its own
+    assert_eq!(naive, 1.0);
+    assert!((compensated as f64 - reference).abs() < (naive as f64 - reference).abs());
+    assert!((compensated as f64 - reference).abs() < 1e-6);
+
+    let mut sum = util::KahanSum::<f64>::new();
+    sum.add(0.5);
+    sum.add(0.25);
+    assert_eq!(sum.value(), 0.75);
+}
+
+struct Square;
+
+impl Activation for Square {
+    fn forward(&self, x: f32) -> f32 {
+        x * x
+    }
+
+    fn derivative(&self, x: f32) -> f32 {
+        2.0 * x
+    }
+}
+
+static SQUARE: Square = Square;
+
+#[test]
+fn custom_activation_is_used_in_both_passes()
+{
+    let make_network = |weights: Vec<f32>| {
+        let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+        neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (1, 1, 2)));
+        neural_network.register_layer(ActivationFunction::Custom(&SQUARE), make_fully_connected(2, weights));
+        neural_network.set_input(&vec![0.5, -1.5]).expect("Set input");
+
+        neural_network
+    };
+
+    let weights = vec![0.8, 0.4];
+    let target = vec![0.2];
+
+    let mut neural_network = make_network(weights.clone());
+    neural_network.forward_propagate().expect("Forward propagate");
+
+    // (0.8 * 0.5 + 0.4 * -1.5)^2
+    let output = neural_network.get_output().expect("Get output");
+    assert!((output[0] - 0.04).abs() < 1e-6);
+
+    neural_network.start_batch();
+    neural_network.back_propagate(&target).expect("Back propagate");
+    let gradients = neural_network.get_layer(1).and_then(Layer::get_weight_gradients).expect("Get gradients").clone();
+
+    let epsilon = 1e-3;
+    for i in 0..weights.len() {
+        let error = |offset: f32| {
+            let mut weights = weights.clone();
+            weights[i] += offset;
+
+            let mut neural_network = make_network(weights);
+            neural_network.forward_propagate().expect("Forward propagate");
+            neural_network.get_error(&target).expect("Get error")
+        };
+
+        let numerical = (error(epsilon) - error(-epsilon)) / (2.0 * epsilon);
+        let gradient = util::from_gradient(gradients[i]);
+        assert!((numerical - gradient).abs() < 1e-3, "{} != {}", numerical, gradient);
+    }
+
+    // a custom activation can't be saved
+    assert!(bincode::serde::encode_to_vec(&neural_network, bincode::config::standard()).is_err());
+}
+
+/// squared error that weights the first output 4 times as much as the second
+struct WeightedSquared;
+
+impl Loss for WeightedSquared {
+    fn eval(&self, values: &[f32], expected: &[f32]) -> f32 {
+        (0..values.len()).map(|i| [4.0, 1.0][i] * (values[i] - expected[i]) * (values[i] - expected[i])).sum()
+    }
+
+    fn eval_derivative(&self, i: usize, values: &[f32], expected: &[f32]) -> f32 {
+        [4.0, 1.0][i] * 2.0 * (values[i] - expected[i])
+    }
+}
+
+static WEIGHTED_SQUARED: WeightedSquared = WeightedSquared;
+
+#[test]
+fn custom_loss_drives_training()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::Custom(&WEIGHTED_SQUARED));
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (1, 1, 2)));
+    neural_network.register_layer(ActivationFunction::None, make_fully_connected(2, vec![0.3, -0.2, 0.1, 0.4]));
+    neural_network.set_input(&vec![1.0, 0.5]).expect("Set input");
+
+    let target = vec![1.0, -1.0];
+    neural_network.forward_propagate().expect("Forward propagate");
+
+    // (0.2 - 1)^2 * 4 + (0.3 + 1)^2
+    let initial_error = neural_network.get_error(&target).expect("Get error");
+    assert!((initial_error - (0.64 * 4.0 + 1.69)).abs() < 1e-5);
+
+    for _ in 0..50 {
+        neural_network.forward_propagate().expect("Forward propagate");
+        neural_network.start_batch();
+        neural_network.back_propagate(&target).expect("Back propagate");
+        neural_network.end_batch(1);
+    }
+
+    neural_network.forward_propagate().expect("Forward propagate");
+    assert!(neural_network.get_error(&target).expect("Get error") < initial_error * 0.01);
+
+    assert!(bincode::serde::encode_to_vec(&neural_network, bincode::config::standard()).is_err());
+}
+
+#[test]
+fn centralized_gradients_have_a_zero_mean_per_kernel_and_neuron()
+{
+    use dataset::Dataset;
+
+    let (mut neural_network, dataset) = make_parallel_training_setup();
+
+    neural_network.start_batch();
+    for i in 0..dataset.len() {
+        let (input, target) = dataset.get(i).expect("Get sample");
+
+        neural_network.set_input(&input).expect("Set input");
+        neural_network.forward_propagate().expect("Forward propagate");
+        neural_network.back_propagate(&target).expect("Back propagate");
+    }
+
+    let gradients_before = neural_network.collect_gradients();
+    neural_network.centralize_gradients();
+
+    let mut centralized_groups = 0;
+    for i in 0..neural_network.num_layers() {
+        let (gradients, group_size) = match neural_network.get_layer(i).expect("Get layer") {
+            Layer::Convolutional(layer) if !layer.kernel_gradients.is_empty() => (&layer.kernel_gradients, layer.kernel_gradients.len() / layer.num_kernels),
+            Layer::FullyConnected(layer) => (&layer.weight_gradients, layer.num_inputs),
+
+            _ => continue,
+        };
+
+        for group in gradients.chunks(group_size) {
+            let mean = group.iter().map(|gradient| util::from_gradient(*gradient)).sum::<f32>() / group.len() as f32;
+            assert!(mean.abs() < 1e-6, "{}", mean);
+            centralized_groups += 1;
+        }
+    }
+
+    assert!(centralized_groups > 0);
+    assert_ne!(neural_network.collect_gradients(), gradients_before);
+}
+
+#[test]
+fn whitened_features_have_an_identity_covariance()
+{
+    // three correlated features with different scales
+    let data: Vec<Vec<f32>> = (0..500).map(|i| {
+        let (a, b, c) = ((i as f32 * 0.37).sin(), (i as f32 * 1.13).cos(), (i as f32 * 2.71).sin());
+        vec![3.0 * a + 1.0, 0.8 * a + 0.3 * b, b - 0.5 * a + 0.2 * c - 2.0]
+    }).collect();
+
+    let whitening = util::Whitening::fit(&data, 1e-6).expect("Fit whitening");
+
+    let mut whitened = data.clone();
+    for sample in &mut whitened {
+        whitening.apply(sample).expect("Apply whitening");
+    }
+
+    for i in 0..3 {
+        let mean = whitened.iter().map(|sample| sample[i]).sum::<f32>() / whitened.len() as f32;
+        assert!(mean.abs() < 1e-4, "{}", mean);
+
+        for j in 0..3 {
+            let covariance = whitened.iter().map(|sample| sample[i] * sample[j]).sum::<f32>() / whitened.len() as f32;
+            let expected = if i == j { 1.0 } else { 0.0 };
+            assert!((covariance - expected).abs() < 1e-3, "({}, {}) {}", i, j, covariance);
+        }
+    }
+
+    assert!(matches!(whitening.apply(&mut [0.0; 2]), Err(Error::DimensionMismatch)));
+    assert!(util::Whitening::fit(&[], 1e-6).is_err());
+}
+
+#[test]
+fn fused_inference_activation_matches_the_two_step_path()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_input_layer(Layer::make_input_layer(1, (5, 5, 2))).expect("Register input layer");
+    neural_network.register_conv(ActivationFunction::ReLU, 0, 1, 3, 3).expect("Register conv");
+    neural_network.register_conv(ActivationFunction::Sigmoid, 0, 1, 1, 2).expect("Register conv");
+    neural_network.register_conv(ActivationFunction::Softsign, 0, 1, 2, 2).expect("Register conv");
+    neural_network.register_fully_connected(ActivationFunction::Sigmoid, 2).expect("Register fully connected");
+    neural_network.initialize_all(Initialization::NormalXavier);
+
+    let input: Vec<f32> = (0..50).map(|i| (i as f32 * 0.61).sin()).collect();
+    let target = [1.0, 0.0];
+
+    let two_step = neural_network.forward_with_activations(&input).expect("Forward propagate");
+    let two_step_gradient = neural_network.input_gradient(&input, &target).expect("Input gradient");
+
+    neural_network.set_training(false);
+    assert!(matches!(neural_network.get_layer(1), Some(Layer::Convolutional(layer)) if layer.fuses_activation()));
+    assert!(matches!(neural_network.get_layer(3), Some(Layer::Convolutional(layer)) if !layer.fuses_activation()));
+
+    assert_eq!(neural_network.forward_with_activations(&input).expect("Forward propagate"), two_step);
+
+    // the derivatives are computed from the fused outputs
+    assert_eq!(neural_network.input_gradient(&input, &target).expect("Input gradient"), two_step_gradient);
+}
+
+#[test]
+fn feature_maps_are_tiled_by_channel()
+{
+    let mut conv_layer = Layer::make_convolutional_layer(0, 1, 2, (3, 2, 3), 1);
+
+    if let Layer::Convolutional(ref mut conv) = conv_layer {
+        // the second kernel is zeroed so its channel is constant
+        conv.set_kernel(vec![1.0, 0.5, 0.25, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.5, -0.25, 2.0]).expect("Set kernel");
+        conv.set_biases(vec![0.0, 0.0, 0.0]).expect("Set biases");
+    }
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (4, 3, 1)));
+    neural_network.register_layer(ActivationFunction::None, conv_layer);
+
+    neural_network.set_input(&vec![3.0, -1.0, 4.0, 1.0, -5.0, 9.0, 2.0, -6.0, 5.0, 3.0, -5.0, 8.0]).expect("Set input");
+    neural_network.forward_propagate().expect("Forward propagate");
+
+    let (grid, (width, height)) = neural_network.feature_maps_as_grid(1).expect("Feature maps");
+
+    // 3 channels of 3x2 fill a 2x2 grid of tiles
+    assert_eq!((width, height), (6, 4));
+    assert_eq!(grid.len(), width * height);
+
+    let volume = neural_network.get_layer(1).expect("Layer").get_output().clone();
+
+    for (z, (offset_x, offset_y)) in [(0, (0, 0)), (2, (0, 2))] {
+        let channel: Vec<f32> = (0..2).flat_map(|y| (0..3).map(move |x| (x, y)))
+            .map(|(x, y)| volume[util::get_index((x, y, z), (3, 2, 3))])
+            .collect();
+
+        let min = channel.iter().cloned().fold(f32::INFINITY, f32::min);
+        let max = channel.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        assert!(max > min);
+
+        for (i, value) in channel.iter().enumerate() {
+            let (x, y) = (i % 3, i / 3);
+            assert!((grid[(offset_y + y) * width + offset_x + x] - (value - min) / (max - min)).abs() < 1e-6);
+        }
+    }
+
+    // the constant channel and the unused tile are blank
+    for y in 0..2 {
+        for x in 3..6 {
+            assert_eq!(grid[y * width + x], 0.0);
+            assert_eq!(grid[(y + 2) * width + x], 0.0);
+        }
+    }
+
+    assert!(matches!(neural_network.feature_maps_as_grid(0), Err(Error::IncompatibleLayers)));
+    assert!(matches!(neural_network.feature_maps_as_grid(2), Err(Error::InvalidInput)));
+}
+
+#[test]
+fn parallel_workers_drop_different_channels()
+{
+    let depth = 16;
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (2, 2, depth)));
+    neural_network.register_spatial_dropout(0.5, 0).expect("Register spatial dropout layer");
+    neural_network.register_fully_connected(ActivationFunction::None, 1).expect("Register fully connected layer");
+    neural_network.initialize_all(Initialization::NormalXavier);
+    neural_network.set_seed(5);
+
+    let input: Vec<f32> = vec![1.0; 4 * depth];
+
+    let dropped = |neural_network: &mut NeuralNetwork| -> Vec<bool> {
+        neural_network.set_input(&input).expect("Set input");
+        neural_network.forward_propagate().expect("Forward propagate");
+
+        neural_network.get_layer(1).expect("Get layer").get_output()[..depth].iter().map(|value| *value == 0.0).collect()
+    };
+
+    let mut workers = neural_network.clone().make_workers(2);
+    let first = dropped(&mut workers[0]);
+    let second = dropped(&mut workers[1]);
+
+    assert_ne!(first, second);
+
+    // the first worker continues like the network itself
+    assert_eq!(first, dropped(&mut neural_network));
+}
+
+#[test]
+fn separable_convolution_weight_helpers_cover_the_depthwise_kernel()
+{
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (4, 4, 2)));
+    neural_network.register_separable_conv(ActivationFunction::ReLU, 0, 1, 3, 3).expect("Register separable convolutional layer");
+    neural_network.register_fully_connected(ActivationFunction::None, 1).expect("Register fully connected layer");
+    neural_network.initialize_all(Initialization::UniformXavier);
+
+    let Some(Layer::SeparableConvolutional(layer)) = neural_network.get_layer(1) else { unreachable!() };
+    let (depthwise, pointwise) = (layer.get_depthwise_kernel().clone(), layer.get_pointwise_kernel().clone());
+
+    // 18 depthwise and 6 pointwise weights
+    let histogram = neural_network.weight_histogram(1, 3).expect("Weight histogram");
+    assert_eq!(histogram.iter().map(|(_, count)| count).sum::<usize>(), 24);
+
+    let gradient_histogram = neural_network.gradient_histogram(1, 2).expect("Gradient histogram");
+    assert_eq!(gradient_histogram.iter().map(|(_, count)| count).sum::<usize>(), 24);
+
+    // the empty kernel of the input layer, then the depthwise kernel before the pointwise kernel
+    let quantized = neural_network.quantize_int8();
+    assert_eq!(quantized.get_tensors().len(), 4);
+
+    let dequantized = quantized.dequantize();
+    let Some(Layer::SeparableConvolutional(restored)) = dequantized.get_layer(1) else { unreachable!() };
+
+    for ((weights, restored), tensor) in [(&depthwise, restored.get_depthwise_kernel()), (&pointwise, restored.get_pointwise_kernel())].into_iter().zip(&quantized.get_tensors()[1..]) {
+        assert_eq!(tensor.values.len(), weights.len());
+        assert!(weights.iter().zip(restored).all(|(weight, restored)| (weight - restored).abs() <= tensor.scale * 0.5 + 1e-6));
+    }
+
+    // a zeroed depthwise kernel leaves the layer uninitialized even with a pointwise kernel
+    let mut separable = Layer::make_separable_convolutional_layer(0, (1, 1), 3, (2, 2, 3), 2);
+    if let Layer::SeparableConvolutional(ref mut layer) = separable {
+        layer.set_pointwise_kernel(pointwise.clone()).expect("Set pointwise kernel");
+    }
+
+    let mut uninitialized = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    uninitialized.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (4, 4, 2)));
+    uninitialized.register_layer(ActivationFunction::ReLU, separable);
+    uninitialized.register_fully_connected(ActivationFunction::None, 1).expect("Register fully connected layer");
+    uninitialized.initialize(2, Initialization::UniformXavier).expect("Initialize");
+
+    assert!(neural_network.validate().is_ok());
+    assert!(matches!(uninitialized.validate(), Err(Error::UninitializedLayer(1))));
+}
+
+#[test]
+fn triplet_gradients_use_the_dropout_masks_of_the_loss()
+{
+    let depth = 8;
+    let identity: Vec<f32> = (0..depth * depth).map(|i| if i % (depth + 1) == 0 { 1.0 } else { 0.0 }).collect();
+
+    let mut neural_network = NeuralNetwork::new(ErrorFunction::HalfMeanSquaredError);
+    neural_network.register_layer(ActivationFunction::None, Layer::make_input_layer(0, (1, 1, depth)));
+    neural_network.register_spatial_dropout(0.5, 0).expect("Register spatial dropout layer");
+    neural_network.register_layer(ActivationFunction::None, make_fully_connected(depth, identity));
+    neural_network.set_seed(11);
+
+    let anchor: Vec<f32> = (0..depth).map(|i| (i as f32 * 0.7).sin()).collect();
+    let positive: Vec<f32> = (0..depth).map(|i| (i as f32 * 0.7).sin() + 0.1).collect();
+    let negative: Vec<f32> = (0..depth).map(|i| (i as f32 * 1.3).cos()).collect();
+    let margin = 4.0;
+
+    // the embeddings are the dropped inputs, so the diagonal of the weight gradients sums to |a - p| - |a - n| of the same masks as the loss
+    for _ in 0..20 {
+        neural_network.start_batch();
+        let loss = neural_network.back_propagate_triplet(&anchor, &positive, &negative, margin).expect("Back propagate triplet");
+        assert!(loss > 0.0);
+
+        let gradients = neural_network.get_layer(2).and_then(Layer::get_weight_gradients).expect("Get gradients").clone();
+        let trace: util::Gradient = (0..depth).map(|i| gradients[i * (depth + 1)]).sum();
+
+        assert!((trace - util::Gradient::from(loss - margin)).abs() < 1e-4);
+    }
+}
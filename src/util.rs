@@ -1,16 +1,121 @@
 use crate::errors::Error;
 
+use rand::{SeedableRng, rngs::StdRng, seq::SliceRandom};
+use serde::{Serialize, Deserialize};
+
+/// the type gradients are accumulated in, f64 with the `f64-gradients` feature since summing many f32 values loses precision
+#[cfg(not(feature = "f64-gradients"))]
+pub type Gradient = f32;
+
+/// the type gradients are accumulated in, f64 with the `f64-gradients` feature since summing many f32 values loses precision
+#[cfg(feature = "f64-gradients")]
+pub type Gradient = f64;
+
+/// converts an accumulated gradient back to the precision of the weights
+#[allow(clippy::unnecessary_cast)]
+pub(crate) fn from_gradient(gradient: Gradient) -> f32 {
+    gradient as f32
+}
+
+/// A compensated (Kahan) sum, which carries the rounding error of every addition into the next one
+///
+/// summing many small values onto a large one stays accurate without moving to f64
+#[derive(Clone, Copy, Debug, Default)]
+pub struct KahanSum<T = f32> {
+    sum: T,
+    compensation: T,
+}
+
+#[allow(clippy::unused_unit)]
+impl<T: Copy + Default + std::ops::Add<Output = T> + std::ops::Sub<Output = T>> KahanSum<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, value: T) -> () {
+        let corrected = value - self.compensation;
+        let sum = self.sum + corrected;
+
+        // the low bits of `corrected` that were lost in `sum`
+        self.compensation = (sum - self.sum) - corrected;
+        self.sum = sum;
+    }
+
+    pub fn value(&self) -> T {
+        self.sum
+    }
+}
+
+/// sums the values with `KahanSum`
+pub fn kahan_sum(values: impl IntoIterator<Item = f32>) -> f32 {
+    let mut sum = KahanSum::new();
+    values.into_iter().for_each(|value| sum.add(value));
+
+    sum.value()
+}
+
+/// A plain running sum with the interface of `KahanSum`
+#[cfg(not(feature = "kahan-summation"))]
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct NaiveSum<T> {
+    sum: T,
+}
+
+#[cfg(not(feature = "kahan-summation"))]
+#[allow(clippy::unused_unit)]
+impl<T: Copy + std::ops::Add<Output = T>> NaiveSum<T> {
+    pub(crate) fn add(&mut self, value: T) -> () {
+        self.sum = self.sum + value;
+    }
+
+    pub(crate) fn value(&self) -> T {
+        self.sum
+    }
+}
+
+/// the running sum of the losses and of the gradients combined by the parallel trainer, compensated with the `kahan-summation` feature
+#[cfg(not(feature = "kahan-summation"))]
+pub(crate) type Accumulator<T> = NaiveSum<T>;
+
+/// the running sum of the losses and of the gradients combined by the parallel trainer, compensated with the `kahan-summation` feature
+#[cfg(feature = "kahan-summation")]
+pub(crate) type Accumulator<T> = KahanSum<T>;
+
+/// How the output size of a convolutional or pooling layer is rounded when the stride doesn't evenly divide its input
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum OutputRounding {
+    /// only windows that lie entirely inside the padded input, i.e. `(size - kernel_size) / stride + 1`
+    #[default]
+    Floor,
+    /// also a last partial window that hangs over the edge, i.e. `ceil((size - kernel_size) / stride) + 1`,
+    /// as long as it starts inside the input. the values outside the input are skipped
+    Ceil,
+}
+
+/// the output dimension with `OutputRounding::Floor`, which every layer uses unless configured otherwise
 pub fn get_output_dimension(
     dimension: (usize, usize, usize),
     zero_padding: usize,
     num_kernels: usize,
     kernel_size: usize,
-    stride: usize
+    stride: (usize, usize)
+) -> Option<(usize, usize, usize)> {
+    get_output_dimension_rounded(dimension, zero_padding, num_kernels, kernel_size, stride, OutputRounding::Floor)
+}
+
+pub fn get_output_dimension_rounded(
+    dimension: (usize, usize, usize),
+    zero_padding: usize,
+    num_kernels: usize,
+    kernel_size: usize,
+    stride: (usize, usize),
+    rounding: OutputRounding
 ) -> Option<(usize, usize, usize)> {
 
     if num_kernels == 0 ||
        kernel_size == 0 ||
-       stride == 0 ||
+       stride.0 == 0 ||
+       stride.1 == 0 ||
        dimension.0 == 0 ||
        dimension.1 == 0 ||
        dimension.2 == 0
@@ -18,30 +123,57 @@ pub fn get_output_dimension(
 
     let (x, y, _) = dimension;
     let (padded_x, padded_y) = (x + zero_padding * 2, y + zero_padding * 2);
-    if kernel_size - 1 >= padded_x || kernel_size - 1 >= padded_y { return None };
+    if kernel_size > padded_x || kernel_size > padded_y { return None };
 
-    let (length_x, length_y) = (padded_x - kernel_size + 1, padded_y - kernel_size + 1);
-    let (result_x, result_y) = ((length_x + stride - 1) / stride, (length_y + stride - 1) / stride);
+    let (result_x, result_y) = (
+        output_length(x, zero_padding, kernel_size, stride.0, rounding),
+        output_length(y, zero_padding, kernel_size, stride.1, rounding)
+    );
 
     if result_x == 0 || result_y == 0 { return None };
 
     Some((result_x, result_y, num_kernels))
 }
 
+/// the zero padding that keeps the output the size of the input with a stride of 1, i.e. `(kernel_size - 1) / 2`
+///
+/// the padding is added on both sides, so an even kernel can't be centered and its output is 1 smaller than the input
+pub fn same_padding(kernel_size: usize) -> usize {
+    kernel_size.saturating_sub(1) / 2
+}
+
+/// the number of window positions along one axis
+fn output_length(size: usize, zero_padding: usize, kernel_size: usize, stride: usize, rounding: OutputRounding) -> usize {
+    let length = size + zero_padding * 2 - kernel_size + 1;
+
+    match rounding {
+        OutputRounding::Floor => length.div_ceil(stride),
+
+        OutputRounding::Ceil => {
+            let result = (length - 1).div_ceil(stride) + 1;
+
+            // a window starting in the right padding would only see zeros
+            if (result - 1) * stride >= size + zero_padding { result - 1 } else { result }
+        }
+    }
+}
+
 pub(crate) fn check_output_dimension(
     dimension: (usize, usize, usize),
     expected_dimension: (usize, usize, usize),
     zero_padding: usize,
     num_kernels: usize,
     kernel_size: usize,
-    stride: usize
+    stride: (usize, usize),
+    rounding: OutputRounding
 ) -> Result<(), Error> {
     let output_dim =
-        get_output_dimension(dimension,
+        get_output_dimension_rounded(dimension,
             zero_padding,
             num_kernels,
             kernel_size,
-            stride
+            stride,
+            rounding
         );
                 
     if let Some(dim) = output_dim {
@@ -55,18 +187,15 @@ pub(crate) fn check_output_dimension(
     Ok(())
 }
 
-/// used to simulate zero padding without using extra memory
+/// the kernel offsets along one axis whose zero padded position `start + offset` lies inside the input
+///
+/// used to simulate zero padding without using extra memory, e.g. only a single row is visited for an input with a height of 1
 #[inline(always)]
-pub(crate) fn query_zero_padded(position: (usize, usize, usize), input_dimension: (usize, usize, usize), zero_padding: usize) -> Option<usize> {
-    let (x, y, z) = position;
-            
-    if x < zero_padding ||
-        x >= input_dimension.0 - zero_padding ||
-        y < zero_padding ||
-        y >= input_dimension.1 - zero_padding
-    { return None };
+pub(crate) fn kernel_range(start: usize, zero_padding: usize, size: usize, kernel_size: usize) -> std::ops::Range<usize> {
+    let first = zero_padding.saturating_sub(start).min(kernel_size);
+    let last = (zero_padding + size).saturating_sub(start).min(kernel_size);
 
-    Some(get_index((x - zero_padding, y - zero_padding, z), input_dimension))
+    first..last.max(first)
 }
 
 #[inline(always)]
@@ -85,4 +214,236 @@ pub fn get_index(position: (usize, usize, usize), dimension: (usize, usize, usiz
     let (_, dim_y, dim_z) = dimension;
 
     z + dim_z * (y + dim_y * x)
+}
+
+/// returns the index of the largest value, or 0 if the slice is empty
+pub fn argmax(values: &[f32]) -> usize {
+    let mut max_index = 0;
+
+    for i in 1..values.len() {
+        if values[i] > values[max_index] {
+            max_index = i;
+        }
+    }
+
+    max_index
+}
+
+/// buckets the values into `bins` equally sized ranges between their minimum and maximum
+///
+/// returns the lower bound and count of every bucket
+pub fn histogram(values: &[f32], bins: usize) -> Result<Vec<(f32, usize)>, Error> {
+    if bins == 0 || values.is_empty() { return Err(Error::InvalidInput) };
+
+    let min = values.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let width = (max - min) / bins as f32;
+
+    let mut result: Vec<(f32, usize)> = (0..bins).map(|i| (min + width * i as f32, 0)).collect();
+
+    for value in values {
+        let bin = if width > 0.0 {
+            (((value - min) / width) as usize).min(bins - 1)
+        } else {
+            0
+        };
+
+        result[bin].1 += 1;
+    }
+
+    Ok(result)
+}
+
+/// shuffles the indices of `data` with the seed and returns (training, validation) indices, both in shuffled order
+///
+/// the validation set gets `val_fraction` of the samples rounded to the nearest count
+pub fn train_val_split<T>(data: &[T], val_fraction: f32, seed: u64) -> Result<(Vec<usize>, Vec<usize>), Error> {
+    if !(0.0..=1.0).contains(&val_fraction) { return Err(Error::InvalidInput) };
+
+    let mut indices: Vec<usize> = (0..data.len()).collect();
+    indices.shuffle(&mut StdRng::seed_from_u64(seed));
+
+    let validation_size = ((data.len() as f32 * val_fraction).round() as usize).min(data.len());
+    let validation = indices.split_off(data.len() - validation_size);
+
+    Ok((indices, validation))
+}
+
+/// computes the mean and standard deviation of every feature over the samples, e.g. to standardize the inputs of a dataset
+///
+/// a constant feature gets a standard deviation of 1 so `apply_normalization` only centers it.
+/// samples of different lengths return `Error::DimensionMismatch`
+pub fn fit_normalization(data: &[Vec<f32>]) -> Result<(Vec<f32>, Vec<f32>), Error> {
+    let Some(first) = data.first() else { return Ok((Vec::new(), Vec::new())) };
+    if data.iter().any(|sample| sample.len() != first.len()) { return Err(Error::DimensionMismatch) };
+
+    let count = data.len() as f64;
+
+    // summed in f64 since a dataset can have far more samples than f32 adds up precisely
+    let mut mean = vec![0.0f64; first.len()];
+
+    for sample in data {
+        for (total, value) in mean.iter_mut().zip(sample) {
+            *total += *value as f64;
+        }
+    }
+
+    mean.iter_mut().for_each(|total| *total /= count);
+
+    let mut variance = vec![0.0f64; first.len()];
+
+    for sample in data {
+        for ((total, value), mean) in variance.iter_mut().zip(sample).zip(&mean) {
+            *total += (*value as f64 - mean).powi(2);
+        }
+    }
+
+    let std = variance.iter()
+        .map(|total| (total / count).sqrt() as f32)
+        .map(|std| if std > 0.0 { std } else { 1.0 })
+        .collect();
+
+    Ok((mean.into_iter().map(|mean| mean as f32).collect(), std))
+}
+
+/// standardizes a sample in place with the mean and standard deviation from `fit_normalization`
+pub fn apply_normalization(sample: &mut [f32], mean: &[f32], std: &[f32]) -> Result<(), Error> {
+    if sample.len() != mean.len() || sample.len() != std.len() { return Err(Error::DimensionMismatch) };
+
+    for i in 0..sample.len() {
+        sample[i] = (sample[i] - mean[i]) / std[i];
+    }
+
+    Ok(())
+}
+
+/// Decorrelates the features of a sample and scales them to unit variance with the inverse square root of their covariance (ZCA whitening)
+///
+/// a heavier alternative to `fit_normalization` that also removes the correlations between the features
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Whitening {
+    mean: Vec<f32>,
+    /// the row-major (features, features) inverse square root of the covariance
+    transform: Vec<f32>,
+}
+
+impl Whitening {
+    /// computes the mean and covariance of every feature over the samples
+    ///
+    /// `epsilon` is added to the eigenvalues of the covariance so features without variance don't blow up
+    pub fn fit(data: &[Vec<f32>], epsilon: f32) -> Result<Self, Error> {
+        let Some(first) = data.first() else { return Err(Error::InvalidInput) };
+        if epsilon.is_nan() || epsilon < 0.0 { return Err(Error::InvalidInput) };
+        if data.iter().any(|sample| sample.len() != first.len()) { return Err(Error::DimensionMismatch) };
+
+        let size = first.len();
+        let count = data.len() as f64;
+
+        let mut mean = vec![0.0f64; size];
+
+        for sample in data {
+            for (total, value) in mean.iter_mut().zip(sample) {
+                *total += *value as f64;
+            }
+        }
+
+        mean.iter_mut().for_each(|total| *total /= count);
+
+        let mut covariance = vec![0.0f64; size * size];
+
+        for sample in data {
+            for i in 0..size {
+                let centered = sample[i] as f64 - mean[i];
+
+                for j in 0..size {
+                    covariance[i * size + j] += centered * (sample[j] as f64 - mean[j]);
+                }
+            }
+        }
+
+        covariance.iter_mut().for_each(|total| *total /= count);
+
+        // C^(-1/2) = V diag(1 / sqrt(eigenvalue + epsilon)) V^T
+        let (eigenvalues, eigenvectors) = symmetric_eigen(covariance, size);
+        let mut transform = vec![0.0f32; size * size];
+
+        for i in 0..size {
+            for j in 0..size {
+                let value: f64 = (0..size)
+                    .map(|k| eigenvectors[i * size + k] * eigenvectors[j * size + k] / (eigenvalues[k].max(0.0) + epsilon as f64).sqrt())
+                    .sum();
+
+                transform[i * size + j] = value as f32;
+            }
+        }
+
+        Ok(Self { mean: mean.into_iter().map(|mean| mean as f32).collect(), transform })
+    }
+
+    /// whitens a sample in place
+    pub fn apply(&self, sample: &mut [f32]) -> Result<(), Error> {
+        if sample.len() != self.mean.len() { return Err(Error::DimensionMismatch) };
+
+        let centered: Vec<f32> = sample.iter().zip(&self.mean).map(|(value, mean)| value - mean).collect();
+
+        for (i, value) in sample.iter_mut().enumerate() {
+            let row = &self.transform[i * centered.len()..(i + 1) * centered.len()];
+            *value = row.iter().zip(&centered).map(|(weight, value)| weight * value).sum();
+        }
+
+        Ok(())
+    }
+}
+
+/// returns the eigenvalues and the row-major matrix whose columns are the eigenvectors of a symmetric (size, size) matrix
+///
+/// uses cyclic Jacobi rotations, which are accurate and simple for the small matrices of input features
+fn symmetric_eigen(mut matrix: Vec<f64>, size: usize) -> (Vec<f64>, Vec<f64>) {
+    let mut vectors = vec![0.0f64; size * size];
+    (0..size).for_each(|i| vectors[i * size + i] = 1.0);
+
+    for _ in 0..100 {
+        let off_diagonal: f64 = (0..size).flat_map(|i| (0..size).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|(i, j)| matrix[i * size + j] * matrix[i * size + j])
+            .sum();
+
+        if off_diagonal < 1e-24 { break };
+
+        for p in 0..size {
+            for q in p + 1..size {
+                let apq = matrix[p * size + q];
+                if apq == 0.0 { continue };
+
+                // the rotation that zeroes matrix[p][q], signum(0.0) is 1 so equal diagonals rotate by 45 degrees
+                let theta = (matrix[q * size + q] - matrix[p * size + p]) / (2.0 * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let (c, s) = (1.0 / (t * t + 1.0).sqrt(), t / (t * t + 1.0).sqrt());
+
+                for k in 0..size {
+                    let (akp, akq) = (matrix[k * size + p], matrix[k * size + q]);
+                    matrix[k * size + p] = c * akp - s * akq;
+                    matrix[k * size + q] = s * akp + c * akq;
+                }
+
+                for k in 0..size {
+                    let (apk, aqk) = (matrix[p * size + k], matrix[q * size + k]);
+                    matrix[p * size + k] = c * apk - s * aqk;
+                    matrix[q * size + k] = s * apk + c * aqk;
+                }
+
+                for k in 0..size {
+                    let (vkp, vkq) = (vectors[k * size + p], vectors[k * size + q]);
+                    vectors[k * size + p] = c * vkp - s * vkq;
+                    vectors[k * size + q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+
+    ((0..size).map(|i| matrix[i * size + i]).collect(), vectors)
+}
+
+/// strides were serialized as a single value for both axes before format version 3
+pub(crate) fn scalar_stride(stride: usize) -> (usize, usize) {
+    (stride, stride)
 }
\ No newline at end of file